@@ -0,0 +1,279 @@
+//! Helper injected into devcontainers so the CLI can probe the environment, watch
+//! ports and supervise processes without ad-hoc shell pipelines.
+//!
+//! The agent only depends on `std`; build it as a static binary (for example for
+//! `x86_64-unknown-linux-musl`) so it runs regardless of the image's libc.
+
+use std::{
+    collections::BTreeSet,
+    env, fs,
+    io::{self, Write},
+    process::{Command, ExitCode},
+    thread,
+    time::{Duration, Instant},
+};
+
+const USAGE: &str = "usage: devcontainer-agent <command>
+
+commands:
+  version                                  print the agent version
+  env [--shell <path> --probe <mode>]      print the environment, NUL-separated
+  ports                                    list listening TCP ports
+  wait-port <port> [--timeout <seconds>]   wait until a TCP port is listening
+  supervise [--restarts <n>] -- <cmd>...   run a command, restarting it on failure";
+
+/// Separates shell startup noise from the probed environment.
+const ENV_MARKER: &str = "DEVCONTAINER_AGENT_ENV";
+
+const TCP_LISTEN_STATE: &str = "0A";
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(10);
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(code) => code,
+        Err(message) => {
+            eprintln!("devcontainer-agent: {message}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<ExitCode, String> {
+    let Some((command, rest)) = args.split_first() else {
+        return Err(USAGE.to_string());
+    };
+
+    match command.as_str() {
+        "version" => {
+            println!("{}", env!("CARGO_PKG_VERSION"));
+            Ok(ExitCode::SUCCESS)
+        }
+        "env" => print_env(rest),
+        "ports" => {
+            for port in listening_ports()? {
+                println!("{port}");
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        "wait-port" => wait_port(rest),
+        "supervise" => supervise(rest),
+        other => Err(format!("unknown command '{other}'\n{USAGE}")),
+    }
+}
+
+/// Prints `NAME=VALUE` entries separated by NUL, optionally as seen by a login or
+/// interactive shell of the given kind.
+fn print_env(args: &[String]) -> Result<ExitCode, String> {
+    let shell = option_value(args, "--shell")?;
+    let probe = option_value(args, "--probe")?.unwrap_or("none");
+
+    let mut stdout = io::stdout().lock();
+    let entries = match (shell, shell_flags(probe)?) {
+        (Some(shell), Some(flags)) => probe_shell_env(shell, flags)?,
+        _ => env::vars()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect(),
+    };
+
+    write!(stdout, "{ENV_MARKER}\0").map_err(|err| err.to_string())?;
+    for entry in entries {
+        write!(stdout, "{entry}\0").map_err(|err| err.to_string())?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn shell_flags(probe: &str) -> Result<Option<&'static str>, String> {
+    match probe {
+        "none" => Ok(None),
+        "loginShell" => Ok(Some("-lc")),
+        "interactiveShell" => Ok(Some("-ic")),
+        "loginInteractiveShell" => Ok(Some("-lic")),
+        other => Err(format!("unknown env probe '{other}'")),
+    }
+}
+
+fn probe_shell_env(shell: &str, flags: &str) -> Result<Vec<String>, String> {
+    let agent = env::current_exe().map_err(|err| format!("cannot locate agent: {err}"))?;
+    let script = format!(
+        "exec '{}' env",
+        agent.display().to_string().replace('\'', "'\\''")
+    );
+    let output = Command::new(shell)
+        .arg(flags)
+        .arg(script)
+        .output()
+        .map_err(|err| format!("failed to run {shell}: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{shell} exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(parse_env_output(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Extracts the entries printed after the marker, ignoring anything the shell's
+/// startup files wrote first.
+fn parse_env_output(output: &str) -> Vec<String> {
+    let mut entries = output.split('\0');
+    let mut found = false;
+    for entry in entries.by_ref() {
+        if entry.ends_with(ENV_MARKER) {
+            found = true;
+            break;
+        }
+    }
+    if !found {
+        return Vec::new();
+    }
+    entries
+        .filter(|entry| entry.contains('='))
+        .map(str::to_string)
+        .collect()
+}
+
+fn listening_ports() -> Result<BTreeSet<u16>, String> {
+    let mut ports = BTreeSet::new();
+    let mut readable = false;
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        if let Ok(contents) = fs::read_to_string(table) {
+            readable = true;
+            ports.extend(parse_listening_ports(&contents));
+        }
+    }
+
+    if readable {
+        Ok(ports)
+    } else {
+        Err("cannot read /proc/net/tcp".to_string())
+    }
+}
+
+/// Parses a `/proc/net/tcp` table, returning the local ports in the LISTEN state.
+///
+/// Mirrors `listening_ports` in `devcontainer_core::ports`: the agent stays std-only
+/// so it can ship as a static binary, and so cannot depend on the core crate.
+fn parse_listening_ports(table: &str) -> BTreeSet<u16> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let local = fields.get(1)?;
+            let state = fields.get(3)?;
+            if *state != TCP_LISTEN_STATE {
+                return None;
+            }
+            let (_, port) = local.rsplit_once(':')?;
+            u16::from_str_radix(port, 16).ok()
+        })
+        .collect()
+}
+
+fn wait_port(args: &[String]) -> Result<ExitCode, String> {
+    let port: u16 = args
+        .first()
+        .ok_or("wait-port requires a port")?
+        .parse()
+        .map_err(|err| format!("invalid port: {err}"))?;
+    let timeout = option_value(args, "--timeout")?
+        .map(|secs| {
+            secs.parse()
+                .map_err(|err| format!("invalid timeout: {err}"))
+        })
+        .transpose()?
+        .map(Duration::from_secs);
+
+    let started = Instant::now();
+    loop {
+        if listening_ports()?.contains(&port) {
+            return Ok(ExitCode::SUCCESS);
+        }
+        if timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+            eprintln!("devcontainer-agent: port {port} is not listening");
+            return Ok(ExitCode::FAILURE);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn supervise(args: &[String]) -> Result<ExitCode, String> {
+    let separator = args
+        .iter()
+        .position(|arg| arg == "--")
+        .ok_or("supervise requires '--' before the command")?;
+    let (options, command) = (&args[..separator], &args[separator + 1..]);
+    let (program, program_args) = command
+        .split_first()
+        .ok_or("supervise requires a command")?;
+    let restarts: u32 = option_value(options, "--restarts")?
+        .map(|count| {
+            count
+                .parse()
+                .map_err(|err| format!("invalid restarts: {err}"))
+        })
+        .transpose()?
+        .unwrap_or(0);
+
+    let mut attempt = 0;
+    loop {
+        let status = Command::new(program)
+            .args(program_args)
+            .status()
+            .map_err(|err| format!("failed to run {program}: {err}"))?;
+        if status.success() || attempt >= restarts {
+            let code = status.code().unwrap_or(1);
+            return Ok(ExitCode::from(u8::try_from(code).unwrap_or(1)));
+        }
+
+        attempt += 1;
+        let backoff = restart_backoff(attempt);
+        eprintln!(
+            "devcontainer-agent: {program} exited with {status}; restarting in {}s ({attempt}/{restarts})",
+            backoff.as_secs()
+        );
+        thread::sleep(backoff);
+    }
+}
+
+fn restart_backoff(attempt: u32) -> Duration {
+    Duration::from_secs(u64::from(attempt)).min(MAX_RESTART_BACKOFF)
+}
+
+fn option_value<'a>(args: &'a [String], name: &str) -> Result<Option<&'a str>, String> {
+    match args.iter().position(|arg| arg == name) {
+        Some(index) => args
+            .get(index + 1)
+            .map(|value| Some(value.as_str()))
+            .ok_or_else(|| format!("{name} requires a value")),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_listening_ports_from_proc_table() {
+        let table = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 1 1
+   1: 0100007F:0CEA 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 2 1
+   2: 0100007F:A2B4 0100007F:1F90 01 00000000:00000000 00:00000000 00000000  1000        0 3 1
+";
+        assert_eq!(parse_listening_ports(table), BTreeSet::from([8080, 3306]));
+    }
+
+    #[test]
+    fn env_output_skips_shell_startup_noise() {
+        let output = format!("welcome!\n{ENV_MARKER}\0PATH=/usr/bin\0EMPTY=\0");
+        assert_eq!(parse_env_output(&output), vec!["PATH=/usr/bin", "EMPTY="]);
+        assert!(parse_env_output("no marker\0PATH=/usr/bin\0").is_empty());
+        assert_eq!(restart_backoff(30), MAX_RESTART_BACKOFF);
+    }
+}
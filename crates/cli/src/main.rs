@@ -1,14 +1,61 @@
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    io::{IsTerminal, Write},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use devcontainer_core::{
-    config::{ConfigOverrides, ConfigResolver, ConfigSource},
-    lifecycle::{LifecycleExecutor, LifecyclePlan, LifecyclePlanOptions},
-    provider::{Provider, ProviderCleanupOptions, RunningContainer},
-    telemetry::{self, LogFormat},
+    agent::HelperAgent,
+    clock::{measure_skew, ClockCheck, DEFAULT_TOLERANCE},
+    config::{
+        discover_config_files, find_workspace_root, merge::merge_configuration, query_document,
+        ConfigOverrides, ConfigResolver, ConfigSource, ContainerLabel, HookUser, Mount,
+        MountConsistency, PullPolicy, ResolvedConfig, LABEL_CONFIG_HASH,
+    },
+    env::{EnvLayer, EnvStack},
+    features::{
+        feature_metadata,
+        test::{FeatureTestHarness, FeatureTestOptions, ScenarioOutcome},
+        FeatureCollection,
+    },
+    idle::IdleReaper,
+    lifecycle::{
+        transcript::{HookRun, HookTranscript},
+        LifecycleExecutor, LifecycleHook, LifecyclePlan, LifecyclePlanOptions,
+    },
+    limits::{format_bytes, ResourceLimits},
+    lockfile::{sync_lockfile, LockMode},
+    manager::DevcontainerManager,
+    policy::{self, Policy},
+    ports::{self, PortWatcher},
+    provider::{
+        artifact_mirror, container_metadata, discover_containers, exec_with_timeout,
+        image_metadata, layer_image_metadata, probe_login_shell, probe_user_env, warn_if_drifted,
+        ContainerDetails, ExecOptions, InteractiveSession, OutputSink, OutputStream, Provider,
+        ProviderCleanupOptions, ProviderKind, ProviderRegistry, RunningContainer, REBUILD_HINT,
+    },
+    registry::{
+        CredentialHelperSelection, RegistryAuth, RegistryCredential, RegistryCredentials,
+        RegistryMirror,
+    },
+    rpc::RpcServer,
+    state::{LifecycleState, StateStore, RUN_LOGS_KEPT},
+    tasks::run_task,
+    telemetry::{
+        self,
+        otlp::{self, OtlpExporter},
+        LogFormat, TelemetryOptions,
+    },
     DevcontainerError, Result,
 };
 use devcontainer_provider_docker::DockerProvider;
+use serde::Serialize;
+use tokio::{sync::Semaphore, task::JoinSet};
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -29,8 +76,81 @@ struct Cli {
     workspace_folder: Option<PathBuf>,
     #[arg(long = "config", global = true)]
     config: Option<PathBuf>,
+    /// Configuration to use among `.devcontainer/<name>/devcontainer.json`.
+    #[arg(
+        long = "config-name",
+        global = true,
+        value_name = "NAME",
+        conflicts_with = "config"
+    )]
+    config_name: Option<String>,
+    /// Container runtime to use instead of the first of docker, podman and nerdctl that answers.
+    #[arg(long = "provider", global = true, env = "DEVCONTAINER_PROVIDER")]
+    provider: Option<ProviderKind>,
     #[arg(long = "docker-path", global = true)]
     docker_path: Option<PathBuf>,
+    /// Docker daemon to use, e.g. `ssh://user@host`; defaults to `DOCKER_HOST` or the current context.
+    /// The workspace is copied into a volume when the daemon is remote.
+    #[arg(long = "docker-host", global = true)]
+    docker_host: Option<String>,
+    /// Pull-through registry cache to pull images and resolve digests through first,
+    /// instead of the daemon's mirror.
+    #[arg(
+        long = "registry-mirror",
+        global = true,
+        env = "DEVCONTAINER_REGISTRY_MIRROR"
+    )]
+    registry_mirror: Option<RegistryMirror>,
+    /// Absolute path the workspace is mounted at in the container, overriding `workspaceFolder`.
+    #[arg(long = "container-workspace-folder", global = true)]
+    container_workspace_folder: Option<PathBuf>,
+    /// Shell running string lifecycle commands, such as `bash -lc`, overriding
+    /// `lifecycleShell`; `/bin/sh -c` by default.
+    #[arg(long = "lifecycle-shell", global = true, value_name = "SHELL")]
+    lifecycle_shell: Option<String>,
+    /// Registry credentials as `host=user:token`; repeatable.
+    #[arg(
+        long = "registry-auth",
+        global = true,
+        env = "DEVCONTAINER_REGISTRY_AUTH",
+        hide_env_values = true,
+        value_delimiter = ','
+    )]
+    registry_auth: Vec<RegistryCredential>,
+    /// Registry credentials as JSON: a docker `config.json` with `auths`, or a map of
+    /// hosts to `{"username", "password"}`.
+    #[arg(
+        long = "registry-auth-json",
+        global = true,
+        env = "DEVCONTAINER_REGISTRY_AUTH_JSON",
+        hide_env_values = true
+    )]
+    registry_auth_json: Option<RegistryCredentials>,
+    /// Docker credential helper to use for a registry as `host=helper`; repeatable.
+    #[arg(long = "registry-credential-helper", global = true)]
+    registry_credential_helper: Vec<CredentialHelperSelection>,
+    /// Entry of the configuration's `profiles` to merge over the base configuration.
+    #[arg(long = "profile", global = true, env = "DEVCONTAINER_PROFILE")]
+    profile: Option<String>,
+    /// Write every lifecycle and provider event of this run to a JSON Lines file.
+    #[arg(long = "event-log", global = true, env = "DEVCONTAINER_EVENT_LOG")]
+    event_log: Option<PathBuf>,
+    /// JSON object of environment variables given to lifecycle hooks and remote
+    /// processes, masked in all output and never stored in the image or container.
+    #[arg(long = "secrets-file", global = true)]
+    secrets_file: Option<PathBuf>,
+    /// OpenTelemetry collector to export lifecycle phase and command spans to
+    /// over OTLP/HTTP, such as `http://localhost:4318`.
+    #[arg(long = "otel-endpoint", global = true, env = otlp::ENDPOINT_ENV)]
+    otel_endpoint: Option<String>,
+    /// Also write every log line of this run, down to trace level, to this file.
+    /// Each run is logged to the state directory as well, keeping the latest ones.
+    #[arg(long = "log-file", global = true, env = "DEVCONTAINER_LOG_FILE")]
+    log_file: Option<PathBuf>,
+    /// Admin policy file the configuration must satisfy, in addition to the
+    /// system policy at /etc/devcontainers/policy.json.
+    #[arg(long = "policy", global = true, env = "DEVCONTAINER_POLICY")]
+    policy: Option<PathBuf>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -58,12 +178,36 @@ enum Commands {
     Down(DownArgs),
     Build(BuildArgs),
     Exec(ExecArgs),
+    /// Open an interactive shell in the devcontainer.
+    #[command(alias = "attach")]
+    Shell(ShellArgs),
+    /// Print prompt and alias setup for a shell's rc file.
+    #[command(name = "shell-integration")]
+    ShellIntegration(ShellIntegrationArgs),
     #[command(name = "run-user-commands")]
     RunUser(RunUserCommandsArgs),
+    /// Set up a running container created by another tool, from its metadata label.
+    #[command(name = "set-up")]
+    SetUp(SetUpArgs),
+    /// Run a named command from the configuration's `tasks`.
+    Task(TaskArgs),
     ReadConfiguration(ReadConfigurationArgs),
     Features(FeaturesArgs),
     Templates(TemplatesArgs),
     Inspect(InspectArgs),
+    /// List the devcontainers `up` has brought up, most recent first.
+    List,
+    /// Resolve the features and image in `devcontainer-lock.json` to their latest digests.
+    Upgrade,
+    Status(StatusArgs),
+    /// Report ports the devcontainer starts listening on, as `portsAttributes` ask.
+    #[command(name = "watch-ports")]
+    WatchPorts(WatchPortsArgs),
+    Events(EventsArgs),
+    /// Serve lifecycle operations over JSON-RPC, for editor extensions.
+    Serve(ServeArgs),
+    /// Report host conditions that can break builds, such as cgroup limits.
+    Doctor,
     Version,
 }
 
@@ -71,43 +215,228 @@ enum Commands {
 struct UpArgs {
     #[arg(long)]
     attach: bool,
+    /// Label identifying the container, as `name=value`; repeatable.
+    #[arg(long = "id-label")]
+    id_label: Vec<ContainerLabel>,
+    #[arg(long = "skip-on-create")]
+    skip_on_create: bool,
+    #[arg(long = "skip-update-content")]
+    skip_update_content: bool,
     #[arg(long = "skip-post-create")]
     skip_post_create: bool,
+    #[arg(long = "skip-post-start")]
+    skip_post_start: bool,
     #[arg(long = "skip-post-attach")]
     skip_post_attach: bool,
+    /// Convert CRLF line endings in lifecycle scripts to LF before running hooks.
+    #[arg(long = "fix-line-endings")]
+    fix_line_endings: bool,
+    /// Environment variable for lifecycle hooks as `NAME=VALUE`; repeatable.
+    #[arg(long = "env", value_parser = parse_env_var)]
+    env: Vec<(String, String)>,
+    /// User to run lifecycle hooks as, as `user` or `hook=user`; repeatable.
+    #[arg(long = "hook-user")]
+    hook_user: Vec<HookUser>,
+    /// Recreate the container even when its configuration is unchanged.
+    #[arg(long = "remove-existing-container")]
+    remove_existing_container: bool,
+    /// Skip the phases an earlier `up` completed and retry from the one that failed.
+    /// Hooks only carry over from a run with `--keep-on-failure`.
+    #[arg(long = "resume", conflicts_with = "remove_existing_container")]
+    resume: bool,
+    /// Keep the container, networks and volumes of a failed `up` instead of removing them.
+    #[arg(long = "keep-on-failure")]
+    keep_on_failure: bool,
+    #[command(flatten)]
+    pull: PullArgs,
+    #[command(flatten)]
+    lockfile: LockfileArgs,
+    /// Resynchronize the engine clock when the container's has drifted from the host's;
+    /// only for a local engine in a VM, such as Docker Desktop or colima.
+    #[arg(long = "fix-clock")]
+    fix_clock: bool,
+    /// Bring the container up even when the host misses `hostRequirements`.
+    #[arg(long = "ignore-host-requirements")]
+    ignore_host_requirements: bool,
+    /// Static `devcontainer-agent` binary to inject into the container.
+    #[arg(long = "helper-agent", env = "DEVCONTAINER_HELPER_AGENT")]
+    helper_agent: Option<PathBuf>,
+    /// Bring up every `.devcontainer/*/devcontainer.json` in the workspace.
+    #[arg(long = "all-configs", conflicts_with_all = ["attach", "id_label"])]
+    all_configs: bool,
+    /// Maximum number of configurations brought up at once with `--all-configs`.
+    #[arg(long = "parallel", default_value_t = 1, requires = "all_configs")]
+    parallel: usize,
+    /// Mount the workspace read-only, e.g. for review or audit containers.
+    #[arg(long = "read-only-workspace")]
+    read_only_workspace: bool,
+    /// Workspace-relative path kept writable in a volume, e.g. `target`; repeatable.
+    #[arg(long = "writable-path")]
+    writable_path: Vec<String>,
+    /// Leave out a feature, by id with or without version, without editing the
+    /// configuration; repeatable.
+    #[arg(long = "skip-feature")]
+    skip_feature: Vec<String>,
+    /// Clone this git repository into a named volume and use it as the workspace,
+    /// instead of bind-mounting the workspace folder.
+    #[arg(long = "clone", conflicts_with = "all_configs")]
+    clone: Option<String>,
+    /// Named volume used as the workspace; with `--clone`, the repository is
+    /// cloned into it while it is empty.
+    #[arg(long = "workspace-volume", conflicts_with = "all_configs")]
+    workspace_volume: Option<String>,
+    /// Consistency of the workspace bind mount on Docker Desktop for Mac:
+    /// `consistent`, `cached` or `delegated`.
+    #[arg(long = "workspace-mount-consistency")]
+    workspace_mount_consistency: Option<MountConsistency>,
+    /// Return once this hook completes and run later hooks in the background,
+    /// overriding the configuration's `waitFor`.
+    #[arg(long = "wait-for", conflicts_with = "all_configs")]
+    wait_for: Option<LifecycleHook>,
+    /// Run the hooks a previous `up` deferred; used by the background continuation.
+    #[arg(long = "continue-pending", hide = true)]
+    continue_pending: bool,
+    /// Stop after `onCreate` and `updateContent` and commit the container to this
+    /// image, labelled with its metadata, for use as a prebuild.
+    #[arg(
+        long = "prebuild",
+        value_name = "IMAGE",
+        conflicts_with_all = ["all_configs", "attach", "wait_for"]
+    )]
+    prebuild: Option<String>,
+    /// Stop the container after this long without exec or attach sessions, e.g. `2h`.
+    #[arg(long = "idle-timeout", value_parser = parse_duration, conflicts_with = "all_configs")]
+    idle_timeout: Option<Duration>,
+    /// Watch the container for `--idle-timeout`; used by the background supervisor.
+    #[arg(long = "supervise-idle", hide = true, requires = "idle_timeout")]
+    supervise_idle: bool,
+    /// Format of the result printed on stdout; `auto` prints JSON with `--log-format json`.
+    #[arg(long = "output", value_enum, default_value_t = OutputFormat::Auto)]
+    output: OutputFormat,
+}
+
+/// Result document of `up`, in the shape the reference CLI prints for IDE integrations.
+#[derive(Debug, Serialize)]
+#[serde(
+    tag = "outcome",
+    rename_all = "lowercase",
+    rename_all_fields = "camelCase"
+)]
+enum UpResult {
+    Success {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        container_id: Option<String>,
+        remote_user: String,
+        remote_workspace_folder: PathBuf,
+        hooks: Vec<HookRun>,
+    },
+    Error {
+        message: String,
+        description: String,
+        /// Hook commands run before the failure; the failing one, if any, is last.
+        hooks: Vec<HookRun>,
+    },
 }
 
 impl UpArgs {
     async fn run(&self, ctx: &CommandContext) -> Result<()> {
-        let source = ctx.config_source();
-        let resolver = ConfigResolver::new(source).with_overrides(ctx.config_overrides());
-        let resolved = resolver.resolve()?;
+        let json = match self.output {
+            OutputFormat::Auto => matches!(ctx.log_format, OutputFormat::Json),
+            OutputFormat::Text => false,
+            OutputFormat::Json => true,
+        };
+        let transcript = HookTranscript::new();
+        let result = self.up(ctx, json, &transcript).await;
+        if let (true, Err(err)) = (json, &result) {
+            print_json(&UpResult::Error {
+                message: err.to_string(),
+                description: "An error occurred setting up the container.".to_string(),
+                hooks: transcript.runs(),
+            })?;
+        }
+        result
+    }
 
-        let plan = LifecyclePlan::for_up(
-            &resolved,
-            LifecyclePlanOptions {
-                skip_post_create: self
-                    .skip_post_create
-                    .then(|| "--skip-post-create flag set".to_string()),
-                skip_post_attach: self
-                    .skip_post_attach
-                    .then(|| "--skip-post-attach flag set".to_string()),
-            },
-        );
+    async fn up(
+        &self,
+        ctx: &CommandContext,
+        json: bool,
+        transcript: &HookTranscript,
+    ) -> Result<()> {
+        if self.all_configs {
+            return self.run_all_configs(ctx).await;
+        }
 
-        let provider = ctx.provider();
-        let executor = LifecycleExecutor::new(provider);
+        let mut resolved = self
+            .resolver(ctx.config_source(), ctx.config_overrides())
+            .resolve()?;
+        ctx.enforce_policies(&resolved)?;
+        let executor = self
+            .executor(ctx)
+            .await?
+            .with_wait_for(self.wait_for)
+            .with_prebuild(self.prebuild.clone())
+            .with_transcript(transcript.clone());
+        sync_lockfile(executor.provider(), &mut resolved, self.lockfile.mode()).await?;
+        let resolved = layer_image_metadata(executor.provider(), resolved).await?;
+        let plan = LifecyclePlan::for_up(&resolved, self.plan_options());
+        if let (true, Some(timeout)) = (self.supervise_idle, self.idle_timeout) {
+            return supervise_idle(executor.provider(), &resolved, timeout).await;
+        }
+        if self.continue_pending {
+            return self.run_pending(&executor, &resolved, &plan).await;
+        }
         let outcome = executor.execute(&resolved, &plan).await?;
+        if !outcome.pending_hooks.is_empty() {
+            let pid = spawn_detached("--continue-pending")?;
+            tracing::info!(
+                pid,
+                "Continuing lifecycle hooks in the background; see `devcontainer inspect`"
+            );
+        }
+        if let Some(timeout) = self.idle_timeout {
+            let pid = spawn_detached("--supervise-idle")?;
+            tracing::info!(pid, ?timeout, "Stopping the container once it is idle");
+        }
 
-        tracing::info!(?outcome.container, "Devcontainer is ready");
+        tracing::info!(
+            ?outcome.container,
+            reused = outcome.reused,
+            agent = ?outcome.agent.as_ref().map(|agent| agent.path()),
+            "Devcontainer is ready"
+        );
+
+        if json {
+            print_json(&UpResult::Success {
+                container_id: outcome
+                    .container
+                    .id
+                    .clone()
+                    .or(outcome.container.name.clone()),
+                remote_user: resolved
+                    .remote_user
+                    .clone()
+                    .unwrap_or_else(|| "root".to_string()),
+                remote_workspace_folder: outcome.remote_workspace_folder.clone(),
+                hooks: transcript.runs(),
+            })?;
+        }
 
-        if self.attach {
-            tracing::info!("Attach requested, placeholder handler invoked");
+        if self.attach && !self.continue_pending {
+            open_shell(executor.provider(), &resolved, &outcome.container, None).await?;
         }
 
-        if self.skip_post_create || self.skip_post_attach {
+        if self.skip_on_create
+            || self.skip_update_content
+            || self.skip_post_create
+            || self.skip_post_start
+            || self.skip_post_attach
+        {
             tracing::debug!(
+                skip_on_create = self.skip_on_create,
+                skip_update_content = self.skip_update_content,
                 skip_post_create = self.skip_post_create,
+                skip_post_start = self.skip_post_start,
                 skip_post_attach = self.skip_post_attach,
                 "Lifecycle hooks skipped",
             );
@@ -115,6 +444,228 @@ impl UpArgs {
 
         Ok(())
     }
+
+    /// Runs the hooks deferred by `waitFor` against the workspace's existing container.
+    async fn run_pending(
+        &self,
+        executor: &LifecycleExecutor<Box<dyn Provider>>,
+        resolved: &ResolvedConfig,
+        plan: &LifecyclePlan,
+    ) -> Result<()> {
+        let container =
+            find_container(executor.provider(), resolved, "continue lifecycle hooks in").await?;
+        let phases = executor
+            .continue_pending(resolved, plan, &container)
+            .await?;
+        tracing::info!(?phases, "Deferred lifecycle hooks completed");
+        Ok(())
+    }
+
+    /// Brings up every configuration in the workspace, at most `--parallel` at a time,
+    /// and prints one outcome line per configuration.
+    async fn run_all_configs(&self, ctx: &CommandContext) -> Result<()> {
+        if ctx.config_path.is_some() || ctx.config_name.is_some() {
+            return Err(DevcontainerError::Configuration(
+                "--all-configs cannot be combined with --config or --config-name".into(),
+            ));
+        }
+
+        let workspace = ctx.workspace_root();
+        let workspace = find_workspace_root(&workspace).unwrap_or(workspace);
+        let configs = discover_config_files(&workspace)?;
+        if configs.is_empty() {
+            return Err(DevcontainerError::Configuration(format!(
+                "No devcontainer configurations found under {}",
+                workspace.join(".devcontainer").display()
+            )));
+        }
+
+        let limit = Arc::new(Semaphore::new(self.parallel.max(1)));
+        let mut tasks = JoinSet::new();
+        for (index, config_path) in configs.iter().enumerate() {
+            let overrides = ctx
+                .config_overrides()
+                .with_workspace_folder(workspace.clone());
+            let resolved = self
+                .resolver(ConfigSource::ExplicitFile(config_path.clone()), overrides)
+                .resolve()
+                .and_then(|resolved| ctx.enforce_policies(&resolved).map(|()| resolved));
+            let options = self.plan_options();
+            let lock_mode = self.lockfile.mode();
+            // Nothing continues deferred hooks per configuration, so run them all.
+            let executor = self
+                .executor(ctx)
+                .await?
+                .with_wait_for(LifecycleHook::ALL.last().copied());
+            let limit = Arc::clone(&limit);
+            tasks.spawn(async move {
+                let _permit = limit
+                    .acquire_owned()
+                    .await
+                    .expect("semaphore is never closed");
+                let outcome = async {
+                    let mut resolved = resolved?;
+                    sync_lockfile(executor.provider(), &mut resolved, lock_mode).await?;
+                    let resolved = layer_image_metadata(executor.provider(), resolved).await?;
+                    let plan = LifecyclePlan::for_up(&resolved, options);
+                    executor.execute(&resolved, &plan).await
+                }
+                .await;
+                (index, outcome)
+            });
+        }
+
+        let mut outcomes: Vec<_> = std::iter::repeat_with(|| None)
+            .take(configs.len())
+            .collect();
+        while let Some(joined) = tasks.join_next().await {
+            let (index, outcome) = joined.map_err(|err| DevcontainerError::Other(err.into()))?;
+            outcomes[index] = Some(outcome);
+        }
+
+        let mut failures = 0;
+        for (config_path, outcome) in configs.iter().zip(outcomes) {
+            let config = config_path
+                .strip_prefix(&workspace)
+                .unwrap_or(config_path)
+                .display();
+            match outcome.expect("every configuration reports an outcome") {
+                Ok(outcome) => {
+                    let container = outcome.container.name.as_deref().unwrap_or("<unnamed>");
+                    let state = if outcome.reused { "reused" } else { "up" };
+                    println!("{state}\t{config}\t{container}");
+                }
+                Err(err) => {
+                    failures += 1;
+                    println!("failed\t{config}\t{err}");
+                }
+            }
+        }
+
+        if failures > 0 {
+            return Err(DevcontainerError::Provider(format!(
+                "{failures} of {} devcontainer configurations failed to come up",
+                configs.len()
+            )));
+        }
+        Ok(())
+    }
+
+    fn resolver(&self, source: ConfigSource, overrides: ConfigOverrides) -> ConfigResolver {
+        ConfigResolver::new(source).with_overrides(
+            overrides
+                .with_id_labels(self.id_label.clone())
+                .with_env(self.env.iter().cloned().collect())
+                .with_hook_users(self.hook_user.clone())
+                .with_read_only_workspace(self.read_only_workspace, self.writable_path.clone())
+                .with_skipped_features(self.skip_feature.clone())
+                .with_workspace_volume(self.workspace_volume.clone(), self.clone.clone())
+                .with_workspace_mount_consistency(self.workspace_mount_consistency)
+                .with_pull_policy(self.pull.policy()),
+        )
+    }
+
+    async fn executor(&self, ctx: &CommandContext) -> Result<LifecycleExecutor<Box<dyn Provider>>> {
+        let executor = LifecycleExecutor::new(ctx.provider().await?)
+            .with_line_ending_fix(self.fix_line_endings)
+            .with_remove_existing_container(self.remove_existing_container)
+            .with_resume(self.resume)
+            .with_keep_on_failure(self.keep_on_failure)
+            .with_clock_check(Some(ClockCheck::new().with_fix(self.fix_clock)))
+            .with_ignore_host_requirements(self.ignore_host_requirements)
+            .with_helper_agent(self.helper_agent.clone().map(HelperAgent::new))
+            .with_cancellation(interrupt_token());
+        Ok(match StateStore::default_location() {
+            Some(store) => executor.with_state_store(store),
+            None => executor,
+        })
+    }
+
+    fn plan_options(&self) -> LifecyclePlanOptions {
+        // Later hooks belong to the containers started from a prebuild, not to its image.
+        let prebuild = self
+            .prebuild
+            .as_ref()
+            .map(|_| "--prebuild flag set".to_string());
+        LifecyclePlanOptions {
+            skip_on_create: self
+                .skip_on_create
+                .then(|| "--skip-on-create flag set".to_string()),
+            skip_update_content: self
+                .skip_update_content
+                .then(|| "--skip-update-content flag set".to_string()),
+            skip_post_create: self
+                .skip_post_create
+                .then(|| "--skip-post-create flag set".to_string())
+                .or(prebuild.clone()),
+            skip_post_start: self
+                .skip_post_start
+                .then(|| "--skip-post-start flag set".to_string())
+                .or(prebuild.clone()),
+            skip_post_attach: self
+                .skip_post_attach
+                .then(|| "--skip-post-attach flag set".to_string())
+                .or(prebuild),
+            ..Default::default()
+        }
+    }
+}
+
+fn print_json(value: &impl Serialize) -> Result<()> {
+    let output =
+        serde_json::to_string(value).map_err(|err| DevcontainerError::Other(err.into()))?;
+    println!("{output}");
+    Ok(())
+}
+
+/// Existing devcontainer of `resolved`, found by its identifying labels.
+async fn find_container(
+    provider: &dyn Provider,
+    resolved: &ResolvedConfig,
+    purpose: &str,
+) -> Result<RunningContainer> {
+    discover_containers(provider, &resolved.id_labels)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| {
+            DevcontainerError::Configuration(format!("No devcontainer found to {purpose}"))
+        })
+}
+
+/// Stops the workspace's devcontainer once it has no sessions for `timeout`.
+async fn supervise_idle(
+    provider: &dyn Provider,
+    resolved: &ResolvedConfig,
+    timeout: Duration,
+) -> Result<()> {
+    let container = find_container(provider, resolved, "supervise").await?;
+    let preparation = provider.prepare(resolved).await?;
+    let outcome = IdleReaper::new(timeout)
+        .run(provider, resolved, &preparation, &container)
+        .await?;
+    tracing::info!(?outcome, "Idle supervision finished");
+    Ok(())
+}
+
+/// Re-runs this `up` invocation detached with the hidden `flag`, such as
+/// `--continue-pending`, so its work outlives this process. Returns the child's pid.
+fn spawn_detached(flag: &str) -> Result<u32> {
+    let exe = std::env::current_exe().map_err(|err| DevcontainerError::Other(err.into()))?;
+    let mut command = std::process::Command::new(exe);
+    command
+        .args(std::env::args_os().skip(1))
+        .arg(flag)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+    let child = command
+        .spawn()
+        .map_err(|err| DevcontainerError::Other(err.into()))?;
+    Ok(child.id())
 }
 
 #[derive(Debug, Args)]
@@ -123,32 +674,22 @@ struct DownArgs {
     remove_volumes: bool,
     #[arg(long = "remove-unknown")]
     remove_unknown: bool,
+    /// Label identifying the container, as `name=value`; repeatable.
+    #[arg(long = "id-label")]
+    id_label: Vec<ContainerLabel>,
 }
 
 impl DownArgs {
     async fn run(&self, ctx: &CommandContext) -> Result<()> {
-        let source = ctx.config_source();
-        let resolver = ConfigResolver::new(source).with_overrides(ctx.config_overrides());
-        let resolved = resolver.resolve()?;
-
-        let provider = ctx.provider();
-        let preparation = provider.prepare(&resolved).await?;
-        let container = RunningContainer {
-            id: None,
-            name: Some(preparation.container_name.clone()),
-        };
-
-        provider
-            .stop_container(&resolved, &preparation, &container)
+        let manager = DevcontainerManager::new(ctx.provider().await?, ctx.config_source())
+            .with_overrides(ctx.config_overrides().with_id_labels(self.id_label.clone()));
+        manager
+            .down(&ProviderCleanupOptions {
+                remove_volumes: self.remove_volumes,
+                remove_unknown: self.remove_unknown,
+            })
             .await?;
 
-        let options = ProviderCleanupOptions {
-            remove_volumes: self.remove_volumes,
-            remove_unknown: self.remove_unknown,
-        };
-
-        provider.cleanup(&resolved, &preparation, &options).await?;
-
         tracing::info!(
             remove_volumes = self.remove_volumes,
             remove_unknown = self.remove_unknown,
@@ -159,41 +700,155 @@ impl DownArgs {
     }
 }
 
+#[derive(Debug, Args)]
+struct ServeArgs {
+    /// Listen on a unix socket at this path instead of serving stdin and stdout.
+    #[arg(long)]
+    socket: Option<PathBuf>,
+}
+
+impl ServeArgs {
+    async fn run(&self, ctx: &CommandContext) -> Result<()> {
+        let server =
+            RpcServer::new(Arc::from(ctx.provider().await?)).with_overrides(ctx.config_overrides());
+        match &self.socket {
+            #[cfg(unix)]
+            Some(path) => server.serve_unix(path).await,
+            #[cfg(not(unix))]
+            Some(_) => Err(DevcontainerError::Unsupported(
+                "--socket requires unix sockets".to_string(),
+            )),
+            None => server.serve(tokio::io::stdin(), tokio::io::stdout()).await,
+        }
+    }
+}
+
 #[derive(Debug, Args)]
 struct BuildArgs {
     #[arg(long = "no-cache")]
     no_cache: bool,
+    /// Push every `--image-name` using the docker login and credential helpers.
     #[arg(long = "push")]
     push: bool,
+    /// Name to tag the image with, e.g. `ghcr.io/org/image:tag`; repeatable.
+    #[arg(long = "image-name")]
+    image_name: Vec<String>,
+    /// Image or BuildKit cache source to reuse layers from; repeatable.
+    #[arg(long = "cache-from")]
+    cache_from: Vec<String>,
+    /// BuildKit cache export destination, e.g. `type=registry,ref=...`; repeatable.
+    #[arg(long = "cache-to")]
+    cache_to: Vec<String>,
+    /// Platforms to build for, e.g. `linux/amd64,linux/arm64`; more than one needs `--push`.
+    #[arg(long = "platform", value_delimiter = ',')]
+    platform: Vec<String>,
+    #[command(flatten)]
+    pull: PullArgs,
+    #[command(flatten)]
+    lockfile: LockfileArgs,
+}
+
+#[derive(Debug, Args)]
+struct PullArgs {
+    /// When to pull the image: `always`, `if-not-present` (the default) or `never`.
+    #[arg(long = "pull-policy")]
+    pull_policy: Option<PullPolicy>,
+    /// Pull the image even when present, refreshing floating tags such as `latest`.
+    #[arg(long = "pull", conflicts_with = "pull_policy")]
+    pull: bool,
+}
+
+impl PullArgs {
+    fn policy(&self) -> PullPolicy {
+        if self.pull {
+            PullPolicy::Always
+        } else {
+            self.pull_policy.unwrap_or_default()
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+struct LockfileArgs {
+    /// Write `devcontainer-lock.json`, pinning the features and image it lacks;
+    /// without it an existing lockfile's image pin is used but never changed.
+    #[arg(long = "lockfile")]
+    lockfile: bool,
+    /// Fail when `devcontainer-lock.json` does not pin exactly the features and
+    /// image the configuration uses, as CI wants.
+    #[arg(long = "frozen-lockfile", conflicts_with = "lockfile")]
+    frozen_lockfile: bool,
+}
+
+impl LockfileArgs {
+    fn mode(&self) -> LockMode {
+        if self.frozen_lockfile {
+            LockMode::Frozen
+        } else if self.lockfile {
+            LockMode::Update
+        } else {
+            LockMode::Use
+        }
+    }
 }
 
 impl BuildArgs {
     async fn run(&self, ctx: &CommandContext) -> Result<()> {
         let source = ctx.config_source();
-        let resolver = ConfigResolver::new(source).with_overrides(ctx.config_overrides());
-        let resolved = resolver.resolve()?;
+        let resolver = ConfigResolver::new(source).with_overrides(
+            ctx.config_overrides()
+                .with_build_cache(self.cache_from.clone(), self.cache_to.clone())
+                .with_platforms(self.platform.clone())
+                .with_image_names(self.image_name.clone(), self.push)
+                .with_pull_policy(self.pull.policy()),
+        );
+        let mut resolved = resolver.resolve()?;
+        ctx.enforce_policies(&resolved)?;
 
         if self.no_cache {
             tracing::warn!("--no-cache flag is not yet implemented; proceeding with cached build");
         }
-        if self.push {
-            tracing::warn!("--push flag is not yet implemented; build output will remain local");
-        }
 
-        let provider = ctx.provider();
+        let provider = ctx.provider().await?;
+        sync_lockfile(provider.as_ref(), &mut resolved, self.lockfile.mode()).await?;
         let preparation = provider.prepare(&resolved).await?;
+        ResourceLimits::detect().warn_if_constrained();
         let image_reference = provider.build_image(&resolved, &preparation).await?;
 
         tracing::info!(image = %image_reference, "Devcontainer image ready");
-        println!("{image_reference}");
+        // Pushed builds report `name@digest`; every name shares the digest.
+        match image_reference.split_once('@') {
+            Some((_, digest)) if self.push => {
+                for name in &self.image_name {
+                    println!("{name}@{digest}");
+                }
+            }
+            _ => println!("{image_reference}"),
+        }
         Ok(())
     }
 }
 
 #[derive(Debug, Args)]
 struct ExecArgs {
+    /// Label identifying the container, as `name=value`; repeatable.
     #[arg(long = "id-label")]
-    id_label: Option<String>,
+    id_label: Vec<ContainerLabel>,
+    /// Environment variable for the command as `NAME=VALUE`; repeatable.
+    #[arg(long = "env", value_parser = parse_env_var)]
+    env: Vec<(String, String)>,
+    /// Directory to run the command in; defaults to the container workspace folder.
+    #[arg(long = "workdir")]
+    workdir: Option<PathBuf>,
+    /// User to run the command as; defaults to `remoteUser`.
+    #[arg(long = "user")]
+    user: Option<String>,
+    /// Never allocate a terminal, even when attached to one.
+    #[arg(long = "no-tty")]
+    no_tty: bool,
+    /// Kill the command and exit with status 124 if it runs longer than this, e.g. `90s` or `5m`.
+    #[arg(long = "timeout", value_parser = parse_duration)]
+    timeout: Option<Duration>,
     #[arg(last = true)]
     command: Vec<String>,
 }
@@ -206,75 +861,478 @@ impl ExecArgs {
             ));
         }
 
-        if self.id_label.is_some() {
-            tracing::warn!("--id-label is not yet implemented; using workspace resolution");
+        let provider = ctx.provider().await?;
+        let (container, mut env, defaults) = self.locate_container(ctx, &provider).await?;
+        let options = ExecOptions {
+            user: self.user.clone().or(defaults.user),
+            working_dir: self.workdir.clone().or(defaults.working_dir),
+        };
+        for (name, value) in probe_user_env(&provider, &container, options.user.as_deref()).await? {
+            env.set(EnvLayer::UserEnvProbe, name, value);
+        }
+        let command = env.wrap_command(&self.command);
+        tracing::debug!(?options, ?command, "Running command");
+
+        // A timed out command is stopped through a pid file, which needs a streamed exec.
+        let tty = !self.no_tty
+            && self.timeout.is_none()
+            && provider.capabilities().supports_attach
+            && std::io::stdin().is_terminal()
+            && std::io::stdout().is_terminal();
+        if tty {
+            let session = InteractiveSession {
+                user: options.user,
+                working_dir: options.working_dir,
+                command,
+            };
+            let exit_code = provider.attach(&container, &session).await?;
+            if exit_code != 0 {
+                return Err(DevcontainerError::CommandFailed { exit_code });
+            }
+            return Ok(());
+        }
+
+        // Output is copied byte for byte, so binary streams such as `tar c` survive.
+        let output = OutputSink::passthrough(|stream, bytes| {
+            let _ = match stream {
+                OutputStream::Stdout => std::io::stdout().write_all(bytes),
+                OutputStream::Stderr => std::io::stderr().write_all(bytes),
+            };
+        });
+        let result = match self.timeout {
+            Some(timeout) => {
+                exec_with_timeout(&provider, &container, &options, &command, &output, timeout)
+                    .await?
+            }
+            None => {
+                provider
+                    .exec_with_options(&container, &options, &command, &output)
+                    .await?
+            }
+        };
+        let _ = std::io::stdout().flush();
+
+        if result.exit_code != 0 {
+            return Err(DevcontainerError::CommandFailed {
+                exit_code: result.exit_code,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl ExecArgs {
+    /// Finds the devcontainer by label, bringing it up when a workspace has none yet,
+    /// along with the environment layers to apply to the command and the user and
+    /// working directory it runs with by default.
+    async fn locate_container(
+        &self,
+        ctx: &CommandContext,
+        provider: &dyn Provider,
+    ) -> Result<(RunningContainer, EnvStack, ExecOptions)> {
+        let cli_env: BTreeMap<String, String> = self.env.iter().cloned().collect();
+        if !self.id_label.is_empty() {
+            let container = discover_containers(provider, &self.id_label)
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| {
+                    let labels: Vec<String> =
+                        self.id_label.iter().map(ToString::to_string).collect();
+                    DevcontainerError::Configuration(format!(
+                        "No container found with labels {}",
+                        labels.join(", ")
+                    ))
+                })?;
+            provider.start_container(&container).await?;
+            return Ok((
+                container,
+                EnvStack::new()
+                    .with_layer(EnvLayer::Secrets, ctx.secrets.clone())
+                    .with_layer(EnvLayer::Cli, cli_env),
+                ExecOptions::default(),
+            ));
         }
 
+        let source = ctx.config_source();
+        let resolver =
+            ConfigResolver::new(source).with_overrides(ctx.config_overrides().with_env(cli_env));
+        let resolved = resolver.resolve()?;
+        let resolved = layer_image_metadata(provider, resolved).await?;
+
+        let container =
+            ensure_container(ctx, provider, &resolved, "exec command requested").await?;
+        let preparation = provider.prepare(&resolved).await?;
+        let defaults = ExecOptions {
+            user: resolved.remote_user.clone(),
+            working_dir: Some(preparation.workspace_mount_path),
+        };
+        Ok((container, resolved.env_stack(), defaults))
+    }
+}
+
+/// Returns the workspace's devcontainer, bringing it up without hooks when none
+/// exists, and warns when an existing one no longer matches the configuration.
+async fn ensure_container(
+    ctx: &CommandContext,
+    provider: &dyn Provider,
+    resolved: &ResolvedConfig,
+    reason: &str,
+) -> Result<RunningContainer> {
+    if let Some(container) = recorded_container(resolved) {
+        match provider.start_container(&container).await {
+            Ok(()) => {
+                tracing::debug!(container = ?container.name, "Using devcontainer recorded by `up`");
+                warn_if_drifted(provider, resolved, &container).await;
+                return Ok(container);
+            }
+            Err(err) => {
+                tracing::debug!(error = %err, "Recorded devcontainer is gone; discovering by label")
+            }
+        }
+    }
+    if let Some(container) = discover_containers(provider, &resolved.id_labels)
+        .await?
+        .into_iter()
+        .next()
+    {
+        tracing::debug!(container = ?container.name, "Found existing devcontainer by label");
+        provider.start_container(&container).await?;
+        warn_if_drifted(provider, resolved, &container).await;
+        return Ok(container);
+    }
+
+    ctx.enforce_policies(resolved)?;
+    let plan = LifecyclePlan::for_up(resolved, LifecyclePlanOptions::skip_all(reason));
+
+    let executor = LifecycleExecutor::new(provider);
+    let outcome = executor.execute(resolved, &plan).await?;
+    Ok(outcome.container)
+}
+
+#[derive(Debug, Args)]
+struct TaskArgs {
+    /// Task to run; the configured tasks are listed when omitted.
+    name: Option<String>,
+    /// Environment variable for the task as `NAME=VALUE`; repeatable.
+    #[arg(long = "env", value_parser = parse_env_var)]
+    env: Vec<(String, String)>,
+}
+
+impl TaskArgs {
+    async fn run(&self, ctx: &CommandContext) -> Result<()> {
+        let cli_env: BTreeMap<String, String> = self.env.iter().cloned().collect();
+        let resolver = ConfigResolver::new(ctx.config_source())
+            .with_overrides(ctx.config_overrides().with_env(cli_env));
+        let resolved = resolver.resolve()?;
+
+        let Some(name) = &self.name else {
+            for task in resolved.tasks.keys() {
+                println!("{task}");
+            }
+            return Ok(());
+        };
+
+        let provider = ctx.provider().await?;
+        let resolved = layer_image_metadata(&provider, resolved).await?;
+        let container = ensure_container(ctx, &provider, &resolved, "task requested").await?;
+        let output = OutputSink::new(|line| match line.stream {
+            OutputStream::Stdout => println!("{}", line.line),
+            OutputStream::Stderr => eprintln!("{}", line.line),
+        });
+        run_task(&provider, &resolved, &container, name, &output).await
+    }
+}
+
+#[derive(Debug, Args)]
+struct ShellArgs {
+    /// Shell to start instead of the remote user's login shell.
+    #[arg(long)]
+    shell: Option<String>,
+}
+
+impl ShellArgs {
+    async fn run(&self, ctx: &CommandContext) -> Result<()> {
         let source = ctx.config_source();
         let resolver = ConfigResolver::new(source).with_overrides(ctx.config_overrides());
         let resolved = resolver.resolve()?;
 
-        let plan = LifecyclePlan::for_up(
-            &resolved,
-            LifecyclePlanOptions {
-                skip_post_create: Some("exec command requested".to_string()),
-                skip_post_attach: Some("exec command requested".to_string()),
-            },
+        let provider = ctx.provider().await?;
+        let resolved = layer_image_metadata(&provider, resolved).await?;
+        let container = ensure_container(ctx, &provider, &resolved, "shell requested").await?;
+        open_shell(&provider, &resolved, &container, self.shell.clone()).await
+    }
+}
+
+/// Attaches an interactive login shell in the container workspace folder.
+async fn open_shell(
+    provider: &dyn Provider,
+    resolved: &ResolvedConfig,
+    container: &RunningContainer,
+    shell: Option<String>,
+) -> Result<()> {
+    let preparation = provider.prepare(resolved).await?;
+    let user = resolved.remote_user.as_deref();
+    let shell = match shell {
+        Some(shell) => shell,
+        None => probe_login_shell(provider, container, user).await?,
+    };
+
+    let mut env = resolved.env_stack();
+    if env.source_of(SESSION_MARKER_ENV).is_none() {
+        env.set(
+            EnvLayer::RemoteEnv,
+            SESSION_MARKER_ENV,
+            resolved.project_name.clone(),
         );
+    }
+    let session = InteractiveSession {
+        user: user.map(str::to_string),
+        working_dir: Some(preparation.workspace_mount_path),
+        command: env.wrap_command(&[shell, "-l".to_string()]),
+    };
+    tracing::debug!(?session, "Attaching interactive shell");
 
-        let provider = ctx.provider();
-        let executor = LifecycleExecutor::new(provider);
-        let outcome = executor.execute(&resolved, &plan).await?;
+    let exit_code = provider.attach(container, &session).await?;
+    if exit_code != 0 {
+        return Err(DevcontainerError::Provider(format!(
+            "Shell exited with status {exit_code}"
+        )));
+    }
 
-        let result = executor
-            .provider()
-            .exec(&outcome.container, &self.command)
-            .await?;
+    Ok(())
+}
+
+/// Set in `shell` sessions to the devcontainer's name, for prompts to show.
+const SESSION_MARKER_ENV: &str = "DEVCONTAINER";
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum IntegrationShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Debug, Args)]
+struct ShellIntegrationArgs {
+    #[arg(value_enum)]
+    shell: IntegrationShell,
+}
 
-        if !result.stdout.is_empty() {
-            print!("{}", result.stdout);
+impl ShellIntegrationArgs {
+    fn run(&self) {
+        print!("{}", shell_integration(self.shell));
+    }
+}
+
+/// Snippet that prefixes the prompt with `[name]` inside `shell` sessions and
+/// adds `dcu`, `dcd` and `dce` for `up`, `down` and `exec`.
+fn shell_integration(shell: IntegrationShell) -> String {
+    let aliases = [("dcu", "up"), ("dcd", "down"), ("dce", "exec")];
+    match shell {
+        IntegrationShell::Bash | IntegrationShell::Zsh => {
+            let (name, prompt) = match shell {
+                IntegrationShell::Bash => ("bash", "PS1"),
+                _ => ("zsh", "PROMPT"),
+            };
+            let mut snippet = format!(
+                "# Add to ~/.{name}rc: eval \"$(devcontainer shell-integration {name})\"\n"
+            );
+            for (alias, command) in aliases {
+                snippet.push_str(&format!("alias {alias}='devcontainer {command}'\n"));
+            }
+            snippet.push_str(&format!(
+                "__devcontainer_prompt() {{\n    \
+                 [ -n \"${{{SESSION_MARKER_ENV}:-}}\" ] && printf '[%s] ' \"${SESSION_MARKER_ENV}\"\n}}\n"
+            ));
+            if matches!(shell, IntegrationShell::Zsh) {
+                snippet.push_str("setopt PROMPT_SUBST\n");
+            }
+            snippet.push_str(&format!(
+                "case \"${prompt}\" in\n    \
+                 *__devcontainer_prompt*) ;;\n    \
+                 *) {prompt}='$(__devcontainer_prompt)'\"${prompt}\" ;;\nesac\n"
+            ));
+            snippet
         }
-        if !result.stderr.is_empty() {
-            eprint!("{}", result.stderr);
+        IntegrationShell::Fish => {
+            let mut snippet = String::from(
+                "# Add to ~/.config/fish/config.fish: devcontainer shell-integration fish | source\n",
+            );
+            for (alias, command) in aliases {
+                snippet.push_str(&format!("alias {alias} 'devcontainer {command}'\n"));
+            }
+            snippet.push_str(&format!(
+                "if not functions -q __devcontainer_original_prompt\n    \
+                 functions -c fish_prompt __devcontainer_original_prompt\n    \
+                 function fish_prompt\n        \
+                 set -q {SESSION_MARKER_ENV}; and printf '[%s] ' ${SESSION_MARKER_ENV}\n        \
+                 __devcontainer_original_prompt\n    \
+                 end\nend\n"
+            ));
+            snippet
         }
+    }
+}
 
-        if result.exit_code != 0 {
-            return Err(DevcontainerError::Provider(format!(
-                "Command exited with status {}",
-                result.exit_code
-            )));
+#[derive(Debug, Args)]
+struct RunUserCommandsArgs {
+    #[arg(long = "trigger", value_enum)]
+    trigger: UserCommandsTrigger,
+    /// Label identifying the container, as `name=value`; repeatable.
+    #[arg(long = "id-label")]
+    id_label: Vec<ContainerLabel>,
+    /// Environment variable for the commands as `NAME=VALUE`; repeatable.
+    #[arg(long = "env", value_parser = parse_env_var)]
+    env: Vec<(String, String)>,
+    /// User to run the commands as, as `user` or `hook=user`; repeatable.
+    #[arg(long = "hook-user")]
+    hook_user: Vec<HookUser>,
+}
+
+/// Point in the container's life from which the user commands run again.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum UserCommandsTrigger {
+    /// Every container hook, as after the container was created.
+    Init,
+    PostCreate,
+    PostAttach,
+}
+
+impl UserCommandsTrigger {
+    fn hooks(self) -> &'static [LifecycleHook] {
+        match self {
+            UserCommandsTrigger::Init => &LifecycleHook::ALL,
+            UserCommandsTrigger::PostCreate => &[
+                LifecycleHook::PostCreate,
+                LifecycleHook::PostStart,
+                LifecycleHook::PostAttach,
+            ],
+            UserCommandsTrigger::PostAttach => &[LifecycleHook::PostAttach],
         }
+    }
+}
 
+impl RunUserCommandsArgs {
+    async fn run(&self, ctx: &CommandContext) -> Result<()> {
+        let resolved = ConfigResolver::new(ctx.config_source())
+            .with_overrides(
+                ctx.config_overrides()
+                    .with_id_labels(self.id_label.clone())
+                    .with_env(self.env.iter().cloned().collect())
+                    .with_hook_users(self.hook_user.clone()),
+            )
+            .resolve()?;
+        let provider = ctx.provider().await?;
+        let resolved = layer_image_metadata(&provider, resolved).await?;
+
+        let container = match recorded_container(&resolved).filter(|_| self.id_label.is_empty()) {
+            Some(container) => container,
+            None => find_container(&provider, &resolved, "run user commands in").await?,
+        };
+        provider.start_container(&container).await?;
+
+        let plan = LifecyclePlan::for_hooks(&resolved, self.trigger.hooks());
+        let executor = LifecycleExecutor::new(provider);
+        let executor = match StateStore::default_location() {
+            Some(store) => executor.with_state_store(store),
+            None => executor,
+        };
+        let phases = executor.run_hooks(&resolved, &plan, &container).await?;
+        tracing::info!(?phases, "User commands completed");
         Ok(())
     }
 }
 
 #[derive(Debug, Args)]
-struct RunUserCommandsArgs {
-    #[arg(
-        long = "trigger",
-        value_parser = clap::builder::PossibleValuesParser::new(["init", "post-create", "post-attach"])
-    )]
-    trigger: String,
+struct SetUpArgs {
+    /// Id or name of the running container to set up.
+    #[arg(long = "container-id")]
+    container_id: String,
+    /// Environment variable for the lifecycle hooks as `NAME=VALUE`; repeatable.
+    #[arg(long = "env", value_parser = parse_env_var)]
+    env: Vec<(String, String)>,
 }
 
-impl RunUserCommandsArgs {
-    async fn run(&self, _ctx: &CommandContext) -> Result<()> {
-        tracing::info!(trigger = %self.trigger, "Running user command (stub)");
+impl SetUpArgs {
+    /// Reads the configuration from the container's `devcontainer.metadata` alone,
+    /// as the container may not come from any workspace on this host.
+    async fn run(&self, ctx: &CommandContext) -> Result<()> {
+        let provider = ctx.provider().await?;
+        let container = RunningContainer {
+            id: Some(self.container_id.clone()),
+            ..Default::default()
+        };
+        let metadata = container_metadata(&provider, &container).await?;
+        if metadata.is_empty() {
+            tracing::warn!(
+                container = %self.container_id,
+                "Container has no devcontainer metadata; nothing to set up"
+            );
+        }
+        let resolved = ResolvedConfig {
+            project_name: self.container_id.clone(),
+            cli_env: self.env.iter().cloned().collect(),
+            secrets: ctx.secrets.clone(),
+            lifecycle_shell: ctx.lifecycle_shell.clone(),
+            ..Default::default()
+        }
+        .with_image_metadata(&metadata)?;
+        ctx.enforce_policies(&resolved)?;
+
+        let plan = LifecyclePlan::for_hooks(&resolved, &LifecycleHook::ALL);
+        let phases = LifecycleExecutor::new(provider)
+            .with_cancellation(interrupt_token())
+            .set_up(&resolved, &plan, &container)
+            .await?;
+        tracing::info!(?phases, "Container is set up");
         Ok(())
     }
 }
 
 #[derive(Debug, Args)]
-struct ReadConfigurationArgs;
+struct ReadConfigurationArgs {
+    /// Print only the value at a dotted path, e.g. `customizations.vscode.extensions`.
+    #[arg(long)]
+    query: Option<String>,
+    /// Also print `mergedConfiguration`: the image's `devcontainer.metadata`, local
+    /// feature contributions and this configuration merged into one document.
+    #[arg(long = "include-merged-configuration")]
+    include_merged_configuration: bool,
+}
 
 impl ReadConfigurationArgs {
     async fn run(&self, ctx: &CommandContext) -> Result<()> {
         let source = ctx.config_source();
         let resolver = ConfigResolver::new(source).with_overrides(ctx.config_overrides());
         let resolved = resolver.resolve()?;
-        let output = serde_json::to_string_pretty(&resolved)
+        if !self.include_merged_configuration {
+            let output = match &self.query {
+                Some(path) => serde_json::to_string_pretty(&resolved.query(path)?),
+                None => serde_json::to_string_pretty(&resolved),
+            }
             .map_err(|err| DevcontainerError::Other(err.into()))?;
+            println!("{output}");
+            return Ok(());
+        }
+
+        let provider = ctx.provider().await?;
+        let mut metadata = image_metadata(&provider, &resolved).await?;
+        metadata.extend(feature_metadata(
+            &resolved.config_path,
+            &resolved.features,
+            &resolved.override_feature_install_order,
+        )?);
+        let document = serde_json::json!({
+            "configuration": resolved,
+            "mergedConfiguration": merge_configuration(&resolver.document()?, &metadata),
+        });
+        let output = match &self.query {
+            Some(path) => serde_json::to_string_pretty(&query_document(&document, path)?),
+            None => serde_json::to_string_pretty(&document),
+        }
+        .map_err(|err| DevcontainerError::Other(err.into()))?;
         println!("{output}");
         Ok(())
     }
@@ -288,14 +1346,105 @@ struct FeaturesArgs {
 
 #[derive(Debug, Subcommand)]
 enum FeaturesSubcommand {
-    Test,
+    Test(FeaturesTestArgs),
     Publish,
     Package,
 }
 
 impl FeaturesArgs {
-    async fn run(&self, _ctx: &CommandContext) -> Result<()> {
-        tracing::info!(subcommand = ?self.command, "Features command invoked (stub)");
+    async fn run(&self, ctx: &CommandContext) -> Result<()> {
+        match &self.command {
+            FeaturesSubcommand::Test(args) => args.run(ctx).await,
+            other => {
+                tracing::info!(subcommand = ?other, "Features command invoked (stub)");
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+struct FeaturesTestArgs {
+    #[arg(short = 'p', long = "project-folder")]
+    project_folder: Option<PathBuf>,
+    #[arg(short = 'f', long = "features", num_args = 1..)]
+    features: Vec<String>,
+    #[arg(long = "skip-scenarios")]
+    skip_scenarios: bool,
+    #[arg(long = "skip-autogenerated")]
+    skip_autogenerated: bool,
+    #[arg(long = "preserve-test-containers")]
+    preserve_test_containers: bool,
+    /// Leave a feature out of every scenario; repeatable.
+    #[arg(long = "skip-feature")]
+    skip_feature: Vec<String>,
+    /// Directory for the full logs of failed feature installs.
+    #[arg(long = "log-dir")]
+    log_dir: Option<PathBuf>,
+    /// Run only the named scenario, such as `autogenerated`; repeatable.
+    #[arg(long = "scenario")]
+    scenario: Vec<String>,
+    /// Run only scenarios whose `feature/scenario` contains this text.
+    #[arg(long = "filter")]
+    filter: Option<String>,
+    /// Image for the autogenerated tests and scenarios that name none.
+    #[arg(long = "base-image")]
+    base_image: Option<String>,
+    /// Scenarios run in parallel, each in its own container.
+    #[arg(long = "concurrency", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    concurrency: u16,
+}
+
+impl FeaturesTestArgs {
+    async fn run(&self, ctx: &CommandContext) -> Result<()> {
+        let project_folder = self
+            .project_folder
+            .clone()
+            .unwrap_or_else(|| ctx.project_root.clone());
+
+        let mut options = FeatureTestOptions {
+            features: self.features.clone(),
+            skip_scenarios: self.skip_scenarios,
+            skip_autogenerated: self.skip_autogenerated,
+            preserve_test_containers: self.preserve_test_containers,
+            skip_features: self.skip_feature.clone(),
+            scenarios: self.scenario.clone(),
+            filter: self.filter.clone(),
+            concurrency: usize::from(self.concurrency),
+            ..Default::default()
+        };
+        if let Some(log_dir) = &self.log_dir {
+            options.log_dir = log_dir.clone();
+        }
+        if let Some(image) = &self.base_image {
+            options.base_image = image.clone();
+        }
+
+        let harness = FeatureTestHarness::new(
+            ctx.provider().await?,
+            FeatureCollection::new(project_folder),
+        );
+        let report = harness.run(&options).await?;
+
+        for result in &report.results {
+            match &result.outcome {
+                ScenarioOutcome::Passed => {
+                    println!("[PASS] {} / {}", result.feature, result.scenario)
+                }
+                ScenarioOutcome::Failed { reason } => {
+                    println!("[FAIL] {} / {}: {reason}", result.feature, result.scenario)
+                }
+            }
+        }
+        println!("{} passed, {} failed", report.passed(), report.failed());
+
+        if !report.is_success() {
+            return Err(DevcontainerError::Provider(format!(
+                "{} feature test scenario(s) failed",
+                report.failed()
+            )));
+        }
+
         Ok(())
     }
 }
@@ -320,21 +1469,502 @@ impl TemplatesArgs {
     }
 }
 
+/// Print a JSON report of the workspace's devcontainer: its configuration, what
+/// the provider creates for it, the live container and the recorded lifecycle.
 #[derive(Debug, Args)]
-struct InspectArgs;
+struct InspectArgs {
+    /// Label identifying the container, as `name=value`; repeatable.
+    #[arg(long = "id-label")]
+    id_label: Vec<ContainerLabel>,
+}
 
 impl InspectArgs {
-    async fn run(&self, _ctx: &CommandContext) -> Result<()> {
-        tracing::info!("Inspect command invoked (stub)");
+    async fn run(&self, ctx: &CommandContext) -> Result<()> {
+        let resolver = ConfigResolver::new(ctx.config_source())
+            .with_overrides(ctx.config_overrides().with_id_labels(self.id_label.clone()));
+        let resolved = resolver.resolve()?;
+        let state = state_store()?.load(&resolved)?;
+        let provider = ctx.provider().await?;
+        let preparation = provider.prepare(&resolved).await?;
+
+        let container = match state.as_ref().and_then(LifecycleState::running_container) {
+            Some(container) => Some(container),
+            None => discover_containers(&provider, &resolved.id_labels)
+                .await?
+                .into_iter()
+                .next(),
+        };
+        let container = match container {
+            Some(container) => {
+                let (activity, running) = match provider.container_activity(&container).await {
+                    Ok(activity) => (
+                        serde_json::json!({
+                            "running": activity.running,
+                            "sessions": activity.sessions,
+                        }),
+                        activity.running,
+                    ),
+                    Err(err) => (serde_json::json!({ "error": err.to_string() }), false),
+                };
+                // Only a running container has a clock to read.
+                let clock = if running {
+                    match measure_skew(&provider, &container).await {
+                        Ok(skew) => serde_json::json!({
+                            "skewSeconds": skew.seconds,
+                            "inSync": !skew.exceeds(DEFAULT_TOLERANCE),
+                        }),
+                        Err(err) => serde_json::json!({ "error": err.to_string() }),
+                    }
+                } else {
+                    serde_json::Value::Null
+                };
+                let details = match provider.inspect_container(&container).await {
+                    Ok(details) => details,
+                    Err(err) => {
+                        tracing::debug!(error = %err, "Container details unavailable");
+                        None
+                    }
+                };
+                serde_json::json!({
+                    "id": container.id,
+                    "name": container.name,
+                    "sidecars": container.sidecars,
+                    "status": activity,
+                    "clock": clock,
+                    "state": details.as_ref().map(|details| details.status.to_string()),
+                    "image": details.as_ref().and_then(|details| details.image.clone()),
+                    "imageDigest": details.as_ref().and_then(|details| details.image_digest.clone()),
+                    "labels": details.as_ref().map(|details| &details.labels),
+                    "mounts": details.as_ref().map(|details| &details.mounts),
+                })
+            }
+            None => serde_json::Value::Null,
+        };
+
+        let report = serde_json::json!({
+            "configuration": resolved,
+            "preparation": {
+                "image": preparation.image.reference(),
+                "containerName": preparation.container_name,
+                "projectSlug": preparation.project_slug,
+                "networks": preparation.networks,
+                "volumes": preparation.volumes.iter().map(|volume| serde_json::json!({
+                    "name": volume.name,
+                    "mountPath": volume.mount_path,
+                })).collect::<Vec<_>>(),
+                "workspaceMountPath": preparation.workspace_mount_path,
+                "sidecars": preparation.sidecars.iter().map(|sidecar| &sidecar.container_name).collect::<Vec<_>>(),
+            },
+            "container": container,
+            "features": resolved.features,
+            "forwardedPorts": state
+                .as_ref()
+                .map_or_else(|| ports::with_attributes(&resolved), |state| state.forwarded_ports.clone()),
+            "detectedPorts": state
+                .as_ref()
+                .map_or_else(Vec::new, |state| state.detected_ports.clone()),
+            "appPorts": resolved.app_ports,
+            "lifecycle": state,
+        });
+        let output = serde_json::to_string_pretty(&report)
+            .map_err(|err| DevcontainerError::Other(err.into()))?;
+        println!("{output}");
         Ok(())
     }
 }
 
+/// Print where the workspace's devcontainer stands, from the record `up` left.
+#[derive(Debug, Args)]
+struct StatusArgs {
+    /// Label identifying the container, as `name=value`; repeatable.
+    #[arg(long = "id-label")]
+    id_label: Vec<ContainerLabel>,
+}
+
+impl StatusArgs {
+    async fn run(&self, ctx: &CommandContext) -> Result<()> {
+        let resolved = ConfigResolver::new(ctx.config_source())
+            .with_overrides(ctx.config_overrides().with_id_labels(self.id_label.clone()))
+            .resolve()?;
+        let Some(state) = state_store()?.load(&resolved)? else {
+            println!("No devcontainer recorded for this workspace; run `devcontainer up`");
+            return Ok(());
+        };
+        print_state(&state);
+
+        let mut current_hash = resolved.config_hash();
+        let mut created_hash = state.config_hash.clone();
+        if let Some(container) = state.running_container() {
+            let provider = ctx.provider().await?;
+            match provider.inspect_container(&container).await {
+                Ok(Some(details)) => {
+                    print_container_state(provider.as_ref(), &details).await;
+                    print_container_details(&details);
+                    // Hashed as `up` hashes it, over the image's metadata.
+                    match layer_image_metadata(provider.as_ref(), resolved.clone()).await {
+                        Ok(layered) => current_hash = layered.config_hash(),
+                        Err(err) => tracing::debug!(error = %err, "Image metadata unavailable"),
+                    }
+                    if let Some(hash) = details.labels.get(LABEL_CONFIG_HASH) {
+                        created_hash = Some(hash.clone());
+                    }
+                }
+                Ok(None) => println!("  state:     removed"),
+                Err(err) => println!("  state:     unavailable ({err})"),
+            }
+        }
+        if created_hash.as_deref() != Some(current_hash.as_str()) {
+            println!("  config:    changed since the container was created; {REBUILD_HINT}");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+struct WatchPortsArgs {
+    /// Label identifying the container, as `name=value`; repeatable.
+    #[arg(long = "id-label")]
+    id_label: Vec<ContainerLabel>,
+    /// How often to look for new listening ports, e.g. `10s`.
+    #[arg(long = "interval", value_parser = parse_duration)]
+    interval: Option<Duration>,
+}
+
+impl WatchPortsArgs {
+    async fn run(&self, ctx: &CommandContext) -> Result<()> {
+        let provider = ctx.provider().await?;
+        let resolved = ConfigResolver::new(ctx.config_source())
+            .with_overrides(ctx.config_overrides().with_id_labels(self.id_label.clone()))
+            .resolve()?;
+        let resolved = layer_image_metadata(provider.as_ref(), resolved).await?;
+        let container = find_container(provider.as_ref(), &resolved, "watch").await?;
+        let store = state_store()?;
+        let mut watcher = PortWatcher::default();
+        if let Some(interval) = self.interval {
+            watcher = watcher.with_poll_interval(interval);
+        }
+        watcher
+            .run(provider.as_ref(), &resolved, &container, |port| {
+                tracing::info!(port = port.container_port, "Detected listening port");
+                let recorded =
+                    store.update(&resolved, |state| state.detected_ports.push(port.clone()));
+                if let Err(err) = recorded {
+                    tracing::warn!(error = %err, "Failed to record detected port");
+                }
+            })
+            .await
+    }
+}
+
+async fn list(ctx: &CommandContext) -> Result<()> {
+    let states = state_store()?.list()?;
+    if states.is_empty() {
+        println!("No devcontainers recorded; run `devcontainer up`");
+        return Ok(());
+    }
+    // Listing what was recorded still works while the engine is down.
+    let provider = match ctx.provider().await {
+        Ok(provider) => Some(provider),
+        Err(err) => {
+            tracing::debug!(error = %err, "No provider; listing recorded state only");
+            None
+        }
+    };
+    for state in &states {
+        print_state(state);
+        let (Some(provider), Some(container)) = (&provider, state.running_container()) else {
+            continue;
+        };
+        match provider.inspect_container(&container).await {
+            Ok(Some(details)) => print_container_state(provider.as_ref(), &details).await,
+            Ok(None) => println!("  state:     removed"),
+            Err(err) => tracing::debug!(error = %err, "Container state unavailable"),
+        }
+    }
+    Ok(())
+}
+
+/// Resolves every entry of the configuration's lockfile afresh and prints the pins.
+async fn upgrade(ctx: &CommandContext) -> Result<()> {
+    let mut resolved = ConfigResolver::new(ctx.config_source())
+        .with_overrides(ctx.config_overrides())
+        .resolve()?;
+    let provider = ctx.provider().await?;
+    let lockfile = sync_lockfile(provider.as_ref(), &mut resolved, LockMode::Upgrade).await?;
+    for (id, feature) in &lockfile.features {
+        println!("{id}\t{}", feature.resolved);
+    }
+    for (image, locked) in &lockfile.images {
+        println!("{image}\t{}", locked.resolved);
+    }
+    Ok(())
+}
+
+/// Prints the engine's state of the container, with its sessions while it runs.
+async fn print_container_state(provider: &dyn Provider, details: &ContainerDetails) {
+    if !details.running() {
+        println!("  state:     {}", details.status);
+        return;
+    }
+    match provider.container_activity(&details.container()).await {
+        Ok(activity) => println!("  state:     running, {} session(s)", activity.sessions),
+        Err(_) => println!("  state:     running"),
+    }
+}
+
+fn print_container_details(details: &ContainerDetails) {
+    if let Some(image) = &details.image {
+        match &details.image_digest {
+            Some(digest) => println!("  image:     {image} ({digest})"),
+            None => println!("  image:     {image}"),
+        }
+    }
+    for mount in &details.mounts {
+        if let Mount::Object {
+            mount_type,
+            source,
+            target,
+        } = mount
+        {
+            let source = source.as_deref().unwrap_or(mount_type);
+            println!("  mount:     {source} -> {target} ({mount_type})");
+        }
+    }
+}
+
+fn print_state(state: &LifecycleState) {
+    let name = state.container.as_deref().unwrap_or("<unnamed>");
+    match &state.container_id {
+        Some(id) => println!("{name} ({})", &id[..id.len().min(12)]),
+        None => println!("{name}"),
+    }
+    if let Some(workspace) = &state.workspace {
+        println!("  workspace: {}", workspace.display());
+    }
+    if !state.forwarded_ports.is_empty() {
+        let ports: Vec<String> = state
+            .forwarded_ports
+            .iter()
+            .map(|port| {
+                let mapping = if port.local_port == port.container_port {
+                    port.container_port.to_string()
+                } else {
+                    format!("{}:{}", port.local_port, port.container_port)
+                };
+                match &port.label {
+                    Some(label) => format!("{mapping} ({label})"),
+                    None => mapping,
+                }
+            })
+            .collect();
+        println!("  ports:     {}", ports.join(", "));
+    }
+    if !state.detected_ports.is_empty() {
+        let ports: Vec<String> = state
+            .detected_ports
+            .iter()
+            .map(|port| match &port.label {
+                Some(label) => format!("{} ({label})", port.container_port),
+                None => port.container_port.to_string(),
+            })
+            .collect();
+        println!("  detected:  {} (not forwarded)", ports.join(", "));
+    }
+    if let Some(last_up) = state.last_up {
+        let elapsed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|now| now.as_secs().saturating_sub(last_up))
+            .unwrap_or_default();
+        println!("  last up:   {}", format_age(elapsed));
+    }
+}
+
+fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{seconds}s ago"),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+fn state_store() -> Result<StateStore> {
+    StateStore::default_location().ok_or_else(|| {
+        DevcontainerError::Configuration("No state directory; set DEVCONTAINER_STATE_DIR".into())
+    })
+}
+
+/// The devcontainer `up` recorded for `resolved`, when the record is readable.
+fn recorded_container(resolved: &ResolvedConfig) -> Option<RunningContainer> {
+    let store = StateStore::default_location()?;
+    match store.load(resolved) {
+        Ok(state) => state?.running_container(),
+        Err(err) => {
+            tracing::debug!(error = %err, "Ignoring unreadable devcontainer record");
+            None
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+struct EventsArgs {
+    #[command(subcommand)]
+    command: EventsSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum EventsSubcommand {
+    /// Pretty-print an event log written with `--event-log`.
+    Show {
+        /// Event log to read; defaults to the `--event-log` path.
+        path: Option<PathBuf>,
+    },
+}
+
+impl EventsArgs {
+    async fn run(&self, ctx: &CommandContext) -> Result<()> {
+        match &self.command {
+            EventsSubcommand::Show { path } => {
+                let path = path.as_ref().or(ctx.event_log.as_ref()).ok_or_else(|| {
+                    DevcontainerError::Configuration(
+                        "No event log given; pass a path or set --event-log".into(),
+                    )
+                })?;
+                let contents = std::fs::read_to_string(path).map_err(|err| {
+                    DevcontainerError::Configuration(format!(
+                        "Failed to read event log {}: {err}",
+                        path.display()
+                    ))
+                })?;
+                for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+                    println!("{}", telemetry::format_event_line(line));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+async fn doctor(ctx: &CommandContext) {
+    let limits = ResourceLimits::detect();
+    let count = |value: Option<u64>| value.map_or("unlimited".to_string(), |v| v.to_string());
+    println!("Resource limits:");
+    println!(
+        "  memory:     {}",
+        limits
+            .memory_bytes
+            .map_or("unlimited".to_string(), format_bytes)
+    );
+    println!(
+        "  cpus:       {}",
+        limits
+            .cpus
+            .map_or("unlimited".to_string(), |cpus| format!("{cpus:.2}"))
+    );
+    println!("  pids:       {}", count(limits.pids));
+    println!("  processes:  {}", count(limits.processes));
+    println!("  open files: {}", count(limits.open_files));
+
+    let warnings = limits.warnings();
+    if warnings.is_empty() {
+        println!("No limits likely to break image builds or feature installs.");
+    }
+    for warning in warnings {
+        println!("warning: {warning}");
+    }
+
+    let provider = match ctx.provider().await {
+        Ok(provider) => {
+            println!("Container runtime: {}", provider.kind());
+            provider
+        }
+        Err(err) => {
+            println!("Container runtime: none ({err})");
+            return;
+        }
+    };
+    let preferred = ctx.registry_mirror.as_ref();
+    match artifact_mirror(&provider, preferred).await {
+        Some(mirror) if preferred.is_some() => {
+            println!("Registry mirror: {mirror} (--registry-mirror)")
+        }
+        Some(mirror) => println!("Registry mirror: {mirror} (docker daemon)"),
+        None => println!("Registry mirror: none"),
+    }
+
+    // The clock check needs the workspace's running devcontainer, when there is one.
+    let container = match ConfigResolver::new(ctx.config_source())
+        .with_overrides(ctx.config_overrides())
+        .resolve()
+    {
+        Ok(resolved) => discover_containers(&provider, &resolved.id_labels)
+            .await
+            .ok()
+            .and_then(|containers| containers.into_iter().next()),
+        Err(_) => None,
+    };
+    match container {
+        Some(container) => match measure_skew(&provider, &container).await {
+            Ok(skew) if skew.exceeds(DEFAULT_TOLERANCE) => {
+                println!("warning: container clock is {skew}; run `devcontainer up --fix-clock`")
+            }
+            Ok(skew) => println!("Container clock: {skew}"),
+            Err(err) => println!("Container clock: unknown ({err})"),
+        },
+        None => println!("Container clock: no devcontainer running for this workspace"),
+    }
+}
+
+fn parse_env_var(value: &str) -> std::result::Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE, got '{value}'")),
+    }
+}
+
+/// Parses durations such as `500ms`, `90s`, `5m` or `1h`; bare numbers are seconds.
+fn parse_duration(value: &str) -> std::result::Result<Duration, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("expected a duration such as 90s or 5m, got '{value}'"))?;
+    let seconds_per_unit = match unit {
+        "ms" => return Ok(Duration::from_millis(amount)),
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => {
+            return Err(format!(
+                "unknown duration unit '{unit}' in '{value}'; use ms, s, m or h"
+            ))
+        }
+    };
+    amount
+        .checked_mul(seconds_per_unit)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration '{value}' is too long"))
+}
+
 struct CommandContext {
     project_root: PathBuf,
     workspace_folder: Option<PathBuf>,
     config_path: Option<PathBuf>,
+    config_name: Option<String>,
+    provider: Option<ProviderKind>,
     docker_path: Option<PathBuf>,
+    docker_host: Option<String>,
+    container_workspace_folder: Option<PathBuf>,
+    lifecycle_shell: Option<Vec<String>>,
+    registry_auth: RegistryAuth,
+    registry_mirror: Option<RegistryMirror>,
+    profile: Option<String>,
+    event_log: Option<PathBuf>,
+    log_format: OutputFormat,
+    secrets: BTreeMap<String, String>,
+    policy: Option<PathBuf>,
 }
 
 impl CommandContext {
@@ -349,42 +1979,195 @@ impl CommandContext {
             project_root,
             workspace_folder: cli.workspace_folder.clone(),
             config_path: cli.config.clone(),
+            config_name: cli.config_name.clone(),
+            provider: cli.provider,
             docker_path: cli.docker_path.clone(),
+            docker_host: cli.docker_host.clone(),
+            container_workspace_folder: cli.container_workspace_folder.clone(),
+            lifecycle_shell: cli
+                .lifecycle_shell
+                .as_deref()
+                .map(|shell| shell.split_whitespace().map(str::to_string).collect()),
+            registry_auth: RegistryAuth {
+                credentials: cli
+                    .registry_auth
+                    .iter()
+                    .chain(cli.registry_auth_json.iter().flat_map(|json| &json.0))
+                    .cloned()
+                    .collect(),
+                credential_helpers: cli.registry_credential_helper.clone(),
+            },
+            registry_mirror: cli.registry_mirror.clone(),
+            profile: cli.profile.clone(),
+            event_log: cli.event_log.clone(),
+            log_format: cli.log_format.clone(),
+            secrets: cli
+                .secrets_file
+                .as_deref()
+                .map(load_secrets)
+                .transpose()?
+                .unwrap_or_default(),
+            policy: cli.policy.clone(),
         })
     }
 
     fn config_source(&self) -> ConfigSource {
         if let Some(config) = &self.config_path {
             ConfigSource::ExplicitFile(config.clone())
+        } else if let Some(name) = &self.config_name {
+            ConfigSource::Named {
+                workspace: self.workspace_root(),
+                name: name.clone(),
+            }
         } else {
-            let workspace = self
-                .workspace_folder
-                .clone()
-                .unwrap_or_else(|| self.project_root.clone());
-            ConfigSource::Workspace(workspace)
+            ConfigSource::Workspace(self.workspace_root())
         }
     }
 
+    fn workspace_root(&self) -> PathBuf {
+        self.workspace_folder
+            .clone()
+            .unwrap_or_else(|| self.project_root.clone())
+    }
+
     fn config_overrides(&self) -> ConfigOverrides {
-        let mut overrides = ConfigOverrides::default();
+        let mut overrides = ConfigOverrides::default()
+            .with_profile(self.profile.clone())
+            .with_secrets(self.secrets.clone());
         if let Some(workspace) = &self.workspace_folder {
             overrides = overrides.with_workspace_folder(workspace.clone());
         }
-        overrides
+        if let Some(folder) = &self.container_workspace_folder {
+            overrides = overrides.with_container_workspace_folder(folder.clone());
+        }
+        overrides.with_lifecycle_shell(self.lifecycle_shell.clone())
+    }
+
+    /// Fails when `resolved` violates the system policy or `--policy`. Only
+    /// `up`, `build`, `set-up` and commands that bring a missing container up
+    /// check, as they build, create or configure a container from it; a
+    /// container can still be inspected and removed after the policy is tightened.
+    fn enforce_policies(&self, resolved: &ResolvedConfig) -> Result<()> {
+        let policies: Vec<Policy> = Policy::system()?
+            .into_iter()
+            .chain(self.policy.as_deref().map(Policy::load).transpose()?)
+            .collect();
+        policy::enforce(&policies, resolved)
     }
 
-    fn provider(&self) -> DockerProvider {
-        match &self.docker_path {
-            Some(path) => DockerProvider::from_path(path.clone()),
-            None => DockerProvider::new(),
+    /// The `--provider` runtime, else the first registered one that answers. Every
+    /// runtime is driven through its docker-compatible CLI.
+    async fn provider(&self) -> Result<Box<dyn Provider>> {
+        let docker_path = self
+            .docker_path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("docker"));
+        let mut registry = ProviderRegistry::new();
+        for (kind, binary) in [
+            (ProviderKind::Docker, docker_path),
+            (ProviderKind::Podman, PathBuf::from("podman")),
+            (ProviderKind::Nerdctl, PathBuf::from("nerdctl")),
+        ] {
+            let docker_host = self.docker_host.clone();
+            let registry_auth = self.registry_auth.clone();
+            let registry_mirror = self.registry_mirror.clone();
+            registry = registry.register(kind, binary, move |path| {
+                Box::new(
+                    DockerProvider::from_path(path)
+                        .with_kind(kind)
+                        .with_docker_host(docker_host.clone())
+                        .with_registry_auth(registry_auth.clone())
+                        .with_registry_mirror(registry_mirror.clone()),
+                )
+            });
         }
+        registry.select(self.provider).await
+    }
+}
+
+/// Exit statuses by error category, so scripts can tell a bad configuration from a
+/// failed hook. A failed lifecycle hook, or a failed `devcontainer exec` command,
+/// exits with the command's own status instead.
+const GENERAL_EXIT_CODE: i32 = 1;
+const CONFIGURATION_EXIT_CODE: i32 = 2;
+const PROVIDER_EXIT_CODE: i32 = 3;
+const UNSUPPORTED_EXIT_CODE: i32 = 4;
+/// Exit status of `devcontainer exec` when `--timeout` expires, matching coreutils `timeout`.
+const TIMEOUT_EXIT_CODE: i32 = 124;
+/// Exit status after Ctrl-C, as shells report a process killed by SIGINT.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Token cancelled by the first Ctrl-C, so the operation can clean up after
+/// itself; a second Ctrl-C exits at once.
+fn interrupt_token() -> CancellationToken {
+    let token = CancellationToken::new();
+    let interrupt = token.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        tracing::warn!("Interrupted; cleaning up (press Ctrl-C again to exit immediately)");
+        interrupt.cancel();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+    });
+    token
+}
+
+/// Reads a `--secrets-file` object of string values and masks them in all log output.
+fn load_secrets(path: &std::path::Path) -> Result<BTreeMap<String, String>> {
+    let content = std::fs::read_to_string(path).map_err(|err| {
+        DevcontainerError::Configuration(format!(
+            "Failed to read secrets file {}: {err}",
+            path.display()
+        ))
+    })?;
+    let secrets: BTreeMap<String, String> = serde_json::from_str(&content).map_err(|err| {
+        DevcontainerError::Configuration(format!(
+            "Secrets file {} must be a JSON object of string values: {err}",
+            path.display()
+        ))
+    })?;
+    telemetry::redact(secrets.values().cloned());
+    Ok(secrets)
+}
+
+fn exit_code(err: &DevcontainerError) -> i32 {
+    match err {
+        DevcontainerError::Configuration(_) => CONFIGURATION_EXIT_CODE,
+        DevcontainerError::Provider(_)
+        | DevcontainerError::DaemonAccess(_)
+        | DevcontainerError::DaemonRestarted { .. } => PROVIDER_EXIT_CODE,
+        DevcontainerError::Unsupported(_) => UNSUPPORTED_EXIT_CODE,
+        DevcontainerError::Timeout(_) => TIMEOUT_EXIT_CODE,
+        DevcontainerError::Cancelled(_) => INTERRUPTED_EXIT_CODE,
+        DevcontainerError::HookFailed { exit_code, .. }
+        | DevcontainerError::CommandFailed { exit_code }
+            if (1..=255).contains(exit_code) =>
+        {
+            *exit_code
+        }
+        DevcontainerError::HookFailed { .. }
+        | DevcontainerError::CommandFailed { .. }
+        | DevcontainerError::Other(_) => GENERAL_EXIT_CODE,
     }
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
+async fn main() {
+    if let Err(err) = run(Cli::parse()).await {
+        // Debug level keeps the console to the single error report below.
+        tracing::debug!(error = %err, "Devcontainer command failed");
+        // Like `docker exec`, a failed command speaks for itself through its status.
+        if !matches!(err, DevcontainerError::CommandFailed { .. }) {
+            eprintln!("Error: {}", telemetry::redact_text(&err.to_string()));
+        }
+        std::process::exit(exit_code(&err));
+    }
+}
 
+async fn run(cli: Cli) -> Result<()> {
     let log_format = cli.log_format.to_core();
     let verbosity = match cli.verbose {
         0 => "info",
@@ -392,8 +2175,50 @@ async fn main() -> Result<()> {
         _ => "trace",
     };
 
-    telemetry::init(verbosity, log_format)?;
+    let mut options = TelemetryOptions::default();
+    // Reading an event log must not truncate it by starting a new one.
+    if let (Some(path), false) = (&cli.event_log, matches!(cli.command, Commands::Events(_))) {
+        options = options.with_event_log(path);
+    }
+    if let Some(path) = &cli.log_file {
+        options = options.with_log_file(path);
+    }
+    let run_log = match cli.command {
+        Commands::Events(_) | Commands::ShellIntegration(_) | Commands::Version => None,
+        _ => StateStore::default_location().and_then(|store| store.new_run_log(RUN_LOGS_KEPT).ok()),
+    };
+    if let Some(path) = &run_log {
+        options = options.with_log_file(path);
+    }
+    let otlp = cli.otel_endpoint.as_deref().map(OtlpExporter::new);
+    if let Some(otlp) = &otlp {
+        options = options.with_otlp(otlp.clone());
+    }
+    telemetry::init_with_options(verbosity, log_format, options)?;
+    tracing::debug!(
+        version = env!("CARGO_PKG_VERSION"),
+        args = ?std::env::args().skip(1).collect::<Vec<_>>(),
+        "Starting devcontainer command"
+    );
+
+    let cli_log_file = cli.log_file.clone();
+    let span = tracing::info_span!(
+        "devcontainer",
+        args = ?std::env::args().skip(1).collect::<Vec<_>>()
+    );
+    let result = dispatch(cli).instrument(span).await;
+    if let Some(otlp) = otlp {
+        if let Err(err) = otlp.flush().await {
+            tracing::warn!(error = %err, "Failed to export telemetry spans");
+        }
+    }
+    if let (Err(_), Some(path)) = (&result, cli_log_file.or(run_log)) {
+        tracing::info!(log = %path.display(), "Full trace log of this run");
+    }
+    result
+}
 
+async fn dispatch(cli: Cli) -> Result<()> {
     let ctx = CommandContext::new(&cli)?;
 
     match cli.command {
@@ -401,11 +2226,22 @@ async fn main() -> Result<()> {
         Commands::Down(args) => args.run(&ctx).await?,
         Commands::Build(args) => args.run(&ctx).await?,
         Commands::Exec(args) => args.run(&ctx).await?,
+        Commands::Shell(args) => args.run(&ctx).await?,
         Commands::RunUser(args) => args.run(&ctx).await?,
+        Commands::SetUp(args) => args.run(&ctx).await?,
+        Commands::Task(args) => args.run(&ctx).await?,
         Commands::ReadConfiguration(args) => args.run(&ctx).await?,
         Commands::Features(args) => args.run(&ctx).await?,
         Commands::Templates(args) => args.run(&ctx).await?,
         Commands::Inspect(args) => args.run(&ctx).await?,
+        Commands::List => list(&ctx).await?,
+        Commands::Upgrade => upgrade(&ctx).await?,
+        Commands::Status(args) => args.run(&ctx).await?,
+        Commands::WatchPorts(args) => args.run(&ctx).await?,
+        Commands::Events(args) => args.run(&ctx).await?,
+        Commands::Serve(args) => args.run(&ctx).await?,
+        Commands::ShellIntegration(args) => args.run(),
+        Commands::Doctor => doctor(&ctx).await,
         Commands::Version => {
             println!("{}", env!("CARGO_PKG_VERSION"));
         }
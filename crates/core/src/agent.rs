@@ -0,0 +1,317 @@
+//! Optional helper agent copied into containers for env probing, port watching and
+//! process supervision, replacing fragile shell pipelines run through `exec`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    provider::{ExecResult, Provider, RunningContainer},
+    DevcontainerError, Result,
+};
+
+/// Location the agent is copied to inside the container.
+pub const AGENT_CONTAINER_PATH: &str = "/tmp/devcontainer-agent";
+
+/// Marker the agent prints before the environment; must match `devcontainer-agent`.
+pub(crate) const ENV_MARKER: &str = "DEVCONTAINER_AGENT_ENV";
+
+/// How the environment is probed, mirroring the `userEnvProbe` values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EnvProbe {
+    None,
+    LoginShell,
+    InteractiveShell,
+    #[default]
+    LoginInteractiveShell,
+}
+
+impl fmt::Display for EnvProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EnvProbe::None => "none",
+            EnvProbe::LoginShell => "loginShell",
+            EnvProbe::InteractiveShell => "interactiveShell",
+            EnvProbe::LoginInteractiveShell => "loginInteractiveShell",
+        })
+    }
+}
+
+/// Host copy of the `devcontainer-agent` binary, installed into containers on demand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HelperAgent {
+    binary: PathBuf,
+}
+
+impl HelperAgent {
+    pub fn new(binary: impl Into<PathBuf>) -> Self {
+        Self {
+            binary: binary.into(),
+        }
+    }
+
+    pub fn binary(&self) -> &Path {
+        &self.binary
+    }
+
+    /// Copies the agent into the container and checks that it runs there.
+    ///
+    /// The copy keeps the host file mode, so the binary must already be executable.
+    pub async fn install<P: Provider + ?Sized>(
+        &self,
+        provider: &P,
+        container: &RunningContainer,
+    ) -> Result<InstalledAgent> {
+        if !self.binary.is_file() {
+            return Err(DevcontainerError::Configuration(format!(
+                "Helper agent binary {} does not exist",
+                self.binary.display()
+            )));
+        }
+
+        let agent = InstalledAgent {
+            path: PathBuf::from(AGENT_CONTAINER_PATH),
+        };
+        provider
+            .copy_into(container, &self.binary, &agent.path)
+            .await?;
+
+        let result = provider
+            .exec(container, &agent.command("version", &[]))
+            .await?;
+        if result.exit_code != 0 {
+            return Err(DevcontainerError::Provider(format!(
+                "Helper agent does not run in the container: {}",
+                result.stderr.trim()
+            )));
+        }
+
+        tracing::debug!(version = %result.stdout.trim(), "Installed helper agent");
+        Ok(agent)
+    }
+}
+
+/// Helper agent available inside a running container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstalledAgent {
+    path: PathBuf,
+}
+
+impl InstalledAgent {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Environment of `user` as seen by `shell` started according to `probe`.
+    pub async fn probe_env<P: Provider + ?Sized>(
+        &self,
+        provider: &P,
+        container: &RunningContainer,
+        user: Option<&str>,
+        shell: &str,
+        probe: EnvProbe,
+    ) -> Result<BTreeMap<String, String>> {
+        let args = [
+            "--shell".to_string(),
+            shell.to_string(),
+            "--probe".to_string(),
+            probe.to_string(),
+        ];
+        let result = self
+            .run(provider, container, user, &self.command("env", &args))
+            .await?;
+        Ok(parse_env(&result.stdout))
+    }
+
+    /// TCP ports currently listening inside the container.
+    pub async fn listening_ports<P: Provider + ?Sized>(
+        &self,
+        provider: &P,
+        container: &RunningContainer,
+    ) -> Result<BTreeSet<u16>> {
+        let result = self
+            .run(provider, container, None, &self.command("ports", &[]))
+            .await?;
+        Ok(result
+            .stdout
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .collect())
+    }
+
+    /// Waits until `port` is listening, failing with [`DevcontainerError::Timeout`].
+    pub async fn wait_for_port<P: Provider + ?Sized>(
+        &self,
+        provider: &P,
+        container: &RunningContainer,
+        port: u16,
+        timeout: Duration,
+    ) -> Result<()> {
+        let args = [
+            port.to_string(),
+            "--timeout".to_string(),
+            timeout.as_secs().max(1).to_string(),
+        ];
+        let result = provider
+            .exec(container, &self.command("wait-port", &args))
+            .await?;
+        match result.exit_code {
+            0 => Ok(()),
+            1 => Err(DevcontainerError::Timeout(format!(
+                "port {port} did not start listening within {timeout:?}"
+            ))),
+            code => Err(DevcontainerError::Provider(format!(
+                "Helper agent wait-port exited with {code}: {}",
+                result.stderr.trim()
+            ))),
+        }
+    }
+
+    /// Wraps `command` so the agent restarts it up to `restarts` times on failure.
+    pub fn supervised(&self, command: &[String], restarts: u32) -> Vec<String> {
+        let mut args = vec![
+            "--restarts".to_string(),
+            restarts.to_string(),
+            "--".to_string(),
+        ];
+        args.extend(command.iter().cloned());
+        self.command("supervise", &args)
+    }
+
+    fn command(&self, subcommand: &str, args: &[String]) -> Vec<String> {
+        let mut command = vec![self.path.display().to_string(), subcommand.to_string()];
+        command.extend(args.iter().cloned());
+        command
+    }
+
+    async fn run<P: Provider + ?Sized>(
+        &self,
+        provider: &P,
+        container: &RunningContainer,
+        user: Option<&str>,
+        command: &[String],
+    ) -> Result<ExecResult> {
+        let result = match user {
+            Some(user) => provider.exec_as(container, user, command).await?,
+            None => provider.exec(container, command).await?,
+        };
+        if result.exit_code != 0 {
+            return Err(DevcontainerError::Provider(format!(
+                "Helper agent {} exited with {}: {}",
+                command[1],
+                result.exit_code,
+                result.stderr.trim()
+            )));
+        }
+        Ok(result)
+    }
+}
+
+pub(crate) fn parse_env(output: &str) -> BTreeMap<String, String> {
+    output
+        .split('\0')
+        .skip_while(|entry| !entry.ends_with(ENV_MARKER))
+        .skip(1)
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::mock::{MockCall, MockProvider};
+    use tempfile::NamedTempFile;
+
+    fn agent_provider() -> MockProvider {
+        MockProvider::new().with_exec_handler(|command| {
+            let (stdout, exit_code) = match command[1].as_str() {
+                "version" => ("0.1.0\n".to_string(), 0),
+                "env" => (
+                    format!("motd\n{ENV_MARKER}\0PATH=/usr/bin\0EDITOR=vim\0"),
+                    0,
+                ),
+                "ports" => ("3000\n8080\n".to_string(), 0),
+                "wait-port" if command[2] == "8080" => (String::new(), 0),
+                _ => (String::new(), 1),
+            };
+            ExecResult {
+                exit_code,
+                stdout,
+                stderr: String::new(),
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn installs_agent_and_runs_probes() {
+        let binary = NamedTempFile::new().expect("agent binary");
+        let provider = agent_provider();
+        let container = RunningContainer::default();
+
+        let agent = HelperAgent::new(binary.path())
+            .install(&provider, &container)
+            .await
+            .expect("install agent");
+        assert_eq!(agent.path(), Path::new(AGENT_CONTAINER_PATH));
+        assert!(provider.calls().contains(&MockCall::CopyInto {
+            source: binary.path().to_path_buf(),
+            destination: PathBuf::from(AGENT_CONTAINER_PATH),
+        }));
+
+        let env = agent
+            .probe_env(
+                &provider,
+                &container,
+                Some("vscode"),
+                "/bin/bash",
+                EnvProbe::default(),
+            )
+            .await
+            .expect("probe env");
+        assert_eq!(env.len(), 2);
+        assert_eq!(env["EDITOR"], "vim");
+        assert!(provider
+            .exec_calls()
+            .iter()
+            .any(|command| command.contains(&"loginInteractiveShell".to_string())));
+
+        let ports = agent
+            .listening_ports(&provider, &container)
+            .await
+            .expect("ports");
+        assert_eq!(ports, BTreeSet::from([3000, 8080]));
+
+        agent
+            .wait_for_port(&provider, &container, 8080, Duration::from_secs(5))
+            .await
+            .expect("port listening");
+        let err = agent
+            .wait_for_port(&provider, &container, 9000, Duration::from_secs(5))
+            .await
+            .expect_err("port never listens");
+        assert!(matches!(err, DevcontainerError::Timeout(_)));
+
+        let supervised = agent.supervised(&["npm".to_string(), "start".to_string()], 3);
+        assert_eq!(
+            supervised,
+            vec![
+                AGENT_CONTAINER_PATH,
+                "supervise",
+                "--restarts",
+                "3",
+                "--",
+                "npm",
+                "start"
+            ]
+        );
+
+        let missing = HelperAgent::new("/nonexistent/devcontainer-agent")
+            .install(&provider, &container)
+            .await;
+        assert!(missing.is_err());
+    }
+}
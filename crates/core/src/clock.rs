@@ -0,0 +1,176 @@
+//! Detection of containers whose clock drifted from the host's, as happens to
+//! engines running in a VM, such as Docker Desktop, across host sleep and wake.
+
+use std::{fmt, time::Duration};
+
+use crate::{
+    provider::{Provider, RunningContainer},
+    state::unix_seconds,
+    DevcontainerError, Result,
+};
+
+/// Skew tolerated before TLS validation and timestamp-based caches are at risk.
+pub const DEFAULT_TOLERANCE: Duration = Duration::from_secs(10);
+
+/// Difference between the container's clock and the host's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSkew {
+    /// Seconds the container is ahead of the host; negative when behind.
+    pub seconds: i64,
+}
+
+impl ClockSkew {
+    pub fn exceeds(&self, tolerance: Duration) -> bool {
+        self.seconds.unsigned_abs() > tolerance.as_secs()
+    }
+}
+
+impl fmt::Display for ClockSkew {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.seconds {
+            0 => f.write_str("in sync with the host"),
+            seconds if seconds > 0 => write!(f, "{seconds}s ahead of the host"),
+            seconds => write!(f, "{}s behind the host", seconds.unsigned_abs()),
+        }
+    }
+}
+
+/// Compares the container's `date +%s` with the host clock at the midpoint of the exec.
+pub async fn measure_skew<P: Provider + ?Sized>(
+    provider: &P,
+    container: &RunningContainer,
+) -> Result<ClockSkew> {
+    let before = unix_seconds() as i64;
+    let result = provider
+        .exec(container, &["date".to_string(), "+%s".to_string()])
+        .await?;
+    let after = unix_seconds() as i64;
+    if result.exit_code != 0 {
+        return Err(DevcontainerError::Provider(format!(
+            "Failed to read the container clock: {}",
+            result.stderr.trim()
+        )));
+    }
+
+    let container_time: i64 = result.stdout.trim().parse().map_err(|_| {
+        DevcontainerError::Provider(format!(
+            "Unexpected container clock reading '{}'",
+            result.stdout.trim()
+        ))
+    })?;
+    Ok(ClockSkew {
+        seconds: container_time - (before + after) / 2,
+    })
+}
+
+/// Check run once a container has started, optionally resynchronizing the clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockCheck {
+    tolerance: Duration,
+    fix: bool,
+}
+
+impl Default for ClockCheck {
+    fn default() -> Self {
+        Self {
+            tolerance: DEFAULT_TOLERANCE,
+            fix: false,
+        }
+    }
+}
+
+impl ClockCheck {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Asks the provider to resynchronize the clock when the skew is too large.
+    pub fn with_fix(mut self, fix: bool) -> Self {
+        self.fix = fix;
+        self
+    }
+
+    /// Measures the skew and warns when it exceeds the tolerance. A clock that
+    /// cannot be read, e.g. in images without `date`, only logs and yields `None`.
+    pub async fn run<P: Provider + ?Sized>(
+        &self,
+        provider: &P,
+        container: &RunningContainer,
+    ) -> Option<ClockSkew> {
+        let skew = match measure_skew(provider, container).await {
+            Ok(skew) => skew,
+            Err(err) => {
+                tracing::debug!(error = %err, "Skipping container clock check");
+                return None;
+            }
+        };
+        if !skew.exceeds(self.tolerance) {
+            tracing::debug!(skew = skew.seconds, "Container clock is in sync");
+            return Some(skew);
+        }
+
+        if !self.fix {
+            tracing::warn!(
+                skew = skew.seconds,
+                "Container clock is {skew}, which breaks TLS and build caches; \
+                 rerun with --fix-clock to resynchronize it"
+            );
+            return Some(skew);
+        }
+
+        tracing::info!(
+            skew = skew.seconds,
+            "Container clock is {skew}; resynchronizing"
+        );
+        if let Err(err) = provider.sync_clock().await {
+            tracing::warn!(error = %err, "Failed to resynchronize the container clock");
+            return Some(skew);
+        }
+        match measure_skew(provider, container).await {
+            Ok(fixed) if fixed.exceeds(self.tolerance) => {
+                tracing::warn!(skew = fixed.seconds, "Container clock is still {fixed}");
+                Some(fixed)
+            }
+            Ok(fixed) => Some(fixed),
+            Err(_) => Some(skew),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{mock::MockProvider, ExecResult};
+
+    #[tokio::test]
+    async fn reports_skew_beyond_tolerance() {
+        let provider = MockProvider::new().with_exec_handler(|_| ExecResult {
+            exit_code: 0,
+            stdout: format!("{}\n", unix_seconds() - 120),
+            stderr: String::new(),
+        });
+        let container = RunningContainer::default();
+
+        let skew = ClockCheck::new()
+            .with_fix(true)
+            .run(&provider, &container)
+            .await
+            .expect("clock readable");
+        assert!((-121..=-119).contains(&skew.seconds), "{skew:?}");
+        assert!(skew.exceeds(DEFAULT_TOLERANCE));
+        assert!(skew.to_string().ends_with("s behind the host"));
+        assert!(!ClockSkew { seconds: 3 }.exceeds(DEFAULT_TOLERANCE));
+
+        let unreadable = MockProvider::new().with_exec_handler(|_| ExecResult {
+            exit_code: 127,
+            stdout: String::new(),
+            stderr: "date: not found".to_string(),
+        });
+        assert_eq!(ClockCheck::new().run(&unreadable, &container).await, None);
+    }
+}
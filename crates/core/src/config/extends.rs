@@ -0,0 +1,168 @@
+//! Configurations built on shared bases: `extends` names one or more files whose
+//! contents the configuration is laid over, such as an organisation-wide
+//! configuration kept next to the repositories using it.
+//!
+//! Layers merge with [`merge_layers`]: the extending configuration wins over its
+//! bases and a later base over an earlier one. Objects such as `features` merge
+//! key by key, `null` removes what a base set and any other value, arrays
+//! included, replaces it. A base may extend other files in turn.
+//!
+//! Paths in `extends` are relative to the file naming them. Every other relative
+//! path a base sets, such as its `build.dockerfile`, is resolved against the
+//! configuration being loaded, as if it had been written there.
+
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use super::{merge::merge_layers, read_document};
+use crate::{errors::DevcontainerError, Result};
+
+/// Top-level property naming the files a configuration extends.
+pub(crate) const EXTENDS_PROPERTY: &str = "extends";
+
+/// Removes `extends` from `document`, read from `config_path`, and lays the
+/// document over the bases it names.
+pub(crate) fn apply_extends(document: Value, config_path: &Path) -> Result<Value> {
+    let mut chain = vec![canonical(config_path)];
+    extend(document, config_path, &mut chain)
+}
+
+/// `document` merged over its bases; `chain` holds the files being extended,
+/// to reject cycles.
+fn extend(mut document: Value, path: &Path, chain: &mut Vec<PathBuf>) -> Result<Value> {
+    let bases = match document
+        .as_object_mut()
+        .and_then(|object| object.remove(EXTENDS_PROPERTY))
+    {
+        None => return Ok(document),
+        Some(Value::String(base)) => vec![base],
+        Some(Value::Array(bases)) => bases
+            .into_iter()
+            .map(|base| match base {
+                Value::String(base) => Ok(base),
+                other => Err(invalid_extends(path, &other)),
+            })
+            .collect::<Result<_>>()?,
+        Some(other) => return Err(invalid_extends(path, &other)),
+    };
+
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut layers = Vec::with_capacity(bases.len() + 1);
+    for base in bases {
+        let base_path = locate(dir, &base)?;
+        let key = canonical(&base_path);
+        if chain.contains(&key) {
+            return Err(DevcontainerError::Configuration(format!(
+                "{} extends {}, which extends it in turn",
+                path.display(),
+                base_path.display()
+            )));
+        }
+        chain.push(key);
+        let base_document = read_document(&base_path)?;
+        layers.push(extend(base_document, &base_path, chain)?);
+        chain.pop();
+    }
+    layers.push(document);
+    Ok(merge_layers(layers))
+}
+
+/// Path of the base `reference`, named in a file in `dir`.
+fn locate(dir: &Path, reference: &str) -> Result<PathBuf> {
+    let path_like = reference.starts_with('.')
+        || Path::new(reference).is_absolute()
+        || reference.ends_with(".json")
+        || reference.ends_with(".jsonc");
+    if !path_like {
+        return Err(DevcontainerError::Unsupported(format!(
+            "Cannot extend '{reference}': configurations from OCI registries are not supported; \
+             reference the base by path, such as ./base.json"
+        )));
+    }
+    Ok(dir.join(reference))
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn invalid_extends(path: &Path, value: &Value) -> DevcontainerError {
+    DevcontainerError::Configuration(format!(
+        "'extends' in {} must be a path or a list of paths, found {value}",
+        path.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn lays_configuration_over_its_bases_in_order() {
+        let root = tempdir().expect("tempdir");
+        let shared = root.path().join("shared");
+        let devcontainer = root.path().join("repo/.devcontainer");
+        fs::create_dir_all(&shared).expect("create shared");
+        fs::create_dir_all(&devcontainer).expect("create devcontainer");
+        fs::write(
+            shared.join("org.json"),
+            r#"{
+                // Organisation defaults.
+                "image": "org:1",
+                "remoteUser": "vscode",
+                "features": { "ghcr.io/devcontainers/features/git:1": {} },
+                "forwardPorts": [3000]
+            }"#,
+        )
+        .expect("write org");
+        fs::write(
+            shared.join("node.json"),
+            r#"{ "extends": "./org.json", "image": "node:1", "features": { "ghcr.io/devcontainers/features/node:1": { "version": "20" } } }"#,
+        )
+        .expect("write node");
+        let config_path = devcontainer.join("devcontainer.json");
+        let document = json!({
+            "extends": ["../../shared/node.json"],
+            "remoteUser": null,
+            "forwardPorts": [8080],
+            "features": { "ghcr.io/devcontainers/features/node:1": { "version": "22" } }
+        });
+
+        let merged = apply_extends(document, &config_path).expect("extend");
+        assert_eq!(
+            merged,
+            json!({
+                "image": "node:1",
+                "features": {
+                    "ghcr.io/devcontainers/features/git:1": {},
+                    "ghcr.io/devcontainers/features/node:1": { "version": "22" }
+                },
+                "forwardPorts": [8080]
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_cycles_and_registry_references() {
+        let root = tempdir().expect("tempdir");
+        fs::write(root.path().join("a.json"), r#"{ "extends": "./b.json" }"#).expect("write a");
+        fs::write(root.path().join("b.json"), r#"{ "extends": "./a.json" }"#).expect("write b");
+        let config_path = root.path().join("devcontainer.json");
+
+        let err = apply_extends(json!({ "extends": "./a.json" }), &config_path).expect_err("cycle");
+        assert!(err.to_string().contains("extends it in turn"));
+
+        let err = apply_extends(
+            json!({ "extends": "ghcr.io/acme/devcontainers/base:1" }),
+            &config_path,
+        )
+        .expect_err("registry reference");
+        assert!(matches!(err, DevcontainerError::Unsupported(_)));
+    }
+}
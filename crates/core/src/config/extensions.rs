@@ -0,0 +1,261 @@
+use std::{collections::BTreeSet, fmt, sync::Arc};
+
+use jsonschema::JSONSchema;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+
+use super::DEVCONTAINER_SCHEMA_JSON;
+use crate::{errors::DevcontainerError, Result};
+
+/// Top-level properties this crate understands beyond the devcontainer specification.
+const EXTENSION_PROPERTIES: &[&str] = &[
+    "sidecars",
+    "hookUsers",
+    "hookEnv",
+    "tasks",
+    "readOnlyWorkspace",
+    "writableWorkspacePaths",
+    "workspaceMountPropagation",
+    "workspaceMountConsistency",
+    "forwardSockets",
+    "lifecycleShell",
+];
+
+/// `customizations` section holding provider-specific settings, keyed by provider
+/// kind such as `docker`, which core passes on without interpreting.
+pub const PROVIDER_CUSTOMIZATION: &str = "devcontainer-rs";
+
+static PROVIDER_SETTINGS: Lazy<SchemaExtension> = Lazy::new(|| {
+    SchemaExtension::customization(
+        PROVIDER_CUSTOMIZATION,
+        &json!({ "type": "object", "additionalProperties": { "type": "object" } }),
+    )
+    .expect("provider settings schema compiles")
+});
+
+/// Definitions in the bundled schema that contribute top-level properties.
+const TOP_LEVEL_DEFINITIONS: &[&str] = &[
+    "devContainerCommon",
+    "nonComposeBase",
+    "dockerfileContainer",
+    "imageContainer",
+    "composeContainer",
+];
+
+static KNOWN_PROPERTIES: Lazy<BTreeSet<String>> = Lazy::new(|| {
+    let mut known: BTreeSet<String> = EXTENSION_PROPERTIES
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    for definition in TOP_LEVEL_DEFINITIONS {
+        if let Some(schema) =
+            DEVCONTAINER_SCHEMA_JSON.pointer(&format!("/definitions/{definition}"))
+        {
+            collect_properties(schema, &mut known);
+        }
+    }
+    known
+});
+
+fn collect_properties(schema: &Value, known: &mut BTreeSet<String>) {
+    if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+        known.extend(properties.keys().cloned());
+    }
+    for combinator in ["allOf", "anyOf", "oneOf"] {
+        if let Some(branches) = schema.get(combinator).and_then(Value::as_array) {
+            for branch in branches {
+                collect_properties(branch, known);
+            }
+        }
+    }
+}
+
+/// Schema fragment validating a custom property of `devcontainer.json`.
+///
+/// The fragment applies to the value at `pointer`, a JSON pointer such as
+/// `/customizations/mytool`, and is only checked when that value is present.
+#[derive(Clone)]
+pub struct SchemaExtension {
+    pointer: String,
+    schema: Arc<JSONSchema>,
+}
+
+impl SchemaExtension {
+    pub fn new(pointer: impl Into<String>, schema: &Value) -> Result<Self> {
+        let pointer = pointer.into();
+        if !pointer.starts_with('/') || pointer.len() < 2 {
+            return Err(DevcontainerError::Configuration(format!(
+                "Schema extension pointer '{pointer}' must be a non-empty JSON pointer"
+            )));
+        }
+
+        let schema = JSONSchema::compile(schema).map_err(|err| {
+            DevcontainerError::Configuration(format!(
+                "Schema extension for '{pointer}' does not compile: {err}"
+            ))
+        })?;
+
+        Ok(Self {
+            pointer,
+            schema: Arc::new(schema),
+        })
+    }
+
+    /// Extension for a tool's section under `customizations`.
+    pub fn customization(tool: &str, schema: &Value) -> Result<Self> {
+        Self::new(format!("/customizations/{tool}"), schema)
+    }
+
+    pub fn pointer(&self) -> &str {
+        &self.pointer
+    }
+
+    fn validate(&self, document: &Value) -> Vec<String> {
+        let Some(value) = document.pointer(&self.pointer) else {
+            return Vec::new();
+        };
+
+        match self.schema.validate(value) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors
+                .map(|err| format!("{}{}: {err}", self.pointer, err.instance_path))
+                .collect(),
+        }
+    }
+
+    fn segments(&self) -> impl Iterator<Item = &str> {
+        self.pointer[1..].split('/')
+    }
+}
+
+impl fmt::Debug for SchemaExtension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SchemaExtension")
+            .field("pointer", &self.pointer)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Validates registered extensions and, in strict mode, rejects properties that
+/// are neither part of the specification nor registered.
+pub(crate) fn validate_extensions(
+    document: &Value,
+    extensions: &[SchemaExtension],
+    strict: bool,
+) -> Result<()> {
+    let extensions: Vec<&SchemaExtension> = std::iter::once(&*PROVIDER_SETTINGS)
+        .chain(extensions)
+        .collect();
+    let mut violations: Vec<String> = extensions
+        .iter()
+        .flat_map(|extension| extension.validate(document))
+        .collect();
+
+    if strict {
+        violations.extend(unknown_properties(document, &extensions));
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(DevcontainerError::Configuration(format!(
+            "Invalid devcontainer.json: {}",
+            violations.join("; ")
+        )))
+    }
+}
+
+fn unknown_properties(document: &Value, extensions: &[&SchemaExtension]) -> Vec<String> {
+    let Some(object) = document.as_object() else {
+        return Vec::new();
+    };
+
+    let registered_top_level: BTreeSet<&str> = extensions
+        .iter()
+        .filter_map(|extension| extension.segments().next())
+        .collect();
+    let registered_tools: BTreeSet<&str> = extensions
+        .iter()
+        .filter_map(|extension| {
+            let mut segments = extension.segments();
+            (segments.next() == Some("customizations"))
+                .then(|| segments.next())
+                .flatten()
+        })
+        .collect();
+
+    let mut violations = Vec::new();
+    for key in object.keys() {
+        if !KNOWN_PROPERTIES.contains(key) && !registered_top_level.contains(key.as_str()) {
+            violations.push(format!("unknown property '{key}'"));
+        }
+    }
+
+    if let Some(customizations) = object.get("customizations").and_then(Value::as_object) {
+        for tool in customizations.keys() {
+            if !registered_tools.contains(tool.as_str()) {
+                violations.push(format!("unregistered customization '{tool}'"));
+            }
+        }
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mytool() -> SchemaExtension {
+        SchemaExtension::customization(
+            "mytool",
+            &json!({
+                "type": "object",
+                "properties": { "theme": { "enum": ["dark", "light"] } },
+                "additionalProperties": false
+            }),
+        )
+        .expect("compile extension")
+    }
+
+    #[test]
+    fn registered_extensions_validate_their_fragment() {
+        let valid = json!({ "image": "x", "customizations": { "mytool": { "theme": "dark" } } });
+        assert!(validate_extensions(&valid, &[mytool()], false).is_ok());
+
+        let invalid = json!({ "image": "x", "customizations": { "mytool": { "theme": "pink" } } });
+        let err = validate_extensions(&invalid, &[mytool()], false).expect_err("invalid theme");
+        assert!(err.to_string().contains("/customizations/mytool/theme"));
+    }
+
+    #[test]
+    fn strict_mode_rejects_unregistered_properties() {
+        let document = json!({
+            "image": "x",
+            "sidecars": {},
+            "customizations": {
+                "mytool": {},
+                "vscode": {},
+                "devcontainer-rs": { "docker": { "storageOpts": ["size=20G"] } }
+            },
+            "mystery": true
+        });
+
+        assert!(validate_extensions(&document, &[mytool()], false).is_ok());
+
+        let err = validate_extensions(&document, &[mytool()], true).expect_err("strict");
+        let message = err.to_string();
+        assert!(message.contains("unknown property 'mystery'"));
+        assert!(message.contains("unregistered customization 'vscode'"));
+        assert!(!message.contains("'sidecars'"));
+        assert!(!message.contains("'mytool'"));
+        assert!(!message.contains("'devcontainer-rs'"));
+
+        let malformed =
+            json!({ "image": "x", "customizations": { "devcontainer-rs": { "docker": [] } } });
+        let err = validate_extensions(&malformed, &[], false).expect_err("provider block");
+        assert!(err.to_string().contains("/customizations/devcontainer-rs"));
+
+        assert!(SchemaExtension::new("mytool", &json!({})).is_err());
+    }
+}
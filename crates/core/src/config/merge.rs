@@ -0,0 +1,595 @@
+//! Merges image metadata, feature contributions and the local configuration into
+//! one document, following the rules of the reference implementation, and layers
+//! configuration documents over one another as JSON merge patches.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::{CommandDefinition, Mount, PortAttributes, ResolvedConfig};
+use crate::{errors::DevcontainerError, Result};
+
+/// Image label holding the metadata of the features and configuration an image was built from.
+pub const IMAGE_METADATA_LABEL: &str = "devcontainer.metadata";
+
+/// Lifecycle command properties, collected into `<name>s` arrays in merge order.
+const LIFECYCLE_COMMANDS: &[&str] = &[
+    "onCreateCommand",
+    "updateContentCommand",
+    "postCreateCommand",
+    "postStartCommand",
+    "postAttachCommand",
+];
+
+/// Properties where the last entry defining them wins.
+const LAST_DEFINED: &[&str] = &[
+    "waitFor",
+    "remoteUser",
+    "containerUser",
+    "userEnvProbe",
+    "overrideCommand",
+    "shutdownAction",
+    "updateRemoteUserUID",
+    "otherPortsAttributes",
+];
+
+/// Object properties merged key by key, later entries winning.
+const MERGED_OBJECTS: &[&str] = &["remoteEnv", "containerEnv", "portsAttributes"];
+
+/// Parses the value of [`IMAGE_METADATA_LABEL`], which is either one entry or an array of entries.
+pub fn parse_image_metadata(label: &str) -> Result<Vec<Value>> {
+    let value: Value = serde_json::from_str(label).map_err(|err| {
+        DevcontainerError::Configuration(format!(
+            "Image label {IMAGE_METADATA_LABEL} is not valid JSON: {err}"
+        ))
+    })?;
+    match value {
+        Value::Array(entries) => Ok(entries),
+        entry @ Value::Object(_) => Ok(vec![entry]),
+        other => Err(DevcontainerError::Configuration(format!(
+            "Image label {IMAGE_METADATA_LABEL} must be an object or an array, found {other}"
+        ))),
+    }
+}
+
+/// Merges `metadata` entries, in order, followed by the `local` configuration.
+///
+/// The result is the local configuration with the merged properties laid over it.
+pub fn merge_configuration(local: &Value, metadata: &[Value]) -> Value {
+    let mut entries: Vec<&Map<String, Value>> =
+        metadata.iter().filter_map(Value::as_object).collect();
+    let mut merged = match local {
+        Value::Object(local) => {
+            entries.push(local);
+            local.clone()
+        }
+        _ => Map::new(),
+    };
+
+    for property in ["init", "privileged"] {
+        let enabled = entries
+            .iter()
+            .any(|entry| entry.get(property).and_then(Value::as_bool) == Some(true));
+        merged.insert(property.to_string(), Value::Bool(enabled));
+    }
+
+    for property in ["capAdd", "securityOpt", "forwardPorts"] {
+        let union = union(entries.iter().filter_map(|entry| entry.get(property)));
+        merged.insert(property.to_string(), Value::Array(union));
+    }
+
+    let entrypoints = entries
+        .iter()
+        .filter_map(|entry| entry.get("entrypoint"))
+        .cloned()
+        .collect();
+    merged.insert("entrypoints".to_string(), Value::Array(entrypoints));
+    merged.insert("mounts".to_string(), Value::Array(merge_mounts(&entries)));
+    merged.insert(
+        "customizations".to_string(),
+        Value::Object(collect_customizations(&entries)),
+    );
+
+    for command in LIFECYCLE_COMMANDS {
+        let commands = entries
+            .iter()
+            .filter_map(|entry| entry.get(*command))
+            .filter(|command| !is_empty_command(command))
+            .cloned()
+            .collect();
+        merged.insert(format!("{command}s"), Value::Array(commands));
+    }
+
+    for property in LAST_DEFINED {
+        if let Some(value) = entries
+            .iter()
+            .rev()
+            .find_map(|entry| entry.get(*property).filter(|value| !value.is_null()))
+        {
+            merged.insert(property.to_string(), value.clone());
+        }
+    }
+
+    for property in MERGED_OBJECTS {
+        let mut object = Map::new();
+        for entry in &entries {
+            if let Some(Value::Object(values)) = entry.get(*property) {
+                object.extend(values.clone());
+            }
+        }
+        if !object.is_empty() {
+            merged.insert(property.to_string(), Value::Object(object));
+        }
+    }
+
+    if let Some(requirements) = merge_host_requirements(&entries) {
+        merged.insert("hostRequirements".to_string(), Value::Object(requirements));
+    }
+
+    Value::Object(merged)
+}
+
+/// Properties of an image metadata entry that `up` applies.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct ImageMetadataEntry {
+    remote_user: Option<String>,
+    container_env: BTreeMap<String, String>,
+    remote_env: BTreeMap<String, Option<String>>,
+    mounts: Vec<Mount>,
+    init: bool,
+    privileged: bool,
+    cap_add: Vec<String>,
+    security_opt: Vec<String>,
+    override_command: Option<bool>,
+    on_create_command: Option<CommandDefinition>,
+    update_content_command: Option<CommandDefinition>,
+    post_create_command: Option<CommandDefinition>,
+    post_start_command: Option<CommandDefinition>,
+    post_attach_command: Option<CommandDefinition>,
+    ports_attributes: BTreeMap<String, PortAttributes>,
+    other_ports_attributes: Option<PortAttributes>,
+}
+
+impl ResolvedConfig {
+    /// Layers image metadata entries, in order, under this configuration.
+    ///
+    /// Environment variables and mounts accumulate, later entries and then the local
+    /// configuration winning per name or target. `init` and `privileged` apply when
+    /// any entry sets them, and capabilities and security options are combined.
+    /// `overrideCommand`, `remoteUser`, `otherPortsAttributes` and each lifecycle
+    /// command come from the local configuration when set, else the last entry
+    /// defining them; unlike the reference CLI, commands are not run once per entry.
+    /// `portsAttributes` merge per port, like environment variables.
+    pub fn with_image_metadata(mut self, metadata: &[Value]) -> Result<Self> {
+        let entries = metadata
+            .iter()
+            .map(|entry| {
+                ImageMetadataEntry::deserialize(entry).map_err(|err| {
+                    DevcontainerError::Configuration(format!(
+                        "Invalid entry in image label {IMAGE_METADATA_LABEL}: {err}"
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut container_env = BTreeMap::new();
+        let mut remote_env = BTreeMap::new();
+        let mut mounts: Vec<Mount> = Vec::new();
+        let mut ports_attributes = BTreeMap::new();
+        for entry in &entries {
+            container_env.extend(entry.container_env.clone());
+            remote_env.extend(entry.remote_env.clone());
+            mounts.extend(entry.mounts.iter().cloned());
+            ports_attributes.extend(entry.ports_attributes.clone());
+        }
+        container_env.append(&mut self.container_env);
+        remote_env.append(&mut self.remote_env);
+        mounts.append(&mut self.mounts);
+        ports_attributes.append(&mut self.ports_attributes);
+        self.ports_attributes = ports_attributes;
+
+        let mut unique_mounts: Vec<Mount> = Vec::new();
+        for mount in mounts {
+            if let Some(target) = mount.target() {
+                unique_mounts.retain(|existing| existing.target() != Some(target));
+            }
+            unique_mounts.push(mount);
+        }
+
+        self.container_env = container_env;
+        self.remote_env = remote_env;
+        self.mounts = unique_mounts;
+
+        self.init |= entries.iter().any(|entry| entry.init);
+        self.privileged |= entries.iter().any(|entry| entry.privileged);
+        let combine = |local: &mut Vec<String>, pick: fn(&ImageMetadataEntry) -> &Vec<String>| {
+            let mut combined: Vec<String> = Vec::new();
+            for value in entries.iter().flat_map(pick).chain(local.iter()) {
+                if !combined.contains(value) {
+                    combined.push(value.clone());
+                }
+            }
+            *local = combined;
+        };
+        combine(&mut self.cap_add, |entry| &entry.cap_add);
+        combine(&mut self.security_opt, |entry| &entry.security_opt);
+
+        let last = |pick: fn(&ImageMetadataEntry) -> Option<&CommandDefinition>| {
+            entries.iter().rev().find_map(pick).cloned()
+        };
+        self.override_command = self.override_command.or_else(|| {
+            entries
+                .iter()
+                .rev()
+                .find_map(|entry| entry.override_command)
+        });
+        self.other_ports_attributes = self.other_ports_attributes.or_else(|| {
+            entries
+                .iter()
+                .rev()
+                .find_map(|entry| entry.other_ports_attributes.clone())
+        });
+        self.remote_user = self.remote_user.or_else(|| {
+            entries
+                .iter()
+                .rev()
+                .find_map(|entry| entry.remote_user.clone())
+        });
+        self.on_create_command = self
+            .on_create_command
+            .or_else(|| last(|entry| entry.on_create_command.as_ref()));
+        self.update_content_command = self
+            .update_content_command
+            .or_else(|| last(|entry| entry.update_content_command.as_ref()));
+        self.post_create_command = self
+            .post_create_command
+            .or_else(|| last(|entry| entry.post_create_command.as_ref()));
+        self.post_start_command = self
+            .post_start_command
+            .or_else(|| last(|entry| entry.post_start_command.as_ref()));
+        self.post_attach_command = self
+            .post_attach_command
+            .or_else(|| last(|entry| entry.post_attach_command.as_ref()));
+        Ok(self)
+    }
+}
+
+impl ResolvedConfig {
+    /// Entry this configuration contributes to the `devcontainer.metadata` label of
+    /// an image built for it, in the camelCase shape other devcontainer tools read.
+    pub fn image_metadata_entry(&self) -> Value {
+        let mut entry = Map::new();
+        let mut insert = |key: &str, value: Value| {
+            let empty = match &value {
+                Value::Null | Value::Bool(false) => true,
+                Value::Object(object) => object.is_empty(),
+                Value::Array(items) => items.is_empty(),
+                _ => false,
+            };
+            if !empty {
+                entry.insert(key.to_string(), value);
+            }
+        };
+        insert("remoteUser", json(&self.remote_user));
+        insert("containerEnv", json(&self.container_env));
+        insert("remoteEnv", json(&self.remote_env));
+        insert("mounts", json(&self.mounts));
+        insert("init", Value::Bool(self.init));
+        insert("privileged", Value::Bool(self.privileged));
+        insert("capAdd", json(&self.cap_add));
+        insert("securityOpt", json(&self.security_opt));
+        insert("onCreateCommand", json(&self.on_create_command));
+        insert("updateContentCommand", json(&self.update_content_command));
+        insert("postCreateCommand", json(&self.post_create_command));
+        insert("postStartCommand", json(&self.post_start_command));
+        insert("postAttachCommand", json(&self.post_attach_command));
+        insert("waitFor", json(&self.wait_for));
+        insert("portsAttributes", json(&self.ports_attributes));
+        insert("otherPortsAttributes", json(&self.other_ports_attributes));
+        insert("customizations", Value::Object(self.customizations.clone()));
+        if let Some(override_command) = self.override_command {
+            entry.insert("overrideCommand".to_string(), Value::Bool(override_command));
+        }
+        Value::Object(entry)
+    }
+}
+
+/// Lays `patch` over `target` with JSON merge patch semantics: objects merge key
+/// by key, `null` removes a property and any other value, arrays included,
+/// replaces what `target` held.
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let target = target.as_object_mut().expect("target is an object");
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// Merges configuration `layers` with [`merge_patch`], each layer winning over
+/// the ones before it.
+pub fn merge_layers(layers: impl IntoIterator<Item = Value>) -> Value {
+    let mut merged = Value::Object(Map::new());
+    for layer in layers {
+        merge_patch(&mut merged, &layer);
+    }
+    merged
+}
+
+fn json(value: &impl Serialize) -> Value {
+    serde_json::to_value(value).expect("configuration values serialize to JSON")
+}
+
+/// Array items across `arrays`, without duplicates, in first-seen order.
+fn union<'a>(arrays: impl Iterator<Item = &'a Value>) -> Vec<Value> {
+    let mut items: Vec<Value> = Vec::new();
+    for item in arrays.filter_map(Value::as_array).flatten() {
+        if !items.contains(item) {
+            items.push(item.clone());
+        }
+    }
+    items
+}
+
+/// Mounts of all entries; a later mount replaces an earlier one with the same target.
+fn merge_mounts(entries: &[&Map<String, Value>]) -> Vec<Value> {
+    let mut mounts: Vec<Value> = Vec::new();
+    for mount in entries
+        .iter()
+        .filter_map(|entry| entry.get("mounts"))
+        .filter_map(Value::as_array)
+        .flatten()
+    {
+        let target = mount_target(mount);
+        mounts.retain(|existing| target.is_none() || mount_target(existing) != target);
+        mounts.push(mount.clone());
+    }
+    mounts
+}
+
+/// Target of a mount object or `type=...,target=...` mount string.
+fn mount_target(mount: &Value) -> Option<String> {
+    match mount {
+        Value::Object(mount) => mount
+            .get("target")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        Value::String(mount) => mount.split(',').find_map(|option| {
+            let (key, value) = option.split_once('=')?;
+            matches!(key.trim(), "target" | "dst" | "destination").then(|| value.trim().to_string())
+        }),
+        _ => None,
+    }
+}
+
+/// Customizations grouped by tool, each tool's values listed in merge order.
+fn collect_customizations(entries: &[&Map<String, Value>]) -> Map<String, Value> {
+    let mut customizations = Map::new();
+    for (tool, value) in entries
+        .iter()
+        .filter_map(|entry| entry.get("customizations"))
+        .filter_map(Value::as_object)
+        .flatten()
+    {
+        if let Value::Array(values) = customizations
+            .entry(tool.clone())
+            .or_insert_with(|| Value::Array(Vec::new()))
+        {
+            values.push(value.clone());
+        }
+    }
+    customizations
+}
+
+fn is_empty_command(command: &Value) -> bool {
+    match command {
+        Value::Null => true,
+        Value::String(command) => command.trim().is_empty(),
+        Value::Array(args) => args.is_empty(),
+        Value::Object(commands) => commands.is_empty(),
+        _ => false,
+    }
+}
+
+/// Largest requirement of each kind across all entries.
+fn merge_host_requirements(entries: &[&Map<String, Value>]) -> Option<Map<String, Value>> {
+    let requirements: Vec<&Map<String, Value>> = entries
+        .iter()
+        .filter_map(|entry| entry.get("hostRequirements"))
+        .filter_map(Value::as_object)
+        .collect();
+    if requirements.is_empty() {
+        return None;
+    }
+
+    let mut merged = Map::new();
+    if let Some(cpus) = requirements
+        .iter()
+        .filter_map(|requirement| requirement.get("cpus"))
+        .max_by_key(|cpus| cpus.as_u64().unwrap_or_default())
+    {
+        merged.insert("cpus".to_string(), cpus.clone());
+    }
+    for size in ["memory", "storage"] {
+        if let Some(value) = requirements
+            .iter()
+            .filter_map(|requirement| requirement.get(size))
+            .max_by_key(|value| value.as_str().and_then(parse_size).unwrap_or_default())
+        {
+            merged.insert(size.to_string(), value.clone());
+        }
+    }
+    if let Some(gpu) = requirements
+        .iter()
+        .rev()
+        .find_map(|requirement| requirement.get("gpu"))
+    {
+        merged.insert("gpu".to_string(), gpu.clone());
+    }
+    Some(merged)
+}
+
+/// Bytes in a host requirement size such as `4gb` or `512mb`.
+pub(crate) fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim().to_ascii_lowercase();
+    let digits = size
+        .find(|ch: char| !ch.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(digits);
+    let number: u64 = number.parse().ok()?;
+    let multiplier: u64 = match unit.trim() {
+        "" | "b" => 1,
+        "kb" => 1 << 10,
+        "mb" => 1 << 20,
+        "gb" => 1 << 30,
+        "tb" => 1 << 40,
+        _ => return None,
+    };
+    number.checked_mul(multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn merges_image_feature_and_local_entries() {
+        let image = parse_image_metadata(
+            r#"[{"remoteUser":"vscode","mounts":["type=volume,source=cache,target=/cache"],"postCreateCommand":"echo image"},{"id":"ghcr.io/devcontainers/features/node:1","capAdd":["SYS_PTRACE"],"init":true}]"#,
+        )
+        .expect("image metadata");
+        let feature = json!({
+            "id": "./local-feature",
+            "capAdd": ["SYS_PTRACE", "NET_ADMIN"],
+            "containerEnv": { "TOOL": "feature", "SHARED": "feature" },
+            "customizations": { "vscode": { "extensions": ["feature.ext"] } },
+            "hostRequirements": { "memory": "8gb" }
+        });
+        let local = json!({
+            "image": "example:image",
+            "containerEnv": { "SHARED": "local" },
+            "mounts": [{ "source": "data", "target": "/cache", "type": "volume" }],
+            "postCreateCommand": "",
+            "postStartCommand": ["echo", "local"],
+            "customizations": { "vscode": { "extensions": ["local.ext"] } },
+            "hostRequirements": { "memory": "512mb", "cpus": 2 }
+        });
+
+        let merged = merge_configuration(&local, &[image[0].clone(), image[1].clone(), feature]);
+
+        assert_eq!(merged["image"], json!("example:image"));
+        assert_eq!(merged["remoteUser"], json!("vscode"));
+        assert_eq!(merged["init"], json!(true));
+        assert_eq!(merged["privileged"], json!(false));
+        assert_eq!(merged["capAdd"], json!(["SYS_PTRACE", "NET_ADMIN"]));
+        assert_eq!(
+            merged["mounts"],
+            json!([{ "source": "data", "target": "/cache", "type": "volume" }])
+        );
+        assert_eq!(
+            merged["containerEnv"],
+            json!({ "TOOL": "feature", "SHARED": "local" })
+        );
+        assert_eq!(merged["postCreateCommands"], json!(["echo image"]));
+        assert_eq!(merged["postStartCommands"], json!([["echo", "local"]]));
+        assert_eq!(
+            merged["customizations"]["vscode"],
+            json!([{ "extensions": ["feature.ext"] }, { "extensions": ["local.ext"] }])
+        );
+        assert_eq!(
+            merged["hostRequirements"],
+            json!({ "cpus": 2, "memory": "8gb" })
+        );
+
+        assert!(parse_image_metadata("\"oops\"").is_err());
+    }
+
+    #[test]
+    fn image_metadata_layers_under_resolved_config() {
+        let config = ResolvedConfig {
+            container_env: BTreeMap::from([("SHARED".to_string(), "local".to_string())]),
+            mounts: vec![Mount::String(
+                "type=bind,source=/host/cache,target=/cache".to_string(),
+            )],
+            post_start_command: Some(CommandDefinition::from_string("echo local")),
+            ..Default::default()
+        };
+        let metadata = parse_image_metadata(
+            r#"[
+                {"remoteUser":"node","containerEnv":{"SHARED":"base","BASE":"1"},"postCreateCommand":"echo base"},
+                {"remoteUser":"vscode","mounts":[{"type":"volume","source":"cache","target":"/cache"},{"type":"volume","source":"home","target":"/home/vscode"}],"postStartCommand":"echo image"}
+            ]"#,
+        )
+        .expect("metadata");
+
+        let layered = config.with_image_metadata(&metadata).expect("layer");
+        assert_eq!(layered.remote_user.as_deref(), Some("vscode"));
+        assert_eq!(
+            layered.container_env,
+            BTreeMap::from([
+                ("BASE".to_string(), "1".to_string()),
+                ("SHARED".to_string(), "local".to_string()),
+            ])
+        );
+        assert_eq!(
+            layered
+                .mounts
+                .iter()
+                .map(Mount::to_mount_arg)
+                .collect::<Vec<_>>(),
+            vec![
+                "type=volume,source=home,target=/home/vscode",
+                "type=bind,source=/host/cache,target=/cache",
+            ]
+        );
+        assert_eq!(
+            layered.post_create_command,
+            Some(CommandDefinition::from_string("echo base"))
+        );
+        assert_eq!(
+            layered.post_start_command,
+            Some(CommandDefinition::from_string("echo local"))
+        );
+    }
+
+    #[test]
+    fn image_metadata_combines_container_security_properties() {
+        let config = ResolvedConfig {
+            cap_add: vec!["NET_ADMIN".to_string()],
+            security_opt: vec!["seccomp=unconfined".to_string()],
+            ..Default::default()
+        };
+        let metadata = parse_image_metadata(
+            r#"[{"init":true,"capAdd":["SYS_PTRACE","NET_ADMIN"],"securityOpt":["seccomp=unconfined"],"overrideCommand":false}]"#,
+        )
+        .expect("metadata");
+
+        let layered = config.with_image_metadata(&metadata).expect("layer");
+        assert!(layered.init);
+        assert!(!layered.privileged);
+        assert_eq!(layered.cap_add, vec!["SYS_PTRACE", "NET_ADMIN"]);
+        assert_eq!(layered.security_opt, vec!["seccomp=unconfined"]);
+        assert_eq!(
+            layered.image_metadata_entry(),
+            json!({
+                "init": true,
+                "capAdd": ["SYS_PTRACE", "NET_ADMIN"],
+                "securityOpt": ["seccomp=unconfined"],
+                "overrideCommand": false
+            })
+        );
+    }
+}
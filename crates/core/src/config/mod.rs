@@ -2,6 +2,7 @@ use std::{
     collections::BTreeMap,
     convert::TryFrom,
     fs,
+    ops::RangeInclusive,
     path::{Path, PathBuf},
 };
 
@@ -9,15 +10,33 @@ use jsonschema::{error::ValidationErrorKind, JSONSchema};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    env::{EnvLayer, EnvStack},
+    errors::DevcontainerError,
+    features::{check_feature_options, feature_matches, install_order},
+    provider::ProviderKind,
+    Result,
+};
 
-use crate::{errors::DevcontainerError, Result};
+mod extends;
+mod extensions;
+pub mod merge;
+mod profiles;
 
-static DEVCONTAINER_SCHEMA: Lazy<JSONSchema> = Lazy::new(|| {
-    let schema_json: Value = serde_json::from_str(include_str!(
+pub use extensions::{SchemaExtension, PROVIDER_CUSTOMIZATION};
+
+static DEVCONTAINER_SCHEMA_JSON: Lazy<Value> = Lazy::new(|| {
+    serde_json::from_str(include_str!(
         "../../../../spec/schemas/devContainer.base.schema.json"
     ))
-    .expect("Bundled devcontainer schema must be valid JSON");
-    JSONSchema::compile(&schema_json).expect("Bundled devcontainer schema must compile")
+    .expect("Bundled devcontainer schema must be valid JSON")
+});
+
+static DEVCONTAINER_SCHEMA: Lazy<JSONSchema> = Lazy::new(|| {
+    JSONSchema::compile(&DEVCONTAINER_SCHEMA_JSON)
+        .expect("Bundled devcontainer schema must compile")
 });
 
 fn validate_against_schema(document: &Value) -> Result<()> {
@@ -26,7 +45,7 @@ fn validate_against_schema(document: &Value) -> Result<()> {
 
         let only_root_one_of_conflict = !collected.is_empty()
             && collected.iter().all(|err| {
-                matches!(err.kind, ValidationErrorKind::OneOfMultipleValid { .. })
+                matches!(err.kind, ValidationErrorKind::OneOfMultipleValid)
                     && err.schema_path.to_string() == "/oneOf"
             });
 
@@ -75,6 +94,30 @@ fn resolve_local_workspace_placeholders(input: &str, workspace_root: &Path) -> S
     result
 }
 
+/// Substitutes `${localEnv:NAME}` and `${localEnv:NAME:default}` with host environment values.
+fn resolve_local_env_placeholders(input: &str) -> String {
+    let mut result = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find("${localEnv:") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        let reference = &rest[start + "${localEnv:".len()..start + end];
+        let (name, default) = match reference.split_once(':') {
+            Some((name, default)) => (name, default),
+            None => (reference, ""),
+        };
+        match std::env::var(name) {
+            Ok(value) => result.push_str(&value),
+            Err(_) => result.push_str(default),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
 /// Raw devcontainer configuration as read from `devcontainer.json`.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct DevcontainerConfig {
@@ -84,16 +127,145 @@ pub struct DevcontainerConfig {
     pub image: Option<String>,
     #[serde(default, rename = "dockerFile")]
     pub docker_file: Option<String>,
+    /// Build context for the legacy `dockerFile` form.
+    #[serde(default)]
+    pub context: Option<String>,
+    #[serde(default)]
+    pub build: Option<BuildDefinition>,
     #[serde(default, rename = "workspaceFolder")]
     pub workspace_folder: Option<String>,
+    /// `--mount` value replacing the default workspace bind mount.
+    #[serde(default, rename = "workspaceMount")]
+    pub workspace_mount: Option<String>,
     #[serde(default)]
     pub features: Map<String, Value>,
+    /// Features, by id without version, to install first and in this order.
+    #[serde(default, rename = "overrideFeatureInstallOrder")]
+    pub override_feature_install_order: Vec<String>,
     #[serde(default, rename = "forwardPorts")]
     pub forward_ports: Vec<ForwardPortDefinition>,
+    /// Legacy ports published when the container is created.
+    #[serde(default, rename = "appPort")]
+    pub app_port: Option<AppPortDefinition>,
+    #[serde(default, rename = "portsAttributes")]
+    pub ports_attributes: BTreeMap<String, PortAttributes>,
+    #[serde(default, rename = "otherPortsAttributes")]
+    pub other_ports_attributes: Option<PortAttributes>,
+    #[serde(default, rename = "initializeCommand")]
+    pub initialize_command: Option<CommandDefinition>,
+    #[serde(default, rename = "onCreateCommand")]
+    pub on_create_command: Option<CommandDefinition>,
+    #[serde(default, rename = "updateContentCommand")]
+    pub update_content_command: Option<CommandDefinition>,
     #[serde(default, rename = "postCreateCommand")]
     pub post_create_command: Option<CommandDefinition>,
+    #[serde(default, rename = "postStartCommand")]
+    pub post_start_command: Option<CommandDefinition>,
     #[serde(default, rename = "postAttachCommand")]
     pub post_attach_command: Option<CommandDefinition>,
+    #[serde(default, rename = "waitFor")]
+    pub wait_for: Option<String>,
+    #[serde(default, rename = "containerEnv")]
+    pub container_env: BTreeMap<String, String>,
+    #[serde(default, rename = "remoteEnv")]
+    pub remote_env: BTreeMap<String, Option<String>>,
+    #[serde(default, rename = "remoteUser")]
+    pub remote_user: Option<String>,
+    #[serde(default)]
+    pub customizations: Map<String, Value>,
+    #[serde(default)]
+    pub mounts: Vec<Mount>,
+    #[serde(default, rename = "runArgs")]
+    pub run_args: Vec<String>,
+    #[serde(default)]
+    pub init: bool,
+    #[serde(default)]
+    pub privileged: bool,
+    #[serde(default, rename = "capAdd")]
+    pub cap_add: Vec<String>,
+    #[serde(default, rename = "securityOpt")]
+    pub security_opt: Vec<String>,
+    #[serde(default, rename = "overrideCommand")]
+    pub override_command: Option<bool>,
+    /// Extension: user to run individual lifecycle hooks as, keyed by hook name.
+    #[serde(default, rename = "hookUsers")]
+    pub hook_users: BTreeMap<String, String>,
+    /// Extension: environment variables for individual lifecycle hooks, keyed by
+    /// hook name and applied over `remoteEnv`; `null` unsets a variable.
+    #[serde(default, rename = "hookEnv")]
+    pub hook_env: BTreeMap<String, BTreeMap<String, Option<String>>>,
+    /// Extension: named commands run with `devcontainer task <name>`.
+    #[serde(default)]
+    pub tasks: BTreeMap<String, CommandDefinition>,
+    /// Extension: extra containers started on the project network, keyed by name.
+    #[serde(default)]
+    pub sidecars: BTreeMap<String, SidecarDefinition>,
+    /// Extension: bind the workspace read-only.
+    #[serde(default, rename = "readOnlyWorkspace")]
+    pub read_only_workspace: bool,
+    /// Extension: workspace-relative paths backed by volumes so a read-only
+    /// workspace can still hold build outputs.
+    #[serde(default, rename = "writableWorkspacePaths")]
+    pub writable_workspace_paths: Vec<String>,
+    /// Extension: bind propagation of the workspace mount.
+    #[serde(default, rename = "workspaceMountPropagation")]
+    pub workspace_mount_propagation: Option<MountPropagation>,
+    /// Extension: consistency of the workspace bind mount on Docker Desktop for Mac.
+    #[serde(default, rename = "workspaceMountConsistency")]
+    pub workspace_mount_consistency: Option<MountConsistency>,
+    /// Extension: host unix sockets bind-mounted into the container.
+    #[serde(default, rename = "forwardSockets")]
+    pub forward_sockets: Vec<SocketForward>,
+    /// Extension: program and flags that run string lifecycle commands.
+    #[serde(default, rename = "lifecycleShell")]
+    pub lifecycle_shell: Option<Vec<String>>,
+    #[serde(default, rename = "hostRequirements")]
+    pub host_requirements: Option<HostRequirements>,
+}
+
+/// `build` object of a Dockerfile-based configuration.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct BuildDefinition {
+    #[serde(default)]
+    pub dockerfile: Option<String>,
+    #[serde(default)]
+    pub context: Option<String>,
+    #[serde(default)]
+    pub args: BTreeMap<String, String>,
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Images to use as a build cache; a single string in `devcontainer.json` is
+    /// accepted as one image.
+    #[serde(default, rename = "cacheFrom", deserialize_with = "string_or_list")]
+    pub cache_from: Vec<String>,
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+fn string_or_list<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrList {
+        String(String),
+        List(Vec<String>),
+    }
+
+    Ok(match StringOrList::deserialize(deserializer)? {
+        StringOrList::String(value) => vec![value],
+        StringOrList::List(values) => values,
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct SidecarDefinition {
+    pub image: String,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub ports: Vec<ForwardPortDefinition>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -103,6 +275,37 @@ pub enum ForwardPortDefinition {
     String(String),
 }
 
+/// `appPort` as a single port or a list of them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AppPortDefinition {
+    Single(ForwardPortDefinition),
+    Multiple(Vec<ForwardPortDefinition>),
+}
+
+impl AppPortDefinition {
+    /// `--publish` values: a number is published on the loopback interface only,
+    /// a string is taken as written, e.g. `8080:80` or `127.0.0.1:5000:5000/udp`.
+    fn publish_specs(self) -> Result<Vec<String>> {
+        let ports = match self {
+            AppPortDefinition::Single(port) => vec![port],
+            AppPortDefinition::Multiple(ports) => ports,
+        };
+        ports
+            .into_iter()
+            .map(|port| match port {
+                ForwardPortDefinition::Number(port) => Ok(format!("127.0.0.1:{port}:{port}")),
+                ForwardPortDefinition::String(value) if !value.trim().is_empty() => {
+                    Ok(value.trim().to_string())
+                }
+                ForwardPortDefinition::String(_) => Err(DevcontainerError::Configuration(
+                    "Invalid appPort value '': value must not be empty".to_string(),
+                )),
+            })
+            .collect()
+    }
+}
+
 impl TryFrom<ForwardPortDefinition> for ForwardPort {
     type Error = DevcontainerError;
 
@@ -112,6 +315,7 @@ impl TryFrom<ForwardPortDefinition> for ForwardPort {
                 local_port: port,
                 container_port: port,
                 protocol: PortProtocol::Tcp,
+                ..Default::default()
             }),
             ForwardPortDefinition::String(value) => {
                 let trimmed = value.trim();
@@ -142,6 +346,7 @@ impl TryFrom<ForwardPortDefinition> for ForwardPort {
                     local_port,
                     container_port,
                     protocol: PortProtocol::Tcp,
+                    ..Default::default()
                 })
             }
         }
@@ -155,11 +360,22 @@ pub enum CommandArgs {
     Array(Vec<String>),
 }
 
+/// Program and flag that run string commands unless `lifecycleShell` says otherwise.
+pub const DEFAULT_LIFECYCLE_SHELL: [&str; 2] = ["/bin/sh", "-c"];
+
 impl CommandArgs {
     pub fn to_exec_args(&self) -> Vec<String> {
+        let shell = DEFAULT_LIFECYCLE_SHELL.map(str::to_string);
+        self.to_exec_args_with(&shell)
+    }
+
+    /// Arguments running the command, a string one through `shell`.
+    pub fn to_exec_args_with(&self, shell: &[String]) -> Vec<String> {
         match self {
             CommandArgs::String(command) => {
-                vec!["/bin/sh".to_string(), "-c".to_string(), command.clone()]
+                let mut args = shell.to_vec();
+                args.push(command.clone());
+                args
             }
             CommandArgs::Array(args) => args.clone(),
         }
@@ -195,196 +411,1463 @@ pub struct ResolvedConfig {
     pub image_reference: Option<String>,
     #[serde(default)]
     pub dockerfile: Option<PathBuf>,
+    /// Options for building `dockerfile`.
+    #[serde(default)]
+    pub build: BuildOptions,
     #[serde(default)]
     pub features: Map<String, Value>,
+    /// `overrideFeatureInstallOrder`, see [`install_order`](crate::features::install_order).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub override_feature_install_order: Vec<String>,
     #[serde(default)]
     pub forward_ports: Vec<ForwardPort>,
+    /// `--publish` values from the legacy `appPort`.
+    #[serde(default)]
+    pub app_ports: Vec<String>,
+    /// Attributes keyed by port, port range such as `40000-55000`, or a pattern
+    /// matched against the command line of the listening process.
+    #[serde(default)]
+    pub ports_attributes: BTreeMap<String, PortAttributes>,
+    /// Attributes of ports no `portsAttributes` key matches.
+    #[serde(default)]
+    pub other_ports_attributes: Option<PortAttributes>,
+    /// Runs on the host before the container is created.
+    #[serde(default)]
+    pub initialize_command: Option<CommandDefinition>,
+    #[serde(default)]
+    pub on_create_command: Option<CommandDefinition>,
+    #[serde(default)]
+    pub update_content_command: Option<CommandDefinition>,
     #[serde(default)]
     pub post_create_command: Option<CommandDefinition>,
     #[serde(default)]
+    pub post_start_command: Option<CommandDefinition>,
+    #[serde(default)]
     pub post_attach_command: Option<CommandDefinition>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct ForwardPort {
-    pub local_port: u16,
-    pub container_port: u16,
+    /// Hook command property, such as `postCreateCommand`, after which `up` returns
+    /// while later hooks continue in the background.
     #[serde(default)]
-    pub protocol: PortProtocol,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
-#[serde(rename_all = "lowercase")]
-pub enum PortProtocol {
-    #[default]
-    Tcp,
-    Udp,
-}
-
-/// Resolves a `DevcontainerConfig` into a `ResolvedConfig`.
-pub struct ConfigResolver {
-    source: ConfigSource,
-    overrides: ConfigOverrides,
+    pub wait_for: Option<String>,
+    #[serde(default)]
+    pub container_env: BTreeMap<String, String>,
+    /// `remoteEnv` entries; `None` unsets the variable for remote processes.
+    #[serde(default)]
+    pub remote_env: BTreeMap<String, Option<String>>,
+    /// `--env` values from the command line; not part of the container identity.
+    #[serde(skip)]
+    pub cli_env: BTreeMap<String, String>,
+    /// `--secrets-file` values, given to remote processes only; never serialized
+    /// nor passed to container creation, so they stay out of images and labels.
+    #[serde(skip)]
+    pub secrets: BTreeMap<String, String>,
+    /// When the image is pulled, from the command line.
+    #[serde(skip)]
+    pub pull_policy: PullPolicy,
+    #[serde(default)]
+    pub remote_user: Option<String>,
+    /// Tool-specific settings, passed through untouched.
+    #[serde(default)]
+    pub customizations: Map<String, Value>,
+    /// Additional mounts, with local workspace and `localEnv` placeholders substituted.
+    #[serde(default)]
+    pub mounts: Vec<Mount>,
+    /// Extra `docker create` arguments, validated and with placeholders substituted.
+    #[serde(default)]
+    pub run_args: Vec<String>,
+    /// Runs an init process as PID 1 to reap zombies and forward signals.
+    #[serde(default)]
+    pub init: bool,
+    #[serde(default)]
+    pub privileged: bool,
+    /// Linux capabilities added to the container, such as `SYS_PTRACE` for debuggers.
+    #[serde(default)]
+    pub cap_add: Vec<String>,
+    #[serde(default)]
+    pub security_opt: Vec<String>,
+    /// Whether the image's command is replaced with one that keeps the container
+    /// running; see [`ResolvedConfig::overrides_command`].
+    #[serde(default)]
+    pub override_command: Option<bool>,
+    /// Per-hook users, taking precedence over `remote_user` for that hook.
+    #[serde(default)]
+    pub hook_users: BTreeMap<String, String>,
+    /// Per-hook environment variables layered over `remote_env` for that hook.
+    #[serde(default)]
+    pub hook_env: BTreeMap<String, BTreeMap<String, Option<String>>>,
+    /// Named commands runnable in the container, keyed by task name.
+    #[serde(default)]
+    pub tasks: BTreeMap<String, CommandDefinition>,
+    #[serde(default)]
+    pub sidecars: Vec<Sidecar>,
+    /// Whether the workspace is mounted read-only.
+    #[serde(default)]
+    pub read_only_workspace: bool,
+    /// Workspace-relative directories that stay writable, each backed by a volume,
+    /// when the workspace is read-only.
+    #[serde(default)]
+    pub writable_workspace_paths: Vec<PathBuf>,
+    /// Bind propagation of the workspace mount, for mounts made inside the
+    /// container, such as FUSE or nested containers, that the host must see.
+    #[serde(default)]
+    pub workspace_mount_propagation: Option<MountPropagation>,
+    /// Consistency of the workspace bind mount, trading how soon the host and
+    /// container see each other's writes for file system speed on macOS.
+    #[serde(default)]
+    pub workspace_mount_consistency: Option<MountConsistency>,
+    /// Mount used for the workspace instead of binding `workspace_folder`, with
+    /// placeholders substituted.
+    #[serde(default)]
+    pub workspace_mount: Option<Mount>,
+    /// Program and flags that run string lifecycle commands in place of
+    /// `/bin/sh -c`, e.g. `bash -lc`, or `pwsh -Command` in Windows containers.
+    #[serde(default)]
+    pub lifecycle_shell: Option<Vec<String>>,
+    /// Named volume `workspace_mount` mounts, from `--workspace-volume` or `--clone`.
+    #[serde(default)]
+    pub workspace_volume: Option<String>,
+    /// Repository cloned into `workspace_volume` while it is empty.
+    #[serde(default)]
+    pub clone_url: Option<String>,
+    /// Host unix sockets mounted into the container, with placeholders substituted.
+    #[serde(default)]
+    pub forward_sockets: Vec<SocketForward>,
+    /// Resources the container host must offer, checked before building.
+    #[serde(default)]
+    pub host_requirements: Option<HostRequirements>,
+    /// Labels identifying the devcontainer, applied at create time and used for discovery.
+    #[serde(default)]
+    pub id_labels: Vec<ContainerLabel>,
+    /// Entry of the `profiles` extension merged over the base configuration.
+    #[serde(default)]
+    pub profile: Option<String>,
 }
 
-impl ConfigResolver {
-    pub fn new(source: ConfigSource) -> Self {
-        Self {
-            source,
-            overrides: ConfigOverrides::default(),
+impl ResolvedConfig {
+    /// Environment layers defined by the configuration and command line.
+    pub fn env_stack(&self) -> EnvStack {
+        let mut stack = EnvStack::new()
+            .with_layer(EnvLayer::ContainerEnv, self.container_env.clone())
+            .with_layer(EnvLayer::Secrets, self.secrets.clone())
+            .with_layer(EnvLayer::Cli, self.cli_env.clone());
+        for (name, value) in &self.remote_env {
+            match value {
+                Some(value) => stack.set(EnvLayer::RemoteEnv, name, value),
+                None => stack.unset(EnvLayer::RemoteEnv, name),
+            }
         }
+        stack
     }
 
-    pub fn with_overrides(mut self, overrides: ConfigOverrides) -> Self {
-        self.overrides = overrides;
-        self
+    /// Environment layers for running `hook`, with its `hookEnv` entries applied.
+    pub fn hook_env_stack(&self, hook: &str) -> EnvStack {
+        let mut stack = self.env_stack();
+        for (name, value) in self.hook_env.get(hook).into_iter().flatten() {
+            match value {
+                Some(value) => stack.set(EnvLayer::HookEnv, name, value),
+                None => stack.unset(EnvLayer::HookEnv, name),
+            }
+        }
+        stack
     }
 
-    pub fn resolve(&self) -> Result<ResolvedConfig> {
-        tracing::debug!(?self.source, "Resolving devcontainer configuration");
-
-        let config_path = self.source.resolve_path()?;
-        let raw_document = fs::read_to_string(&config_path).map_err(|err| {
-            DevcontainerError::Configuration(format!(
-                "Failed to read {}: {err}",
-                config_path.display()
-            ))
-        })?;
+    /// Settings for `provider` under `customizations.devcontainer-rs`, which only
+    /// that provider interprets.
+    pub fn provider_settings(&self, provider: ProviderKind) -> Option<&Map<String, Value>> {
+        self.customizations
+            .get(PROVIDER_CUSTOMIZATION)?
+            .get(provider.to_string())?
+            .as_object()
+    }
 
-        // Allow comments/trailing commas by parsing with JSON5-compatible parser.
-        let document: Value = json5::from_str(&raw_document).map_err(|err| {
-            DevcontainerError::Configuration(format!(
-                "{} is not valid JSON: {err}",
-                config_path.display()
-            ))
-        })?;
+    /// Whether to run a keep-alive command instead of the image's ENTRYPOINT/CMD,
+    /// which is the default when `overrideCommand` is unset.
+    pub fn overrides_command(&self) -> bool {
+        self.override_command.unwrap_or(true)
+    }
 
-        validate_against_schema(&document)?;
+    /// Program and flags that run string lifecycle commands.
+    pub fn lifecycle_shell(&self) -> Vec<String> {
+        match &self.lifecycle_shell {
+            Some(shell) => shell.clone(),
+            None => DEFAULT_LIFECYCLE_SHELL.map(str::to_string).to_vec(),
+        }
+    }
 
-        let config: DevcontainerConfig = serde_json::from_value(document).map_err(|err| {
-            DevcontainerError::Configuration(format!(
-                "{} does not match expected structure: {err}",
-                config_path.display()
-            ))
-        })?;
+    /// Arguments running `command` in the container, through the lifecycle shell
+    /// when it is a string.
+    pub fn command_args(&self, command: &CommandArgs) -> Vec<String> {
+        command.to_exec_args_with(&self.lifecycle_shell())
+    }
 
-        let DevcontainerConfig {
-            name,
-            image,
-            docker_file,
-            workspace_folder: config_workspace_folder,
-            features,
-            forward_ports: raw_forward_ports,
-            post_create_command,
-            post_attach_command,
-        } = config;
+    /// User a lifecycle hook runs as, or `None` for the container's default user.
+    pub fn hook_user(&self, hook: &str) -> Option<&str> {
+        self.hook_users
+            .get(hook)
+            .or(self.remote_user.as_ref())
+            .map(String::as_str)
+    }
 
-        let forward_ports: Vec<ForwardPort> = raw_forward_ports
-            .into_iter()
-            .map(ForwardPort::try_from)
-            .collect::<std::result::Result<_, _>>()?;
+    /// Attributes of `port`: the `portsAttributes` entry naming it or a range
+    /// holding it, else `otherPortsAttributes`.
+    pub fn port_attributes(&self, port: u16) -> Option<&PortAttributes> {
+        self.ports_attributes
+            .iter()
+            .find(|(key, _)| attribute_port_range(key).is_some_and(|range| range.contains(&port)))
+            .map(|(_, attributes)| attributes)
+            .or(self.other_ports_attributes.as_ref())
+    }
 
-        let config_dir = config_path
-            .parent()
-            .map(Path::to_path_buf)
-            .unwrap_or_else(|| PathBuf::from("."));
+    /// Stable digest of the resolved configuration, used to detect when a container is stale.
+    /// It also covers the Dockerfile and local feature files the image is built
+    /// from, so editing them changes it as well.
+    pub fn config_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(
+            serde_json::to_vec(self).expect("Resolved configuration must serialize to JSON"),
+        );
+        let config_dir = self.config_path.parent().unwrap_or_else(|| Path::new(""));
+        for path in self.build_inputs() {
+            // Unreadable inputs fail the build itself, with a better message.
+            let Ok(contents) = fs::read(&path) else {
+                continue;
+            };
+            let name = path.strip_prefix(config_dir).unwrap_or(&path);
+            hasher.update(name.to_string_lossy().as_bytes());
+            hasher.update((contents.len() as u64).to_le_bytes());
+            hasher.update(contents);
+        }
+        format!("{:x}", hasher.finalize())
+    }
 
-        let dockerfile = docker_file.map(|path| {
-            let path = PathBuf::from(path);
-            if path.is_absolute() {
-                path
-            } else {
-                config_dir.join(path)
+    /// Files the image is built from: the Dockerfile and every file of the local
+    /// features, in a stable order.
+    pub fn build_inputs(&self) -> Vec<PathBuf> {
+        let mut inputs: Vec<PathBuf> = self.dockerfile.iter().cloned().collect();
+        let config_dir = self.config_path.parent().unwrap_or_else(|| Path::new(""));
+        for id in self.features.keys() {
+            if id.starts_with("./") || id.starts_with("../") {
+                collect_files(&config_dir.join(id), &mut inputs);
             }
-        });
-
-        let workspace_root = match &self.source {
-            ConfigSource::Workspace(path) => path.clone(),
-            ConfigSource::ExplicitFile(_) => config_dir.clone(),
-        };
+        }
+        inputs
+    }
 
-        let workspace_folder_override = self.overrides.workspace_folder.clone();
+    /// Looks up a dotted path such as `customizations.vscode.extensions` in the
+    /// serialized configuration; numeric segments index into arrays.
+    pub fn query(&self, path: &str) -> Result<Value> {
+        let document =
+            serde_json::to_value(self).expect("Resolved configuration must serialize to JSON");
+        query_document(&document, path)
+    }
 
-        let workspace_folder_from_config = config_workspace_folder.as_ref().and_then(|folder| {
-            if folder.trim_start().starts_with('/') {
-                return None;
-            }
+    /// Label recording [`ResolvedConfig::config_hash`] on the created container.
+    pub fn config_hash_label(&self) -> ContainerLabel {
+        ContainerLabel::new(LABEL_CONFIG_HASH, self.config_hash())
+    }
+}
 
-            let substituted = resolve_local_workspace_placeholders(folder, &workspace_root);
-            let path = PathBuf::from(&substituted);
-            if Path::new(&substituted).is_absolute() {
-                Some(path)
-            } else {
-                Some(workspace_root.join(path))
-            }
-        });
+/// Appends the files under `dir` to `files`, sorted by path.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
 
-        let workspace_folder = workspace_folder_override
-            .or(workspace_folder_from_config)
-            .unwrap_or_else(|| workspace_root.clone());
+/// Value at a dotted path of `document`, with array items addressed by index.
+pub fn query_document(document: &Value, path: &str) -> Result<Value> {
+    path.split('.')
+        .filter(|segment| !segment.is_empty())
+        .try_fold(document, |value, segment| match value {
+            Value::Object(map) => map.get(segment),
+            Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        })
+        .cloned()
+        .ok_or_else(|| {
+            DevcontainerError::Configuration(format!("No configuration value at '{path}'"))
+        })
+}
 
-        let container_workspace_folder = config_workspace_folder.as_ref().and_then(|folder| {
-            if folder.trim_start().starts_with('/') {
-                let substituted = resolve_local_workspace_placeholders(folder, &workspace_root);
-                Some(PathBuf::from(substituted))
-            } else {
-                None
-            }
-        });
+/// Lifecycle hooks that may be assigned a user through `hookUsers` or `--hook-user`.
+pub const HOOK_NAMES: &[&str] = &[
+    "onCreate",
+    "updateContent",
+    "postCreate",
+    "postStart",
+    "postAttach",
+];
+
+/// User override for lifecycle hooks, parsed from `user` or `hook=user`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookUser {
+    /// Hook the user applies to; every hook when `None`.
+    pub hook: Option<String>,
+    pub user: String,
+}
 
-        let project_name = self
-            .overrides
-            .project_name
-            .clone()
-            .or(name)
-            .or_else(|| {
-                workspace_root
-                    .file_name()
-                    .map(|n| n.to_string_lossy().into_owned())
-            })
-            .unwrap_or_else(|| "devcontainer".to_string());
+impl std::str::FromStr for HookUser {
+    type Err = DevcontainerError;
 
-        let image_reference = self.overrides.image_reference.clone().or(image);
+    fn from_str(value: &str) -> Result<Self> {
+        let (hook, user) = match value.split_once('=') {
+            Some((hook, user)) => (Some(validate_hook_name(hook)?), user),
+            None => (None, value),
+        };
+        if user.is_empty() {
+            return Err(DevcontainerError::Configuration(format!(
+                "Invalid hook user '{value}': expected 'user' or 'hook=user'"
+            )));
+        }
 
-        Ok(ResolvedConfig {
-            project_name,
-            workspace_folder,
-            container_workspace_folder,
-            config_path,
-            image_reference,
-            dockerfile,
-            features,
-            forward_ports,
-            post_create_command,
-            post_attach_command,
+        Ok(Self {
+            hook,
+            user: user.to_string(),
         })
     }
 }
 
-/// Source of configuration data.
-#[derive(Debug, Clone)]
-pub enum ConfigSource {
-    Workspace(PathBuf),
-    ExplicitFile(PathBuf),
+fn validate_hook_name(hook: &str) -> Result<String> {
+    if HOOK_NAMES.contains(&hook) {
+        Ok(hook.to_string())
+    } else {
+        Err(DevcontainerError::Configuration(format!(
+            "Unknown lifecycle hook '{hook}'; expected one of {}",
+            HOOK_NAMES.join(", ")
+        )))
+    }
 }
 
-impl ConfigSource {
-    fn resolve_path(&self) -> Result<PathBuf> {
-        match self {
-            ConfigSource::Workspace(path) => {
-                let candidate = path.join(".devcontainer").join("devcontainer.json");
-                if candidate.exists() {
-                    return Ok(candidate);
-                }
+/// Writable paths must stay inside the workspace so their volumes shadow part of it.
+fn validate_writable_path(path: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(path.trim_end_matches('/'));
+    let inside = path.components().count() > 0
+        && path
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)));
+    if inside {
+        Ok(path)
+    } else {
+        Err(DevcontainerError::Configuration(format!(
+            "Writable workspace path '{}' must be relative to the workspace and must not use '..'",
+            path.display()
+        )))
+    }
+}
 
-                let fallback = path.join("devcontainer.json");
-                if fallback.exists() {
-                    return Ok(fallback);
-                }
+/// `docker create` flags the provider sets itself, which `runArgs` must not override.
+const MANAGED_RUN_FLAGS: &[&str] = &[
+    "--name",
+    "--hostname",
+    "-h",
+    "--workdir",
+    "-w",
+    "--entrypoint",
+    "--rm",
+    "--detach",
+    "-d",
+];
+
+/// Rejects `runArgs`, or other extra create arguments named by `property`, that
+/// conflict with the provider's own flags or share host namespaces and the host
+/// root with the container.
+pub fn validate_run_args(property: &str, args: &[String], has_sidecars: bool) -> Result<()> {
+    let mut iter = args.iter().peekable();
+    while let Some(arg) = iter.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with('-') => (flag, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || {
+            inline
+                .map(str::to_string)
+                .or_else(|| iter.peek().map(|next| next.to_string()))
+                .unwrap_or_default()
+        };
 
-                Err(DevcontainerError::Configuration(format!(
-                    "Failed to locate devcontainer.json under {path:?}"
-                )))
+        let rejection = if MANAGED_RUN_FLAGS.contains(&flag) {
+            Some("is set by the provider")
+        } else if matches!(flag, "--network" | "--net") && has_sidecars {
+            Some("conflicts with the sidecar network")
+        } else if matches!(flag, "--pid" | "--userns") && value() == "host" {
+            Some("shares a host namespace")
+        } else if flag == "--cap-add" && value().eq_ignore_ascii_case("ALL") {
+            Some("grants every capability; set `\"privileged\": true` instead")
+        } else if matches!(flag, "--volume" | "-v") && value().split(':').next() == Some("/") {
+            Some("mounts the host root filesystem")
+        } else if flag == "--mount" {
+            let value = value();
+            let root = value
+                .split(',')
+                .any(|option| matches!(option.split_once('='), Some(("source" | "src", "/"))));
+            root.then_some("mounts the host root filesystem")
+        } else {
+            None
+        };
+
+        if let Some(reason) = rejection {
+            return Err(DevcontainerError::Configuration(format!(
+                "{property} entry '{arg}' is not allowed: it {reason}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+pub const LABEL_LOCAL_FOLDER: &str = "devcontainer.local_folder";
+pub const LABEL_CONFIG_FILE: &str = "devcontainer.config_file";
+pub const LABEL_CONFIG_HASH: &str = "devcontainer.config_hash";
+/// Label tying containers, networks and volumes to the project that created them.
+pub const LABEL_PROJECT: &str = "devcontainer.project";
+
+/// Container label in `name=value` form, as accepted by `--id-label`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ContainerLabel {
+    pub name: String,
+    pub value: String,
+}
+
+impl ContainerLabel {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+
+    /// Default labels identifying the devcontainer of a workspace and config file.
+    pub fn defaults_for(workspace_folder: &Path, config_path: &Path) -> Vec<Self> {
+        vec![
+            Self::new(
+                LABEL_LOCAL_FOLDER,
+                workspace_folder.to_string_lossy().into_owned(),
+            ),
+            Self::new(
+                LABEL_CONFIG_FILE,
+                config_path.to_string_lossy().into_owned(),
+            ),
+        ]
+    }
+}
+
+impl std::fmt::Display for ContainerLabel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+impl std::str::FromStr for ContainerLabel {
+    type Err = DevcontainerError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.split_once('=') {
+            Some((name, label_value)) if !name.trim().is_empty() => {
+                Ok(Self::new(name.trim(), label_value))
+            }
+            _ => Err(DevcontainerError::Configuration(format!(
+                "Invalid label '{value}': expected 'name=value'"
+            ))),
+        }
+    }
+}
+
+/// When `up` and `build` pull the image from its registry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PullPolicy {
+    /// Pull even a present image, refreshing floating tags such as `latest`;
+    /// Dockerfile builds refresh their base images too.
+    Always,
+    /// Pull only an image missing locally.
+    #[default]
+    IfNotPresent,
+    /// Never pull; a missing image is an error.
+    Never,
+}
+
+impl std::fmt::Display for PullPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PullPolicy::Always => "always",
+            PullPolicy::IfNotPresent => "if-not-present",
+            PullPolicy::Never => "never",
+        })
+    }
+}
+
+impl std::str::FromStr for PullPolicy {
+    type Err = DevcontainerError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "always" => Ok(PullPolicy::Always),
+            "if-not-present" => Ok(PullPolicy::IfNotPresent),
+            "never" => Ok(PullPolicy::Never),
+            _ => Err(DevcontainerError::Configuration(format!(
+                "Invalid pull policy '{value}': expected 'always', 'if-not-present' or 'never'"
+            ))),
+        }
+    }
+}
+
+/// Additional container created next to the devcontainer on the project network.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct Sidecar {
+    pub name: String,
+    pub image: String,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub ports: Vec<ForwardPort>,
+}
+
+impl Sidecar {
+    fn from_definition(name: String, definition: SidecarDefinition) -> Result<Self> {
+        let valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.'));
+        if !valid_name {
+            return Err(DevcontainerError::Configuration(format!(
+                "Invalid sidecar name '{name}': only alphanumerics, '-', '_' and '.' are allowed"
+            )));
+        }
+
+        if definition.image.trim().is_empty() {
+            return Err(DevcontainerError::Configuration(format!(
+                "Sidecar '{name}' must define an image"
+            )));
+        }
+
+        let ports = definition
+            .ports
+            .into_iter()
+            .map(ForwardPort::try_from)
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            name,
+            image: definition.image,
+            env: definition.env,
+            ports,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct ForwardPort {
+    pub local_port: u16,
+    pub container_port: u16,
+    #[serde(default)]
+    pub protocol: PortProtocol,
+    /// Label from the port's attributes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Scheme the forwarded port is reached with, from the port's attributes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<PortScheme>,
+}
+
+impl ForwardPort {
+    /// Address of the forwarded port on the host, as a URL when a scheme is known.
+    pub fn address(&self) -> String {
+        match self.scheme {
+            Some(scheme) => format!("{scheme}://localhost:{}", self.local_port),
+            None => format!("localhost:{}", self.local_port),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PortProtocol {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+/// Application protocol of a forwarded port, the `protocol` of its attributes.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PortScheme {
+    Http,
+    Https,
+}
+
+impl std::fmt::Display for PortScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PortScheme::Http => "http",
+            PortScheme::Https => "https",
+        })
+    }
+}
+
+/// What happens when a port is detected and forwarded automatically.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum OnAutoForward {
+    #[default]
+    Notify,
+    OpenBrowser,
+    OpenBrowserOnce,
+    OpenPreview,
+    Silent,
+    Ignore,
+}
+
+/// An entry of `portsAttributes`, or `otherPortsAttributes`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PortAttributes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<PortScheme>,
+    #[serde(default)]
+    pub on_auto_forward: OnAutoForward,
+    /// Fail rather than forward from another local port when this one is taken.
+    #[serde(default)]
+    pub require_local_port: bool,
+    #[serde(default)]
+    pub elevate_if_needed: bool,
+}
+
+/// Ports a `portsAttributes` key stands for, when it is a port or a range of them.
+fn attribute_port_range(key: &str) -> Option<RangeInclusive<u16>> {
+    match key.split_once('-') {
+        Some((start, end)) => Some(start.parse().ok()?..=end.parse().ok()?),
+        None => {
+            let port = key.parse().ok()?;
+            Some(port..=port)
+        }
+    }
+}
+
+/// Last path segment of a git URL without `.git`, e.g. `repo` for
+/// `git@github.com:org/repo.git`.
+fn repository_name(url: &str) -> &str {
+    let path = url.trim_end_matches('/');
+    let name = path.rsplit(['/', ':']).next().unwrap_or(path);
+    name.strip_suffix(".git").unwrap_or(name)
+}
+
+/// Volume a repository is cloned into when `--workspace-volume` is not given.
+fn clone_volume_name(url: &str) -> String {
+    let name: String = repository_name(url)
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.') {
+                ch
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("devcontainer-{name}-workspace")
+}
+
+/// Docker build settings of a Dockerfile-based configuration, with paths resolved.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct BuildOptions {
+    /// Build context; the Dockerfile's directory when unset.
+    #[serde(default)]
+    pub context: Option<PathBuf>,
+    /// `--build-arg` values, with local placeholders substituted.
+    #[serde(default)]
+    pub args: BTreeMap<String, String>,
+    /// Stage of a multi-stage Dockerfile to build.
+    #[serde(default)]
+    pub target: Option<String>,
+    #[serde(default)]
+    pub cache_from: Vec<String>,
+    /// Cache export destinations from the command line; not part of the
+    /// container identity.
+    #[serde(skip)]
+    pub cache_to: Vec<String>,
+    /// Target platforms from the command line.
+    #[serde(skip)]
+    pub platforms: Vec<String>,
+    /// Registry names to tag the image with, from the command line.
+    #[serde(skip)]
+    pub image_names: Vec<String>,
+    /// Push the image names once built, from the command line.
+    #[serde(skip)]
+    pub push: bool,
+    /// Extra arguments passed to the build command as written.
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+/// Entry of `mounts`: a `--mount` style string or an object.
+///
+/// Objects support `bind`, `volume` (where `source` names the volume) and
+/// `tmpfs` (which has no source) mounts.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Mount {
+    String(String),
+    Object {
+        #[serde(rename = "type")]
+        mount_type: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source: Option<String>,
+        target: String,
+    },
+}
+
+/// Entry of the `forwardSockets` extension: a host unix socket, such as a
+/// language server's or a test daemon's, made available at `target`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SocketForward {
+    pub source: PathBuf,
+    /// Path in the container; defaults to `source`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<PathBuf>,
+}
+
+impl SocketForward {
+    pub fn target(&self) -> &Path {
+        self.target.as_deref().unwrap_or(&self.source)
+    }
+
+    /// Bind mount carrying the socket into the container.
+    pub fn to_mount(&self) -> Mount {
+        Mount::Object {
+            mount_type: "bind".to_string(),
+            source: Some(self.source.display().to_string()),
+            target: self.target().display().to_string(),
+        }
+    }
+
+    /// Substitutes placeholders such as `${localEnv:SSH_AUTH_SOCK}` and checks the
+    /// source is a socket, since docker would otherwise create a directory there.
+    fn resolve(self, substitute: &impl Fn(&str) -> String) -> Result<Self> {
+        let source = PathBuf::from(substitute(&self.source.to_string_lossy()));
+        let target = self
+            .target
+            .map(|target| PathBuf::from(substitute(&target.to_string_lossy())));
+        if !source.is_absolute() || target.as_ref().is_some_and(|target| !target.is_absolute()) {
+            return Err(DevcontainerError::Configuration(format!(
+                "forwardSockets entry {} must use absolute paths",
+                source.display()
+            )));
+        }
+        if !is_socket(&source) {
+            return Err(DevcontainerError::Configuration(format!(
+                "forwardSockets source {} is not a unix socket",
+                source.display()
+            )));
+        }
+        Ok(Self { source, target })
+    }
+}
+
+#[cfg(unix)]
+fn is_socket(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket())
+}
+
+#[cfg(not(unix))]
+fn is_socket(path: &Path) -> bool {
+    path.exists()
+}
+
+/// `hostRequirements`: resources the machine running the container must offer.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct HostRequirements {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<u64>,
+    /// Size such as `8gb`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+    /// Free disk space, as a size such as `32gb`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage: Option<String>,
+    /// `true`, `false`, `"optional"`, or an object of `cores` and `memory`, which
+    /// requires a GPU.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpu: Option<Value>,
+}
+
+/// Whether the container needs a GPU, from `hostRequirements.gpu`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuRequirement {
+    None,
+    /// Used when present; the container still starts without one.
+    Optional,
+    Required,
+}
+
+impl HostRequirements {
+    pub fn memory_bytes(&self) -> Option<u64> {
+        self.memory.as_deref().and_then(merge::parse_size)
+    }
+
+    pub fn storage_bytes(&self) -> Option<u64> {
+        self.storage.as_deref().and_then(merge::parse_size)
+    }
+
+    pub fn gpu(&self) -> GpuRequirement {
+        match &self.gpu {
+            Some(Value::Bool(true) | Value::Object(_)) => GpuRequirement::Required,
+            Some(Value::String(value)) if value == "optional" => GpuRequirement::Optional,
+            _ => GpuRequirement::None,
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (name, size) in [("memory", &self.memory), ("storage", &self.storage)] {
+            if let Some(size) = size
+                .as_deref()
+                .filter(|size| merge::parse_size(size).is_none())
+            {
+                return Err(DevcontainerError::Configuration(format!(
+                    "hostRequirements.{name} '{size}' is not a size such as 8gb"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bind propagation of a mount, as in `mount(8)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MountPropagation {
+    Private,
+    Rprivate,
+    Shared,
+    Rshared,
+    Slave,
+    Rslave,
+}
+
+impl std::fmt::Display for MountPropagation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MountPropagation::Private => "private",
+            MountPropagation::Rprivate => "rprivate",
+            MountPropagation::Shared => "shared",
+            MountPropagation::Rshared => "rshared",
+            MountPropagation::Slave => "slave",
+            MountPropagation::Rslave => "rslave",
+        })
+    }
+}
+
+/// Consistency of a bind mount on Docker Desktop for Mac. Linux engines, and
+/// Docker Desktop file sharing with VirtioFS, accept and ignore it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MountConsistency {
+    /// Host and container always see the same files.
+    Consistent,
+    /// The host is authoritative; the container may see its writes late.
+    Cached,
+    /// The container is authoritative; the host may see its writes late.
+    Delegated,
+}
+
+impl std::fmt::Display for MountConsistency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MountConsistency::Consistent => "consistent",
+            MountConsistency::Cached => "cached",
+            MountConsistency::Delegated => "delegated",
+        })
+    }
+}
+
+impl std::str::FromStr for MountConsistency {
+    type Err = DevcontainerError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "consistent" => Ok(MountConsistency::Consistent),
+            "cached" => Ok(MountConsistency::Cached),
+            "delegated" => Ok(MountConsistency::Delegated),
+            _ => Err(DevcontainerError::Configuration(format!(
+                "Invalid mount consistency '{value}': expected 'consistent', 'cached' or 'delegated'"
+            ))),
+        }
+    }
+}
+
+impl Mount {
+    /// Path the mount is attached at in the container.
+    pub fn target(&self) -> Option<&str> {
+        match self {
+            Mount::String(mount) => mount.split(',').find_map(|option| {
+                let (key, value) = option.split_once('=')?;
+                matches!(key.trim(), "target" | "dst" | "destination").then_some(value.trim())
+            }),
+            Mount::Object { target, .. } => Some(target),
+        }
+    }
+
+    /// Value of a `docker run --mount` argument.
+    pub fn to_mount_arg(&self) -> String {
+        match self {
+            Mount::String(mount) => mount.clone(),
+            Mount::Object {
+                mount_type,
+                source: Some(source),
+                target,
+            } => format!("type={mount_type},source={source},target={target}"),
+            Mount::Object {
+                mount_type, target, ..
+            } => format!("type={mount_type},target={target}"),
+        }
+    }
+
+    fn with_placeholders(self, workspace_root: &Path) -> Self {
+        let substitute = |value: &str| {
+            resolve_local_env_placeholders(&resolve_local_workspace_placeholders(
+                value,
+                workspace_root,
+            ))
+        };
+        match self {
+            Mount::String(mount) => Mount::String(substitute(&mount)),
+            Mount::Object {
+                mount_type,
+                source,
+                target,
+            } => Mount::Object {
+                mount_type,
+                source: source
+                    .map(|source| substitute(&source))
+                    .filter(|source| !source.is_empty()),
+                target,
+            },
+        }
+    }
+}
+
+/// Resolves a `DevcontainerConfig` into a `ResolvedConfig`.
+pub struct ConfigResolver {
+    source: ConfigSource,
+    overrides: ConfigOverrides,
+    extensions: Vec<SchemaExtension>,
+    strict: bool,
+}
+
+impl ConfigResolver {
+    pub fn new(source: ConfigSource) -> Self {
+        Self {
+            source: source.located(),
+            overrides: ConfigOverrides::default(),
+            extensions: Vec::new(),
+            strict: false,
+        }
+    }
+
+    pub fn with_overrides(mut self, overrides: ConfigOverrides) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Validates a custom property, such as `customizations.mytool`, with an extra schema fragment.
+    pub fn with_schema_extension(mut self, extension: SchemaExtension) -> Self {
+        self.extensions.push(extension);
+        self
+    }
+
+    /// Rejects properties that are neither in the specification nor registered as extensions.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn resolve(&self) -> Result<ResolvedConfig> {
+        tracing::debug!(?self.source, "Resolving devcontainer configuration");
+
+        let (config_path, document) = self.load_document()?;
+        let config: DevcontainerConfig = serde_json::from_value(document).map_err(|err| {
+            DevcontainerError::Configuration(format!(
+                "{} does not match expected structure: {err}",
+                config_path.display()
+            ))
+        })?;
+
+        let DevcontainerConfig {
+            name,
+            image,
+            docker_file,
+            context: legacy_context,
+            build: build_definition,
+            workspace_folder: config_workspace_folder,
+            workspace_mount,
+            features,
+            override_feature_install_order,
+            forward_ports: raw_forward_ports,
+            app_port,
+            ports_attributes,
+            other_ports_attributes,
+            initialize_command,
+            on_create_command,
+            update_content_command,
+            post_create_command,
+            post_start_command,
+            post_attach_command,
+            wait_for,
+            container_env,
+            remote_env,
+            remote_user,
+            customizations,
+            mounts,
+            run_args: raw_run_args,
+            init,
+            privileged,
+            cap_add,
+            security_opt,
+            override_command,
+            hook_users: raw_hook_users,
+            hook_env: raw_hook_env,
+            tasks,
+            sidecars: raw_sidecars,
+            read_only_workspace,
+            writable_workspace_paths: raw_writable_paths,
+            workspace_mount_propagation,
+            workspace_mount_consistency,
+            forward_sockets: raw_forward_sockets,
+            lifecycle_shell,
+            host_requirements,
+        } = config;
+
+        let lifecycle_shell = self.overrides.lifecycle_shell.clone().or(lifecycle_shell);
+        if lifecycle_shell.as_ref().is_some_and(Vec::is_empty) {
+            return Err(DevcontainerError::Configuration(
+                "lifecycleShell must name the program that runs lifecycle commands".into(),
+            ));
+        }
+
+        let read_only_workspace = read_only_workspace || self.overrides.read_only_workspace;
+        let workspace_mount_consistency = self
+            .overrides
+            .workspace_mount_consistency
+            .or(workspace_mount_consistency);
+        let mut writable_workspace_paths = Vec::new();
+        for path in raw_writable_paths
+            .iter()
+            .chain(&self.overrides.writable_workspace_paths)
+        {
+            let path = validate_writable_path(path)?;
+            if !writable_workspace_paths.contains(&path) {
+                writable_workspace_paths.push(path);
+            }
+        }
+
+        let mut hook_users = BTreeMap::new();
+        for (hook, user) in raw_hook_users {
+            hook_users.insert(validate_hook_name(&hook)?, user);
+        }
+        for override_user in &self.overrides.hook_users {
+            match &override_user.hook {
+                Some(hook) => {
+                    hook_users.insert(hook.clone(), override_user.user.clone());
+                }
+                None => {
+                    for hook in HOOK_NAMES {
+                        hook_users.insert(hook.to_string(), override_user.user.clone());
+                    }
+                }
+            }
+        }
+
+        let mut hook_env = BTreeMap::new();
+        for (hook, env) in raw_hook_env {
+            hook_env.insert(validate_hook_name(&hook)?, env);
+        }
+
+        let forward_ports: Vec<ForwardPort> = raw_forward_ports
+            .into_iter()
+            .map(ForwardPort::try_from)
+            .collect::<std::result::Result<_, _>>()?;
+        let app_ports = app_port
+            .map(AppPortDefinition::publish_specs)
+            .transpose()?
+            .unwrap_or_default();
+
+        let sidecars = raw_sidecars
+            .into_iter()
+            .map(|(name, definition)| Sidecar::from_definition(name, definition))
+            .collect::<Result<Vec<_>>>()?;
+
+        let config_dir = config_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        let in_config_dir = |path: String| {
+            let path = PathBuf::from(path);
+            if path.is_absolute() {
+                path
+            } else {
+                config_dir.join(path)
+            }
+        };
+        let build_definition = build_definition.unwrap_or_default();
+        let dockerfile = build_definition
+            .dockerfile
+            .or(docker_file)
+            .map(in_config_dir);
+
+        let workspace_root = match &self.source {
+            ConfigSource::Workspace(path)
+            | ConfigSource::Named {
+                workspace: path, ..
+            } => path.clone(),
+            ConfigSource::ExplicitFile(_) => config_dir.clone(),
+        };
+
+        let workspace_folder_override = self.overrides.workspace_folder.clone();
+
+        let workspace_folder_from_config = config_workspace_folder.as_ref().and_then(|folder| {
+            if folder.trim_start().starts_with('/') {
+                return None;
+            }
+
+            let substituted = resolve_local_workspace_placeholders(folder, &workspace_root);
+            let path = PathBuf::from(&substituted);
+            if Path::new(&substituted).is_absolute() {
+                Some(path)
+            } else {
+                Some(workspace_root.join(path))
+            }
+        });
+
+        let workspace_folder = workspace_folder_override
+            .or(workspace_folder_from_config)
+            .unwrap_or_else(|| workspace_root.clone());
+
+        let container_workspace_folder = match &self.overrides.container_workspace_folder {
+            Some(folder) if !folder.to_string_lossy().starts_with('/') => {
+                return Err(DevcontainerError::Configuration(format!(
+                    "Container workspace folder '{}' must be an absolute path",
+                    folder.display()
+                )));
+            }
+            Some(folder) => Some(folder.clone()),
+            None => config_workspace_folder.as_ref().and_then(|folder| {
+                if folder.trim_start().starts_with('/') {
+                    let substituted = resolve_local_workspace_placeholders(folder, &workspace_root);
+                    Some(PathBuf::from(substituted))
+                } else {
+                    None
+                }
+            }),
+        };
+
+        let workspace_mount = workspace_mount
+            .map(|mount| Mount::String(mount).with_placeholders(&workspace_root))
+            .map(|mount| match mount.target() {
+                Some(target) if target.starts_with('/') => Ok(mount),
+                _ => Err(DevcontainerError::Configuration(format!(
+                    "workspaceMount '{}' must have an absolute target",
+                    mount.to_mount_arg()
+                ))),
+            })
+            .transpose()?;
+        let clone_url = self.overrides.clone_url.clone();
+        let workspace_volume = self
+            .overrides
+            .workspace_volume
+            .clone()
+            .or_else(|| clone_url.as_deref().map(clone_volume_name));
+        let workspace_mount = match &workspace_volume {
+            Some(volume) => {
+                let target = container_workspace_folder.clone().unwrap_or_else(|| {
+                    let repository = clone_url.as_deref().map(repository_name);
+                    PathBuf::from("/workspaces").join(repository.unwrap_or(volume))
+                });
+                Some(Mount::String(format!(
+                    "type=volume,source={volume},target={}",
+                    target.display()
+                )))
+            }
+            None => workspace_mount,
+        };
+        // The workspace is opened where the custom mount puts it unless
+        // `workspaceFolder` says otherwise.
+        let container_workspace_folder = container_workspace_folder.or_else(|| {
+            workspace_mount
+                .as_ref()
+                .and_then(Mount::target)
+                .map(PathBuf::from)
+        });
+
+        let project_name = self
+            .overrides
+            .project_name
+            .clone()
+            .or(name)
+            .or_else(|| {
+                workspace_root
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "devcontainer".to_string());
+
+        let image_reference = self.overrides.image_reference.clone().or(image);
+
+        let mut features = features;
+        if let Some(unknown) = override_feature_install_order
+            .iter()
+            .find(|pattern| !features.keys().any(|id| feature_matches(id, pattern)))
+        {
+            return Err(DevcontainerError::Configuration(format!(
+                "overrideFeatureInstallOrder names '{unknown}', which is not in features"
+            )));
+        }
+        features.retain(|id, _| {
+            let skip = self
+                .overrides
+                .skip_features
+                .iter()
+                .any(|pattern| feature_matches(id, pattern));
+            if skip {
+                tracing::warn!(feature = %id, "Skipping feature (--skip-feature)");
+            }
+            !skip
+        });
+        // Bad options and features installing after each other fail here, not
+        // at build time.
+        check_feature_options(&config_path, &features)?;
+        install_order(&config_path, &features, &override_feature_install_order)?;
+
+        let mounts = mounts
+            .into_iter()
+            .map(|mount| mount.with_placeholders(&workspace_root))
+            .collect();
+
+        let substitute = |value: &str| {
+            resolve_local_env_placeholders(&resolve_local_workspace_placeholders(
+                value,
+                &workspace_root,
+            ))
+        };
+        let run_args: Vec<String> = raw_run_args.iter().map(|arg| substitute(arg)).collect();
+        let forward_sockets = raw_forward_sockets
+            .into_iter()
+            .map(|socket| socket.resolve(&substitute))
+            .collect::<Result<Vec<_>>>()?;
+        validate_run_args("runArgs", &run_args, !sidecars.is_empty())?;
+        if let Some(requirements) = &host_requirements {
+            requirements.validate()?;
+        }
+
+        let build = BuildOptions {
+            context: build_definition
+                .context
+                .or(legacy_context)
+                .map(in_config_dir),
+            args: build_definition
+                .args
+                .iter()
+                .map(|(name, value)| (name.clone(), substitute(value)))
+                .collect(),
+            target: build_definition.target,
+            cache_from: build_definition
+                .cache_from
+                .into_iter()
+                .chain(self.overrides.cache_from.iter().cloned())
+                .collect(),
+            cache_to: self.overrides.cache_to.clone(),
+            platforms: self.overrides.platforms.clone(),
+            image_names: self.overrides.image_names.clone(),
+            push: self.overrides.push,
+            options: build_definition.options,
+        };
+
+        let id_labels = if self.overrides.id_labels.is_empty() {
+            ContainerLabel::defaults_for(&workspace_folder, &config_path)
+        } else {
+            self.overrides.id_labels.clone()
+        };
+
+        Ok(ResolvedConfig {
+            project_name,
+            workspace_folder,
+            container_workspace_folder,
+            config_path,
+            image_reference,
+            dockerfile,
+            build,
+            features,
+            override_feature_install_order,
+            forward_ports,
+            app_ports,
+            ports_attributes,
+            other_ports_attributes,
+            initialize_command,
+            on_create_command,
+            update_content_command,
+            post_create_command,
+            post_start_command,
+            post_attach_command,
+            wait_for,
+            container_env,
+            remote_env,
+            cli_env: self.overrides.env.clone(),
+            secrets: self.overrides.secrets.clone(),
+            pull_policy: self.overrides.pull_policy,
+            remote_user,
+            customizations,
+            mounts,
+            run_args,
+            init,
+            privileged,
+            cap_add,
+            security_opt,
+            override_command,
+            hook_users,
+            hook_env,
+            tasks,
+            sidecars,
+            read_only_workspace,
+            writable_workspace_paths,
+            workspace_mount_propagation,
+            workspace_mount_consistency,
+            workspace_mount,
+            lifecycle_shell,
+            workspace_volume,
+            clone_url,
+            forward_sockets,
+            host_requirements,
+            id_labels,
+            profile: self.overrides.profile.clone(),
+        })
+    }
+
+    /// The configuration document with the profile applied, validated but otherwise as written.
+    pub fn document(&self) -> Result<Value> {
+        self.load_document().map(|(_, document)| document)
+    }
+
+    fn load_document(&self) -> Result<(PathBuf, Value)> {
+        let config_path = self.source.resolve_path()?;
+        let document = read_document(&config_path)?;
+        let document = extends::apply_extends(document, &config_path)?;
+        let document = profiles::apply_profile(document, self.overrides.profile.as_deref())?;
+        validate_against_schema(&document)?;
+        extensions::validate_extensions(&document, &self.extensions, self.strict)?;
+        Ok((config_path, document))
+    }
+}
+
+/// Parses the configuration file at `path`.
+fn read_document(path: &Path) -> Result<Value> {
+    let raw_document = fs::read_to_string(path).map_err(|err| {
+        DevcontainerError::Configuration(format!("Failed to read {}: {err}", path.display()))
+    })?;
+
+    // Allow comments/trailing commas by parsing with JSON5-compatible parser.
+    json5::from_str(&raw_document).map_err(|err| {
+        DevcontainerError::Configuration(format!("{} is not valid JSON: {err}", path.display()))
+    })
+}
+
+/// Source of configuration data.
+#[derive(Debug, Clone)]
+pub enum ConfigSource {
+    /// The workspace's `.devcontainer/devcontainer.json`, or its only
+    /// `.devcontainer/<name>/devcontainer.json`.
+    Workspace(PathBuf),
+    ExplicitFile(PathBuf),
+    /// The workspace's `.devcontainer/<name>/devcontainer.json`.
+    Named {
+        workspace: PathBuf,
+        name: String,
+    },
+}
+
+impl ConfigSource {
+    /// The source with its workspace moved up to the closest directory holding
+    /// a configuration, as cargo and git look for theirs from a subdirectory.
+    fn located(self) -> Self {
+        let (workspace, name) = match &self {
+            ConfigSource::Workspace(workspace) => (workspace, None),
+            ConfigSource::Named { workspace, name } => (workspace, Some(name.as_str())),
+            ConfigSource::ExplicitFile(_) => return self,
+        };
+        let holds_config = |dir: &Path| match name {
+            Some(name) => dir
+                .join(".devcontainer")
+                .join(name)
+                .join("devcontainer.json")
+                .is_file(),
+            None => has_config(dir),
+        };
+        let Some(root) = find_upwards(workspace, holds_config) else {
+            return self;
+        };
+        if root == *workspace {
+            return self;
+        }
+        tracing::debug!(workspace = %root.display(), "Found the configuration in a parent directory");
+        match self {
+            ConfigSource::Named { name, .. } => ConfigSource::Named {
+                workspace: root,
+                name,
+            },
+            _ => ConfigSource::Workspace(root),
+        }
+    }
+
+    fn resolve_path(&self) -> Result<PathBuf> {
+        match self {
+            ConfigSource::Workspace(path) => {
+                let candidate = path.join(".devcontainer").join("devcontainer.json");
+                if candidate.exists() {
+                    return Ok(candidate);
+                }
+
+                let fallback = path.join("devcontainer.json");
+                if fallback.exists() {
+                    return Ok(fallback);
+                }
+
+                let mut nested = discover_config_files(path)?;
+                match nested.len() {
+                    0 => Err(DevcontainerError::Configuration(format!(
+                        "Failed to locate devcontainer.json under {path:?}"
+                    ))),
+                    1 => Ok(nested.remove(0)),
+                    _ => Err(DevcontainerError::Configuration(format!(
+                        "Found several devcontainer configurations under {path:?}: {}; \
+                         choose one with --config-name",
+                        config_names(path)?.join(", ")
+                    ))),
+                }
+            }
+            ConfigSource::Named { workspace, name } => {
+                let path = workspace
+                    .join(".devcontainer")
+                    .join(name)
+                    .join("devcontainer.json");
+                if path.is_file() {
+                    return Ok(path);
+                }
+                let names = config_names(workspace)?;
+                Err(DevcontainerError::Configuration(if names.is_empty() {
+                    format!("No devcontainer configuration '{name}' under {workspace:?}")
+                } else {
+                    format!(
+                        "No devcontainer configuration '{name}' under {workspace:?}; \
+                         available: {}",
+                        names.join(", ")
+                    )
+                }))
             }
             ConfigSource::ExplicitFile(path) => {
                 if path.exists() {
@@ -399,13 +1882,129 @@ impl ConfigSource {
     }
 }
 
+/// Lists every devcontainer configuration in a workspace: `.devcontainer/devcontainer.json`
+/// followed by each `.devcontainer/<name>/devcontainer.json`, sorted by folder name.
+pub fn discover_config_files(workspace: &Path) -> Result<Vec<PathBuf>> {
+    let devcontainer_dir = workspace.join(".devcontainer");
+    let mut configs = Vec::new();
+
+    let primary = devcontainer_dir.join("devcontainer.json");
+    if primary.is_file() {
+        configs.push(primary);
+    }
+
+    if devcontainer_dir.is_dir() {
+        let entries = fs::read_dir(&devcontainer_dir).map_err(|err| {
+            DevcontainerError::Configuration(format!(
+                "Failed to list {}: {err}",
+                devcontainer_dir.display()
+            ))
+        })?;
+        let mut nested: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path().join("devcontainer.json"))
+            .filter(|path| path.is_file())
+            .collect();
+        nested.sort();
+        configs.extend(nested);
+    }
+
+    Ok(configs)
+}
+
+/// Closest directory holding a devcontainer configuration: `start` itself, or
+/// the first of its parents that does, searching no higher than the root of the
+/// git repository `start` is in.
+pub fn find_workspace_root(start: &Path) -> Option<PathBuf> {
+    find_upwards(start, has_config)
+}
+
+fn has_config(dir: &Path) -> bool {
+    dir.join(".devcontainer")
+        .join("devcontainer.json")
+        .is_file()
+        || dir.join("devcontainer.json").is_file()
+        || discover_config_files(dir).is_ok_and(|configs| !configs.is_empty())
+}
+
+fn find_upwards(start: &Path, found: impl Fn(&Path) -> bool) -> Option<PathBuf> {
+    if found(start) {
+        return Some(start.to_path_buf());
+    }
+    // Parents of a relative path are only known against the current directory.
+    let absolute = if start.is_absolute() {
+        start.to_path_buf()
+    } else {
+        std::env::current_dir().ok()?.join(start)
+    };
+    for dir in absolute.ancestors() {
+        if found(dir) {
+            return Some(dir.to_path_buf());
+        }
+        if dir.join(".git").exists() {
+            return None;
+        }
+    }
+    None
+}
+
+/// Names of the `.devcontainer/<name>/devcontainer.json` configurations of a
+/// workspace, as `--config-name` takes them.
+pub fn config_names(workspace: &Path) -> Result<Vec<String>> {
+    let devcontainer_dir = workspace.join(".devcontainer");
+    Ok(discover_config_files(workspace)?
+        .iter()
+        .filter_map(|path| path.parent())
+        .filter(|dir| *dir != devcontainer_dir)
+        .filter_map(|dir| dir.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect())
+}
+
 /// Overrides applied on top of the configuration source.
 #[derive(Debug, Clone, Default)]
 pub struct ConfigOverrides {
     pub project_name: Option<String>,
     pub workspace_folder: Option<PathBuf>,
     pub image_reference: Option<String>,
-    pub env: Map<String, Value>,
+    /// Highest-precedence environment variables, from `--env`.
+    pub env: BTreeMap<String, String>,
+    /// Environment variables from `--secrets-file`, below `--env` in precedence.
+    pub secrets: BTreeMap<String, String>,
+    /// Replaces the default identifying labels when non-empty.
+    pub id_labels: Vec<ContainerLabel>,
+    /// Applied in order over `hookUsers` from the configuration.
+    pub hook_users: Vec<HookUser>,
+    /// Name of the `profiles` entry to merge over the base configuration.
+    pub profile: Option<String>,
+    /// Mounts the workspace read-only even when the configuration does not.
+    pub read_only_workspace: bool,
+    /// Added to `writableWorkspacePaths` from the configuration.
+    pub writable_workspace_paths: Vec<String>,
+    /// Features removed from the configuration, matched by
+    /// [`feature_matches`](crate::features::feature_matches).
+    pub skip_features: Vec<String>,
+    /// Absolute in-container workspace path, replacing `workspaceFolder`.
+    pub container_workspace_folder: Option<PathBuf>,
+    /// Named volume mounted as the workspace, replacing `workspaceMount`.
+    pub workspace_volume: Option<String>,
+    /// Replaces `workspaceMountConsistency` from the configuration.
+    pub workspace_mount_consistency: Option<MountConsistency>,
+    /// Replaces `lifecycleShell` from the configuration.
+    pub lifecycle_shell: Option<Vec<String>>,
+    /// Repository cloned into the workspace volume while it is empty.
+    pub clone_url: Option<String>,
+    /// Build cache sources added to `build.cacheFrom`.
+    pub cache_from: Vec<String>,
+    /// Build cache export destinations.
+    pub cache_to: Vec<String>,
+    /// Platforms to build the image for.
+    pub platforms: Vec<String>,
+    /// Names to tag the built image with.
+    pub image_names: Vec<String>,
+    /// Push the image names after building.
+    pub push: bool,
+    pub pull_policy: PullPolicy,
 }
 
 impl ConfigOverrides {
@@ -414,10 +2013,103 @@ impl ConfigOverrides {
         self
     }
 
+    pub fn with_container_workspace_folder(mut self, path: PathBuf) -> Self {
+        self.container_workspace_folder = Some(path);
+        self
+    }
+
+    pub fn with_build_cache(mut self, cache_from: Vec<String>, cache_to: Vec<String>) -> Self {
+        self.cache_from = cache_from;
+        self.cache_to = cache_to;
+        self
+    }
+
+    pub fn with_platforms(mut self, platforms: Vec<String>) -> Self {
+        self.platforms = platforms;
+        self
+    }
+
+    pub fn with_image_names(mut self, image_names: Vec<String>, push: bool) -> Self {
+        self.image_names = image_names;
+        self.push = push;
+        self
+    }
+
     pub fn with_project_name(mut self, name: impl Into<String>) -> Self {
         self.project_name = Some(name.into());
         self
     }
+
+    pub fn with_id_labels(mut self, labels: Vec<ContainerLabel>) -> Self {
+        self.id_labels = labels;
+        self
+    }
+
+    pub fn with_env(mut self, env: BTreeMap<String, String>) -> Self {
+        self.env = env;
+        self
+    }
+
+    pub fn with_secrets(mut self, secrets: BTreeMap<String, String>) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    pub fn with_hook_users(mut self, users: Vec<HookUser>) -> Self {
+        self.hook_users = users;
+        self
+    }
+
+    pub fn with_profile(mut self, profile: Option<String>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_read_only_workspace(
+        mut self,
+        read_only: bool,
+        writable_paths: Vec<String>,
+    ) -> Self {
+        self.read_only_workspace = read_only;
+        self.writable_workspace_paths = writable_paths;
+        self
+    }
+
+    /// Mounts the named `volume` as the workspace, cloning `clone_url` into it
+    /// first; a volume named after the repository is used when only
+    /// `clone_url` is given.
+    pub fn with_workspace_volume(
+        mut self,
+        volume: Option<String>,
+        clone_url: Option<String>,
+    ) -> Self {
+        self.workspace_volume = volume;
+        self.clone_url = clone_url;
+        self
+    }
+
+    pub fn with_workspace_mount_consistency(
+        mut self,
+        consistency: Option<MountConsistency>,
+    ) -> Self {
+        self.workspace_mount_consistency = consistency;
+        self
+    }
+
+    pub fn with_lifecycle_shell(mut self, shell: Option<Vec<String>>) -> Self {
+        self.lifecycle_shell = shell;
+        self
+    }
+
+    pub fn with_skipped_features(mut self, features: Vec<String>) -> Self {
+        self.skip_features = features;
+        self
+    }
+
+    pub fn with_pull_policy(mut self, policy: PullPolicy) -> Self {
+        self.pull_policy = policy;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -487,6 +2179,233 @@ mod tests {
         );
     }
 
+    #[test]
+    fn container_labels_parse_and_override_defaults() {
+        let label: ContainerLabel = "team=platform=core".parse().expect("parse label");
+        assert_eq!(label, ContainerLabel::new("team", "platform=core"));
+        assert_eq!(label.to_string(), "team=platform=core");
+        assert!("no-separator".parse::<ContainerLabel>().is_err());
+
+        let workspace = tempdir().expect("tempdir");
+        let workspace_path = workspace.path();
+        let config_path = workspace_path.join("devcontainer.json");
+        fs::write(
+            &config_path,
+            json!({ "image": "example:image" }).to_string(),
+        )
+        .expect("write config");
+
+        let resolved = ConfigResolver::new(ConfigSource::Workspace(workspace_path.to_path_buf()))
+            .resolve()
+            .expect("resolve config");
+        assert_eq!(
+            resolved.id_labels,
+            ContainerLabel::defaults_for(workspace_path, &config_path)
+        );
+
+        let resolver = ConfigResolver::new(ConfigSource::Workspace(workspace_path.to_path_buf()))
+            .with_overrides(ConfigOverrides::default().with_id_labels(vec![label.clone()]));
+        let resolved = resolver.resolve().expect("resolve config");
+        assert_eq!(resolved.id_labels, vec![label]);
+    }
+
+    #[test]
+    fn hook_users_layer_over_remote_user() {
+        let workspace = tempdir().expect("tempdir");
+        let workspace_path = workspace.path();
+        fs::write(
+            workspace_path.join("devcontainer.json"),
+            json!({
+                "image": "example:image",
+                "remoteUser": "vscode",
+                "hookUsers": { "postCreate": "root" }
+            })
+            .to_string(),
+        )
+        .expect("write config");
+
+        let source = ConfigSource::Workspace(workspace_path.to_path_buf());
+        let resolved = ConfigResolver::new(source.clone())
+            .resolve()
+            .expect("resolve config");
+        assert_eq!(resolved.hook_user("postCreate"), Some("root"));
+        assert_eq!(resolved.hook_user("postAttach"), Some("vscode"));
+
+        let overridden = ConfigResolver::new(source)
+            .with_overrides(ConfigOverrides::default().with_hook_users(vec![
+                "admin".parse().expect("hook user"),
+                "postAttach=root".parse().expect("hook user"),
+            ]))
+            .resolve()
+            .expect("resolve config");
+        assert_eq!(overridden.hook_user("postCreate"), Some("admin"));
+        assert_eq!(overridden.hook_user("postAttach"), Some("root"));
+
+        assert!("onCreate=root".parse::<HookUser>().is_ok());
+        assert!("preBuild=root".parse::<HookUser>().is_err());
+        assert!("postCreate=".parse::<HookUser>().is_err());
+    }
+
+    #[test]
+    fn env_stack_layers_configuration_env() {
+        let workspace = tempdir().expect("tempdir");
+        let workspace_path = workspace.path();
+        fs::write(
+            workspace_path.join("devcontainer.json"),
+            json!({
+                "image": "example:image",
+                "containerEnv": { "MODE": "container", "LANG": "C.UTF-8" },
+                "remoteEnv": { "MODE": "remote", "LANG": null }
+            })
+            .to_string(),
+        )
+        .expect("write config");
+
+        let resolved = ConfigResolver::new(ConfigSource::Workspace(workspace_path.to_path_buf()))
+            .with_overrides(
+                ConfigOverrides::default()
+                    .with_env(BTreeMap::from([("MODE".to_string(), "cli".to_string())]))
+                    .with_secrets(BTreeMap::from([(
+                        "TOKEN".to_string(),
+                        "s3cret".to_string(),
+                    )])),
+            )
+            .resolve()
+            .expect("resolve config");
+
+        let stack = resolved.env_stack();
+        assert_eq!(stack.container_env()["MODE"], "container");
+        assert!(!stack.container_env().contains_key("TOKEN"));
+        assert_eq!(stack.resolve()["MODE"], "cli");
+        assert_eq!(stack.resolve()["TOKEN"], "s3cret");
+        assert!(!stack.resolve().contains_key("LANG"));
+        assert!(!serde_json::to_string(&resolved)
+            .expect("serialize")
+            .contains("s3cret"));
+    }
+
+    #[test]
+    fn resolver_applies_registered_schema_extensions() {
+        let workspace = tempdir().expect("tempdir");
+        let workspace_path = workspace.path();
+        fs::write(
+            workspace_path.join("devcontainer.json"),
+            json!({
+                "image": "example:image",
+                "customizations": { "mytool": { "port": "not-a-number" } }
+            })
+            .to_string(),
+        )
+        .expect("write config");
+
+        let source = ConfigSource::Workspace(workspace_path.to_path_buf());
+        assert!(ConfigResolver::new(source.clone()).resolve().is_ok());
+
+        let extension = SchemaExtension::customization(
+            "mytool",
+            &json!({ "properties": { "port": { "type": "integer" } } }),
+        )
+        .expect("compile extension");
+        let err = ConfigResolver::new(source.clone())
+            .with_schema_extension(extension)
+            .resolve()
+            .expect_err("extension rejects port");
+        assert!(err.to_string().contains("/customizations/mytool/port"));
+
+        let err = ConfigResolver::new(source)
+            .with_strict(true)
+            .resolve()
+            .expect_err("strict mode rejects unregistered customization");
+        assert!(err
+            .to_string()
+            .contains("unregistered customization 'mytool'"));
+    }
+
+    #[test]
+    fn customizations_are_retained_and_queryable() {
+        let workspace = tempdir().expect("tempdir");
+        let workspace_path = workspace.path();
+        fs::write(
+            workspace_path.join("devcontainer.json"),
+            json!({
+                "image": "example:image",
+                "customizations": {
+                    "vscode": { "extensions": ["rust-lang.rust-analyzer", "tamasfe.even-better-toml"] }
+                }
+            })
+            .to_string(),
+        )
+        .expect("write config");
+
+        let resolved = ConfigResolver::new(ConfigSource::Workspace(workspace_path.to_path_buf()))
+            .resolve()
+            .expect("resolve config");
+
+        assert_eq!(
+            resolved
+                .query("customizations.vscode.extensions")
+                .expect("query"),
+            json!(["rust-lang.rust-analyzer", "tamasfe.even-better-toml"])
+        );
+        assert_eq!(
+            resolved
+                .query("customizations.vscode.extensions.1")
+                .expect("query"),
+            json!("tamasfe.even-better-toml")
+        );
+        assert_eq!(
+            resolved.query("image_reference").expect("query"),
+            json!("example:image")
+        );
+        assert!(resolved.query("customizations.jetbrains").is_err());
+    }
+
+    #[test]
+    fn config_hash_changes_with_configuration() {
+        let config = ResolvedConfig {
+            project_name: "demo".into(),
+            image_reference: Some("example:1".into()),
+            ..Default::default()
+        };
+        let same = config.clone();
+        let changed = ResolvedConfig {
+            image_reference: Some("example:2".into()),
+            ..config.clone()
+        };
+
+        assert_eq!(config.config_hash(), same.config_hash());
+        assert_ne!(config.config_hash(), changed.config_hash());
+        assert_eq!(config.config_hash_label().name, LABEL_CONFIG_HASH);
+    }
+
+    #[test]
+    fn config_hash_covers_dockerfile_and_local_features() {
+        let workspace = tempdir().expect("tempdir");
+        let devcontainer_dir = workspace.path().join(".devcontainer");
+        let feature_dir = devcontainer_dir.join("tools");
+        fs::create_dir_all(&feature_dir).expect("create feature dir");
+        let dockerfile = devcontainer_dir.join("Dockerfile");
+        fs::write(&dockerfile, "FROM debian\n").expect("write Dockerfile");
+        fs::write(feature_dir.join("install.sh"), "apt-get install -y jq\n").expect("write");
+        let config = ResolvedConfig {
+            config_path: devcontainer_dir.join("devcontainer.json"),
+            dockerfile: Some(dockerfile.clone()),
+            features: serde_json::from_value(json!({ "./tools": {} })).expect("features"),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.build_inputs(),
+            vec![dockerfile.clone(), feature_dir.join("install.sh")]
+        );
+
+        let original = config.config_hash();
+        fs::write(&dockerfile, "FROM ubuntu\n").expect("edit Dockerfile");
+        let edited = config.config_hash();
+        assert_ne!(original, edited);
+        fs::write(feature_dir.join("install.sh"), "apk add jq\n").expect("edit feature");
+        assert_ne!(edited, config.config_hash());
+    }
+
     #[test]
     fn resolve_supports_parallel_post_create_commands() {
         let workspace = tempdir().expect("tempdir");
@@ -525,6 +2444,152 @@ mod tests {
         );
     }
 
+    #[test]
+    fn resolver_merges_selected_profile() {
+        let workspace = tempdir().expect("tempdir");
+        fs::write(
+            workspace.path().join("devcontainer.json"),
+            json!({
+                "image": "example:minimal",
+                "containerEnv": { "MODE": "minimal", "LANG": "C" },
+                "profiles": {
+                    "full": {
+                        "image": "example:full",
+                        "containerEnv": { "MODE": "full" },
+                        "postCreateCommand": "make setup"
+                    },
+                    "broken": { "forwardPorts": "not-a-list" }
+                }
+            })
+            .to_string(),
+        )
+        .expect("write config");
+        let resolver = |profile: Option<&str>| {
+            ConfigResolver::new(ConfigSource::Workspace(workspace.path().to_path_buf()))
+                .with_overrides(
+                    ConfigOverrides::default().with_profile(profile.map(str::to_string)),
+                )
+                .with_strict(true)
+        };
+
+        let base = resolver(None).resolve().expect("resolve base");
+        assert_eq!(base.image_reference.as_deref(), Some("example:minimal"));
+        assert!(base.post_create_command.is_none());
+
+        let full = resolver(Some("full"))
+            .resolve()
+            .expect("resolve full profile");
+        assert_eq!(full.profile.as_deref(), Some("full"));
+        assert_eq!(full.image_reference.as_deref(), Some("example:full"));
+        assert_eq!(full.container_env["MODE"], "full");
+        assert_eq!(full.container_env["LANG"], "C");
+        assert!(full.post_create_command.is_some());
+        assert_ne!(full.config_hash(), base.config_hash());
+
+        assert!(resolver(Some("broken")).resolve().is_err());
+        assert!(resolver(Some("missing")).resolve().is_err());
+    }
+
+    #[test]
+    fn discovers_every_devcontainer_configuration() {
+        let workspace = tempdir().expect("tempdir");
+        let devcontainer_dir = workspace.path().join(".devcontainer");
+        for folder in ["web", "api", "empty"] {
+            fs::create_dir_all(devcontainer_dir.join(folder)).expect("create config dir");
+        }
+        for config in [
+            devcontainer_dir.join("devcontainer.json"),
+            devcontainer_dir.join("web/devcontainer.json"),
+            devcontainer_dir.join("api/devcontainer.json"),
+        ] {
+            fs::write(config, json!({ "image": "example:image" }).to_string())
+                .expect("write config");
+        }
+
+        assert_eq!(
+            discover_config_files(workspace.path()).expect("discover configs"),
+            vec![
+                devcontainer_dir.join("devcontainer.json"),
+                devcontainer_dir.join("api/devcontainer.json"),
+                devcontainer_dir.join("web/devcontainer.json"),
+            ]
+        );
+        assert!(discover_config_files(&devcontainer_dir.join("empty"))
+            .expect("discover configs")
+            .is_empty());
+    }
+
+    #[test]
+    fn resolve_searches_parent_directories_up_to_the_git_root() {
+        let root = tempdir().expect("tempdir");
+        let project = root.path().join("project");
+        let nested = project.join("crates/app/src");
+        fs::create_dir_all(&nested).expect("create nested dir");
+        fs::create_dir_all(project.join(".devcontainer")).expect("create config dir");
+        fs::write(
+            project.join(".devcontainer/devcontainer.json"),
+            json!({ "image": "example:image" }).to_string(),
+        )
+        .expect("write config");
+
+        let resolved = ConfigResolver::new(ConfigSource::Workspace(nested.clone()))
+            .resolve()
+            .expect("config found above");
+        assert_eq!(resolved.workspace_folder, project);
+        assert_eq!(
+            resolved.config_path,
+            project.join(".devcontainer/devcontainer.json")
+        );
+
+        let repository = project.join("vendor/lib");
+        fs::create_dir_all(repository.join(".git")).expect("create git dir");
+        assert_eq!(find_workspace_root(&repository.join("src")), None);
+        assert!(ConfigResolver::new(ConfigSource::Workspace(repository))
+            .resolve()
+            .is_err());
+    }
+
+    #[test]
+    fn resolve_picks_named_configurations() {
+        let workspace = tempdir().expect("tempdir");
+        let devcontainer_dir = workspace.path().join(".devcontainer");
+        let write = |folder: &str, name: &str| {
+            fs::create_dir_all(devcontainer_dir.join(folder)).expect("create config dir");
+            fs::write(
+                devcontainer_dir.join(folder).join("devcontainer.json"),
+                json!({ "name": name, "image": "example:image" }).to_string(),
+            )
+            .expect("write config");
+        };
+        let resolve = |source: ConfigSource| ConfigResolver::new(source).resolve();
+
+        write("web", "Web");
+        let resolved = resolve(ConfigSource::Workspace(workspace.path().to_path_buf()))
+            .expect("only configuration");
+        assert_eq!(resolved.project_name, "Web");
+
+        write("api", "Api");
+        let err = resolve(ConfigSource::Workspace(workspace.path().to_path_buf()))
+            .expect_err("ambiguous");
+        assert!(err.to_string().contains("api, web"));
+        assert!(err.to_string().contains("--config-name"));
+
+        let resolved = resolve(ConfigSource::Named {
+            workspace: workspace.path().to_path_buf(),
+            name: "api".to_string(),
+        })
+        .expect("named configuration");
+        assert_eq!(resolved.project_name, "Api");
+        assert_eq!(resolved.workspace_folder, workspace.path());
+
+        let err = resolve(ConfigSource::Named {
+            workspace: workspace.path().to_path_buf(),
+            name: "docs".to_string(),
+        })
+        .expect_err("unknown name");
+        assert!(err.to_string().contains("available: api, web"));
+    }
+
     #[test]
     fn workspace_folder_from_config_is_relative_to_workspace_root() {
         let workspace = tempdir().expect("tempdir");
@@ -586,6 +2651,308 @@ mod tests {
         );
     }
 
+    #[test]
+    fn container_workspace_folder_override_must_be_absolute() {
+        let workspace = tempdir().expect("tempdir");
+        let workspace_path = workspace.path();
+        let devcontainer_dir = workspace_path.join(".devcontainer");
+        fs::create_dir_all(&devcontainer_dir).expect("create devcontainer dir");
+        fs::write(
+            devcontainer_dir.join("devcontainer.json"),
+            json!({
+                "image": "example/image",
+                "workspaceFolder": "/workspace"
+            })
+            .to_string(),
+        )
+        .expect("write config");
+
+        let overridden = ConfigResolver::new(ConfigSource::Workspace(workspace_path.to_path_buf()))
+            .with_overrides(
+                ConfigOverrides::default().with_container_workspace_folder(PathBuf::from("/src")),
+            )
+            .resolve()
+            .expect("resolve with override");
+        assert_eq!(
+            overridden.container_workspace_folder,
+            Some(PathBuf::from("/src"))
+        );
+
+        let err = ConfigResolver::new(ConfigSource::Workspace(workspace_path.to_path_buf()))
+            .with_overrides(
+                ConfigOverrides::default().with_container_workspace_folder(PathBuf::from("src")),
+            )
+            .resolve()
+            .expect_err("relative override");
+        assert!(matches!(err, DevcontainerError::Configuration(_)));
+    }
+
+    #[test]
+    fn workspace_mount_resolves_placeholders_and_sets_the_container_folder() {
+        let workspace = tempdir().expect("tempdir");
+        let workspace_path = workspace.path();
+        let devcontainer_dir = workspace_path.join(".devcontainer");
+        fs::create_dir_all(&devcontainer_dir).expect("create devcontainer dir");
+        let config_path = devcontainer_dir.join("devcontainer.json");
+        let resolve = |config: Value| {
+            fs::write(&config_path, serde_json::to_string_pretty(&config).unwrap())
+                .expect("write config");
+            ConfigResolver::new(ConfigSource::Workspace(workspace_path.to_path_buf())).resolve()
+        };
+
+        let resolved = resolve(json!({
+            "image": "example/image",
+            "workspaceMount": "source=${localWorkspaceFolder},target=/src,type=bind,consistency=cached"
+        }))
+        .expect("resolve config");
+        assert_eq!(
+            resolved.workspace_mount.as_ref().map(Mount::to_mount_arg),
+            Some(format!(
+                "source={},target=/src,type=bind,consistency=cached",
+                workspace_path.display()
+            ))
+        );
+        assert_eq!(
+            resolved.container_workspace_folder,
+            Some(PathBuf::from("/src"))
+        );
+
+        let resolved = resolve(json!({
+            "image": "example/image",
+            "workspaceMount": "type=volume,source=src,target=/volume",
+            "workspaceFolder": "/volume/project"
+        }))
+        .expect("resolve config");
+        assert_eq!(
+            resolved.container_workspace_folder,
+            Some(PathBuf::from("/volume/project"))
+        );
+
+        let err = resolve(json!({
+            "image": "example/image",
+            "workspaceMount": "type=volume,source=src"
+        }))
+        .expect_err("mount without target");
+        assert!(err.to_string().contains("workspaceMount"));
+    }
+
+    #[test]
+    fn clone_mounts_a_volume_named_after_the_repository() {
+        let workspace = tempdir().expect("tempdir");
+        let devcontainer_dir = workspace.path().join(".devcontainer");
+        fs::create_dir_all(&devcontainer_dir).expect("create devcontainer dir");
+        fs::write(
+            devcontainer_dir.join("devcontainer.json"),
+            r#"{ "image": "example/image" }"#,
+        )
+        .expect("write config");
+        let resolve = |overrides: ConfigOverrides| {
+            ConfigResolver::new(ConfigSource::Workspace(workspace.path().to_path_buf()))
+                .with_overrides(overrides)
+                .resolve()
+                .expect("resolve config")
+        };
+
+        let cloned = resolve(
+            ConfigOverrides::default()
+                .with_workspace_volume(None, Some("git@github.com:org/my.repo.git".to_string())),
+        );
+        assert_eq!(
+            cloned.workspace_volume.as_deref(),
+            Some("devcontainer-my.repo-workspace")
+        );
+        assert_eq!(
+            cloned.workspace_mount.as_ref().map(Mount::to_mount_arg),
+            Some(
+                "type=volume,source=devcontainer-my.repo-workspace,target=/workspaces/my.repo"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            cloned.container_workspace_folder,
+            Some(PathBuf::from("/workspaces/my.repo"))
+        );
+
+        let named = resolve(
+            ConfigOverrides::default()
+                .with_workspace_volume(Some("scratch".to_string()), None)
+                .with_container_workspace_folder(PathBuf::from("/src")),
+        );
+        assert_eq!(
+            named.workspace_mount.as_ref().map(Mount::to_mount_arg),
+            Some("type=volume,source=scratch,target=/src".to_string())
+        );
+        assert!(named.clone_url.is_none());
+    }
+
+    #[test]
+    fn mounts_resolve_placeholders_in_both_forms() {
+        let workspace = tempdir().expect("tempdir");
+        let workspace_path = workspace.path();
+        let devcontainer_dir = workspace_path.join(".devcontainer");
+        fs::create_dir_all(&devcontainer_dir).expect("create devcontainer dir");
+        std::env::set_var("DEVCONTAINER_TEST_MOUNT_HOME", "/home/tester");
+
+        let config = json!({
+            "image": "example/image",
+            "mounts": [
+                "source=${localEnv:DEVCONTAINER_TEST_MOUNT_HOME}/.ssh,target=/root/.ssh,type=bind",
+                { "type": "bind", "source": "${localWorkspaceFolder}/cache", "target": "/cache" },
+                { "type": "volume", "source": "${localWorkspaceFolderBasename}-node", "target": "/node_modules" },
+                { "type": "tmpfs", "target": "/tmp/scratch" },
+                { "type": "bind", "source": "${localEnv:DEVCONTAINER_TEST_MOUNT_UNSET:/opt/fallback}", "target": "/fallback" }
+            ]
+        });
+        fs::write(
+            devcontainer_dir.join("devcontainer.json"),
+            serde_json::to_string_pretty(&config).unwrap(),
+        )
+        .expect("write config");
+
+        let resolved = ConfigResolver::new(ConfigSource::Workspace(workspace_path.to_path_buf()))
+            .resolve()
+            .expect("resolve config");
+        let basename = workspace_path.file_name().unwrap().to_string_lossy();
+        let args: Vec<String> = resolved.mounts.iter().map(Mount::to_mount_arg).collect();
+        assert_eq!(
+            args,
+            vec![
+                "source=/home/tester/.ssh,target=/root/.ssh,type=bind".to_string(),
+                format!(
+                    "type=bind,source={},target=/cache",
+                    workspace_path.join("cache").display()
+                ),
+                format!("type=volume,source={basename}-node,target=/node_modules"),
+                "type=tmpfs,target=/tmp/scratch".to_string(),
+                "type=bind,source=/opt/fallback,target=/fallback".to_string(),
+            ]
+        );
+        assert_eq!(resolved.mounts[3].target(), Some("/tmp/scratch"));
+        assert_eq!(resolved.mounts[0].target(), Some("/root/.ssh"));
+    }
+
+    #[test]
+    fn run_args_are_substituted_and_validated() {
+        let workspace = tempdir().expect("tempdir");
+        let workspace_path = workspace.path();
+        let devcontainer_dir = workspace_path.join(".devcontainer");
+        fs::create_dir_all(&devcontainer_dir).expect("create devcontainer dir");
+        let config_path = devcontainer_dir.join("devcontainer.json");
+        let write = |run_args: Value| {
+            let config = json!({ "image": "example/image", "runArgs": run_args });
+            fs::write(&config_path, serde_json::to_string_pretty(&config).unwrap())
+                .expect("write config");
+            ConfigResolver::new(ConfigSource::Workspace(workspace_path.to_path_buf())).resolve()
+        };
+
+        let resolved = write(json!([
+            "--add-host=host.docker.internal:host-gateway",
+            "--shm-size",
+            "1g",
+            "--label=folder=${localWorkspaceFolderBasename}"
+        ]))
+        .expect("resolve config");
+        let basename = workspace_path.file_name().unwrap().to_string_lossy();
+        assert_eq!(
+            resolved.run_args,
+            vec![
+                "--add-host=host.docker.internal:host-gateway".to_string(),
+                "--shm-size".to_string(),
+                "1g".to_string(),
+                format!("--label=folder={basename}"),
+            ]
+        );
+
+        for rejected in [
+            json!(["--name=other"]),
+            json!(["--pid", "host"]),
+            json!(["-v", "/:/host"]),
+            json!(["--mount", "type=bind,source=/,target=/host"]),
+            json!(["--cap-add=all"]),
+        ] {
+            let err = write(rejected.clone()).expect_err("dangerous runArgs");
+            assert!(
+                matches!(&err, DevcontainerError::Configuration(message) if message.contains("runArgs")),
+                "{rejected}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn resolve_parses_container_security_properties() {
+        let workspace = tempdir().expect("tempdir");
+        let workspace_path = workspace.path();
+        let devcontainer_dir = workspace_path.join(".devcontainer");
+        fs::create_dir_all(&devcontainer_dir).expect("create devcontainer dir");
+        let config = json!({
+            "image": "example/image",
+            "init": true,
+            "privileged": true,
+            "capAdd": ["SYS_PTRACE"],
+            "securityOpt": ["seccomp=unconfined"]
+        });
+        fs::write(
+            devcontainer_dir.join("devcontainer.json"),
+            serde_json::to_string_pretty(&config).unwrap(),
+        )
+        .expect("write config");
+
+        let resolved = ConfigResolver::new(ConfigSource::Workspace(workspace_path.to_path_buf()))
+            .resolve()
+            .expect("resolve config");
+        assert!(resolved.init);
+        assert!(resolved.privileged);
+        assert_eq!(resolved.cap_add, vec!["SYS_PTRACE".to_string()]);
+        assert_eq!(
+            resolved.security_opt,
+            vec!["seccomp=unconfined".to_string()]
+        );
+        assert!(resolved.overrides_command());
+    }
+
+    #[test]
+    fn resolve_reads_build_object() {
+        let workspace = tempdir().expect("tempdir");
+        let workspace_path = workspace.path();
+        let devcontainer_dir = workspace_path.join(".devcontainer");
+        fs::create_dir_all(&devcontainer_dir).expect("create devcontainer dir");
+        let config = json!({
+            "build": {
+                "dockerfile": "Dockerfile",
+                "context": "..",
+                "args": { "WORKSPACE": "${localWorkspaceFolderBasename}" },
+                "target": "dev",
+                "cacheFrom": "ghcr.io/example/cache:latest",
+                "options": ["--network=host"]
+            }
+        });
+        fs::write(
+            devcontainer_dir.join("devcontainer.json"),
+            serde_json::to_string_pretty(&config).unwrap(),
+        )
+        .expect("write config");
+
+        let resolved = ConfigResolver::new(ConfigSource::Workspace(workspace_path.to_path_buf()))
+            .resolve()
+            .expect("resolve config");
+        let basename = workspace_path.file_name().unwrap().to_string_lossy();
+        assert_eq!(
+            resolved.dockerfile,
+            Some(devcontainer_dir.join("Dockerfile"))
+        );
+        assert_eq!(
+            resolved.build,
+            BuildOptions {
+                context: Some(devcontainer_dir.join("..")),
+                args: BTreeMap::from([("WORKSPACE".to_string(), basename.into_owned())]),
+                target: Some("dev".to_string()),
+                cache_from: vec!["ghcr.io/example/cache:latest".to_string()],
+                options: vec!["--network=host".to_string()],
+                ..Default::default()
+            }
+        );
+    }
+
     #[test]
     fn overrides_take_precedence() {
         let workspace = tempdir().expect("tempdir");
@@ -647,6 +3014,285 @@ mod tests {
         }
     }
 
+    #[test]
+    fn resolve_translates_app_port_forms() {
+        let workspace = tempdir().expect("tempdir");
+        let devcontainer_dir = workspace.path().join(".devcontainer");
+        fs::create_dir_all(&devcontainer_dir).expect("create devcontainer dir");
+        let config_path = devcontainer_dir.join("devcontainer.json");
+        let resolve = |app_port: Value| {
+            let config = json!({ "image": "example:image", "appPort": app_port });
+            fs::write(&config_path, serde_json::to_string_pretty(&config).unwrap())
+                .expect("write config");
+            ConfigResolver::new(ConfigSource::Workspace(workspace.path().to_path_buf())).resolve()
+        };
+
+        let single = resolve(json!(3000)).expect("resolve number");
+        assert_eq!(single.app_ports, ["127.0.0.1:3000:3000"]);
+
+        let mapped = resolve(json!("8080:80")).expect("resolve string");
+        assert_eq!(mapped.app_ports, ["8080:80"]);
+
+        let list = resolve(json!([5000, "127.0.0.1:6000:6000/udp"])).expect("resolve list");
+        assert_eq!(
+            list.app_ports,
+            ["127.0.0.1:5000:5000", "127.0.0.1:6000:6000/udp"]
+        );
+
+        assert!(matches!(
+            resolve(json!(" ")),
+            Err(DevcontainerError::Configuration(_))
+        ));
+    }
+
+    #[test]
+    fn resolve_parses_ports_attributes() {
+        let workspace = tempdir().expect("tempdir");
+        let devcontainer_dir = workspace.path().join(".devcontainer");
+        fs::create_dir_all(&devcontainer_dir).expect("create devcontainer dir");
+        let config = json!({
+            "image": "example:image",
+            "forwardPorts": [3000],
+            "portsAttributes": {
+                "3000": { "label": "Web", "protocol": "https", "requireLocalPort": true },
+                "40000-55000": { "onAutoForward": "ignore" }
+            },
+            "otherPortsAttributes": { "onAutoForward": "silent" }
+        });
+        fs::write(
+            devcontainer_dir.join("devcontainer.json"),
+            serde_json::to_string_pretty(&config).unwrap(),
+        )
+        .expect("write config");
+
+        let resolved = ConfigResolver::new(ConfigSource::Workspace(workspace.path().to_path_buf()))
+            .resolve()
+            .expect("resolve config");
+
+        let web = resolved.port_attributes(3000).expect("attributes of 3000");
+        assert_eq!(web.label.as_deref(), Some("Web"));
+        assert_eq!(web.protocol, Some(PortScheme::Https));
+        assert_eq!(web.on_auto_forward, OnAutoForward::Notify);
+        assert!(web.require_local_port);
+        assert_eq!(
+            resolved.port_attributes(45000).map(|a| a.on_auto_forward),
+            Some(OnAutoForward::Ignore)
+        );
+        assert_eq!(
+            resolved.port_attributes(8080).map(|a| a.on_auto_forward),
+            Some(OnAutoForward::Silent)
+        );
+    }
+
+    #[test]
+    fn resolve_parses_sidecars() {
+        let workspace = tempdir().expect("tempdir");
+        let workspace_path = workspace.path();
+        let devcontainer_dir = workspace_path.join(".devcontainer");
+        fs::create_dir_all(&devcontainer_dir).expect("create devcontainer dir");
+        let config_path = devcontainer_dir.join("devcontainer.json");
+
+        let config = json!({
+            "image": "example:image",
+            "sidecars": {
+                "db": {
+                    "image": "postgres:16",
+                    "env": { "POSTGRES_PASSWORD": "secret" },
+                    "ports": ["15432:5432"]
+                },
+                "cache": { "image": "redis:7" }
+            }
+        });
+        fs::write(&config_path, serde_json::to_string_pretty(&config).unwrap())
+            .expect("write config");
+
+        let resolver = ConfigResolver::new(ConfigSource::Workspace(workspace_path.to_path_buf()));
+        let resolved = resolver.resolve().expect("resolve config");
+
+        assert_eq!(resolved.sidecars.len(), 2);
+        assert_eq!(resolved.sidecars[0].name, "cache");
+        assert_eq!(resolved.sidecars[1].name, "db");
+        assert_eq!(resolved.sidecars[1].image, "postgres:16");
+        assert_eq!(
+            resolved.sidecars[1]
+                .env
+                .get("POSTGRES_PASSWORD")
+                .map(String::as_str),
+            Some("secret")
+        );
+        assert_eq!(resolved.sidecars[1].ports[0].local_port, 15432);
+        assert_eq!(resolved.sidecars[1].ports[0].container_port, 5432);
+    }
+
+    #[test]
+    fn resolve_rejects_invalid_sidecar_names() {
+        let workspace = tempdir().expect("tempdir");
+        let workspace_path = workspace.path();
+        let devcontainer_dir = workspace_path.join(".devcontainer");
+        fs::create_dir_all(&devcontainer_dir).expect("create devcontainer dir");
+        let config_path = devcontainer_dir.join("devcontainer.json");
+
+        let config = json!({
+            "image": "example:image",
+            "sidecars": { "bad name": { "image": "redis:7" } }
+        });
+        fs::write(&config_path, serde_json::to_string_pretty(&config).unwrap())
+            .expect("write config");
+
+        let resolver = ConfigResolver::new(ConfigSource::Workspace(workspace_path.to_path_buf()));
+        let err = resolver.resolve().expect_err("invalid sidecar name");
+        assert!(
+            matches!(err, DevcontainerError::Configuration(message) if message.contains("bad name"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_checks_forwarded_sockets() {
+        let workspace = tempdir().expect("tempdir");
+        let devcontainer_dir = workspace.path().join(".devcontainer");
+        fs::create_dir_all(&devcontainer_dir).expect("create devcontainer dir");
+        let socket = workspace.path().join("lsp.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&socket).expect("bind socket");
+        let resolve = |source: &Path| {
+            let config = json!({
+                "image": "example:image",
+                "forwardSockets": [{ "source": source, "target": "/run/lsp.sock" }]
+            });
+            fs::write(
+                devcontainer_dir.join("devcontainer.json"),
+                serde_json::to_string_pretty(&config).unwrap(),
+            )
+            .expect("write config");
+            ConfigResolver::new(ConfigSource::Workspace(workspace.path().to_path_buf())).resolve()
+        };
+
+        let resolved = resolve(&socket).expect("resolve config");
+        assert_eq!(
+            resolved.forward_sockets[0].to_mount().to_mount_arg(),
+            format!("type=bind,source={},target=/run/lsp.sock", socket.display())
+        );
+
+        let err = resolve(&devcontainer_dir).expect_err("directory is not a socket");
+        assert!(err.to_string().contains("is not a unix socket"));
+    }
+
+    #[test]
+    fn resolve_reads_lifecycle_shell_with_override() {
+        let workspace = tempdir().expect("tempdir");
+        let devcontainer_dir = workspace.path().join(".devcontainer");
+        fs::create_dir_all(&devcontainer_dir).expect("create devcontainer dir");
+        let resolve = |shell: Value, overrides: ConfigOverrides| {
+            let config = json!({ "image": "example:image", "lifecycleShell": shell });
+            fs::write(
+                devcontainer_dir.join("devcontainer.json"),
+                serde_json::to_string_pretty(&config).unwrap(),
+            )
+            .expect("write config");
+            ConfigResolver::new(ConfigSource::Workspace(workspace.path().to_path_buf()))
+                .with_overrides(overrides)
+                .resolve()
+        };
+
+        let resolved =
+            resolve(json!(["bash", "-lc"]), ConfigOverrides::default()).expect("resolve config");
+        assert_eq!(
+            resolved.command_args(&CommandArgs::String("make".to_string())),
+            vec!["bash", "-lc", "make"]
+        );
+        assert_eq!(
+            resolved.command_args(&CommandArgs::Array(vec!["make".to_string()])),
+            vec!["make"]
+        );
+
+        let overrides = ConfigOverrides::default()
+            .with_lifecycle_shell(Some(vec!["ash".to_string(), "-c".to_string()]));
+        let resolved = resolve(json!(["bash", "-lc"]), overrides).expect("resolve config");
+        assert_eq!(resolved.lifecycle_shell(), vec!["ash", "-c"]);
+
+        let err = resolve(json!([]), ConfigOverrides::default()).expect_err("empty shell");
+        assert!(err.to_string().contains("lifecycleShell"));
+    }
+
+    #[test]
+    fn resolve_checks_override_feature_install_order() {
+        let workspace = tempdir().expect("tempdir");
+        let devcontainer_dir = workspace.path().join(".devcontainer");
+        fs::create_dir_all(&devcontainer_dir).expect("create devcontainer dir");
+        let resolve = |order: Value| {
+            let config = json!({
+                "image": "example:image",
+                "features": { "ghcr.io/devcontainers/features/docker-in-docker:2": {} },
+                "overrideFeatureInstallOrder": order
+            });
+            fs::write(
+                devcontainer_dir.join("devcontainer.json"),
+                serde_json::to_string_pretty(&config).unwrap(),
+            )
+            .expect("write config");
+            ConfigResolver::new(ConfigSource::Workspace(workspace.path().to_path_buf())).resolve()
+        };
+
+        let resolved =
+            resolve(json!(["ghcr.io/devcontainers/features/docker-in-docker"])).expect("resolve");
+        assert_eq!(
+            resolved.override_feature_install_order,
+            ["ghcr.io/devcontainers/features/docker-in-docker"]
+        );
+
+        let err = resolve(json!(["ghcr.io/devcontainers/features/node"])).expect_err("unknown");
+        assert!(err
+            .to_string()
+            .contains("'ghcr.io/devcontainers/features/node', which is not in features"));
+    }
+
+    #[test]
+    fn resolve_reads_read_only_workspace_with_writable_paths() {
+        let workspace = tempdir().expect("tempdir");
+        let workspace_path = workspace.path();
+        let devcontainer_dir = workspace_path.join(".devcontainer");
+        fs::create_dir_all(&devcontainer_dir).expect("create devcontainer dir");
+        let config_path = devcontainer_dir.join("devcontainer.json");
+        let write_config = |paths: Value| {
+            let config = json!({
+                "image": "example:image",
+                "writableWorkspacePaths": paths,
+                "workspaceMountPropagation": "rslave",
+                "workspaceMountConsistency": "cached"
+            });
+            fs::write(&config_path, serde_json::to_string_pretty(&config).unwrap())
+                .expect("write config");
+        };
+
+        write_config(json!(["target/"]));
+        let resolved = ConfigResolver::new(ConfigSource::Workspace(workspace_path.to_path_buf()))
+            .with_overrides(ConfigOverrides::default().with_read_only_workspace(
+                true,
+                vec!["node_modules".to_string(), "target".to_string()],
+            ))
+            .resolve()
+            .expect("resolve config");
+        assert!(resolved.read_only_workspace);
+        assert_eq!(
+            resolved.workspace_mount_propagation,
+            Some(MountPropagation::Rslave)
+        );
+        assert_eq!(
+            resolved.workspace_mount_consistency,
+            Some(MountConsistency::Cached)
+        );
+        assert_eq!(
+            resolved.writable_workspace_paths,
+            vec![PathBuf::from("target"), PathBuf::from("node_modules")]
+        );
+
+        write_config(json!(["../outside"]));
+        let err = ConfigResolver::new(ConfigSource::Workspace(workspace_path.to_path_buf()))
+            .resolve()
+            .expect_err("path escapes the workspace");
+        assert!(err.to_string().contains("../outside"));
+    }
+
     #[test]
     fn resolve_resolves_dockerfile_path_relative_to_config() {
         let workspace = tempdir().expect("tempdir");
@@ -0,0 +1,102 @@
+use serde_json::{Map, Value};
+
+use super::merge::merge_patch;
+use crate::{errors::DevcontainerError, Result};
+
+/// Top-level property holding named variants of the configuration.
+pub(crate) const PROFILES_PROPERTY: &str = "profiles";
+
+/// Removes `profiles` from `document` and merges the selected profile over the rest.
+///
+/// Profiles use JSON merge patch semantics: objects such as `features` merge key by
+/// key, `null` removes a property and any other value replaces the base value.
+pub(crate) fn apply_profile(mut document: Value, profile: Option<&str>) -> Result<Value> {
+    let profiles = match document.as_object_mut() {
+        Some(object) => object.remove(PROFILES_PROPERTY),
+        None => None,
+    };
+
+    let Some(name) = profile else {
+        return Ok(document);
+    };
+
+    let profiles = match profiles {
+        Some(Value::Object(profiles)) => profiles,
+        Some(_) => {
+            return Err(DevcontainerError::Configuration(
+                "'profiles' must map profile names to configuration objects".into(),
+            ))
+        }
+        None => Map::new(),
+    };
+
+    let Some(overlay) = profiles.get(name) else {
+        let available: Vec<&str> = profiles.keys().map(String::as_str).collect();
+        return Err(DevcontainerError::Configuration(format!(
+            "Unknown profile '{name}'; available profiles: {}",
+            if available.is_empty() {
+                "none".to_string()
+            } else {
+                available.join(", ")
+            }
+        )));
+    };
+
+    match overlay {
+        Value::Object(object) if object.contains_key(PROFILES_PROPERTY) => {
+            Err(DevcontainerError::Configuration(format!(
+                "Profile '{name}' cannot declare nested profiles"
+            )))
+        }
+        Value::Object(_) => {
+            merge_patch(&mut document, overlay);
+            Ok(document)
+        }
+        _ => Err(DevcontainerError::Configuration(format!(
+            "Profile '{name}' must be an object"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn document() -> Value {
+        json!({
+            "image": "base:latest",
+            "features": { "ghcr.io/devcontainers/features/git:1": {} },
+            "remoteUser": "vscode",
+            "profiles": {
+                "full": {
+                    "image": "full:latest",
+                    "features": { "ghcr.io/devcontainers/features/node:1": { "version": "20" } },
+                    "remoteUser": null
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn merges_selected_profile_over_base() {
+        let merged = apply_profile(document(), Some("full")).expect("apply profile");
+        assert_eq!(
+            merged,
+            json!({
+                "image": "full:latest",
+                "features": {
+                    "ghcr.io/devcontainers/features/git:1": {},
+                    "ghcr.io/devcontainers/features/node:1": { "version": "20" }
+                }
+            })
+        );
+
+        let base = apply_profile(document(), None).expect("no profile");
+        assert_eq!(base["image"], "base:latest");
+        assert!(base.get(PROFILES_PROPERTY).is_none());
+
+        let err = apply_profile(document(), Some("minimal")).expect_err("unknown profile");
+        assert!(err.to_string().contains("available profiles: full"));
+    }
+}
@@ -0,0 +1,525 @@
+//! Fast Dockerfile checks run before a potentially long `docker build`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    path::Path,
+};
+
+use crate::{DevcontainerError, Result};
+
+const INSTRUCTIONS: &[&str] = &[
+    "ADD",
+    "ARG",
+    "CMD",
+    "COPY",
+    "ENTRYPOINT",
+    "ENV",
+    "EXPOSE",
+    "FROM",
+    "HEALTHCHECK",
+    "LABEL",
+    "MAINTAINER",
+    "ONBUILD",
+    "RUN",
+    "SHELL",
+    "STOPSIGNAL",
+    "USER",
+    "VOLUME",
+    "WORKDIR",
+];
+
+/// Build args docker defines without an `ARG` instruction.
+const PREDEFINED_ARGS: &[&str] = &[
+    "HTTP_PROXY",
+    "http_proxy",
+    "HTTPS_PROXY",
+    "https_proxy",
+    "FTP_PROXY",
+    "ftp_proxy",
+    "NO_PROXY",
+    "no_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+    "BUILDPLATFORM",
+    "BUILDOS",
+    "BUILDARCH",
+    "BUILDVARIANT",
+    "TARGETPLATFORM",
+    "TARGETOS",
+    "TARGETARCH",
+    "TARGETVARIANT",
+    "BUILDKIT_INLINE_CACHE",
+    "BUILDKIT_SYNTAX",
+];
+
+/// A problem found in a Dockerfile, with the line it starts on when it has one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DockerfileIssue {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for DockerfileIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {line}: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// One instruction, with continuation lines joined.
+struct Instruction {
+    line: usize,
+    keyword: String,
+    arguments: String,
+}
+
+/// Reads `path` and fails with every issue [`lint`] finds, each prefixed by its location.
+pub fn preflight(path: &Path, build_args: &BTreeMap<String, String>) -> Result<()> {
+    let contents = fs::read_to_string(path).map_err(|err| {
+        DevcontainerError::Configuration(format!(
+            "Failed to read Dockerfile {}: {err}",
+            path.display()
+        ))
+    })?;
+
+    let issues = lint(&contents, build_args);
+    if issues.is_empty() {
+        return Ok(());
+    }
+    let diagnostics: Vec<String> = issues
+        .iter()
+        .map(|issue| match issue.line {
+            Some(line) => format!("  {}:{line}: {}", path.display(), issue.message),
+            None => format!("  {}: {}", path.display(), issue.message),
+        })
+        .collect();
+    Err(DevcontainerError::Configuration(format!(
+        "Dockerfile {} failed preflight checks:\n{}",
+        path.display(),
+        diagnostics.join("\n")
+    )))
+}
+
+/// Checks for a leading `FROM`, unknown instructions, undeclared variables in
+/// `FROM` and `build.args` that no `ARG` consumes.
+pub fn lint(contents: &str, build_args: &BTreeMap<String, String>) -> Vec<DockerfileIssue> {
+    let instructions = parse(contents);
+    let mut issues = Vec::new();
+    let mut declared = BTreeSet::new();
+    let mut global_args = BTreeSet::new();
+    let mut seen_from = false;
+
+    for instruction in &instructions {
+        let keyword = instruction.keyword.to_ascii_uppercase();
+        if !INSTRUCTIONS.contains(&keyword.as_str()) {
+            issues.push(DockerfileIssue {
+                line: Some(instruction.line),
+                message: format!("unknown instruction `{}`", instruction.keyword),
+            });
+            continue;
+        }
+
+        match keyword.as_str() {
+            "ARG" => {
+                for name in arg_names(&instruction.arguments) {
+                    if !seen_from {
+                        global_args.insert(name.clone());
+                    }
+                    declared.insert(name);
+                }
+            }
+            "FROM" => {
+                seen_from = true;
+                let Some(image) = instruction
+                    .arguments
+                    .split_whitespace()
+                    .find(|token| !token.starts_with("--"))
+                else {
+                    issues.push(DockerfileIssue {
+                        line: Some(instruction.line),
+                        message: "`FROM` needs a base image".to_string(),
+                    });
+                    continue;
+                };
+                for name in variable_references(image) {
+                    if !global_args.contains(&name) && !PREDEFINED_ARGS.contains(&name.as_str()) {
+                        issues.push(DockerfileIssue {
+                            line: Some(instruction.line),
+                            message: format!(
+                                "`FROM` references ${name}, which no `ARG` before the first `FROM` declares"
+                            ),
+                        });
+                    }
+                }
+            }
+            _ if !seen_from => {
+                issues.push(DockerfileIssue {
+                    line: Some(instruction.line),
+                    message: format!("`{keyword}` appears before the first `FROM`"),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    if !seen_from {
+        issues.push(DockerfileIssue {
+            line: None,
+            message: "no `FROM` instruction".to_string(),
+        });
+    }
+
+    for name in build_args.keys() {
+        if !declared.contains(name) && !PREDEFINED_ARGS.contains(&name.as_str()) {
+            issues.push(DockerfileIssue {
+                line: None,
+                message: format!("build arg {name} is not declared by any `ARG` instruction"),
+            });
+        }
+    }
+
+    issues
+}
+
+/// Images the stages of a Dockerfile start from, with the `ARG`s before the
+/// first `FROM` substituted and `build_args` winning over their defaults.
+/// Earlier stages and `scratch` are left out.
+pub fn base_images(contents: &str, build_args: &BTreeMap<String, String>) -> Vec<String> {
+    let mut args = BTreeMap::new();
+    let mut stages = BTreeSet::new();
+    let mut seen_from = false;
+    let mut images = Vec::new();
+
+    for instruction in parse(contents) {
+        match instruction.keyword.to_ascii_uppercase().as_str() {
+            "ARG" if !seen_from => record_args(&instruction.arguments, build_args, &mut args),
+            "FROM" => {
+                seen_from = true;
+                let mut tokens = instruction
+                    .arguments
+                    .split_whitespace()
+                    .filter(|token| !token.starts_with("--"));
+                let Some(image) = tokens.next().map(|image| substitute(image, &args)) else {
+                    continue;
+                };
+                let earlier_stage = stages.contains(&image.to_ascii_lowercase());
+                if let (Some(keyword), Some(stage)) = (tokens.next(), tokens.next()) {
+                    if keyword.eq_ignore_ascii_case("AS") {
+                        stages.insert(stage.to_ascii_lowercase());
+                    }
+                }
+                if !earlier_stage && image != "scratch" && !images.contains(&image) {
+                    images.push(image);
+                }
+            }
+            _ => {}
+        }
+    }
+    images
+}
+
+/// Image the `target` stage of a Dockerfile, or its last stage, starts from,
+/// following stages built on earlier ones; `None` when it starts from `scratch`.
+pub fn target_base_image(
+    contents: &str,
+    build_args: &BTreeMap<String, String>,
+    target: Option<&str>,
+) -> Option<String> {
+    let mut args = BTreeMap::new();
+    let mut stages: BTreeMap<String, Option<String>> = BTreeMap::new();
+    let mut seen_from = false;
+    let mut base = None;
+
+    for instruction in parse(contents) {
+        match instruction.keyword.to_ascii_uppercase().as_str() {
+            "ARG" if !seen_from => record_args(&instruction.arguments, build_args, &mut args),
+            "FROM" => {
+                seen_from = true;
+                let mut tokens = instruction
+                    .arguments
+                    .split_whitespace()
+                    .filter(|token| !token.starts_with("--"));
+                let Some(image) = tokens.next().map(|image| substitute(image, &args)) else {
+                    continue;
+                };
+                base = match stages.get(&image.to_ascii_lowercase()) {
+                    Some(stage_base) => stage_base.clone(),
+                    None => Some(image).filter(|image| image != "scratch"),
+                };
+                if let (Some(keyword), Some(stage)) = (tokens.next(), tokens.next()) {
+                    if keyword.eq_ignore_ascii_case("AS") {
+                        if target.is_some_and(|target| target.eq_ignore_ascii_case(stage)) {
+                            return base;
+                        }
+                        stages.insert(stage.to_ascii_lowercase(), base.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    base
+}
+
+/// Records the `ARG`s declared by `arguments`, with `build_args` winning over
+/// their defaults.
+fn record_args(
+    arguments: &str,
+    build_args: &BTreeMap<String, String>,
+    args: &mut BTreeMap<String, String>,
+) {
+    for token in arguments.split_whitespace() {
+        let (name, default) = token.split_once('=').unwrap_or((token, ""));
+        let value = build_args
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| default.trim_matches('"').to_string());
+        args.insert(name.to_string(), value);
+    }
+}
+
+/// `value` with `$NAME`, `${NAME}` and `${NAME:-default}` replaced from `args`,
+/// and unknown variables by nothing, as docker does.
+fn substitute(value: &str, args: &BTreeMap<String, String>) -> String {
+    let mut result = String::new();
+    let mut rest = value;
+    while let Some(index) = rest.find('$') {
+        result.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+        if let Some(braced) = rest.strip_prefix('{') {
+            let Some(end) = braced.find('}') else {
+                rest = braced;
+                break;
+            };
+            let inner = &braced[..end];
+            let (name, default) = match inner.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (inner, None),
+            };
+            match args.get(name).filter(|value| !value.is_empty()) {
+                Some(value) => result.push_str(value),
+                None => result.push_str(default.unwrap_or_default()),
+            }
+            rest = &braced[end + 1..];
+        } else {
+            let name: String = rest
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+                .collect();
+            if let Some(value) = args.get(&name) {
+                result.push_str(value);
+            }
+            rest = &rest[name.len()..];
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+fn parse(contents: &str) -> Vec<Instruction> {
+    let mut lines = contents.lines().enumerate();
+    let mut escape = '\\';
+    let mut instructions = Vec::new();
+
+    // Parser directives are only recognised at the very top of the file.
+    let mut directives = true;
+    while let Some((index, raw)) = lines.next() {
+        let trimmed = raw.trim();
+        if directives {
+            if let Some(directive) = trimmed.strip_prefix('#') {
+                if let Some((key, value)) = directive.split_once('=') {
+                    if key.trim().eq_ignore_ascii_case("escape") {
+                        escape = value.trim().chars().next().unwrap_or(escape);
+                    }
+                    continue;
+                }
+            }
+            directives = false;
+        }
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let mut text = String::new();
+        let mut current = trimmed.to_string();
+        loop {
+            match current.strip_suffix(escape) {
+                Some(stripped) => {
+                    text.push_str(stripped);
+                    text.push(' ');
+                }
+                None => {
+                    text.push_str(&current);
+                    break;
+                }
+            }
+            // Comment lines inside a continuation are dropped, like docker does.
+            let next = lines
+                .by_ref()
+                .map(|(_, line)| line.trim())
+                .find(|line| !line.starts_with('#'));
+            match next {
+                Some(line) => current = line.to_string(),
+                None => break,
+            }
+        }
+
+        let (keyword, arguments) = text.split_once(char::is_whitespace).unwrap_or((&text, ""));
+        let arguments = arguments.trim().to_string();
+        if let Some(delimiter) = heredoc_delimiter(&arguments) {
+            lines.by_ref().find(|(_, line)| line.trim() == delimiter);
+        }
+        instructions.push(Instruction {
+            line: index + 1,
+            keyword: keyword.to_string(),
+            arguments,
+        });
+    }
+
+    instructions
+}
+
+/// The delimiter of a `<<EOF`, `<<-EOF` or quoted heredoc started by an instruction.
+fn heredoc_delimiter(arguments: &str) -> Option<String> {
+    let start = arguments.find("<<")?;
+    let rest = arguments[start + 2..].trim_start_matches('-');
+    let rest = rest.trim_start_matches(['"', '\'']);
+    let delimiter: String = rest
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect();
+    (!delimiter.is_empty()).then_some(delimiter)
+}
+
+fn arg_names(arguments: &str) -> Vec<String> {
+    arguments
+        .split_whitespace()
+        .map(|token| {
+            token
+                .split_once('=')
+                .map_or(token, |(name, _)| name)
+                .to_string()
+        })
+        .collect()
+}
+
+/// Variables referenced as `$NAME` or `${NAME}`; `${NAME:-default}` carries its own fallback.
+fn variable_references(value: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = value;
+    while let Some(index) = rest.find('$') {
+        rest = &rest[index + 1..];
+        if let Some(braced) = rest.strip_prefix('{') {
+            let Some(end) = braced.find('}') else { break };
+            let inner = &braced[..end];
+            if !inner.contains(':') {
+                names.push(inner.to_string());
+            }
+            rest = &braced[end + 1..];
+        } else {
+            let name: String = rest
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+                .collect();
+            rest = &rest[name.len()..];
+            if !name.is_empty() {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_continuations_heredocs_and_declared_args() {
+        let dockerfile = "# syntax=docker/dockerfile:1\n\
+            ARG VARIANT=bookworm\n\
+            FROM mcr.microsoft.com/devcontainers/base:${VARIANT}\n\
+            ARG NODE_VERSION\n\
+            RUN apt-get update \\\n\
+            # comments may sit inside continuations\n\
+                && apt-get install -y curl\n\
+            COPY <<EOF /etc/motd\n\
+            NOT AN INSTRUCTION\n\
+            EOF\n\
+            run echo lowercase works\n";
+        let args = BTreeMap::from([
+            ("NODE_VERSION".to_string(), "20".to_string()),
+            ("HTTP_PROXY".to_string(), "http://proxy".to_string()),
+        ]);
+
+        assert_eq!(lint(dockerfile, &args), Vec::new());
+    }
+
+    #[test]
+    fn reports_issues_with_line_numbers() {
+        let dockerfile = "RUN echo too early\n\
+            FROM base:$TAG\n\
+            RUNN make\n";
+        let args = BTreeMap::from([("VARAINT".to_string(), "bookworm".to_string())]);
+
+        let messages: Vec<String> = lint(dockerfile, &args)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "line 1: `RUN` appears before the first `FROM`",
+                "line 2: `FROM` references $TAG, which no `ARG` before the first `FROM` declares",
+                "line 3: unknown instruction `RUNN`",
+                "build arg VARAINT is not declared by any `ARG` instruction",
+            ]
+        );
+        assert_eq!(
+            lint("# just a comment\n", &BTreeMap::new()),
+            [DockerfileIssue {
+                line: None,
+                message: "no `FROM` instruction".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn lists_base_images_with_args_substituted() {
+        let contents = "\
+ARG VARIANT=bookworm
+ARG REGISTRY
+FROM --platform=$BUILDPLATFORM ${REGISTRY:-docker.io}/library/golang:1.22-${VARIANT} AS build
+FROM build AS test
+FROM scratch
+FROM mcr.microsoft.com/devcontainers/base:$VARIANT
+";
+        let build_args = BTreeMap::from([("VARIANT".to_string(), "bullseye".to_string())]);
+        assert_eq!(
+            base_images(contents, &build_args),
+            [
+                "docker.io/library/golang:1.22-bullseye",
+                "mcr.microsoft.com/devcontainers/base:bullseye",
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_the_image_the_target_stage_starts_from() {
+        let contents = "\
+ARG VARIANT=bookworm
+FROM debian:${VARIANT} AS base
+FROM base AS dev
+FROM scratch AS release
+";
+        let build_args = BTreeMap::new();
+        assert_eq!(
+            target_base_image(contents, &build_args, Some("dev")).as_deref(),
+            Some("debian:bookworm")
+        );
+        assert_eq!(target_base_image(contents, &build_args, None), None);
+    }
+}
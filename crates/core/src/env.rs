@@ -0,0 +1,228 @@
+//! Layered environment variables with the documented precedence chain.
+
+use std::collections::BTreeMap;
+
+/// Source of an environment variable, ordered from lowest to highest precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EnvLayer {
+    /// `ENV` baked into the image.
+    Image,
+    /// `containerEnv` from `devcontainer.json`.
+    ContainerEnv,
+    /// `containerEnv` contributed by installed features.
+    FeatureContainerEnv,
+    /// Environment probed from the remote user's login shell.
+    UserEnvProbe,
+    /// `remoteEnv` from `devcontainer.json`.
+    RemoteEnv,
+    /// `hookEnv` entries of the lifecycle hook being run.
+    HookEnv,
+    /// Secrets injected for the session.
+    Secrets,
+    /// `--env` values passed on the command line.
+    Cli,
+}
+
+impl EnvLayer {
+    /// Whether the layer is applied when the container is created rather than per process.
+    pub fn is_container_level(self) -> bool {
+        self <= EnvLayer::FeatureContainerEnv
+    }
+}
+
+/// Environment variables grouped by [`EnvLayer`]; higher layers win on conflicts.
+///
+/// A `None` value unsets the variable for every lower layer, matching `remoteEnv`
+/// entries set to `null`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvStack {
+    layers: BTreeMap<EnvLayer, BTreeMap<String, Option<String>>>,
+}
+
+impl EnvStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_layer<I, K, V>(mut self, layer: EnvLayer, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        for (name, value) in vars {
+            self.set(layer, name, value);
+        }
+        self
+    }
+
+    pub fn set(&mut self, layer: EnvLayer, name: impl Into<String>, value: impl Into<String>) {
+        self.layer_mut(layer)
+            .insert(name.into(), Some(value.into()));
+    }
+
+    pub fn unset(&mut self, layer: EnvLayer, name: impl Into<String>) {
+        self.layer_mut(layer).insert(name.into(), None);
+    }
+
+    /// Final environment after applying every layer in precedence order.
+    pub fn resolve(&self) -> BTreeMap<String, String> {
+        apply(self.layers.iter())
+    }
+
+    /// Layer that determines the final value (or removal) of `name`.
+    pub fn source_of(&self, name: &str) -> Option<EnvLayer> {
+        self.layers
+            .iter()
+            .rev()
+            .find(|(_, vars)| vars.contains_key(name))
+            .map(|(layer, _)| *layer)
+    }
+
+    /// Variables to pass at container creation; image env is already in the image.
+    pub fn container_env(&self) -> BTreeMap<String, String> {
+        apply(
+            self.layers
+                .iter()
+                .filter(|(layer, _)| layer.is_container_level() && **layer != EnvLayer::Image),
+        )
+    }
+
+    /// Per-process overrides applied on top of the container environment.
+    pub fn process_overrides(&self) -> BTreeMap<String, Option<String>> {
+        let mut overrides = BTreeMap::new();
+        for (_, vars) in self
+            .layers
+            .iter()
+            .filter(|(layer, _)| !layer.is_container_level())
+        {
+            overrides.extend(
+                vars.iter()
+                    .map(|(name, value)| (name.clone(), value.clone())),
+            );
+        }
+        overrides
+    }
+
+    /// Prefixes `command` with `env` so per-process overrides apply inside the container.
+    pub fn wrap_command(&self, command: &[String]) -> Vec<String> {
+        let overrides = self.process_overrides();
+        if overrides.is_empty() || command.is_empty() {
+            return command.to_vec();
+        }
+
+        let mut wrapped = vec!["env".to_string()];
+        for (name, value) in &overrides {
+            match value {
+                Some(value) => wrapped.push(format!("{name}={value}")),
+                None => {
+                    wrapped.push("-u".to_string());
+                    wrapped.push(name.clone());
+                }
+            }
+        }
+        wrapped.extend(command.iter().cloned());
+        wrapped
+    }
+
+    fn layer_mut(&mut self, layer: EnvLayer) -> &mut BTreeMap<String, Option<String>> {
+        self.layers.entry(layer).or_default()
+    }
+}
+
+fn apply<'a>(
+    layers: impl Iterator<Item = (&'a EnvLayer, &'a BTreeMap<String, Option<String>>)>,
+) -> BTreeMap<String, String> {
+    let mut env = BTreeMap::new();
+    for (_, vars) in layers {
+        for (name, value) in vars {
+            match value {
+                Some(value) => {
+                    env.insert(name.clone(), value.clone());
+                }
+                None => {
+                    env.remove(name);
+                }
+            }
+        }
+    }
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_stack() -> EnvStack {
+        let mut stack = EnvStack::new();
+        for (layer, value) in [
+            (EnvLayer::Cli, "cli"),
+            (EnvLayer::Image, "image"),
+            (EnvLayer::Secrets, "secrets"),
+            (EnvLayer::ContainerEnv, "container"),
+            (EnvLayer::RemoteEnv, "remote"),
+            (EnvLayer::FeatureContainerEnv, "feature"),
+        ] {
+            stack.set(layer, "SHARED", value);
+            stack.set(layer, format!("ONLY_{value}").to_uppercase(), value);
+        }
+        stack
+    }
+
+    #[test]
+    fn higher_layers_take_precedence() {
+        let stack = full_stack();
+        assert_eq!(stack.resolve()["SHARED"], "cli");
+        assert_eq!(stack.source_of("SHARED"), Some(EnvLayer::Cli));
+        assert_eq!(stack.source_of("ONLY_IMAGE"), Some(EnvLayer::Image));
+        assert_eq!(stack.resolve().len(), 7);
+
+        let mut partial = EnvStack::new();
+        for layer in [
+            EnvLayer::Image,
+            EnvLayer::ContainerEnv,
+            EnvLayer::FeatureContainerEnv,
+            EnvLayer::RemoteEnv,
+            EnvLayer::HookEnv,
+            EnvLayer::Secrets,
+        ] {
+            partial.set(layer, "SHARED", format!("{layer:?}"));
+            assert_eq!(partial.source_of("SHARED"), Some(layer));
+            assert_eq!(partial.resolve()["SHARED"], format!("{layer:?}"));
+        }
+    }
+
+    #[test]
+    fn splits_container_and_process_environment() {
+        let stack = full_stack();
+
+        let container = stack.container_env();
+        assert_eq!(container["SHARED"], "feature");
+        assert!(!container.contains_key("ONLY_IMAGE"));
+        assert!(!container.contains_key("ONLY_REMOTE"));
+
+        let overrides = stack.process_overrides();
+        assert_eq!(overrides["SHARED"].as_deref(), Some("cli"));
+        assert!(overrides.contains_key("ONLY_SECRETS"));
+        assert!(!overrides.contains_key("ONLY_CONTAINER"));
+    }
+
+    #[test]
+    fn unset_removes_lower_values_and_wraps_commands() {
+        let mut stack = EnvStack::new()
+            .with_layer(EnvLayer::ContainerEnv, [("DEBUG", "1"), ("LANG", "C")])
+            .with_layer(EnvLayer::RemoteEnv, [("PATH_EXTRA", "/opt/bin")]);
+        stack.unset(EnvLayer::RemoteEnv, "DEBUG");
+
+        let resolved = stack.resolve();
+        assert!(!resolved.contains_key("DEBUG"));
+        assert_eq!(resolved["LANG"], "C");
+
+        let command = vec!["make".to_string(), "test".to_string()];
+        assert_eq!(
+            stack.wrap_command(&command),
+            vec!["env", "-u", "DEBUG", "PATH_EXTRA=/opt/bin", "make", "test"]
+        );
+        assert_eq!(EnvStack::new().wrap_command(&command), command);
+    }
+}
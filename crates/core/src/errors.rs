@@ -10,6 +10,131 @@ pub enum DevcontainerError {
     Provider(String),
     #[error("unsupported feature: {0}")]
     Unsupported(String),
+    #[error("timed out: {0}")]
+    Timeout(String),
+    /// A lifecycle command ran and exited unsuccessfully.
+    #[error("{message}")]
+    HookFailed {
+        hook: String,
+        exit_code: i32,
+        message: String,
+    },
+    /// A command run in the container exited unsuccessfully.
+    #[error("command exited with status {exit_code}")]
+    CommandFailed { exit_code: i32 },
+    /// The container engine CLI works but its daemon could not be reached.
+    #[error("provider error: {0}")]
+    DaemonAccess(#[from] DaemonAccessError),
+    /// The daemon went away while a lifecycle phase ran and it could not be continued.
+    #[error("the container daemon restarted during the {phase} phase: {message}")]
+    DaemonRestarted { phase: String, message: String },
+    /// The operation was interrupted, e.g. by Ctrl-C, before it finished.
+    #[error("cancelled: {0}")]
+    Cancelled(String),
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
+
+impl DevcontainerError {
+    /// Whether the error came from losing the connection to the daemon, rather
+    /// than from the operation itself.
+    pub fn is_daemon_disconnect(&self) -> bool {
+        let message = match self {
+            DevcontainerError::DaemonAccess(DaemonAccessError::NotRunning { .. }) => return true,
+            DevcontainerError::Provider(message) => message,
+            DevcontainerError::HookFailed { message, .. } => message,
+            _ => return false,
+        };
+        let lower = message.to_ascii_lowercase();
+        matches!(
+            DaemonAccessError::from_stderr(message),
+            Some(DaemonAccessError::NotRunning { .. })
+        ) || lower.contains("unexpected eof")
+            || lower.contains("connection reset by peer")
+    }
+}
+
+/// Why the container engine daemon refused or failed a connection.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DaemonAccessError {
+    /// The daemon socket exists but the current user may not open it.
+    #[error(
+        "permission denied on the daemon socket {socket}. Add your user to the `docker` group \
+         (`sudo usermod -aG docker $USER`, then log in again), use rootless Docker, \
+         or run Podman with `--docker-path podman`"
+    )]
+    PermissionDenied { socket: String },
+    /// Nothing is listening at the daemon endpoint.
+    #[error(
+        "cannot connect to the daemon at {endpoint}. Start it (for example \
+         `sudo systemctl start docker`) or check DOCKER_HOST"
+    )]
+    NotRunning { endpoint: String },
+}
+
+impl DaemonAccessError {
+    /// Recognizes daemon connection failures in engine CLI stderr.
+    pub fn from_stderr(stderr: &str) -> Option<Self> {
+        let endpoint = || {
+            stderr
+                .split_whitespace()
+                .find(|word| word.contains("://"))
+                .map(|word| word.trim_end_matches(['.', ':', ',']).to_string())
+                .unwrap_or_else(|| "unix:///var/run/docker.sock".to_string())
+        };
+        let lower = stderr.to_ascii_lowercase();
+        if lower.contains("permission denied") && lower.contains("daemon") {
+            Some(Self::PermissionDenied { socket: endpoint() })
+        } else if lower.contains("cannot connect to the docker daemon")
+            || lower.contains("is the docker daemon running")
+            || (lower.contains("error during connect") && lower.contains("daemon"))
+        {
+            Some(Self::NotRunning {
+                endpoint: endpoint(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_daemon_connection_failures() {
+        let denied = "permission denied while trying to connect to the Docker daemon socket at \
+            unix:///var/run/docker.sock: Get \"http://%2Fvar%2Frun%2Fdocker.sock/v1.24/version\": \
+            dial unix /var/run/docker.sock: connect: permission denied";
+        assert_eq!(
+            DaemonAccessError::from_stderr(denied),
+            Some(DaemonAccessError::PermissionDenied {
+                socket: "unix:///var/run/docker.sock".to_string()
+            })
+        );
+
+        let down = "Cannot connect to the Docker daemon at unix:///run/user/1000/docker.sock. \
+            Is the docker daemon running?";
+        assert_eq!(
+            DaemonAccessError::from_stderr(down),
+            Some(DaemonAccessError::NotRunning {
+                endpoint: "unix:///run/user/1000/docker.sock".to_string()
+            })
+        );
+
+        assert_eq!(
+            DaemonAccessError::from_stderr("unknown flag: --format"),
+            None
+        );
+
+        assert!(
+            DevcontainerError::Provider(format!("Failed to exec: {down}")).is_daemon_disconnect()
+        );
+        assert!(
+            DevcontainerError::Provider("error reading from server: unexpected EOF".into())
+                .is_daemon_disconnect()
+        );
+        assert!(!DevcontainerError::Provider("No such image: demo".into()).is_daemon_disconnect());
+    }
+}
@@ -0,0 +1,680 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{errors::DevcontainerError, Result};
+
+pub mod test;
+
+pub const FEATURE_MANIFEST_FILE: &str = "devcontainer-feature.json";
+pub const FEATURE_INSTALL_SCRIPT: &str = "install.sh";
+
+/// Manifest properties a feature contributes to the merged configuration.
+const METADATA_PROPERTIES: &[&str] = &[
+    "init",
+    "privileged",
+    "capAdd",
+    "securityOpt",
+    "entrypoint",
+    "mounts",
+    "containerEnv",
+    "customizations",
+    "onCreateCommand",
+    "updateContentCommand",
+    "postCreateCommand",
+    "postStartCommand",
+    "postAttachCommand",
+];
+
+/// Feature metadata as read from `devcontainer-feature.json`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct FeatureManifest {
+    pub id: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub options: BTreeMap<String, FeatureOption>,
+    #[serde(default, rename = "containerEnv")]
+    pub container_env: BTreeMap<String, String>,
+    #[serde(default, rename = "installsAfter")]
+    pub installs_after: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct FeatureOption {
+    #[serde(default, rename = "type")]
+    pub option_type: Option<String>,
+    #[serde(default)]
+    pub default: Option<Value>,
+    #[serde(default, rename = "enum")]
+    pub allowed_values: Option<Vec<Value>>,
+    #[serde(default)]
+    pub proposals: Option<Vec<Value>>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+impl FeatureManifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path).map_err(|err| {
+            DevcontainerError::Configuration(format!("Failed to read {}: {err}", path.display()))
+        })?;
+        json5::from_str(&raw).map_err(|err| {
+            DevcontainerError::Configuration(format!(
+                "{} is not a valid feature manifest: {err}",
+                path.display()
+            ))
+        })
+    }
+
+    /// Builds the environment passed to `install.sh`, applying manifest defaults
+    /// for options that were not provided by the user.
+    pub fn install_env(&self, options: &Map<String, Value>) -> BTreeMap<String, String> {
+        let mut env = BTreeMap::new();
+
+        for (name, option) in &self.options {
+            if let Some(default) = &option.default {
+                env.insert(option_env_name(name), option_value_to_string(default));
+            }
+        }
+
+        for (name, value) in options {
+            env.insert(option_env_name(name), option_value_to_string(value));
+        }
+
+        env
+    }
+
+    /// Checks `options`, given to `feature`, against the manifest: every option
+    /// must be declared, match its type and be one of its `enum` values, and an
+    /// option with an `enum` but no default must be given. All problems are
+    /// reported in one configuration error.
+    pub fn check_options(&self, feature: &str, options: &Map<String, Value>) -> Result<()> {
+        let mut problems = Vec::new();
+        for (name, value) in options {
+            let Some(option) = self.options.get(name) else {
+                let declared: Vec<&str> = self.options.keys().map(String::as_str).collect();
+                problems.push(format!(
+                    "{name}: unknown option (declared: {})",
+                    if declared.is_empty() {
+                        "none".to_string()
+                    } else {
+                        declared.join(", ")
+                    }
+                ));
+                continue;
+            };
+            if let Some(problem) = option.check(value) {
+                problems.push(format!("{name}: {problem}"));
+            }
+        }
+        for (name, option) in &self.options {
+            if let (Some(allowed), None, false) = (
+                &option.allowed_values,
+                &option.default,
+                options.contains_key(name),
+            ) {
+                problems.push(format!(
+                    "{name}: has no default, set one of {}",
+                    join_values(allowed)
+                ));
+            }
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(DevcontainerError::Configuration(format!(
+            "Invalid options for feature {feature}: {}",
+            problems.join("; ")
+        )))
+    }
+}
+
+impl FeatureOption {
+    /// What is wrong with `value` for this option, if anything.
+    fn check(&self, value: &Value) -> Option<String> {
+        let typed = match self.option_type.as_deref() {
+            Some("boolean") => {
+                matches!(value, Value::Bool(_)) || matches!(value.as_str(), Some("true" | "false"))
+            }
+            // Numbers pass, as install scripts get every value as a string anyway.
+            Some("string") => matches!(value, Value::String(_) | Value::Number(_)),
+            _ => true,
+        };
+        if !typed {
+            return Some(format!(
+                "expected a {}, got {value}",
+                self.option_type.as_deref().unwrap_or_default()
+            ));
+        }
+        match &self.allowed_values {
+            Some(allowed)
+                if !allowed.iter().any(|candidate| {
+                    option_value_to_string(candidate) == option_value_to_string(value)
+                }) =>
+            {
+                Some(format!("{value} is not one of {}", join_values(allowed)))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn join_values(values: &[Value]) -> String {
+    values
+        .iter()
+        .map(Value::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Checks the options the configuration at `config_path` gives its local
+/// features, see [`FeatureManifest::check_options`]. A string value sets the
+/// `version` option, as the specification allows, and is ignored by features
+/// without one. Registry features are checked by their install scripts once
+/// downloaded.
+pub fn check_feature_options(config_path: &Path, features: &Map<String, Value>) -> Result<()> {
+    let config_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
+    for (id, value) in features {
+        if !id.starts_with("./") && !id.starts_with("../") {
+            continue;
+        }
+        // A missing manifest is reported where the feature is used.
+        let Ok(manifest) = FeatureManifest::load(&config_dir.join(id).join(FEATURE_MANIFEST_FILE))
+        else {
+            continue;
+        };
+        let options = match value {
+            Value::Object(options) => options.clone(),
+            Value::String(version) if manifest.options.contains_key("version") => {
+                Map::from_iter([("version".to_string(), Value::String(version.clone()))])
+            }
+            _ => Map::new(),
+        };
+        manifest.check_options(id, &options)?;
+    }
+    Ok(())
+}
+
+/// Converts a feature option id into the environment variable name expected by
+/// `install.sh`, following the features specification.
+pub fn option_env_name(option: &str) -> String {
+    let mut name: String = option
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '_' {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let leading = name
+        .chars()
+        .take_while(|ch| ch.is_ascii_digit() || *ch == '_')
+        .count();
+    if leading > 0 {
+        name.replace_range(..leading, "_");
+    }
+
+    name.to_ascii_uppercase()
+}
+
+fn option_value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Whether the feature `id` is selected by `pattern`, given either as the full id or
+/// without its version tag or digest, e.g. `ghcr.io/devcontainers/features/node`
+/// for `ghcr.io/devcontainers/features/node:1`. Local features match with or without `./`.
+pub fn feature_matches(id: &str, pattern: &str) -> bool {
+    let unversioned = |id: &str| -> String {
+        let id = id.trim_start_matches("./");
+        let name_start = id.rfind('/').map_or(0, |slash| slash + 1);
+        match id[name_start..].find([':', '@']) {
+            Some(tag) => id[..name_start + tag].to_string(),
+            None => id.to_string(),
+        }
+    };
+    let pattern = pattern.trim_start_matches("./");
+    id.trim_start_matches("./") == pattern
+        || (unversioned(pattern) == pattern && unversioned(id) == pattern)
+}
+
+/// Ids of `features` in the order they install: those `override_order` names
+/// first, in its order, then the rest after the features their `installsAfter`
+/// names, alphabetically where that leaves a choice.
+///
+/// `override_order` takes ids without version, as [`feature_matches`] accepts
+/// them, and wins over `installsAfter`. Only local features' manifests are read
+/// for `installsAfter`; others install after nothing.
+pub fn install_order(
+    config_path: &Path,
+    features: &Map<String, Value>,
+    override_order: &[String],
+) -> Result<Vec<String>> {
+    let config_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
+    let mut order: Vec<String> = Vec::with_capacity(features.len());
+    for pattern in override_order {
+        for id in features.keys() {
+            if feature_matches(id, pattern) && !order.contains(id) {
+                order.push(id.clone());
+            }
+        }
+    }
+
+    let mut pending: Vec<(&String, Vec<String>)> = features
+        .keys()
+        .filter(|id| !order.contains(id))
+        .map(|id| {
+            let after = if id.starts_with("./") || id.starts_with("../") {
+                FeatureManifest::load(&config_dir.join(id).join(FEATURE_MANIFEST_FILE))
+                    .map(|manifest| manifest.installs_after)
+                    .unwrap_or_default()
+            } else {
+                Vec::new()
+            };
+            (id, after)
+        })
+        .collect();
+    while !pending.is_empty() {
+        let installable = pending.iter().position(|(id, after)| {
+            features.keys().all(|other| {
+                other == *id
+                    || order.contains(other)
+                    || !after.iter().any(|pattern| feature_matches(other, pattern))
+            })
+        });
+        let Some(index) = installable else {
+            let ids: Vec<&str> = pending.iter().map(|(id, _)| id.as_str()).collect();
+            return Err(DevcontainerError::Configuration(format!(
+                "Features {} install after each other; order them with overrideFeatureInstallOrder",
+                ids.join(", ")
+            )));
+        };
+        order.push(pending.remove(index).0.clone());
+    }
+    Ok(order)
+}
+
+/// Configuration contributed by the `features` of the configuration at `config_path`,
+/// one entry per feature in [`install_order`], ready for
+/// [`merge_configuration`](crate::config::merge::merge_configuration).
+///
+/// Only local features (`./path` relative to the configuration) are read; others
+/// contribute once installed, through the image metadata.
+pub fn feature_metadata(
+    config_path: &Path,
+    features: &Map<String, Value>,
+    override_order: &[String],
+) -> Result<Vec<Value>> {
+    let config_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
+    let mut metadata = Vec::new();
+    for id in &install_order(config_path, features, override_order)? {
+        if !id.starts_with("./") && !id.starts_with("../") {
+            tracing::debug!(feature = %id, "Skipping metadata of non-local feature");
+            continue;
+        }
+
+        let path = config_dir.join(id).join(FEATURE_MANIFEST_FILE);
+        let raw = fs::read_to_string(&path).map_err(|err| {
+            DevcontainerError::Configuration(format!("Failed to read {}: {err}", path.display()))
+        })?;
+        let manifest: Map<String, Value> = json5::from_str(&raw).map_err(|err| {
+            DevcontainerError::Configuration(format!(
+                "{} is not a valid feature manifest: {err}",
+                path.display()
+            ))
+        })?;
+
+        let mut entry: Map<String, Value> = manifest
+            .into_iter()
+            .filter(|(key, _)| METADATA_PROPERTIES.contains(&key.as_str()))
+            .collect();
+        entry.insert("id".to_string(), Value::String(id.clone()));
+        metadata.push(Value::Object(entry));
+    }
+    Ok(metadata)
+}
+
+/// Local feature collection laid out as `src/<feature>` and `test/<feature>`.
+#[derive(Debug, Clone)]
+pub struct FeatureCollection {
+    root: PathBuf,
+}
+
+impl FeatureCollection {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn src_dir(&self) -> PathBuf {
+        self.root.join("src")
+    }
+
+    pub fn test_dir(&self) -> PathBuf {
+        self.root.join("test")
+    }
+
+    pub fn feature_dir(&self, feature: &str) -> PathBuf {
+        self.src_dir().join(feature)
+    }
+
+    pub fn feature_test_dir(&self, feature: &str) -> PathBuf {
+        self.test_dir().join(feature)
+    }
+
+    /// Lists features that have a manifest under `src/`, sorted by id.
+    pub fn discover(&self) -> Result<Vec<String>> {
+        let src = self.src_dir();
+        let entries = fs::read_dir(&src).map_err(|err| {
+            DevcontainerError::Configuration(format!(
+                "Failed to read feature collection {}: {err}",
+                src.display()
+            ))
+        })?;
+
+        let mut features = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|err| DevcontainerError::Other(err.into()))?;
+            let path = entry.path();
+            if path.is_dir() && path.join(FEATURE_MANIFEST_FILE).exists() {
+                features.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+
+        features.sort();
+        Ok(features)
+    }
+
+    pub fn manifest(&self, feature: &str) -> Result<FeatureManifest> {
+        FeatureManifest::load(&self.feature_dir(feature).join(FEATURE_MANIFEST_FILE))
+    }
+
+    /// `sha256:<hex>` over the paths and contents of every file of a feature, identifying
+    /// the exact revision installed.
+    pub fn digest(&self, feature: &str) -> Result<String> {
+        let root = self.feature_dir(feature);
+        let mut files = Vec::new();
+        let mut pending = vec![root.clone()];
+        while let Some(dir) = pending.pop() {
+            let entries = fs::read_dir(&dir).map_err(|err| {
+                DevcontainerError::Configuration(format!(
+                    "Failed to read feature directory {}: {err}",
+                    dir.display()
+                ))
+            })?;
+            for entry in entries {
+                let path = entry
+                    .map_err(|err| DevcontainerError::Other(err.into()))?
+                    .path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+
+        let mut hasher = Sha256::new();
+        for file in files {
+            let relative = file.strip_prefix(&root).unwrap_or(&file);
+            hasher.update(relative.to_string_lossy().as_bytes());
+            hasher.update([0]);
+            let contents = fs::read(&file).map_err(|err| {
+                DevcontainerError::Configuration(format!(
+                    "Failed to read {}: {err}",
+                    file.display()
+                ))
+            })?;
+            hasher.update(&contents);
+            hasher.update([0]);
+        }
+        let hex: String = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        Ok(format!("sha256:{hex}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn option_env_name_follows_spec_rules() {
+        assert_eq!(option_env_name("version"), "VERSION");
+        assert_eq!(option_env_name("install-tools"), "INSTALL_TOOLS");
+        assert_eq!(option_env_name("9lives"), "_LIVES");
+        assert_eq!(option_env_name("__private"), "_PRIVATE");
+    }
+
+    #[test]
+    fn install_env_applies_defaults_and_overrides() {
+        let manifest: FeatureManifest = serde_json::from_value(json!({
+            "id": "color",
+            "options": {
+                "favorite": { "type": "string", "default": "red" },
+                "bright": { "type": "boolean", "default": false }
+            }
+        }))
+        .expect("manifest");
+
+        let mut options = Map::new();
+        options.insert("favorite".into(), json!("green"));
+
+        let env = manifest.install_env(&options);
+        assert_eq!(env.get("FAVORITE").map(String::as_str), Some("green"));
+        assert_eq!(env.get("BRIGHT").map(String::as_str), Some("false"));
+    }
+
+    #[test]
+    fn check_options_reports_every_invalid_option() {
+        let manifest: FeatureManifest = serde_json::from_value(json!({
+            "id": "color",
+            "options": {
+                "favorite": { "type": "string", "enum": ["red", "green"], "default": "red" },
+                "bright": { "type": "boolean", "default": false },
+                "shade": { "type": "string", "enum": ["light", "dark"] }
+            }
+        }))
+        .expect("manifest");
+        let options = |value: Value| value.as_object().cloned().expect("options");
+
+        manifest
+            .check_options(
+                "./color",
+                &options(json!({ "bright": "true", "shade": "dark" })),
+            )
+            .expect("valid options");
+
+        let err = manifest
+            .check_options(
+                "./color",
+                &options(json!({ "favorite": "purple", "bright": "yes", "size": 3 })),
+            )
+            .expect_err("invalid options");
+        assert_eq!(
+            err.to_string(),
+            "configuration error: Invalid options for feature ./color: \
+             bright: expected a boolean, got \"yes\"; \
+             favorite: \"purple\" is not one of \"red\", \"green\"; \
+             size: unknown option (declared: bright, favorite, shade); \
+             shade: has no default, set one of \"light\", \"dark\""
+        );
+    }
+
+    #[test]
+    fn check_feature_options_ignores_version_shorthand_without_a_version_option() {
+        let root = tempdir().expect("tempdir");
+        let config_path = root.path().join(".devcontainer/devcontainer.json");
+        for (name, manifest) in [
+            ("plain", r#"{ "id": "plain" }"#),
+            (
+                "pinned",
+                r#"{ "id": "pinned", "options": { "version": { "type": "string", "enum": ["1", "2"], "default": "2" } } }"#,
+            ),
+        ] {
+            let feature_dir = root.path().join(".devcontainer").join(name);
+            fs::create_dir_all(&feature_dir).expect("feature dir");
+            fs::write(feature_dir.join(FEATURE_MANIFEST_FILE), manifest).expect("manifest");
+        }
+        let features = |id: &str| {
+            json!({ id: "latest" })
+                .as_object()
+                .cloned()
+                .expect("features")
+        };
+
+        check_feature_options(&config_path, &features("./plain")).expect("shorthand ignored");
+        let err = check_feature_options(&config_path, &features("./pinned"))
+            .expect_err("version is checked");
+        assert!(err.to_string().contains("\"latest\" is not one of"));
+    }
+
+    #[test]
+    fn feature_metadata_reads_local_manifests() {
+        let root = tempdir().expect("tempdir");
+        let config_path = root.path().join(".devcontainer/devcontainer.json");
+        let feature_dir = root.path().join(".devcontainer/tools");
+        fs::create_dir_all(&feature_dir).expect("feature dir");
+        fs::write(
+            feature_dir.join(FEATURE_MANIFEST_FILE),
+            r#"{ "id": "tools", "version": "1.0.0", "privileged": true, "containerEnv": { "TOOLS": "1" } }"#,
+        )
+        .expect("manifest");
+
+        let mut features = Map::new();
+        features.insert("./tools".into(), json!({}));
+        features.insert("ghcr.io/devcontainers/features/node:1".into(), json!({}));
+
+        assert_eq!(
+            feature_metadata(&config_path, &features, &[]).expect("metadata"),
+            vec![json!({ "id": "./tools", "privileged": true, "containerEnv": { "TOOLS": "1" } })]
+        );
+    }
+
+    #[test]
+    fn install_order_puts_overrides_first_then_follows_installs_after() {
+        let root = tempdir().expect("tempdir");
+        let config_path = root.path().join(".devcontainer/devcontainer.json");
+        for (name, after) in [
+            ("alpha", "./zeta"),
+            ("zeta", "ghcr.io/devcontainers/features/node"),
+        ] {
+            let dir = root.path().join(".devcontainer").join(name);
+            fs::create_dir_all(&dir).expect("feature dir");
+            fs::write(
+                dir.join(FEATURE_MANIFEST_FILE),
+                json!({ "id": name, "installsAfter": [after] }).to_string(),
+            )
+            .expect("manifest");
+        }
+        let mut features = Map::new();
+        for id in [
+            "./alpha",
+            "./zeta",
+            "ghcr.io/devcontainers/features/docker-in-docker:2",
+            "ghcr.io/devcontainers/features/node:1",
+        ] {
+            features.insert(id.into(), json!({}));
+        }
+
+        assert_eq!(
+            install_order(&config_path, &features, &[]).expect("order"),
+            [
+                "ghcr.io/devcontainers/features/docker-in-docker:2",
+                "ghcr.io/devcontainers/features/node:1",
+                "./zeta",
+                "./alpha",
+            ]
+        );
+        let overridden = [
+            "ghcr.io/devcontainers/features/docker-in-docker".to_string(),
+            "alpha".to_string(),
+        ];
+        assert_eq!(
+            install_order(&config_path, &features, &overridden).expect("order"),
+            [
+                "ghcr.io/devcontainers/features/docker-in-docker:2",
+                "./alpha",
+                "ghcr.io/devcontainers/features/node:1",
+                "./zeta",
+            ]
+        );
+
+        fs::write(
+            root.path()
+                .join(".devcontainer/zeta")
+                .join(FEATURE_MANIFEST_FILE),
+            r#"{ "id": "zeta", "installsAfter": ["./alpha"] }"#,
+        )
+        .expect("cyclic manifest");
+        let err = install_order(&config_path, &features, &[]).expect_err("cycle");
+        assert!(err.to_string().contains("./alpha, ./zeta"));
+    }
+
+    #[test]
+    fn feature_matches_ids_with_and_without_versions() {
+        let node = "ghcr.io/devcontainers/features/node:1";
+        assert!(feature_matches(node, node));
+        assert!(feature_matches(node, "ghcr.io/devcontainers/features/node"));
+        assert!(!feature_matches(
+            node,
+            "ghcr.io/devcontainers/features/node:2"
+        ));
+        assert!(!feature_matches(node, "ghcr.io/devcontainers/features/no"));
+        assert!(feature_matches("./color", "color"));
+        assert!(feature_matches(
+            "localhost:5000/features/go@sha256:ab",
+            "localhost:5000/features/go"
+        ));
+    }
+
+    #[test]
+    fn discover_lists_features_with_manifests() {
+        let root = tempdir().expect("tempdir");
+        for feature in ["beta", "alpha"] {
+            let dir = root.path().join("src").join(feature);
+            fs::create_dir_all(&dir).expect("feature dir");
+            fs::write(
+                dir.join(FEATURE_MANIFEST_FILE),
+                format!("{{\"id\": \"{feature}\"}}"),
+            )
+            .expect("manifest");
+        }
+        fs::create_dir_all(root.path().join("src/not-a-feature")).expect("stray dir");
+
+        let collection = FeatureCollection::new(root.path());
+        assert_eq!(
+            collection.discover().expect("discover"),
+            vec!["alpha".to_string(), "beta".to_string()]
+        );
+        assert_eq!(collection.manifest("alpha").expect("manifest").id, "alpha");
+    }
+}
@@ -0,0 +1,752 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use futures_util::stream::{self, StreamExt};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use super::{feature_matches, FeatureCollection, FEATURE_INSTALL_SCRIPT};
+use crate::{
+    config::ResolvedConfig,
+    provider::{
+        ExecResult, Provider, ProviderCleanupOptions, ProviderPreparation, RunningContainer,
+    },
+    DevcontainerError, Result,
+};
+
+pub const DEFAULT_BASE_IMAGE: &str = "ubuntu:focal";
+pub const AUTOGENERATED_SCENARIO: &str = "autogenerated";
+
+const SCENARIOS_FILE: &str = "scenarios.json";
+const AUTOGENERATED_TEST_SCRIPT: &str = "test.sh";
+const CONTAINER_FEATURES_DIR: &str = "/tmp/dev-container-features";
+const CONTAINER_TESTS_DIR: &str = "/tmp/dev-container-features-test";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeatureTestOptions {
+    /// Features to test; all features in the collection when empty.
+    pub features: Vec<String>,
+    pub base_image: String,
+    pub skip_scenarios: bool,
+    pub skip_autogenerated: bool,
+    pub preserve_test_containers: bool,
+    /// Features left out of every scenario, matched by [`feature_matches`].
+    pub skip_features: Vec<String>,
+    /// Directory receiving the full output of failed `install.sh` runs.
+    pub log_dir: PathBuf,
+    /// Scenarios to run by name, such as `autogenerated`; all when empty.
+    pub scenarios: Vec<String>,
+    /// Runs only cases whose `feature/scenario` contains this text.
+    pub filter: Option<String>,
+    /// Scenarios run at once, each in its own container.
+    pub concurrency: usize,
+}
+
+impl Default for FeatureTestOptions {
+    fn default() -> Self {
+        Self {
+            features: Vec::new(),
+            base_image: DEFAULT_BASE_IMAGE.to_string(),
+            skip_scenarios: false,
+            skip_autogenerated: false,
+            preserve_test_containers: false,
+            skip_features: Vec::new(),
+            log_dir: std::env::temp_dir().join("devcontainer-feature-logs"),
+            scenarios: Vec::new(),
+            filter: None,
+            concurrency: 1,
+        }
+    }
+}
+
+/// Scenario entry from `test/<feature>/scenarios.json`.
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct FeatureTestScenario {
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub features: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureTestCase {
+    pub feature: String,
+    pub scenario: String,
+    pub image: String,
+    pub features: Vec<(String, Map<String, Value>)>,
+    pub script: PathBuf,
+}
+
+impl FeatureTestCase {
+    fn selected_by(&self, options: &FeatureTestOptions) -> bool {
+        let named = options.scenarios.is_empty() || options.scenarios.contains(&self.scenario);
+        let filtered = options.filter.as_deref().map_or(true, |filter| {
+            format!("{}/{}", self.feature, self.scenario).contains(filter)
+        });
+        named && filtered
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScenarioOutcome {
+    Passed,
+    Failed { reason: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScenarioResult {
+    pub feature: String,
+    pub scenario: String,
+    pub outcome: ScenarioOutcome,
+}
+
+impl ScenarioResult {
+    pub fn passed(&self) -> bool {
+        matches!(self.outcome, ScenarioOutcome::Passed)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeatureTestReport {
+    pub results: Vec<ScenarioResult>,
+}
+
+impl FeatureTestReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|result| result.passed()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.failed() == 0
+    }
+}
+
+/// Runs feature test scenarios from a local collection inside provider containers.
+pub struct FeatureTestHarness<P: Provider> {
+    provider: P,
+    collection: FeatureCollection,
+}
+
+impl<P: Provider> FeatureTestHarness<P> {
+    pub fn new(provider: P, collection: FeatureCollection) -> Self {
+        Self {
+            provider,
+            collection,
+        }
+    }
+
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    /// Expands the selected features into the test cases that will be executed.
+    pub fn plan(&self, options: &FeatureTestOptions) -> Result<Vec<FeatureTestCase>> {
+        let available = self.collection.discover()?;
+        let selected = if options.features.is_empty() {
+            available.clone()
+        } else {
+            for feature in &options.features {
+                if !available.contains(feature) {
+                    return Err(DevcontainerError::Configuration(format!(
+                        "Feature '{feature}' not found under {}",
+                        self.collection.src_dir().display()
+                    )));
+                }
+            }
+            options.features.clone()
+        };
+        let skipped = |feature: &str| {
+            options
+                .skip_features
+                .iter()
+                .any(|skip| feature_matches(feature, skip))
+        };
+
+        let mut cases = Vec::new();
+        for feature in selected {
+            if skipped(&feature) {
+                tracing::warn!(feature = %feature, "Skipping feature tests (--skip-feature)");
+                continue;
+            }
+            let test_dir = self.collection.feature_test_dir(&feature);
+
+            let autogenerated_script = test_dir.join(AUTOGENERATED_TEST_SCRIPT);
+            if !options.skip_autogenerated && autogenerated_script.exists() {
+                cases.push(FeatureTestCase {
+                    feature: feature.clone(),
+                    scenario: AUTOGENERATED_SCENARIO.to_string(),
+                    image: options.base_image.clone(),
+                    features: vec![(feature.clone(), Map::new())],
+                    script: autogenerated_script,
+                });
+            }
+
+            let scenarios_path = test_dir.join(SCENARIOS_FILE);
+            if options.skip_scenarios || !scenarios_path.exists() {
+                continue;
+            }
+
+            for (name, scenario) in load_scenarios(&scenarios_path)? {
+                let features = scenario
+                    .features
+                    .into_iter()
+                    .map(|(id, value)| {
+                        let options = match value {
+                            Value::Object(map) => map,
+                            _ => Map::new(),
+                        };
+                        (id.trim_start_matches("./").to_string(), options)
+                    })
+                    .filter(|(id, _)| {
+                        let skip = skipped(id);
+                        if skip {
+                            tracing::warn!(feature = %id, scenario = %name, "Not installing skipped feature");
+                        }
+                        !skip
+                    })
+                    .collect();
+
+                cases.push(FeatureTestCase {
+                    feature: feature.clone(),
+                    script: test_dir.join(format!("{name}.sh")),
+                    scenario: name,
+                    image: scenario.image.unwrap_or_else(|| options.base_image.clone()),
+                    features,
+                });
+            }
+        }
+
+        cases.retain(|case| case.selected_by(options));
+        if cases.is_empty() && (!options.scenarios.is_empty() || options.filter.is_some()) {
+            return Err(DevcontainerError::Configuration(
+                "No feature test scenario matches the --scenario and --filter selection".into(),
+            ));
+        }
+        Ok(cases)
+    }
+
+    /// Runs the planned cases, up to `concurrency` at once, reporting them in plan order.
+    pub async fn run(&self, options: &FeatureTestOptions) -> Result<FeatureTestReport> {
+        let cases = self.plan(options)?;
+        tracing::info!(
+            case_count = cases.len(),
+            concurrency = options.concurrency,
+            "Running feature tests"
+        );
+
+        let results = stream::iter(&cases)
+            .map(|case| self.run_reported(case, options))
+            .buffered(options.concurrency.max(1))
+            .collect()
+            .await;
+        Ok(FeatureTestReport { results })
+    }
+
+    async fn run_reported(
+        &self,
+        case: &FeatureTestCase,
+        options: &FeatureTestOptions,
+    ) -> ScenarioResult {
+        let outcome = match self.run_case(case, options).await {
+            Ok(outcome) => outcome,
+            Err(err) => ScenarioOutcome::Failed {
+                reason: err.to_string(),
+            },
+        };
+
+        match &outcome {
+            ScenarioOutcome::Passed => tracing::info!(
+                feature = %case.feature,
+                scenario = %case.scenario,
+                "Feature test passed"
+            ),
+            ScenarioOutcome::Failed { reason } => tracing::error!(
+                feature = %case.feature,
+                scenario = %case.scenario,
+                reason = %reason,
+                "Feature test failed"
+            ),
+        }
+
+        ScenarioResult {
+            feature: case.feature.clone(),
+            scenario: case.scenario.clone(),
+            outcome,
+        }
+    }
+
+    async fn run_case(
+        &self,
+        case: &FeatureTestCase,
+        options: &FeatureTestOptions,
+    ) -> Result<ScenarioOutcome> {
+        if !case.script.exists() {
+            return Ok(ScenarioOutcome::Failed {
+                reason: format!("Test script {} does not exist", case.script.display()),
+            });
+        }
+
+        let config = ResolvedConfig {
+            project_name: format!("features-test-{}-{}", case.feature, case.scenario),
+            workspace_folder: self.collection.root().to_path_buf(),
+            config_path: self.collection.feature_test_dir(&case.feature),
+            image_reference: Some(case.image.clone()),
+            ..Default::default()
+        };
+
+        let preparation = self.provider.prepare(&config).await?;
+        let (container, outcome) = match self.start(&config, &preparation).await {
+            Ok(container) => {
+                let outcome = self.install_and_test(case, options, &container).await;
+                (Some(container), outcome)
+            }
+            Err(err) => (None, Err(err)),
+        };
+
+        if options.preserve_test_containers {
+            tracing::info!(
+                container = %preparation.container_name,
+                "Preserving feature test container"
+            );
+        } else {
+            self.clean_up(&config, &preparation, container.as_ref())
+                .await;
+        }
+
+        outcome
+    }
+
+    /// Creates and starts the scenario's container, with its networks and volumes.
+    async fn start(
+        &self,
+        config: &ResolvedConfig,
+        preparation: &ProviderPreparation,
+    ) -> Result<RunningContainer> {
+        self.provider.ensure_networks(config, preparation).await?;
+        self.provider.ensure_volumes(config, preparation).await?;
+        let image = self.provider.build_image(config, preparation).await?;
+        let container = self
+            .provider
+            .create_container(config, preparation, &image)
+            .await?;
+        self.provider.start_container(&container).await?;
+        Ok(container)
+    }
+
+    /// Removes whatever [`start`](Self::start) got to create. Failures are only
+    /// logged, so they do not hide the scenario's outcome.
+    async fn clean_up(
+        &self,
+        config: &ResolvedConfig,
+        preparation: &ProviderPreparation,
+        container: Option<&RunningContainer>,
+    ) {
+        if let Some(container) = container {
+            if let Err(err) = self
+                .provider
+                .stop_container(config, preparation, container)
+                .await
+            {
+                tracing::warn!(error = %err, "Failed to stop feature test container");
+            }
+        }
+        let cleanup = self
+            .provider
+            .cleanup(
+                config,
+                preparation,
+                &ProviderCleanupOptions {
+                    remove_volumes: true,
+                    remove_unknown: false,
+                },
+            )
+            .await;
+        if let Err(err) = cleanup {
+            tracing::warn!(
+                container = %preparation.container_name,
+                error = %err,
+                "Failed to clean up after feature test"
+            );
+        }
+    }
+
+    async fn install_and_test(
+        &self,
+        case: &FeatureTestCase,
+        options: &FeatureTestOptions,
+        container: &RunningContainer,
+    ) -> Result<ScenarioOutcome> {
+        self.exec_checked(
+            container,
+            vec![
+                "mkdir".to_string(),
+                "-p".to_string(),
+                CONTAINER_FEATURES_DIR.to_string(),
+                CONTAINER_TESTS_DIR.to_string(),
+            ],
+        )
+        .await?;
+
+        for (feature, feature_options) in &case.features {
+            let manifest = self.collection.manifest(feature)?;
+            manifest.check_options(feature, feature_options)?;
+            let destination = Path::new(CONTAINER_FEATURES_DIR).join(feature);
+            self.provider
+                .copy_into(
+                    container,
+                    &self.collection.feature_dir(feature),
+                    &destination,
+                )
+                .await?;
+
+            let command = script_command(
+                &destination,
+                FEATURE_INSTALL_SCRIPT,
+                &manifest.install_env(feature_options),
+            );
+            tracing::info!(feature = %feature, scenario = %case.scenario, "Installing feature");
+            let result = self.provider.exec(container, &command).await?;
+            if result.exit_code != 0 {
+                let digest = self.collection.digest(feature)?;
+                let options_json = Value::Object(feature_options.clone());
+                let log = options
+                    .log_dir
+                    .join(format!("{}-{}-{feature}.log", case.feature, case.scenario));
+                write_install_log(&log, feature, &digest, &options_json, &result)?;
+                tracing::error!(
+                    feature = %feature,
+                    digest = %digest,
+                    options = %options_json,
+                    log = %log.display(),
+                    "Feature install failed"
+                );
+                return Ok(ScenarioOutcome::Failed {
+                    reason: format!(
+                        "{FEATURE_INSTALL_SCRIPT} for feature '{feature}' ({digest}, options {options_json}) exited with code {}{}; full log: {}",
+                        result.exit_code,
+                        output_tail(&result.stderr),
+                        log.display()
+                    ),
+                });
+            }
+        }
+
+        let test_destination = Path::new(CONTAINER_TESTS_DIR).join(&case.feature);
+        self.provider
+            .copy_into(
+                container,
+                &self.collection.feature_test_dir(&case.feature),
+                &test_destination,
+            )
+            .await?;
+
+        let script = case
+            .script
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| AUTOGENERATED_TEST_SCRIPT.to_string());
+        let command = script_command(&test_destination, &script, &BTreeMap::new());
+        let result = self.provider.exec(container, &command).await?;
+
+        let stdout = result.stdout.trim();
+        if !stdout.is_empty() {
+            tracing::info!(scenario = %case.scenario, stdout = %stdout, "Feature test output");
+        }
+
+        if result.exit_code == 0 {
+            Ok(ScenarioOutcome::Passed)
+        } else {
+            Ok(ScenarioOutcome::Failed {
+                reason: format!(
+                    "{script} exited with code {}{}",
+                    result.exit_code,
+                    output_tail(&result.stderr)
+                ),
+            })
+        }
+    }
+
+    async fn exec_checked(&self, container: &RunningContainer, command: Vec<String>) -> Result<()> {
+        let result = self.provider.exec(container, &command).await?;
+        if result.exit_code != 0 {
+            return Err(DevcontainerError::Provider(format!(
+                "'{}' failed with exit code {}{}",
+                command.join(" "),
+                result.exit_code,
+                output_tail(&result.stderr)
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn load_scenarios(path: &Path) -> Result<BTreeMap<String, FeatureTestScenario>> {
+    let raw = fs::read_to_string(path).map_err(|err| {
+        DevcontainerError::Configuration(format!("Failed to read {}: {err}", path.display()))
+    })?;
+    json5::from_str(&raw).map_err(|err| {
+        DevcontainerError::Configuration(format!(
+            "{} is not a valid scenarios file: {err}",
+            path.display()
+        ))
+    })
+}
+
+fn script_command(directory: &Path, script: &str, env: &BTreeMap<String, String>) -> Vec<String> {
+    let mut command = vec!["env".to_string()];
+    command.extend(env.iter().map(|(key, value)| format!("{key}={value}")));
+    command.push("/bin/sh".to_string());
+    command.push("-c".to_string());
+    command.push(format!(
+        "cd '{}' && chmod +x '{script}' && './{script}'",
+        directory.display()
+    ));
+    command
+}
+
+fn write_install_log(
+    path: &Path,
+    feature: &str,
+    digest: &str,
+    options: &Value,
+    result: &ExecResult,
+) -> Result<()> {
+    let contents = format!(
+        "feature: {feature}\ndigest: {digest}\noptions: {options}\nexit code: {}\n\n--- stdout ---\n{}\n--- stderr ---\n{}\n",
+        result.exit_code, result.stdout, result.stderr
+    );
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(path, contents))
+        .map_err(|err| {
+            DevcontainerError::Configuration(format!(
+                "Failed to write feature install log {}: {err}",
+                path.display()
+            ))
+        })
+}
+
+fn output_tail(stderr: &str) -> String {
+    let stderr = stderr.trim();
+    if stderr.is_empty() {
+        String::new()
+    } else {
+        format!(" ({stderr})")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        features::FEATURE_MANIFEST_FILE,
+        provider::{
+            mock::{MockCall, MockProvider},
+            ExecResult,
+        },
+    };
+    use serde_json::json;
+    use tempfile::{tempdir, TempDir};
+
+    fn sample_collection() -> TempDir {
+        let root = tempdir().expect("tempdir");
+        let src = root.path().join("src/color");
+        let test = root.path().join("test/color");
+        fs::create_dir_all(&src).expect("src dir");
+        fs::create_dir_all(&test).expect("test dir");
+
+        fs::write(
+            src.join(FEATURE_MANIFEST_FILE),
+            json!({
+                "id": "color",
+                "options": { "favorite": { "type": "string", "default": "red" } }
+            })
+            .to_string(),
+        )
+        .expect("manifest");
+        fs::write(src.join(FEATURE_INSTALL_SCRIPT), "#!/bin/sh\n").expect("install");
+        fs::write(test.join("test.sh"), "#!/bin/sh\n").expect("test script");
+        fs::write(test.join("green.sh"), "#!/bin/sh\n").expect("scenario script");
+        fs::write(
+            test.join(SCENARIOS_FILE),
+            json!({
+                "green": {
+                    "image": "debian:bookworm",
+                    "features": { "color": { "favorite": "green" } }
+                }
+            })
+            .to_string(),
+        )
+        .expect("scenarios");
+
+        root
+    }
+
+    #[test]
+    fn plan_includes_autogenerated_and_scenarios() {
+        let root = sample_collection();
+        let harness =
+            FeatureTestHarness::new(MockProvider::new(), FeatureCollection::new(root.path()));
+
+        let cases = harness
+            .plan(&FeatureTestOptions::default())
+            .expect("plan cases");
+
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[0].scenario, AUTOGENERATED_SCENARIO);
+        assert_eq!(cases[0].image, DEFAULT_BASE_IMAGE);
+        assert_eq!(cases[1].scenario, "green");
+        assert_eq!(cases[1].image, "debian:bookworm");
+        assert_eq!(cases[1].features[0].0, "color");
+        assert_eq!(
+            cases[1].features[0].1.get("favorite"),
+            Some(&json!("green"))
+        );
+
+        let only_scenarios = harness
+            .plan(&FeatureTestOptions {
+                skip_autogenerated: true,
+                ..Default::default()
+            })
+            .expect("plan scenarios");
+        assert_eq!(only_scenarios.len(), 1);
+
+        let selected = harness
+            .plan(&FeatureTestOptions {
+                scenarios: vec!["green".into(), AUTOGENERATED_SCENARIO.into()],
+                filter: Some("color/gr".into()),
+                base_image: "alpine:3".into(),
+                ..Default::default()
+            })
+            .expect("plan selection");
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].scenario, "green");
+        let err = harness
+            .plan(&FeatureTestOptions {
+                scenarios: vec!["blue".into()],
+                ..Default::default()
+            })
+            .expect_err("no scenario matches");
+        assert!(err.to_string().contains("No feature test scenario matches"));
+    }
+
+    #[test]
+    fn plan_rejects_unknown_features() {
+        let root = sample_collection();
+        let harness =
+            FeatureTestHarness::new(MockProvider::new(), FeatureCollection::new(root.path()));
+
+        let err = harness
+            .plan(&FeatureTestOptions {
+                features: vec!["missing".into()],
+                ..Default::default()
+            })
+            .expect_err("unknown feature");
+        assert!(matches!(err, DevcontainerError::Configuration(_)));
+    }
+
+    #[tokio::test]
+    async fn run_installs_feature_and_reports_per_scenario() {
+        let root = sample_collection();
+        let provider = MockProvider::new().with_exec_handler(|command| {
+            let script = command.last().cloned().unwrap_or_default();
+            if script.contains("green.sh") {
+                ExecResult {
+                    exit_code: 1,
+                    stdout: String::new(),
+                    stderr: "wrong color".into(),
+                }
+            } else {
+                ExecResult::default()
+            }
+        });
+        let harness =
+            FeatureTestHarness::new(provider.clone(), FeatureCollection::new(root.path()));
+
+        let report = harness
+            .run(&FeatureTestOptions::default())
+            .await
+            .expect("run tests");
+
+        assert_eq!(report.passed(), 1);
+        assert_eq!(report.failed(), 1);
+        assert!(!report.is_success());
+        match &report.results[1].outcome {
+            ScenarioOutcome::Failed { reason } => assert!(reason.contains("wrong color")),
+            other => panic!("unexpected outcome: {other:?}"),
+        }
+
+        let installs: Vec<_> = provider
+            .exec_calls()
+            .into_iter()
+            .filter(|command| command.last().is_some_and(|c| c.contains("install.sh")))
+            .collect();
+        assert_eq!(installs.len(), 2);
+        assert!(installs[0].contains(&"FAVORITE=red".to_string()));
+        assert!(installs[1].contains(&"FAVORITE=green".to_string()));
+
+        let calls = provider.calls();
+        assert!(calls.contains(&MockCall::BuildImage {
+            image: "debian:bookworm".into()
+        }));
+        assert_eq!(
+            calls
+                .iter()
+                .filter(|call| matches!(call, MockCall::Cleanup { .. }))
+                .count(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_install_is_logged_and_skipped_features_are_excluded() {
+        let root = sample_collection();
+        let logs = tempdir().expect("log dir");
+        let provider = MockProvider::new().with_exec_handler(|command| {
+            if command.last().is_some_and(|c| c.contains("install.sh")) {
+                ExecResult {
+                    exit_code: 2,
+                    stdout: "fetching palette\n".into(),
+                    stderr: "palette unavailable".into(),
+                }
+            } else {
+                ExecResult::default()
+            }
+        });
+        let harness =
+            FeatureTestHarness::new(provider.clone(), FeatureCollection::new(root.path()));
+        let options = FeatureTestOptions {
+            skip_autogenerated: true,
+            log_dir: logs.path().to_path_buf(),
+            ..Default::default()
+        };
+
+        let report = harness.run(&options).await.expect("run tests");
+        let ScenarioOutcome::Failed { reason } = &report.results[0].outcome else {
+            panic!("install failure must fail the scenario");
+        };
+        let digest = FeatureCollection::new(root.path())
+            .digest("color")
+            .expect("digest");
+        assert!(reason.contains(&digest), "{reason}");
+        assert!(reason.contains(r#"{"favorite":"green"}"#), "{reason}");
+        let log = fs::read_to_string(logs.path().join("color-green-color.log")).expect("log");
+        assert!(log.contains("fetching palette"));
+        assert!(log.contains("palette unavailable"));
+
+        let skipped = harness
+            .plan(&FeatureTestOptions {
+                skip_features: vec!["color".into()],
+                ..Default::default()
+            })
+            .expect("plan");
+        assert!(skipped.is_empty());
+    }
+}
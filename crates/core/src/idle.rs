@@ -0,0 +1,129 @@
+//! Host-side supervisor stopping a devcontainer once nothing has run in it for a while.
+
+use std::time::Duration;
+
+use tokio::time::{sleep, Instant};
+
+use crate::{
+    config::ResolvedConfig,
+    provider::{Provider, ProviderPreparation, RunningContainer},
+    Result,
+};
+
+/// How often container activity is checked when no interval is configured.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Why [`IdleReaper::run`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleOutcome {
+    /// The container was idle for the whole timeout and has been stopped.
+    Stopped,
+    /// The container stopped or disappeared by other means.
+    Gone,
+}
+
+/// Stops a container after `timeout` without exec or attach sessions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdleReaper {
+    timeout: Duration,
+    poll_interval: Duration,
+}
+
+impl IdleReaper {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            poll_interval: DEFAULT_POLL_INTERVAL.min(timeout),
+        }
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Polls the container until it has been idle for the timeout, then stops it.
+    pub async fn run<P: Provider + ?Sized>(
+        &self,
+        provider: &P,
+        config: &ResolvedConfig,
+        preparation: &ProviderPreparation,
+        container: &RunningContainer,
+    ) -> Result<IdleOutcome> {
+        let mut last_active = Instant::now();
+        loop {
+            let activity = provider.container_activity(container).await?;
+            if !activity.running {
+                tracing::info!(?container.name, "Container is no longer running; idle supervision ended");
+                return Ok(IdleOutcome::Gone);
+            }
+
+            if activity.sessions > 0 {
+                last_active = Instant::now();
+            } else if last_active.elapsed() >= self.timeout {
+                tracing::info!(
+                    ?container.name,
+                    timeout = ?self.timeout,
+                    "Stopping idle devcontainer"
+                );
+                provider
+                    .stop_container(config, preparation, container)
+                    .await?;
+                return Ok(IdleOutcome::Stopped);
+            }
+
+            sleep(self.poll_interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{
+        mock::{MockCall, MockProvider},
+        ContainerActivity,
+    };
+
+    #[tokio::test]
+    async fn stops_container_once_sessions_end() {
+        let busy = ContainerActivity {
+            running: true,
+            sessions: 1,
+        };
+        let provider = MockProvider::new().with_activity(vec![busy, busy, busy]);
+        let config = ResolvedConfig::default();
+        let preparation = provider.prepare(&config).await.expect("prepare");
+        let container = RunningContainer {
+            name: Some("mock-demo".to_string()),
+            ..Default::default()
+        };
+        let reaper =
+            IdleReaper::new(Duration::from_millis(20)).with_poll_interval(Duration::from_millis(5));
+
+        let outcome = reaper
+            .run(&provider, &config, &preparation, &container)
+            .await
+            .expect("reap");
+        assert_eq!(outcome, IdleOutcome::Stopped);
+        let calls = provider.calls();
+        let polls = calls
+            .iter()
+            .filter(|call| matches!(call, MockCall::ContainerActivity { .. }))
+            .count();
+        assert!(polls > 4, "polled {polls} times");
+        assert_eq!(
+            calls.last(),
+            Some(&MockCall::StopContainer {
+                container_name: "mock-demo".to_string()
+            })
+        );
+
+        let stopped = MockProvider::new().with_activity(vec![ContainerActivity::default()]);
+        let outcome = reaper
+            .run(&stopped, &config, &preparation, &container)
+            .await
+            .expect("reap");
+        assert_eq!(outcome, IdleOutcome::Gone);
+    }
+}
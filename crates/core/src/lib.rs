@@ -1,9 +1,25 @@
 //! Core domain logic for the Devcontainer CLI.
 
+pub mod agent;
+pub mod clock;
 pub mod config;
+pub mod dockerfile;
+pub mod env;
 pub mod errors;
+pub mod features;
+pub mod idle;
 pub mod lifecycle;
+pub mod limits;
+pub mod lockfile;
+pub mod manager;
+pub mod policy;
+pub mod ports;
 pub mod provider;
+pub mod registry;
+pub mod rpc;
+pub mod sockets;
+pub mod state;
+pub mod tasks;
 pub mod telemetry;
 
-pub use crate::errors::{DevcontainerError, Result};
+pub use crate::errors::{DaemonAccessError, DevcontainerError, Result};
@@ -0,0 +1,178 @@
+use std::process::Stdio;
+
+use futures_util::future::join_all;
+
+use super::aggregate_parallel_results;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::Command,
+};
+
+use crate::{
+    config::{CommandArgs, CommandDefinition, ResolvedConfig},
+    provider::{OutputSink, OutputStream},
+    DevcontainerError, Result,
+};
+
+/// Runs a lifecycle command on the host, from the local workspace folder and with
+/// the CLI's own environment, forwarding output to `output` line by line.
+pub async fn run_host_command(
+    config: &ResolvedConfig,
+    hook: &str,
+    command: &CommandDefinition,
+    output: &OutputSink,
+) -> Result<()> {
+    match command {
+        CommandDefinition::Single(args) => run_host_args(config, hook, None, args, output).await,
+        CommandDefinition::Parallel(commands) => {
+            let results = join_all(commands.iter().map(|(name, args)| {
+                run_host_args(config, hook, Some(name.as_str()), args, output)
+            }))
+            .await;
+            aggregate_parallel_results(hook, results)
+        }
+    }
+}
+
+async fn run_host_args(
+    config: &ResolvedConfig,
+    hook: &str,
+    command_name: Option<&str>,
+    args: &CommandArgs,
+    output: &OutputSink,
+) -> Result<()> {
+    let argv = host_args(args);
+    let Some((program, program_args)) = argv.split_first() else {
+        return Ok(());
+    };
+    let output = match command_name {
+        Some(name) => output.prefixed(name),
+        None => output.clone(),
+    };
+    tracing::debug!(
+        hook,
+        command_name,
+        command = ?argv,
+        cwd = %config.workspace_folder.display(),
+        "Executing host lifecycle command"
+    );
+
+    let mut child = Command::new(program)
+        .args(program_args)
+        .current_dir(&config.workspace_folder)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| {
+            DevcontainerError::Configuration(format!(
+                "Failed to run {hook} command {program}: {err}"
+            ))
+        })?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+
+    let (_, stderr, status) = tokio::try_join!(
+        forward_lines(stdout, OutputStream::Stdout, &output),
+        forward_lines(stderr, OutputStream::Stderr, &output),
+        child.wait(),
+    )
+    .map_err(|err| DevcontainerError::Other(err.into()))?;
+
+    if status.success() {
+        return Ok(());
+    }
+
+    let code = status.code().unwrap_or(-1);
+    let mut message = match command_name {
+        Some(name) => format!("{hook} command '{name}' failed with exit code {code}"),
+        None => format!("{hook} command failed with exit code {code}"),
+    };
+    let stderr = stderr.trim();
+    if !stderr.is_empty() {
+        message.push_str(&format!(" ({stderr})"));
+    }
+    Err(DevcontainerError::HookFailed {
+        hook: hook.to_string(),
+        exit_code: code,
+        message,
+    })
+}
+
+/// Shell form commands go through the host's shell, like in the container.
+fn host_args(args: &CommandArgs) -> Vec<String> {
+    match args {
+        CommandArgs::String(command) if cfg!(windows) => {
+            vec!["cmd".to_string(), "/C".to_string(), command.clone()]
+        }
+        _ => args.to_exec_args(),
+    }
+}
+
+async fn forward_lines(
+    reader: impl AsyncRead + Unpin,
+    stream: OutputStream,
+    output: &OutputSink,
+) -> std::io::Result<String> {
+    let mut reader = BufReader::new(reader);
+    let mut captured = String::new();
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line).await? > 0 {
+        let text = String::from_utf8_lossy(&line);
+        output.send(stream, text.trim_end_matches(['\r', '\n']));
+        captured.push_str(&text);
+        line.clear();
+    }
+    Ok(captured)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn runs_in_workspace_folder_and_reports_failures() {
+        let workspace = tempdir().expect("tempdir");
+        let config = ResolvedConfig {
+            workspace_folder: workspace.path().to_path_buf(),
+            ..Default::default()
+        };
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let sink = {
+            let lines = Arc::clone(&lines);
+            OutputSink::new(move |line| lines.lock().expect("lines").push(line.line))
+        };
+
+        run_host_command(
+            &config,
+            "initialize",
+            &CommandDefinition::from_string("pwd; touch initialized"),
+            &sink,
+        )
+        .await
+        .expect("initialize command succeeds");
+        assert!(workspace.path().join("initialized").exists());
+        let cwd = lines.lock().expect("lines")[0].clone();
+        assert_eq!(
+            std::fs::canonicalize(cwd).expect("canonical cwd"),
+            std::fs::canonicalize(workspace.path()).expect("canonical workspace")
+        );
+
+        let err = run_host_command(
+            &config,
+            "initialize",
+            &CommandDefinition::from_string("echo broken >&2; exit 4"),
+            &sink,
+        )
+        .await
+        .expect_err("failing command");
+        assert!(err.to_string().contains("exit code 4 (broken)"));
+        assert!(matches!(
+            err,
+            DevcontainerError::HookFailed { exit_code: 4, .. }
+        ));
+    }
+}
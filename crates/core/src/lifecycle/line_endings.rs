@@ -0,0 +1,161 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::LifecycleHook;
+use crate::{
+    config::{CommandArgs, CommandDefinition, ResolvedConfig},
+    DevcontainerError, Result,
+};
+
+/// `.gitattributes` rule that keeps shell scripts checked out with LF endings.
+pub const GITATTRIBUTES_RULE: &str = "*.sh text eol=lf";
+
+/// Lifecycle script on the host whose contents use CRLF line endings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrlfScript {
+    pub hook: LifecycleHook,
+    pub path: PathBuf,
+}
+
+/// Finds scripts referenced by the given hooks that contain CRLF line endings.
+///
+/// Hook commands run with the workspace folder as their working directory, so
+/// relative paths and paths below the container workspace folder are mapped back
+/// to the local workspace before being inspected.
+pub fn find_crlf_scripts(config: &ResolvedConfig, hooks: &[LifecycleHook]) -> Vec<CrlfScript> {
+    let mut scripts = Vec::new();
+    for hook in hooks {
+        let Some(command) = hook.command(config) else {
+            continue;
+        };
+
+        for path in referenced_scripts(config, command) {
+            let script = CrlfScript { hook: *hook, path };
+            if has_crlf(&script.path) && !scripts.contains(&script) {
+                scripts.push(script);
+            }
+        }
+    }
+    scripts
+}
+
+/// Rewrites a file in place with LF line endings. Returns whether it changed.
+pub fn normalize_line_endings(path: &Path) -> Result<bool> {
+    let contents = fs::read(path).map_err(|err| {
+        DevcontainerError::Configuration(format!("Failed to read {}: {err}", path.display()))
+    })?;
+
+    let mut normalized = Vec::with_capacity(contents.len());
+    let mut bytes = contents.iter().peekable();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'\r' && bytes.peek() == Some(&&b'\n') {
+            continue;
+        }
+        normalized.push(byte);
+    }
+
+    if normalized.len() == contents.len() {
+        return Ok(false);
+    }
+
+    fs::write(path, normalized).map_err(|err| {
+        DevcontainerError::Configuration(format!("Failed to write {}: {err}", path.display()))
+    })?;
+    Ok(true)
+}
+
+fn referenced_scripts(config: &ResolvedConfig, command: &CommandDefinition) -> Vec<PathBuf> {
+    let commands: Vec<&CommandArgs> = match command {
+        CommandDefinition::Single(args) => vec![args],
+        CommandDefinition::Parallel(commands) => commands.values().collect(),
+    };
+
+    commands
+        .into_iter()
+        .flat_map(|args| match args {
+            CommandArgs::String(command) => command
+                .split(|c: char| c.is_whitespace() || matches!(c, ';' | '&' | '|' | '(' | ')'))
+                .map(|token| token.trim_matches(|c| c == '"' || c == '\''))
+                .filter(|token| !token.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>(),
+            CommandArgs::Array(args) => args.clone(),
+        })
+        .filter_map(|token| local_path_for(config, &token))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+fn local_path_for(config: &ResolvedConfig, token: &str) -> Option<PathBuf> {
+    for variable in ["${containerWorkspaceFolder}", "${localWorkspaceFolder}"] {
+        if let Some(rest) = token.strip_prefix(variable) {
+            return Some(config.workspace_folder.join(rest.trim_start_matches('/')));
+        }
+    }
+
+    let path = Path::new(token);
+    if path.is_relative() {
+        return Some(config.workspace_folder.join(path));
+    }
+
+    config
+        .container_workspace_folder
+        .as_ref()
+        .and_then(|folder| path.strip_prefix(folder).ok())
+        .map(|relative| config.workspace_folder.join(relative))
+}
+
+fn has_crlf(path: &Path) -> bool {
+    fs::read(path)
+        .map(|contents| contents.windows(2).any(|pair| pair == b"\r\n"))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn finds_and_normalizes_crlf_scripts() {
+        let workspace = tempdir().expect("tempdir");
+        let scripts = workspace.path().join(".devcontainer");
+        fs::create_dir_all(&scripts).expect("create scripts dir");
+        fs::write(scripts.join("setup.sh"), "#!/bin/sh\r\necho hi\r\n").expect("write script");
+        fs::write(scripts.join("attach.sh"), "#!/bin/sh\necho hi\n").expect("write script");
+
+        let config = ResolvedConfig {
+            workspace_folder: workspace.path().to_path_buf(),
+            container_workspace_folder: Some(PathBuf::from("/workspaces/app")),
+            post_create_command: Some(CommandDefinition::from_string(
+                "bash .devcontainer/setup.sh && echo done",
+            )),
+            post_attach_command: Some(CommandDefinition::from_array(vec![
+                "/workspaces/app/.devcontainer/attach.sh".to_string(),
+            ])),
+            ..Default::default()
+        };
+
+        let found = find_crlf_scripts(
+            &config,
+            &[LifecycleHook::PostCreate, LifecycleHook::PostAttach],
+        );
+        assert_eq!(
+            found,
+            vec![CrlfScript {
+                hook: LifecycleHook::PostCreate,
+                path: workspace.path().join(".devcontainer/setup.sh"),
+            }]
+        );
+
+        assert!(normalize_line_endings(&found[0].path).expect("normalize"));
+        assert_eq!(
+            fs::read_to_string(&found[0].path).expect("read"),
+            "#!/bin/sh\necho hi\n"
+        );
+        assert!(!normalize_line_endings(&found[0].path).expect("normalize"));
+        assert!(find_crlf_scripts(&config, &[LifecycleHook::PostCreate]).is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+//! Marker files recording, inside the container, which create-only hooks have
+//! finished there, so a reused container does not run them a second time.
+//!
+//! Markers live in the hook user's `~/.devcontainer`, as the reference
+//! implementation keeps them. Each holds the identifier of the container it was
+//! written in: a home directory on a volume outlives its container, and the
+//! markers it carries must not stop the hooks of the next one.
+
+use super::LifecycleHook;
+use crate::{
+    config::ResolvedConfig,
+    provider::{Provider, RunningContainer},
+};
+
+/// Directory holding the markers, relative to the hook user's home.
+const MARKER_DIR: &str = ".devcontainer";
+
+/// Path of the marker of `hook`, such as `~/.devcontainer/.onCreateCommandMarker`.
+pub fn marker_path(hook: LifecycleHook) -> String {
+    format!("$HOME/{MARKER_DIR}/.{hook}CommandMarker")
+}
+
+/// Whether `hook` has a marker showing it finished in `container`. A marker that
+/// cannot be read counts as missing, so the hook runs.
+pub async fn hook_marked<P: Provider + ?Sized>(
+    provider: &P,
+    config: &ResolvedConfig,
+    container: &RunningContainer,
+    hook: LifecycleHook,
+) -> bool {
+    let Some(identifier) = container_identifier(container) else {
+        return false;
+    };
+    let script = format!("cat \"{}\"", marker_path(hook));
+    match exec_script(provider, config, container, hook, script).await {
+        Some(contents) => contents.trim() == identifier,
+        None => false,
+    }
+}
+
+/// Writes the marker recording that `hook` finished in `container`. Failing to
+/// write it is logged: the hook only runs again on the next reuse.
+pub async fn mark_hook<P: Provider + ?Sized>(
+    provider: &P,
+    config: &ResolvedConfig,
+    container: &RunningContainer,
+    hook: LifecycleHook,
+) {
+    let Some(identifier) = container_identifier(container) else {
+        return;
+    };
+    let script = format!(
+        "mkdir -p \"$HOME/{MARKER_DIR}\" && printf '%s\\n' '{identifier}' > \"{}\"",
+        marker_path(hook)
+    );
+    if exec_script(provider, config, container, hook, script)
+        .await
+        .is_none()
+    {
+        tracing::warn!(hook = %hook, "Failed to write lifecycle hook marker");
+    }
+}
+
+fn container_identifier(container: &RunningContainer) -> Option<&str> {
+    container.id.as_deref().or(container.name.as_deref())
+}
+
+/// Standard output of `script` run as the hook's user, if it succeeded.
+async fn exec_script<P: Provider + ?Sized>(
+    provider: &P,
+    config: &ResolvedConfig,
+    container: &RunningContainer,
+    hook: LifecycleHook,
+    script: String,
+) -> Option<String> {
+    let command = vec!["/bin/sh".to_string(), "-c".to_string(), script];
+    let result = match config.hook_user(&hook.to_string()) {
+        Some(user) => provider.exec_as(container, user, &command).await,
+        None => provider.exec(container, &command).await,
+    };
+    match result {
+        Ok(result) if result.exit_code == 0 => Some(result.stdout),
+        Ok(_) => None,
+        Err(err) => {
+            tracing::debug!(hook = %hook, error = %err, "Lifecycle hook marker unavailable");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{mock::MockProvider, ExecResult};
+
+    #[tokio::test]
+    async fn markers_only_count_for_the_container_that_wrote_them() {
+        let provider = MockProvider::new().with_exec_handler(|command| ExecResult {
+            exit_code: 0,
+            stdout: if command[2].starts_with("cat") {
+                "abc123\n".to_string()
+            } else {
+                String::new()
+            },
+            stderr: String::new(),
+        });
+        let config = ResolvedConfig::default();
+        let container = RunningContainer {
+            id: Some("abc123".to_string()),
+            ..Default::default()
+        };
+        assert!(hook_marked(&provider, &config, &container, LifecycleHook::PostCreate).await);
+
+        let other = RunningContainer {
+            id: Some("def456".to_string()),
+            ..Default::default()
+        };
+        assert!(!hook_marked(&provider, &config, &other, LifecycleHook::PostCreate).await);
+
+        mark_hook(&provider, &config, &other, LifecycleHook::OnCreate).await;
+        let execs = provider.exec_calls();
+        let write = &execs.last().expect("marker written")[2];
+        assert!(write.contains("'def456'"));
+        assert!(write.ends_with("\"$HOME/.devcontainer/.onCreateCommandMarker\""));
+    }
+}
@@ -1,21 +1,60 @@
-use std::{fmt::Display, path::PathBuf};
+pub mod host;
+pub mod line_endings;
+pub mod markers;
+pub mod transcript;
+
+use std::{
+    fmt::Display,
+    future::Future,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures_util::future::join_all;
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    config::{CommandArgs, CommandDefinition, ResolvedConfig},
-    provider::{Provider, RunningContainer},
+    agent::{HelperAgent, InstalledAgent},
+    clock::ClockCheck,
+    config::{CommandArgs, CommandDefinition, GpuRequirement, ResolvedConfig},
+    limits::ResourceLimits,
+    ports,
+    provider::{
+        config_drifted, discover_containers, owned_by_workspace, CreatedResources, OutputSink,
+        Provider, ProviderPreparation, RunningContainer,
+    },
+    sockets::grant_socket_access,
+    state::{HookStatus, LifecycleState, StateStore},
+    telemetry::{PhaseProgress, ProgressEvent, ProgressHandler},
     DevcontainerError, Result,
 };
+use transcript::{output_tail, HookRun, HookTranscript, OutputCapture};
 
+const NO_INITIALIZE_COMMAND_REASON: &str = "No initialize command defined in configuration";
+const NO_ON_CREATE_COMMAND_REASON: &str = "No onCreate command defined in configuration";
+const NO_UPDATE_CONTENT_COMMAND_REASON: &str = "No updateContent command defined in configuration";
 const NO_POST_CREATE_COMMAND_REASON: &str = "No postCreate command defined in configuration";
+const NO_POST_START_COMMAND_REASON: &str = "No postStart command defined in configuration";
 const NO_POST_ATTACH_COMMAND_REASON: &str = "No postAttach command defined in configuration";
 
+/// How long a restarting daemon may take to answer again, e.g. across a Docker
+/// Desktop update or WSL restart, before the interrupted phase fails.
+const DAEMON_RESTART_TIMEOUT: Duration = Duration::from_secs(90);
+const DAEMON_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LifecyclePhase {
     Resolve,
+    Initialize,
     Build,
     Create,
     Start,
+    OnCreate,
+    UpdateContent,
     PostCreate,
+    PostStart,
     PostAttach,
 }
 
@@ -26,10 +65,14 @@ impl Display for LifecyclePhase {
             "{}",
             match self {
                 LifecyclePhase::Resolve => "resolve",
+                LifecyclePhase::Initialize => "initialize",
                 LifecyclePhase::Build => "build",
                 LifecyclePhase::Create => "create",
                 LifecyclePhase::Start => "start",
+                LifecyclePhase::OnCreate => "onCreate",
+                LifecyclePhase::UpdateContent => "updateContent",
                 LifecyclePhase::PostCreate => "postCreate",
+                LifecyclePhase::PostStart => "postStart",
                 LifecyclePhase::PostAttach => "postAttach",
             }
         )
@@ -80,14 +123,115 @@ pub enum LifecycleEventDetail {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LifecycleHook {
+    Initialize,
+    OnCreate,
+    UpdateContent,
     PostCreate,
+    PostStart,
     PostAttach,
 }
 
+impl LifecycleHook {
+    /// Hooks run inside the container, in the order the specification runs them.
+    pub const ALL: [LifecycleHook; 5] = [
+        LifecycleHook::OnCreate,
+        LifecycleHook::UpdateContent,
+        LifecycleHook::PostCreate,
+        LifecycleHook::PostStart,
+        LifecycleHook::PostAttach,
+    ];
+
+    pub fn phase(self) -> LifecyclePhase {
+        match self {
+            LifecycleHook::Initialize => LifecyclePhase::Initialize,
+            LifecycleHook::OnCreate => LifecyclePhase::OnCreate,
+            LifecycleHook::UpdateContent => LifecyclePhase::UpdateContent,
+            LifecycleHook::PostCreate => LifecyclePhase::PostCreate,
+            LifecycleHook::PostStart => LifecyclePhase::PostStart,
+            LifecycleHook::PostAttach => LifecyclePhase::PostAttach,
+        }
+    }
+
+    /// Command configured for this hook, if any.
+    pub fn command(self, config: &ResolvedConfig) -> Option<&CommandDefinition> {
+        match self {
+            LifecycleHook::Initialize => config.initialize_command.as_ref(),
+            LifecycleHook::OnCreate => config.on_create_command.as_ref(),
+            LifecycleHook::UpdateContent => config.update_content_command.as_ref(),
+            LifecycleHook::PostCreate => config.post_create_command.as_ref(),
+            LifecycleHook::PostStart => config.post_start_command.as_ref(),
+            LifecycleHook::PostAttach => config.post_attach_command.as_ref(),
+        }
+    }
+
+    /// Whether the hook only runs when the container is first created.
+    pub fn runs_on_create_only(self) -> bool {
+        matches!(
+            self,
+            LifecycleHook::OnCreate | LifecycleHook::UpdateContent | LifecycleHook::PostCreate
+        )
+    }
+
+    fn codes(self) -> (&'static str, &'static str) {
+        match self {
+            LifecycleHook::Initialize => (
+                "lifecycle.hook.initialize",
+                "lifecycle.hook.initialize.skip",
+            ),
+            LifecycleHook::OnCreate => ("lifecycle.hook.onCreate", "lifecycle.hook.onCreate.skip"),
+            LifecycleHook::UpdateContent => (
+                "lifecycle.hook.updateContent",
+                "lifecycle.hook.updateContent.skip",
+            ),
+            LifecycleHook::PostCreate => (
+                "lifecycle.hook.postCreate",
+                "lifecycle.hook.postCreate.skip",
+            ),
+            LifecycleHook::PostStart => {
+                ("lifecycle.hook.postStart", "lifecycle.hook.postStart.skip")
+            }
+            LifecycleHook::PostAttach => (
+                "lifecycle.hook.postAttach",
+                "lifecycle.hook.postAttach.skip",
+            ),
+        }
+    }
+
+    fn missing_command_reason(self) -> &'static str {
+        match self {
+            LifecycleHook::Initialize => NO_INITIALIZE_COMMAND_REASON,
+            LifecycleHook::OnCreate => NO_ON_CREATE_COMMAND_REASON,
+            LifecycleHook::UpdateContent => NO_UPDATE_CONTENT_COMMAND_REASON,
+            LifecycleHook::PostCreate => NO_POST_CREATE_COMMAND_REASON,
+            LifecycleHook::PostStart => NO_POST_START_COMMAND_REASON,
+            LifecycleHook::PostAttach => NO_POST_ATTACH_COMMAND_REASON,
+        }
+    }
+}
+
+impl FromStr for LifecycleHook {
+    type Err = DevcontainerError;
+
+    /// Accepts hook names (`postCreate`) and command properties (`postCreateCommand`).
+    fn from_str(value: &str) -> Result<Self> {
+        let name = value.strip_suffix("Command").unwrap_or(value);
+        std::iter::once(LifecycleHook::Initialize)
+            .chain(LifecycleHook::ALL)
+            .find(|hook| hook.to_string() == name)
+            .ok_or_else(|| {
+                DevcontainerError::Configuration(format!("Unknown lifecycle hook '{value}'"))
+            })
+    }
+}
+
 impl Display for LifecycleHook {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let name = match self {
+            LifecycleHook::Initialize => "initialize",
+            LifecycleHook::OnCreate => "onCreate",
+            LifecycleHook::UpdateContent => "updateContent",
             LifecycleHook::PostCreate => "postCreate",
+            LifecycleHook::PostStart => "postStart",
             LifecycleHook::PostAttach => "postAttach",
         };
         write!(f, "{name}")
@@ -127,13 +271,44 @@ impl LifecyclePlan {
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct LifecyclePlanOptions {
+    pub skip_initialize: Option<String>,
+    pub skip_on_create: Option<String>,
+    pub skip_update_content: Option<String>,
     pub skip_post_create: Option<String>,
+    pub skip_post_start: Option<String>,
     pub skip_post_attach: Option<String>,
 }
 
+impl LifecyclePlanOptions {
+    /// Skips every lifecycle hook for the same reason.
+    pub fn skip_all(reason: impl Into<String>) -> Self {
+        let reason = reason.into();
+        Self {
+            skip_initialize: Some(reason.clone()),
+            skip_on_create: Some(reason.clone()),
+            skip_update_content: Some(reason.clone()),
+            skip_post_create: Some(reason.clone()),
+            skip_post_start: Some(reason.clone()),
+            skip_post_attach: Some(reason),
+        }
+    }
+
+    fn skip_reason(&mut self, hook: LifecycleHook) -> Option<String> {
+        match hook {
+            LifecycleHook::Initialize => self.skip_initialize.take(),
+            LifecycleHook::OnCreate => self.skip_on_create.take(),
+            LifecycleHook::UpdateContent => self.skip_update_content.take(),
+            LifecycleHook::PostCreate => self.skip_post_create.take(),
+            LifecycleHook::PostStart => self.skip_post_start.take(),
+            LifecycleHook::PostAttach => self.skip_post_attach.take(),
+        }
+    }
+}
+
 impl LifecyclePlan {
     pub fn for_up(config: &ResolvedConfig, options: LifecyclePlanOptions) -> Self {
         let mut plan = LifecyclePlan::new();
+        let mut options = options;
 
         plan.push(
             LifecyclePhase::Resolve,
@@ -149,6 +324,8 @@ impl LifecyclePlan {
             ),
         );
 
+        plan.push_hook(config, &mut options, LifecycleHook::Initialize);
+
         let build_message = match &config.image_reference {
             Some(image) => format!("Ensure devcontainer image {image} is available"),
             None => "Build devcontainer image from workspace configuration".to_string(),
@@ -188,80 +365,53 @@ impl LifecyclePlan {
             ),
         );
 
-        let LifecyclePlanOptions {
-            mut skip_post_create,
-            mut skip_post_attach,
-        } = options;
-
-        let post_create_action = if let Some(reason) = skip_post_create.take() {
-            HookAction::Skip { reason }
-        } else if config.post_create_command.is_none() {
-            HookAction::Skip {
-                reason: NO_POST_CREATE_COMMAND_REASON.to_string(),
-            }
-        } else {
-            HookAction::Execute
-        };
+        for hook in LifecycleHook::ALL {
+            plan.push_hook(config, &mut options, hook);
+        }
 
-        let post_create_code = match &post_create_action {
-            HookAction::Execute => "lifecycle.hook.postCreate",
-            HookAction::Skip { .. } => "lifecycle.hook.postCreate.skip",
-        };
+        plan
+    }
 
-        let post_create_message = match &post_create_action {
-            HookAction::Execute => "Run postCreate lifecycle hook".to_string(),
-            HookAction::Skip { reason } => {
-                format!("Skip postCreate lifecycle hook ({reason})")
+    /// Plan of only `hooks`, for running them again in an existing container.
+    pub fn for_hooks(config: &ResolvedConfig, hooks: &[LifecycleHook]) -> Self {
+        let mut plan = LifecyclePlan::new();
+        let mut options = LifecyclePlanOptions::default();
+        for hook in LifecycleHook::ALL {
+            if hooks.contains(&hook) {
+                plan.push_hook(config, &mut options, hook);
             }
-        };
-
-        plan.push(
-            LifecyclePhase::PostCreate,
-            LifecycleEvent::new(
-                post_create_code,
-                post_create_message,
-                LifecycleEventDetail::Hook {
-                    hook: LifecycleHook::PostCreate,
-                    action: post_create_action.clone(),
-                },
-            ),
-        );
+        }
+        plan
+    }
 
-        let post_attach_action = if let Some(reason) = skip_post_attach.take() {
+    fn push_hook(
+        &mut self,
+        config: &ResolvedConfig,
+        options: &mut LifecyclePlanOptions,
+        hook: LifecycleHook,
+    ) {
+        let action = if let Some(reason) = options.skip_reason(hook) {
             HookAction::Skip { reason }
-        } else if config.post_attach_command.is_none() {
+        } else if hook.command(config).is_none() {
             HookAction::Skip {
-                reason: NO_POST_ATTACH_COMMAND_REASON.to_string(),
+                reason: hook.missing_command_reason().to_string(),
             }
         } else {
             HookAction::Execute
         };
 
-        let post_attach_code = match &post_attach_action {
-            HookAction::Execute => "lifecycle.hook.postAttach",
-            HookAction::Skip { .. } => "lifecycle.hook.postAttach.skip",
-        };
-
-        let post_attach_message = match &post_attach_action {
-            HookAction::Execute => "Run postAttach lifecycle hook".to_string(),
+        let (execute_code, skip_code) = hook.codes();
+        let (code, message) = match &action {
+            HookAction::Execute => (execute_code, format!("Run {hook} lifecycle hook")),
             HookAction::Skip { reason } => {
-                format!("Skip postAttach lifecycle hook ({reason})")
+                (skip_code, format!("Skip {hook} lifecycle hook ({reason})"))
             }
         };
 
-        plan.push(
-            LifecyclePhase::PostAttach,
-            LifecycleEvent::new(
-                post_attach_code,
-                post_attach_message,
-                LifecycleEventDetail::Hook {
-                    hook: LifecycleHook::PostAttach,
-                    action: post_attach_action,
-                },
-            ),
+        self.push(
+            hook.phase(),
+            LifecycleEvent::new(code, message, LifecycleEventDetail::Hook { hook, action }),
         );
-
-        plan
     }
 }
 
@@ -269,15 +419,159 @@ impl LifecyclePlan {
 pub struct LifecycleOutcome {
     pub container: RunningContainer,
     pub executed_phases: Vec<LifecyclePhase>,
+    /// Whether an existing container with an unchanged configuration was reused.
+    pub reused: bool,
+    /// Workspace folder inside the container.
+    pub remote_workspace_folder: PathBuf,
+    /// Hooks after `waitFor` left for [`LifecycleExecutor::continue_pending`].
+    pub pending_hooks: Vec<LifecycleHook>,
+    /// Helper agent installed in the container, when one was requested and works there.
+    pub agent: Option<InstalledAgent>,
 }
 
 pub struct LifecycleExecutor<P: Provider> {
     provider: P,
+    fix_line_endings: bool,
+    remove_existing_container: bool,
+    output: Option<OutputSink>,
+    helper_agent: Option<HelperAgent>,
+    wait_for: Option<LifecycleHook>,
+    state: Option<StateStore>,
+    resume: bool,
+    clock_check: Option<ClockCheck>,
+    transcript: HookTranscript,
+    ignore_host_requirements: bool,
+    progress: Option<Arc<ProgressHandler>>,
+    cancellation: Option<CancellationToken>,
+    keep_on_failure: bool,
+    prebuild: Option<String>,
+}
+
+/// What an `up` began to create instead of reusing.
+struct PartialUp {
+    preparation: ProviderPreparation,
+    created: CreatedResources,
 }
 
 impl<P: Provider> LifecycleExecutor<P> {
     pub fn new(provider: P) -> Self {
-        Self { provider }
+        Self {
+            provider,
+            fix_line_endings: false,
+            remove_existing_container: false,
+            output: None,
+            helper_agent: None,
+            wait_for: None,
+            state: None,
+            resume: false,
+            clock_check: None,
+            transcript: HookTranscript::new(),
+            ignore_host_requirements: false,
+            progress: None,
+            cancellation: None,
+            prebuild: None,
+            keep_on_failure: false,
+        }
+    }
+
+    /// Returns after `hook` instead of the configuration's `waitFor`.
+    pub fn with_wait_for(mut self, hook: Option<LifecycleHook>) -> Self {
+        self.wait_for = hook;
+        self
+    }
+
+    /// Records hook progress, including hooks deferred by `waitFor`, in `store`.
+    pub fn with_state_store(mut self, store: StateStore) -> Self {
+        self.state = Some(store);
+        self
+    }
+
+    /// Continues from the state store's record of an earlier run: the recorded image
+    /// is reused when it still exists, and hooks that finished in the reused
+    /// container are not run again.
+    pub fn with_resume(mut self, enabled: bool) -> Self {
+        self.resume = enabled;
+        self
+    }
+
+    /// Checks the container clock against the host's once it has started, before
+    /// hooks that may need TLS run.
+    pub fn with_clock_check(mut self, check: Option<ClockCheck>) -> Self {
+        self.clock_check = check;
+        self
+    }
+
+    /// Records every container hook command run into `transcript`, with the full
+    /// output written next to the state store's lifecycle state when there is one.
+    pub fn with_transcript(mut self, transcript: HookTranscript) -> Self {
+        self.transcript = transcript;
+        self
+    }
+
+    /// Only warns when the host misses the configuration's `hostRequirements`
+    /// instead of failing before the build.
+    pub fn with_ignore_host_requirements(mut self, enabled: bool) -> Self {
+        self.ignore_host_requirements = enabled;
+        self
+    }
+
+    /// Installs the helper agent once the container has started, before hooks run.
+    pub fn with_helper_agent(mut self, agent: Option<HelperAgent>) -> Self {
+        self.helper_agent = agent;
+        self
+    }
+
+    /// Sends hook output to `output` as it is produced instead of logging it per hook.
+    pub fn with_output_sink(mut self, output: OutputSink) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// Also sends the progress events of each phase to `handler`, for embedders
+    /// that do not read them from the log.
+    pub fn with_progress_handler(
+        mut self,
+        handler: impl Fn(&ProgressEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Arc::new(handler));
+        self
+    }
+
+    /// Aborts [`execute`](Self::execute) once `token` is cancelled, stopping the
+    /// engine command in flight and removing the container and networks it had
+    /// begun to create, then failing with [`DevcontainerError::Cancelled`].
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Keeps the container, networks and volumes a failed [`execute`](Self::execute)
+    /// created, e.g. to inspect them or continue with resume, instead of
+    /// removing them. Cancellation removes them regardless.
+    pub fn with_keep_on_failure(mut self, enabled: bool) -> Self {
+        self.keep_on_failure = enabled;
+        self
+    }
+
+    /// Commits the container to the image `tag` once its hooks ran, for use as a
+    /// prebuild. `updateContent` runs again in a reused container, to refresh
+    /// the content the image is built with; the plan should skip the hooks that
+    /// belong to the container rather than the image, from `postCreate` on.
+    pub fn with_prebuild(mut self, tag: Option<String>) -> Self {
+        self.prebuild = tag;
+        self
+    }
+
+    /// Always recreates the container, even when its configuration is unchanged.
+    pub fn with_remove_existing_container(mut self, enabled: bool) -> Self {
+        self.remove_existing_container = enabled;
+        self
+    }
+
+    /// Rewrites CRLF lifecycle scripts to LF before hooks run instead of only warning.
+    pub fn with_line_ending_fix(mut self, enabled: bool) -> Self {
+        self.fix_line_endings = enabled;
+        self
     }
 
     pub fn provider(&self) -> &P {
@@ -288,9 +582,65 @@ impl<P: Provider> LifecycleExecutor<P> {
         &self,
         config: &ResolvedConfig,
         plan: &LifecyclePlan,
+    ) -> Result<LifecycleOutcome> {
+        let partial = Mutex::new(None);
+        let result = match &self.cancellation {
+            Some(token) => tokio::select! {
+                biased;
+                () = token.cancelled() => Err(cancelled()),
+                result = self.execute_up(config, plan, &partial) => result,
+            },
+            None => self.execute_up(config, plan, &partial).await,
+        };
+        // The engine command may see the interrupt first and fail on its own.
+        let interrupted = self
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled);
+        let err = match result {
+            Ok(outcome) => return Ok(outcome),
+            Err(_) if interrupted => cancelled(),
+            Err(err) => err,
+        };
+        match partial.into_inner().expect("rollback lock") {
+            Some(partial) if interrupted || !self.keep_on_failure => {
+                self.roll_back(config, &partial).await;
+            }
+            Some(partial) => tracing::warn!(
+                container = %partial.preparation.container_name,
+                "Keeping the failed devcontainer for inspection"
+            ),
+            None => {}
+        }
+        Err(err)
+    }
+
+    /// Runs `up`, leaving in `partial` what it began to create rather than reuse.
+    async fn execute_up(
+        &self,
+        config: &ResolvedConfig,
+        plan: &LifecyclePlan,
+        partial: &Mutex<Option<PartialUp>>,
     ) -> Result<LifecycleOutcome> {
         tracing::info!("Starting lifecycle execution");
+        let capabilities = self.provider.capabilities();
+        if self.prebuild.is_some() && !capabilities.supports_commit {
+            return Err(DevcontainerError::Unsupported(format!(
+                "{:?} provider cannot commit containers, which --prebuild needs",
+                self.provider.kind()
+            )));
+        }
         let mut executed_phases = Vec::new();
+        let wait_for = match self.wait_for {
+            Some(hook) => Some(hook),
+            None => config.wait_for.as_deref().map(str::parse).transpose()?,
+        };
+        let previous = if self.resume {
+            self.load_state(config)
+        } else {
+            None
+        };
+        self.record(config, |state| *state = LifecycleState::default());
 
         tracing::debug!(
             ?config,
@@ -309,16 +659,46 @@ impl<P: Provider> LifecycleExecutor<P> {
             );
         }
 
-        if let Some(step) = plan.step_for_phase(LifecyclePhase::Resolve) {
-            tracing::info!(
-                phase = %step.phase,
-                code = step.event.code,
-                message = %step.event.message,
-                "Executing lifecycle phase"
-            );
-        }
+        let progress = plan
+            .step_for_phase(LifecyclePhase::Resolve)
+            .map(|step| self.begin_phase(step));
         let preparation = self.provider.prepare(config).await?;
+        self.check_line_endings(config, plan)?;
+        let forwarded_ports = if capabilities.supports_port_forwarding {
+            ports::forward(config)?
+        } else {
+            if !config.forward_ports.is_empty() {
+                tracing::warn!(
+                    provider = ?self.provider.kind(),
+                    "Provider cannot publish ports; not forwarding forwardPorts"
+                );
+            }
+            Vec::new()
+        };
         executed_phases.push(LifecyclePhase::Resolve);
+        succeed(progress);
+
+        if let Some(step) = plan.step_for_phase(LifecyclePhase::Initialize) {
+            let progress = self.begin_phase(step);
+            if let LifecycleEventDetail::Hook { action, .. } = &step.event.detail {
+                let hook = LifecycleHook::Initialize;
+                if previous
+                    .as_ref()
+                    .is_some_and(|state| state.hook_done("initialize"))
+                {
+                    self.skip_completed_hook(config, hook, RESUMED_REASON);
+                } else {
+                    self.record(config, |state| {
+                        state.set("initialize", HookStatus::Running, None)
+                    });
+                    let result = self.handle_host_hook(hook, action, config).await;
+                    self.record_result(config, hook, action, &result);
+                    result?;
+                }
+            }
+            executed_phases.push(LifecyclePhase::Initialize);
+            progress.succeed();
+        }
 
         tracing::debug!(
             container_name = %preparation.container_name,
@@ -330,168 +710,787 @@ impl<P: Provider> LifecycleExecutor<P> {
             "Provider preparation complete"
         );
 
-        self.provider.ensure_networks(config, &preparation).await?;
-        self.provider.ensure_volumes(config, &preparation).await?;
-
-        if let Some(step) = plan.step_for_phase(LifecyclePhase::Build) {
-            tracing::info!(
-                phase = %step.phase,
-                code = step.event.code,
-                message = %step.event.message,
-                "Executing lifecycle phase"
-            );
-        }
-        let image_reference = self.provider.build_image(config, &preparation).await?;
-        executed_phases.push(LifecyclePhase::Build);
+        let reusable = self.find_reusable_container(config, &preparation).await?;
+        let reused = reusable.is_some();
+        let container = match reusable {
+            Some(container) => {
+                tracing::info!(
+                    container = ?container.name,
+                    "Reusing existing devcontainer; configuration is unchanged"
+                );
+                container
+            }
+            None => {
+                for existing in discover_containers(&self.provider, &config.id_labels).await? {
+                    if !self.remove_existing_container
+                        && config_drifted(&self.provider, config, &existing).await
+                    {
+                        tracing::warn!(
+                            container = ?existing.name,
+                            "The devcontainer no longer matches its configuration; recreating it"
+                        );
+                    }
+                    if existing.name.as_deref() == Some(preparation.container_name.as_str()) {
+                        continue;
+                    }
+                    if owned_by_workspace(&self.provider, config, &existing).await? {
+                        tracing::info!(
+                            container = ?existing.name,
+                            "Removing stale devcontainer found by label"
+                        );
+                        self.provider.remove_container(&existing, false).await?;
+                    } else {
+                        tracing::warn!(
+                            container = ?existing.name,
+                            "Leaving a container with the same id labels that belongs to another workspace"
+                        );
+                    }
+                }
 
-        if let Some(step) = plan.step_for_phase(LifecyclePhase::Create) {
-            tracing::info!(
-                phase = %step.phase,
-                code = step.event.code,
-                message = %step.event.message,
-                "Executing lifecycle phase"
-            );
-        }
-        let container = self
-            .provider
-            .create_container(config, &preparation, &image_reference)
-            .await?;
-        executed_phases.push(LifecyclePhase::Create);
+                self.check_host_requirements(config).await?;
+                *partial.lock().expect("rollback lock") = Some(PartialUp {
+                    preparation: preparation.clone(),
+                    created: CreatedResources::default(),
+                });
+                if capabilities.supports_networks {
+                    let networks = self.provider.ensure_networks(config, &preparation).await?;
+                    if let Some(partial) = partial.lock().expect("rollback lock").as_mut() {
+                        partial.created.networks = networks;
+                    }
+                } else if !preparation.networks.is_empty() {
+                    tracing::warn!(
+                        networks = ?preparation.networks,
+                        "Provider has no networks; the container joins its default one"
+                    );
+                }
+                if capabilities.supports_volumes {
+                    let volumes = self.provider.ensure_volumes(config, &preparation).await?;
+                    if let Some(partial) = partial.lock().expect("rollback lock").as_mut() {
+                        partial.created.volumes = volumes;
+                    }
+                } else if !preparation.volumes.is_empty() {
+                    tracing::warn!(
+                        volumes = ?preparation.volumes.iter().map(|volume| &volume.name).collect::<Vec<_>>(),
+                        "Provider cannot create named volumes ahead of the container"
+                    );
+                }
 
-        if let Some(step) = plan.step_for_phase(LifecyclePhase::Start) {
-            tracing::info!(
-                phase = %step.phase,
-                code = step.event.code,
-                message = %step.event.message,
-                "Executing lifecycle phase"
-            );
-        }
+                let progress = plan
+                    .step_for_phase(LifecyclePhase::Build)
+                    .map(|step| self.begin_phase(step));
+                let image_reference = match self.resumable_image(previous.as_ref()).await {
+                    Some(image) => {
+                        tracing::info!(image = %image, "Reusing image built before resuming");
+                        image
+                    }
+                    None => {
+                        ResourceLimits::detect().warn_if_constrained();
+                        let image = self
+                            .with_daemon_recovery(config, LifecyclePhase::Build, None, || {
+                                self.provider.build_image(config, &preparation)
+                            })
+                            .await?;
+                        executed_phases.push(LifecyclePhase::Build);
+                        image
+                    }
+                };
+                self.record(config, |state| {
+                    state.complete_phase(&LifecyclePhase::Build.to_string());
+                    state.image = Some(image_reference.clone());
+                });
+                succeed(progress);
+
+                let progress = plan
+                    .step_for_phase(LifecyclePhase::Create)
+                    .map(|step| self.begin_phase(step));
+                let container = self
+                    .with_daemon_recovery(config, LifecyclePhase::Create, None, || {
+                        self.provider
+                            .create_container(config, &preparation, &image_reference)
+                    })
+                    .await?;
+                executed_phases.push(LifecyclePhase::Create);
+                self.record(config, |state| {
+                    state.complete_phase(&LifecyclePhase::Create.to_string())
+                });
+                succeed(progress);
+                container
+            }
+        };
 
-        self.provider.start_container(&container).await?;
+        let progress = plan
+            .step_for_phase(LifecyclePhase::Start)
+            .map(|step| self.begin_phase(step));
+        self.with_daemon_recovery(config, LifecyclePhase::Start, None, || {
+            self.provider.start_container(&container)
+        })
+        .await?;
         executed_phases.push(LifecyclePhase::Start);
+        succeed(progress);
+
+        let agent = match &self.helper_agent {
+            Some(agent) => match agent.install(&self.provider, &container).await {
+                Ok(installed) => Some(installed),
+                Err(err) => {
+                    tracing::warn!(error = %err, "Helper agent unavailable; falling back to shell probes");
+                    None
+                }
+            },
+            None => None,
+        };
 
-        if let Some(step) = plan.step_for_phase(LifecyclePhase::PostCreate) {
-            tracing::info!(
-                phase = %step.phase,
-                code = step.event.code,
-                message = %step.event.message,
-                "Executing lifecycle phase"
-            );
+        self.record(config, |state| {
+            state.record_up(config, &container, forwarded_ports)
+        });
+        if let Some(check) = &self.clock_check {
+            if let Some(skew) = check.run(&self.provider, &container).await {
+                self.record(config, |state| {
+                    state.clock_skew_seconds = Some(skew.seconds)
+                });
+            }
+        }
+        grant_socket_access(&self.provider, config, &container).await?;
+        // Hook progress only carries over when the hooks ran in this very container.
+        let resumed = previous.filter(|state| reused && state.container == container.name);
+        let mut deferring = wait_for == Some(LifecycleHook::Initialize);
+        let mut pending_hooks = Vec::new();
+        for hook in LifecycleHook::ALL {
+            if deferring {
+                if plan_executes(plan, hook)
+                    && self
+                        .already_ran(config, hook, &container, reused, resumed.as_ref())
+                        .await
+                        .is_none()
+                {
+                    pending_hooks.push(hook);
+                }
+                continue;
+            }
 
-            if let LifecycleEventDetail::Hook {
-                hook: LifecycleHook::PostCreate,
-                action,
-            } = &step.event.detail
+            if let Some(phase) = self
+                .with_daemon_recovery(config, hook.phase(), Some(&container), || {
+                    self.execute_hook(config, plan, hook, &container, reused, resumed.as_ref())
+                })
+                .await?
             {
-                self.handle_hook(
-                    LifecycleHook::PostCreate,
-                    action,
-                    config.post_create_command.as_ref(),
-                    &container,
-                )
-                .await?;
+                executed_phases.push(phase);
             }
-
-            executed_phases.push(LifecyclePhase::PostCreate);
+            deferring = wait_for == Some(hook);
         }
 
-        if let Some(step) = plan.step_for_phase(LifecyclePhase::PostAttach) {
+        if !pending_hooks.is_empty() {
             tracing::info!(
-                phase = %step.phase,
-                code = step.event.code,
-                message = %step.event.message,
-                "Executing lifecycle phase"
+                wait_for = ?wait_for.map(|hook| hook.to_string()),
+                pending = ?pending_hooks.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                "Deferring lifecycle hooks after waitFor"
             );
+            self.record(config, |state| {
+                for hook in &pending_hooks {
+                    state.set(&hook.to_string(), HookStatus::Pending, None);
+                }
+            });
+        }
 
-            if let LifecycleEventDetail::Hook {
-                hook: LifecycleHook::PostAttach,
-                action,
-            } = &step.event.detail
-            {
-                self.handle_hook(
-                    LifecycleHook::PostAttach,
-                    action,
-                    config.post_attach_command.as_ref(),
-                    &container,
-                )
+        if let Some(tag) = &self.prebuild {
+            self.provider
+                .commit_container(config, &container, tag)
                 .await?;
-            }
-
-            executed_phases.push(LifecyclePhase::PostAttach);
+            tracing::info!(image = %tag, "Committed prebuild image");
         }
 
         Ok(LifecycleOutcome {
             container,
             executed_phases,
+            reused,
+            remote_workspace_folder: preparation.workspace_mount_path,
+            agent,
+            pending_hooks,
         })
     }
 
-    async fn handle_hook(
+    /// Runs the hooks a previous [`execute`](Self::execute) deferred, as recorded in
+    /// the state store, returning the phases that ran.
+    pub async fn continue_pending(
         &self,
-        hook: LifecycleHook,
-        action: &HookAction,
-        command: Option<&CommandDefinition>,
+        config: &ResolvedConfig,
+        plan: &LifecyclePlan,
         container: &RunningContainer,
-    ) -> Result<()> {
-        match action {
-            HookAction::Execute => {
-                if let Some(command) = command {
-                    self.run_hook(container, hook, command).await
-                } else {
-                    tracing::warn!(
-                        hook = %hook,
-                        "Hook marked for execution without a resolved command"
-                    );
-                    Ok(())
-                }
-            }
-            HookAction::Skip { reason } => {
-                tracing::info!(hook = %hook, reason = %reason, "Skipping lifecycle hook");
-                Ok(())
-            }
-        }
+    ) -> Result<Vec<LifecyclePhase>> {
+        let pending: Vec<String> = match &self.state {
+            Some(store) => store
+                .load(config)?
+                .map(|state| {
+                    state
+                        .pending_hooks()
+                        .into_iter()
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+
+        self.run_hooks_in(config, plan, container, false, |hook| {
+            pending.contains(&hook.to_string())
+        })
+        .await
     }
 
-    async fn run_hook(
+    /// Runs the hooks of `plan`, such as one from [`LifecyclePlan::for_hooks`], in
+    /// the already running `container`, returning the phases that ran.
+    pub async fn run_hooks(
         &self,
+        config: &ResolvedConfig,
+        plan: &LifecyclePlan,
         container: &RunningContainer,
-        hook: LifecycleHook,
-        command: &CommandDefinition,
-    ) -> Result<()> {
-        match command {
-            CommandDefinition::Single(cmd) => {
-                self.run_hook_command(container, hook, None, cmd).await
+    ) -> Result<Vec<LifecyclePhase>> {
+        self.run_hooks_in(config, plan, container, false, |_| true)
+            .await
+    }
+
+    /// Sets up `container`, created and started by someone else, for its remote
+    /// user: grants access to forwarded sockets and runs the hooks of `plan`,
+    /// skipping create-only hooks its markers show already ran there.
+    pub async fn set_up(
+        &self,
+        config: &ResolvedConfig,
+        plan: &LifecyclePlan,
+        container: &RunningContainer,
+    ) -> Result<Vec<LifecyclePhase>> {
+        grant_socket_access(&self.provider, config, container).await?;
+        self.run_hooks_in(config, plan, container, true, |_| true)
+            .await
+    }
+
+    async fn run_hooks_in(
+        &self,
+        config: &ResolvedConfig,
+        plan: &LifecyclePlan,
+        container: &RunningContainer,
+        reused: bool,
+        selected: impl Fn(LifecycleHook) -> bool,
+    ) -> Result<Vec<LifecyclePhase>> {
+        let mut executed_phases = Vec::new();
+        for hook in LifecycleHook::ALL {
+            if !selected(hook) {
+                continue;
             }
-            CommandDefinition::Parallel(commands) => {
-                for (name, cmd) in commands {
-                    self.run_hook_command(container, hook, Some(name.as_str()), cmd)
-                        .await?;
-                }
-                Ok(())
+            if let Some(phase) = self
+                .with_daemon_recovery(config, hook.phase(), Some(container), || {
+                    self.execute_hook(config, plan, hook, container, reused, None)
+                })
+                .await?
+            {
+                executed_phases.push(phase);
             }
         }
+        Ok(executed_phases)
     }
 
-    async fn run_hook_command(
+    async fn execute_hook(
         &self,
-        container: &RunningContainer,
+        config: &ResolvedConfig,
+        plan: &LifecyclePlan,
         hook: LifecycleHook,
-        command_name: Option<&str>,
-        command: &CommandArgs,
+        container: &RunningContainer,
+        reused: bool,
+        resumed: Option<&LifecycleState>,
+    ) -> Result<Option<LifecyclePhase>> {
+        let Some(step) = plan.step_for_phase(hook.phase()) else {
+            return Ok(None);
+        };
+        if self
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(cancelled());
+        }
+        let progress = self.begin_phase(step);
+
+        if let Some(reason) = self
+            .already_ran(config, hook, container, reused, resumed)
+            .await
+        {
+            self.skip_completed_hook(config, hook, reason);
+        } else if let LifecycleEventDetail::Hook { action, .. } = &step.event.detail {
+            self.record(config, |state| {
+                state.set(&hook.to_string(), HookStatus::Running, None)
+            });
+            let result = self
+                .handle_hook(hook, action, hook.command(config), config, container)
+                .await;
+            self.record_result(config, hook, action, &result);
+            result?;
+            if hook.runs_on_create_only() && *action == HookAction::Execute {
+                markers::mark_hook(&self.provider, config, container, hook).await;
+            }
+        }
+
+        progress.succeed();
+        Ok(Some(step.phase))
+    }
+
+    /// Runs `step` for `phase`, and once more when it failed because the daemon
+    /// restarted underneath it. `container`, when the phase runs in one, is
+    /// looked up again and restarted before the retry.
+    async fn with_daemon_recovery<T, F, Fut>(
+        &self,
+        config: &ResolvedConfig,
+        phase: LifecyclePhase,
+        container: Option<&RunningContainer>,
+        step: F,
+    ) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let err = match step().await {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+        if !self.daemon_restarted(phase, &err, container).await? {
+            return Err(err);
+        }
+
+        tracing::warn!(phase = %phase, error = %err, "Container daemon restarted; retrying the phase");
+        let restarted = |err: DevcontainerError| DevcontainerError::DaemonRestarted {
+            phase: phase.to_string(),
+            message: err.to_string(),
+        };
+        if let Some(container) = container {
+            self.restart_container(config, container)
+                .await
+                .map_err(restarted)?;
+        }
+        step().await.map_err(|err| {
+            if err.is_daemon_disconnect() {
+                restarted(err)
+            } else {
+                err
+            }
+        })
+    }
+
+    /// Whether `err` came from the daemon restarting, waiting for it while it is
+    /// still down. That takes evidence of the restart: the connection dropping
+    /// in `err`, or the daemon not answering now. A stopped `container` alone is
+    /// not enough, as a hook stopping it or an OOM kill look the same, and
+    /// rerunning such a hook would run it twice.
+    async fn daemon_restarted(
+        &self,
+        phase: LifecyclePhase,
+        err: &DevcontainerError,
+        container: Option<&RunningContainer>,
+    ) -> Result<bool> {
+        if err.is_daemon_disconnect() {
+            self.wait_for_daemon(phase).await?;
+            return Ok(true);
+        }
+        if container.is_none() {
+            return Ok(false);
+        }
+        self.wait_for_daemon(phase).await
+    }
+
+    /// Polls the daemon until it answers, returning whether it was down at all,
+    /// or `false` when the provider cannot tell.
+    async fn wait_for_daemon(&self, phase: LifecyclePhase) -> Result<bool> {
+        let deadline = Instant::now() + DAEMON_RESTART_TIMEOUT;
+        let mut was_down = false;
+        loop {
+            match self.provider.daemon_ready().await {
+                Ok(()) => return Ok(was_down),
+                Err(DevcontainerError::Unsupported(_)) => return Ok(false),
+                Err(err) if Instant::now() >= deadline => {
+                    return Err(DevcontainerError::DaemonRestarted {
+                        phase: phase.to_string(),
+                        message: format!(
+                            "it did not answer again within {}s: {err}",
+                            DAEMON_RESTART_TIMEOUT.as_secs()
+                        ),
+                    });
+                }
+                Err(err) => {
+                    if !was_down {
+                        tracing::warn!(phase = %phase, error = %err, "Container daemon is down; waiting for it to restart");
+                    }
+                    was_down = true;
+                    tokio::time::sleep(DAEMON_POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    /// Starts `container` again after a restart, which stops every container on
+    /// daemons without live-restore, once discovery confirms it still exists.
+    async fn restart_container(
+        &self,
+        config: &ResolvedConfig,
+        container: &RunningContainer,
+    ) -> Result<()> {
+        if !config.id_labels.is_empty() {
+            match self.provider.find_containers(&config.id_labels).await {
+                Ok(found)
+                    if !found
+                        .iter()
+                        .any(|candidate| candidate.name == container.name) =>
+                {
+                    return Err(DevcontainerError::Provider(format!(
+                        "container {} no longer exists",
+                        container.name.as_deref().unwrap_or("<unnamed>")
+                    )));
+                }
+                Ok(_) | Err(DevcontainerError::Unsupported(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        self.provider.start_container(container).await
+    }
+
+    fn record_result(
+        &self,
+        config: &ResolvedConfig,
+        hook: LifecycleHook,
+        action: &HookAction,
+        result: &Result<()>,
+    ) {
+        let (status, message) = match (result, action) {
+            (Err(err), _) => (HookStatus::Failed, Some(err.to_string())),
+            (Ok(()), HookAction::Skip { reason }) => (HookStatus::Skipped, Some(reason.clone())),
+            (Ok(()), HookAction::Execute) => (HookStatus::Completed, None),
+        };
+        self.record(config, |state| {
+            state.set(&hook.to_string(), status, message)
+        });
+    }
+
+    /// Logs that `step` is executing and reports its progress until it succeeds.
+    fn begin_phase(&self, step: &LifecycleStep) -> PhaseProgress {
+        tracing::info!(
+            phase = %step.phase,
+            code = step.event.code,
+            message = %step.event.message,
+            "Executing lifecycle phase"
+        );
+        PhaseProgress::observed(
+            step.event.code,
+            step.event.message.clone(),
+            self.progress.clone(),
+        )
+    }
+
+    /// Best-effort removal of what a failed or cancelled `up` created, and of
+    /// its record.
+    async fn roll_back(&self, config: &ResolvedConfig, partial: &PartialUp) {
+        tracing::warn!(
+            container = %partial.preparation.container_name,
+            "Removing the partially created devcontainer"
+        );
+        if let Err(err) = self
+            .provider
+            .rollback(config, &partial.preparation, &partial.created)
+            .await
+        {
+            tracing::warn!(error = %err, "Failed to remove the partially created devcontainer");
+        }
+        if let Some(store) = &self.state {
+            if let Err(err) = store.remove(config) {
+                tracing::warn!(error = %err, "Failed to forget the interrupted devcontainer");
+            }
+        }
+    }
+
+    /// Why `hook` need not run again: a resumed run skips whatever already
+    /// finished, and a reused container skips create-only hooks whose marker
+    /// shows they finished in it, bar `updateContent` in a prebuild.
+    async fn already_ran(
+        &self,
+        config: &ResolvedConfig,
+        hook: LifecycleHook,
+        container: &RunningContainer,
+        reused: bool,
+        resumed: Option<&LifecycleState>,
+    ) -> Option<&'static str> {
+        if resumed.is_some_and(|previous| previous.hook_done(&hook.to_string())) {
+            return Some(RESUMED_REASON);
+        }
+        let refreshed = self.prebuild.is_some() && hook == LifecycleHook::UpdateContent;
+        let marked = reused
+            && hook.runs_on_create_only()
+            && !refreshed
+            && markers::hook_marked(&self.provider, config, container, hook).await;
+        marked.then_some(REUSED_REASON)
+    }
+
+    fn skip_completed_hook(&self, config: &ResolvedConfig, hook: LifecycleHook, reason: &str) {
+        tracing::info!(hook = %hook, reason, "Skipping lifecycle hook that already ran");
+        self.record(config, |state| {
+            state.set(
+                &hook.to_string(),
+                HookStatus::Skipped,
+                Some(reason.to_string()),
+            )
+        });
+    }
+
+    /// The image an earlier run built, if it is still available.
+    async fn resumable_image(&self, previous: Option<&LifecycleState>) -> Option<String> {
+        let image = previous
+            .filter(|state| state.has_completed(&LifecyclePhase::Build.to_string()))?
+            .image
+            .clone()?;
+        match self.provider.image_exists(&image).await {
+            Ok(Some(_)) => Some(image),
+            Ok(None) => None,
+            Err(err) => {
+                tracing::debug!(error = %err, image = %image, "Cannot verify image; rebuilding");
+                None
+            }
+        }
+    }
+
+    fn load_state(&self, config: &ResolvedConfig) -> Option<LifecycleState> {
+        let store = self.state.as_ref()?;
+        match store.load(config) {
+            Ok(state) => state,
+            Err(err) => {
+                tracing::warn!(error = %err, "Cannot resume; lifecycle state is unreadable");
+                None
+            }
+        }
+    }
+
+    /// Lifecycle state is advisory, so failing to record it only logs a warning.
+    fn record(&self, config: &ResolvedConfig, change: impl FnOnce(&mut LifecycleState)) {
+        if let Some(store) = &self.state {
+            if let Err(err) = store.update(config, change) {
+                tracing::warn!(error = %err, "Failed to record lifecycle state");
+            }
+        }
+    }
+
+    /// Fails when the host is known to miss `hostRequirements`, unless they are
+    /// ignored. Providers that cannot report their host are not checked.
+    async fn check_host_requirements(&self, config: &ResolvedConfig) -> Result<()> {
+        let Some(requirements) = &config.host_requirements else {
+            return Ok(());
+        };
+        let host = match self.provider.host_capabilities().await {
+            Ok(host) => host,
+            Err(DevcontainerError::Unsupported(reason)) => {
+                tracing::debug!(reason = %reason, "Skipping hostRequirements check");
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+        if requirements.gpu() == GpuRequirement::Optional && host.gpu == Some(false) {
+            tracing::info!("No GPU available; starting without the optional GPU");
+        }
+
+        let unmet = host.unmet(requirements);
+        if unmet.is_empty() {
+            return Ok(());
+        }
+        if self.ignore_host_requirements {
+            tracing::warn!(unmet = ?unmet, "Host does not meet hostRequirements; continuing anyway");
+            return Ok(());
+        }
+        Err(DevcontainerError::Configuration(format!(
+            "The container host does not meet hostRequirements: {}. \
+             Pass --ignore-host-requirements to continue anyway",
+            unmet.join("; ")
+        )))
+    }
+
+    /// Returns the existing container when its recorded config hash still matches.
+    async fn find_reusable_container(
+        &self,
+        config: &ResolvedConfig,
+        preparation: &ProviderPreparation,
+    ) -> Result<Option<RunningContainer>> {
+        if self.remove_existing_container || config.id_labels.is_empty() {
+            return Ok(None);
+        }
+
+        let mut labels = config.id_labels.clone();
+        labels.push(config.config_hash_label());
+        let candidates = discover_containers(&self.provider, &labels).await?;
+        Ok(candidates.into_iter().find(|container| {
+            container.name.as_deref() == Some(preparation.container_name.as_str())
+        }))
+    }
+
+    fn check_line_endings(&self, config: &ResolvedConfig, plan: &LifecyclePlan) -> Result<()> {
+        let hooks: Vec<LifecycleHook> = plan
+            .steps
+            .iter()
+            .filter_map(|step| match &step.event.detail {
+                LifecycleEventDetail::Hook {
+                    hook,
+                    action: HookAction::Execute,
+                } => Some(*hook),
+                _ => None,
+            })
+            .collect();
+
+        for script in line_endings::find_crlf_scripts(config, &hooks) {
+            if self.fix_line_endings {
+                line_endings::normalize_line_endings(&script.path)?;
+                tracing::info!(
+                    hook = %script.hook,
+                    path = %script.path.display(),
+                    "Normalized CRLF line endings in lifecycle script"
+                );
+            } else {
+                tracing::warn!(
+                    hook = %script.hook,
+                    path = %script.path.display(),
+                    "Lifecycle script has CRLF line endings and will likely fail with \
+                     '/bin/sh^M: not found'; rerun with --fix-line-endings or add '{}' to \
+                     .gitattributes",
+                    line_endings::GITATTRIBUTES_RULE
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_hook(
+        &self,
+        hook: LifecycleHook,
+        action: &HookAction,
+        command: Option<&CommandDefinition>,
+        config: &ResolvedConfig,
+        container: &RunningContainer,
+    ) -> Result<()> {
+        match action {
+            HookAction::Execute => {
+                if let Some(command) = command {
+                    self.run_hook(container, hook, config, command).await
+                } else {
+                    tracing::warn!(
+                        hook = %hook,
+                        "Hook marked for execution without a resolved command"
+                    );
+                    Ok(())
+                }
+            }
+            HookAction::Skip { reason } => {
+                tracing::info!(hook = %hook, reason = %reason, "Skipping lifecycle hook");
+                Ok(())
+            }
+        }
+    }
+
+    async fn handle_host_hook(
+        &self,
+        hook: LifecycleHook,
+        action: &HookAction,
+        config: &ResolvedConfig,
+    ) -> Result<()> {
+        match (action, hook.command(config)) {
+            (HookAction::Execute, Some(command)) => {
+                let output = self
+                    .output
+                    .clone()
+                    .unwrap_or_else(|| OutputSink::tracing(hook.to_string()));
+                host::run_host_command(config, &hook.to_string(), command, &output).await
+            }
+            (HookAction::Execute, None) => {
+                tracing::warn!(
+                    hook = %hook,
+                    "Hook marked for execution without a resolved command"
+                );
+                Ok(())
+            }
+            (HookAction::Skip { reason }, _) => {
+                tracing::info!(hook = %hook, reason = %reason, "Skipping lifecycle hook");
+                Ok(())
+            }
+        }
+    }
+
+    async fn run_hook(
+        &self,
+        container: &RunningContainer,
+        hook: LifecycleHook,
+        config: &ResolvedConfig,
+        command: &CommandDefinition,
+    ) -> Result<()> {
+        match command {
+            CommandDefinition::Single(cmd) => {
+                self.run_hook_command(container, hook, config, None, cmd)
+                    .await
+            }
+            CommandDefinition::Parallel(commands) => {
+                let results = join_all(commands.iter().map(|(name, cmd)| {
+                    self.run_hook_command(container, hook, config, Some(name.as_str()), cmd)
+                }))
+                .await;
+                aggregate_parallel_results(hook, results)
+            }
+        }
+    }
+
+    async fn run_hook_command(
+        &self,
+        container: &RunningContainer,
+        hook: LifecycleHook,
+        config: &ResolvedConfig,
+        command_name: Option<&str>,
+        command: &CommandArgs,
     ) -> Result<()> {
-        let args = command.to_exec_args();
+        let user = config.hook_user(&hook.to_string());
+        let args = config
+            .hook_env_stack(&hook.to_string())
+            .wrap_command(&config.command_args(command));
         if let Some(name) = command_name {
             tracing::debug!(
                 hook = %hook,
                 command_name = name,
                 command = ?args,
+                user = ?user,
                 "Executing lifecycle hook command"
             );
         } else {
-            tracing::debug!(hook = %hook, command = ?args, "Executing lifecycle hook command");
+            tracing::debug!(
+                hook = %hook,
+                command = ?args,
+                user = ?user,
+                "Executing lifecycle hook command"
+            );
         }
 
-        let result = self.provider.exec(container, &args).await?;
+        let output = match (&self.output, command_name) {
+            (Some(output), Some(name)) => output.prefixed(name),
+            (Some(output), None) => output.clone(),
+            (None, Some(name)) => OutputSink::tracing(format!("{hook}:{name}")),
+            (None, None) => OutputSink::tracing(hook.to_string()),
+        };
+        let capture = OutputCapture::new(output);
+        let started = Instant::now();
+        let result = self
+            .provider
+            .exec_streaming(container, user, &args, capture.sink())
+            .await;
+        let lines = capture.lines();
+        self.record_run(
+            config,
+            HookRun {
+                hook: hook.to_string(),
+                command_name: command_name.map(str::to_string),
+                // Without the env wrapper, which may carry secrets.
+                command: config.command_args(command),
+                exit_code: result.as_ref().ok().map(|result| result.exit_code),
+                duration_ms: started.elapsed().as_millis() as u64,
+                output_tail: output_tail(&lines),
+                log_file: None,
+            },
+            &lines,
+        );
+        let result = result?;
         if let Some(name) = command_name {
             tracing::debug!(
                 hook = %hook,
@@ -507,34 +1506,16 @@ impl<P: Provider> LifecycleExecutor<P> {
             );
         }
 
-        let stdout = result.stdout.trim();
-        if !stdout.is_empty() {
-            if let Some(name) = command_name {
-                tracing::info!(
-                    hook = %hook,
-                    command_name = name,
-                    stdout = %stdout,
-                    "Lifecycle hook stdout"
-                );
-            } else {
-                tracing::info!(hook = %hook, stdout = %stdout, "Lifecycle hook stdout");
-            }
-        }
-
         let stderr = result.stderr.trim();
-        if !stderr.is_empty() {
-            if let Some(name) = command_name {
-                tracing::warn!(
-                    hook = %hook,
-                    command_name = name,
-                    stderr = %stderr,
-                    "Lifecycle hook stderr"
-                );
-            } else {
-                tracing::warn!(hook = %hook, stderr = %stderr, "Lifecycle hook stderr");
+        if let (CommandArgs::String(_), Some(shell)) = (command, config.lifecycle_shell().first()) {
+            if shell_missing(result.exit_code, stderr, shell) {
+                return Err(DevcontainerError::Configuration(format!(
+                    "{hook} command needs {shell}, which the container does not have; \
+                     set lifecycleShell or --lifecycle-shell to a shell it does, \
+                     or give the command as an array"
+                )));
             }
         }
-
         if result.exit_code != 0 {
             let mut message = if let Some(name) = command_name {
                 format!(
@@ -549,19 +1530,115 @@ impl<P: Provider> LifecycleExecutor<P> {
                 message.push_str(&format!(" ({stderr})"));
             }
 
-            return Err(DevcontainerError::Provider(message));
+            return Err(DevcontainerError::HookFailed {
+                hook: hook.to_string(),
+                exit_code: result.exit_code,
+                message,
+            });
         }
 
         Ok(())
     }
+
+    /// Adds `run` to the transcript, keeping its full output in the state store's logs.
+    fn record_run(&self, config: &ResolvedConfig, mut run: HookRun, lines: &[String]) {
+        let log_name = match &run.command_name {
+            Some(name) => format!("{}-{name}", run.hook),
+            None => run.hook.clone(),
+        };
+        run.log_file = self.state.as_ref().and_then(|store| {
+            store
+                .write_log(config, &log_name, lines)
+                .inspect_err(|err| tracing::warn!(error = %err, "Failed to write hook log"))
+                .ok()
+        });
+        self.transcript.push(run);
+    }
+}
+
+const REUSED_REASON: &str = "container reused";
+const RESUMED_REASON: &str = "completed before resuming";
+
+/// Whether a command exiting with `exit_code` and `stderr` failed because the
+/// container has no `shell`. The container runtime and `env` quote the program
+/// they could not start, unlike a shell reporting a command missing in its script.
+fn shell_missing(exit_code: i32, stderr: &str, shell: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    matches!(exit_code, 126 | 127)
+        && ['"', '\'', '`']
+            .iter()
+            .any(|quote| stderr.contains(&format!("{quote}{shell}")))
+        && (stderr.contains("no such file") || stderr.contains("not found"))
+}
+
+fn cancelled() -> DevcontainerError {
+    DevcontainerError::Cancelled("devcontainer up was interrupted".to_string())
+}
+
+fn succeed(progress: Option<PhaseProgress>) {
+    if let Some(progress) = progress {
+        progress.succeed();
+    }
+}
+
+fn plan_executes(plan: &LifecyclePlan, hook: LifecycleHook) -> bool {
+    plan.step_for_phase(hook.phase()).is_some_and(|step| {
+        matches!(
+            step.event.detail,
+            LifecycleEventDetail::Hook {
+                action: HookAction::Execute,
+                ..
+            }
+        )
+    })
+}
+
+/// Combines the results of an object-form command's entries, reporting every failure
+/// and keeping the exit code of the first failed command.
+pub(crate) fn aggregate_parallel_results(
+    hook: impl Display,
+    results: Vec<Result<()>>,
+) -> Result<()> {
+    let total = results.len();
+    let mut failures: Vec<DevcontainerError> = results
+        .into_iter()
+        .filter_map(|result| result.err())
+        .collect();
+    if failures.len() <= 1 {
+        return failures.pop().map_or(Ok(()), Err);
+    }
+
+    let exit_code = failures.iter().find_map(|err| match err {
+        DevcontainerError::HookFailed { exit_code, .. } => Some(*exit_code),
+        _ => None,
+    });
+    let message = format!(
+        "{} of {total} {hook} commands failed: {}",
+        failures.len(),
+        failures
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("; ")
+    );
+    Err(match exit_code {
+        Some(exit_code) => DevcontainerError::HookFailed {
+            hook: hook.to_string(),
+            exit_code,
+            message,
+        },
+        None => DevcontainerError::Provider(message),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{CommandArgs, ContainerLabel, LABEL_LOCAL_FOLDER, LABEL_PROJECT};
     use crate::provider::{
-        ExecResult, Provider, ProviderCleanupOptions, ProviderImage, ProviderKind,
-        ProviderPreparation, VolumeSpec,
+        mock::{MockCall, MockProvider},
+        ContainerActivity, ExecResult, Provider, ProviderCapabilities, ProviderCleanupOptions,
+        ProviderImage, ProviderKind, ProviderPreparation, VolumeSpec,
     };
     use async_trait::async_trait;
     use std::sync::{Arc, Mutex};
@@ -573,17 +1650,51 @@ mod tests {
             container_workspace_folder: Some(PathBuf::from("/workspace")),
             config_path: PathBuf::from("/workspace/.devcontainer/devcontainer.json"),
             image_reference: Some("example:image".to_string()),
-            dockerfile: None,
-            features: Default::default(),
-            forward_ports: vec![],
             post_create_command: Some(CommandDefinition::from_string("echo post create")),
             post_attach_command: Some(CommandDefinition::from_array(vec![
                 "echo".to_string(),
                 "post-attach".to_string(),
             ])),
+            id_labels: vec![ContainerLabel::new(LABEL_LOCAL_FOLDER, "/workspace")],
+            ..Default::default()
         }
     }
 
+    fn is_marker_command(command: &[String]) -> bool {
+        command
+            .last()
+            .is_some_and(|script| script.contains("CommandMarker"))
+    }
+
+    /// Commands run by hooks, leaving out the reads and writes of hook markers.
+    fn hook_execs(exec_calls: Vec<Vec<String>>) -> Vec<Vec<String>> {
+        exec_calls
+            .into_iter()
+            .filter(|command| !is_marker_command(command))
+            .collect()
+    }
+
+    /// Provider whose containers hold a marker for every create-only hook.
+    fn marked_provider() -> MockProvider {
+        MockProvider::new().with_exec_handler(|command| ExecResult {
+            exit_code: 0,
+            stdout: if is_marker_command(command) && command[2].starts_with("cat") {
+                "mock-demo-id\n".to_string()
+            } else {
+                String::new()
+            },
+            stderr: String::new(),
+        })
+    }
+
+    fn hook_detail(plan: &LifecyclePlan, phase: LifecyclePhase) -> LifecycleEventDetail {
+        plan.step_for_phase(phase)
+            .expect("hook phase planned")
+            .event
+            .detail
+            .clone()
+    }
+
     #[test]
     fn plan_for_up_contains_all_phases() {
         let config = sample_config();
@@ -594,23 +1705,30 @@ mod tests {
             phases,
             vec![
                 LifecyclePhase::Resolve,
+                LifecyclePhase::Initialize,
                 LifecyclePhase::Build,
                 LifecyclePhase::Create,
                 LifecyclePhase::Start,
+                LifecyclePhase::OnCreate,
+                LifecyclePhase::UpdateContent,
                 LifecyclePhase::PostCreate,
+                LifecyclePhase::PostStart,
                 LifecyclePhase::PostAttach,
             ]
         );
 
         assert!(matches!(
-            plan.steps[1].event.detail,
+            plan.step_for_phase(LifecyclePhase::Build)
+                .expect("build phase planned")
+                .event
+                .detail,
             LifecycleEventDetail::BuildImage {
                 image_reference: Some(ref image)
             } if image == "example:image"
         ));
 
         assert!(matches!(
-            plan.steps[4].event.detail,
+            hook_detail(&plan, LifecyclePhase::PostCreate),
             LifecycleEventDetail::Hook {
                 hook: LifecycleHook::PostCreate,
                 action: HookAction::Execute
@@ -618,7 +1736,7 @@ mod tests {
         ));
 
         assert!(matches!(
-            plan.steps[5].event.detail,
+            hook_detail(&plan, LifecyclePhase::PostAttach),
             LifecycleEventDetail::Hook {
                 hook: LifecycleHook::PostAttach,
                 action: HookAction::Execute
@@ -636,18 +1754,22 @@ mod tests {
             LifecyclePlanOptions {
                 skip_post_create: Some("--skip-post-create flag set".to_string()),
                 skip_post_attach: Some("--skip-post-attach flag set".to_string()),
+                ..Default::default()
             },
         );
 
         assert!(matches!(
-            plan.steps[1].event.detail,
+            plan.step_for_phase(LifecyclePhase::Build)
+                .expect("build phase planned")
+                .event
+                .detail,
             LifecycleEventDetail::BuildImage {
                 image_reference: None
             }
         ));
 
         assert!(matches!(
-            plan.steps[4].event.detail,
+            hook_detail(&plan, LifecyclePhase::PostCreate),
             LifecycleEventDetail::Hook {
                 hook: LifecycleHook::PostCreate,
                 action: HookAction::Skip { ref reason }
@@ -655,7 +1777,7 @@ mod tests {
         ));
 
         assert!(matches!(
-            plan.steps[5].event.detail,
+            hook_detail(&plan, LifecyclePhase::PostAttach),
             LifecycleEventDetail::Hook {
                 hook: LifecycleHook::PostAttach,
                 action: HookAction::Skip { ref reason }
@@ -672,7 +1794,7 @@ mod tests {
         let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
 
         assert!(matches!(
-            plan.steps[4].event.detail,
+            hook_detail(&plan, LifecyclePhase::PostCreate),
             LifecycleEventDetail::Hook {
                 hook: LifecycleHook::PostCreate,
                 action: HookAction::Skip { ref reason }
@@ -680,7 +1802,7 @@ mod tests {
         ));
 
         assert!(matches!(
-            plan.steps[5].event.detail,
+            hook_detail(&plan, LifecyclePhase::PostAttach),
             LifecycleEventDetail::Hook {
                 hook: LifecycleHook::PostAttach,
                 action: HookAction::Skip { ref reason }
@@ -720,6 +1842,10 @@ mod tests {
             ProviderKind::Mock
         }
 
+        fn capabilities(&self) -> ProviderCapabilities {
+            ProviderCapabilities::all()
+        }
+
         async fn prepare(&self, _config: &ResolvedConfig) -> Result<ProviderPreparation> {
             Ok(ProviderPreparation {
                 image: ProviderImage::Reference("example:image".to_string()),
@@ -731,6 +1857,8 @@ mod tests {
                     mount_path: PathBuf::from("/data"),
                 }],
                 workspace_mount_path: PathBuf::from("/workspace"),
+                workspace_volume: None,
+                sidecars: vec![],
             })
         }
 
@@ -738,16 +1866,16 @@ mod tests {
             &self,
             _config: &ResolvedConfig,
             _preparation: &ProviderPreparation,
-        ) -> Result<()> {
-            Ok(())
+        ) -> Result<Vec<String>> {
+            Ok(Vec::new())
         }
 
         async fn ensure_volumes(
             &self,
             _config: &ResolvedConfig,
             _preparation: &ProviderPreparation,
-        ) -> Result<()> {
-            Ok(())
+        ) -> Result<Vec<String>> {
+            Ok(Vec::new())
         }
 
         async fn build_image(
@@ -767,6 +1895,7 @@ mod tests {
             Ok(RunningContainer {
                 id: Some("container-id".to_string()),
                 name: Some("test-container".to_string()),
+                ..Default::default()
             })
         }
 
@@ -822,15 +1951,19 @@ mod tests {
             outcome.executed_phases,
             vec![
                 LifecyclePhase::Resolve,
+                LifecyclePhase::Initialize,
                 LifecyclePhase::Build,
                 LifecyclePhase::Create,
                 LifecyclePhase::Start,
+                LifecyclePhase::OnCreate,
+                LifecyclePhase::UpdateContent,
                 LifecyclePhase::PostCreate,
+                LifecyclePhase::PostStart,
                 LifecyclePhase::PostAttach,
             ]
         );
 
-        let calls = provider.exec_calls();
+        let calls = hook_execs(provider.exec_calls());
         assert_eq!(calls.len(), 2);
         assert_eq!(
             calls[0],
@@ -863,9 +1996,13 @@ mod tests {
             .expect_err("postCreate failure propagates");
 
         match err {
-            DevcontainerError::Provider(message) => {
-                assert!(message.contains("postCreate"));
-                assert!(message.contains("5"));
+            DevcontainerError::HookFailed {
+                hook,
+                exit_code,
+                message,
+            } => {
+                assert_eq!(hook, "postCreate");
+                assert_eq!(exit_code, 5);
                 assert!(message.contains("boom"));
             }
             other => panic!("Unexpected error: {other:?}"),
@@ -883,6 +2020,791 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn executor_runs_string_commands_in_the_lifecycle_shell() {
+        let mut config = sample_config();
+        config.lifecycle_shell = Some(vec!["bash".to_string(), "-lc".to_string()]);
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let provider = TestProvider::new(ExecResult {
+            exit_code: 127,
+            stdout: String::new(),
+            stderr: "env: 'bash': No such file or directory".to_string(),
+        });
+        let executor = LifecycleExecutor::new(provider.clone());
+
+        let err = executor
+            .execute(&config, &plan)
+            .await
+            .expect_err("missing shell is reported");
+        match err {
+            DevcontainerError::Configuration(message) => {
+                assert!(message.contains("bash"));
+                assert!(message.contains("lifecycleShell"));
+            }
+            other => panic!("Unexpected error: {other:?}"),
+        }
+        assert_eq!(
+            provider.exec_calls()[0],
+            vec![
+                "bash".to_string(),
+                "-lc".to_string(),
+                "echo post create".to_string(),
+            ]
+        );
+
+        // A command missing inside the script is the hook's own failure.
+        assert!(!shell_missing(127, "/bin/sh: 1: npm: not found", "/bin/sh"));
+        assert!(shell_missing(
+            126,
+            "OCI runtime exec failed: exec: \"/bin/sh\": stat /bin/sh: no such file or directory",
+            "/bin/sh"
+        ));
+    }
+
+    #[tokio::test]
+    async fn executor_rolls_back_a_failed_or_cancelled_up() {
+        let state_dir = tempfile::tempdir().expect("tempdir");
+        let config = sample_config();
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let store = StateStore::new(state_dir.path());
+        let cleaned_up = |provider: &MockProvider| {
+            provider.calls().contains(&MockCall::Cleanup {
+                container_name: "mock-demo".to_string(),
+            })
+        };
+
+        let failing = MockProvider::new().with_exec_handler(|_| ExecResult {
+            exit_code: 1,
+            ..Default::default()
+        });
+        let executor = LifecycleExecutor::new(failing.clone()).with_state_store(store.clone());
+        executor
+            .execute(&config, &plan)
+            .await
+            .expect_err("hook fails");
+        assert!(cleaned_up(&failing));
+        assert!(store.load(&config).expect("load").is_none());
+
+        let kept = MockProvider::new().with_exec_handler(|_| ExecResult {
+            exit_code: 1,
+            ..Default::default()
+        });
+        let executor = LifecycleExecutor::new(kept.clone()).with_keep_on_failure(true);
+        executor
+            .execute(&config, &plan)
+            .await
+            .expect_err("hook fails");
+        assert!(!cleaned_up(&kept));
+
+        let token = CancellationToken::new();
+        let interrupt = token.clone();
+        let provider = MockProvider::new().with_exec_handler(move |_| {
+            interrupt.cancel();
+            ExecResult::default()
+        });
+        let executor = LifecycleExecutor::new(provider.clone())
+            .with_state_store(store.clone())
+            .with_keep_on_failure(true)
+            .with_cancellation(token);
+        let err = executor
+            .execute(&config, &plan)
+            .await
+            .expect_err("cancelled");
+        assert!(matches!(err, DevcontainerError::Cancelled(_)));
+        assert_eq!(hook_execs(provider.exec_calls()).len(), 1);
+        assert!(cleaned_up(&provider));
+        assert!(store.load(&config).expect("load").is_none());
+    }
+
+    #[tokio::test]
+    async fn executor_removes_stale_containers_found_by_label() {
+        let config = sample_config();
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let provider = MockProvider::new().with_container(
+            RunningContainer {
+                name: Some("renamed-project".to_string()),
+                ..Default::default()
+            },
+            config.id_labels.clone(),
+        );
+        let executor = LifecycleExecutor::new(provider.clone());
+
+        executor
+            .execute(&config, &plan)
+            .await
+            .expect("lifecycle execution succeeds");
+
+        let calls = provider.calls();
+        assert!(calls.contains(&MockCall::RemoveContainer {
+            container_name: "renamed-project".to_string()
+        }));
+        let found = executor
+            .provider()
+            .find_containers(&config.id_labels)
+            .await
+            .expect("find containers");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].name.as_deref(), Some("mock-demo"));
+    }
+
+    #[tokio::test]
+    async fn executor_leaves_other_workspaces_containers_sharing_id_labels() {
+        let team = ContainerLabel::new("team", "platform");
+        let config = ResolvedConfig {
+            id_labels: vec![team.clone()],
+            ..sample_config()
+        };
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let provider = MockProvider::new()
+            .with_container(
+                RunningContainer {
+                    name: Some("other-workspace".to_string()),
+                    ..Default::default()
+                },
+                vec![team.clone(), ContainerLabel::new(LABEL_PROJECT, "other")],
+            )
+            .with_container(
+                RunningContainer {
+                    name: Some("renamed-project".to_string()),
+                    ..Default::default()
+                },
+                vec![team, ContainerLabel::new(LABEL_PROJECT, "demo")],
+            );
+        let executor = LifecycleExecutor::new(provider.clone());
+
+        executor
+            .execute(&config, &plan)
+            .await
+            .expect("lifecycle execution succeeds");
+
+        let calls = provider.calls();
+        assert!(calls.contains(&MockCall::RemoveContainer {
+            container_name: "renamed-project".to_string()
+        }));
+        assert!(!calls.contains(&MockCall::RemoveContainer {
+            container_name: "other-workspace".to_string()
+        }));
+    }
+
+    #[tokio::test]
+    async fn executor_reuses_container_when_config_unchanged() {
+        let config = sample_config();
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let provider = marked_provider();
+        let creates = |provider: &MockProvider| {
+            provider
+                .calls()
+                .iter()
+                .filter(|call| matches!(call, MockCall::CreateContainer { .. }))
+                .count()
+        };
+
+        let executor = LifecycleExecutor::new(provider.clone());
+        let first = executor.execute(&config, &plan).await.expect("first up");
+        assert!(!first.reused);
+
+        let second = executor.execute(&config, &plan).await.expect("second up");
+        assert!(second.reused);
+        assert_eq!(creates(&provider), 1);
+        assert!(!second.executed_phases.contains(&LifecyclePhase::Create));
+        assert_eq!(
+            hook_execs(provider.exec_calls()).len(),
+            3,
+            "postCreate runs only on create"
+        );
+
+        let mut changed = config.clone();
+        changed.image_reference = Some("example:changed".to_string());
+        let changed_plan = LifecyclePlan::for_up(&changed, LifecyclePlanOptions::default());
+        let third = executor
+            .execute(&changed, &changed_plan)
+            .await
+            .expect("up after config change");
+        assert!(!third.reused);
+        assert_eq!(creates(&provider), 2);
+
+        let forced = LifecycleExecutor::new(provider.clone())
+            .with_remove_existing_container(true)
+            .execute(&changed, &changed_plan)
+            .await
+            .expect("forced recreate");
+        assert!(!forced.reused);
+        assert_eq!(creates(&provider), 3);
+    }
+
+    #[tokio::test]
+    async fn executor_commits_prebuild_after_update_content() {
+        let mut config = sample_config();
+        config.on_create_command = Some(CommandDefinition::from_string("echo on create"));
+        config.update_content_command = Some(CommandDefinition::from_string("echo update content"));
+        let options = LifecyclePlanOptions {
+            skip_post_create: Some("prebuild".to_string()),
+            skip_post_start: Some("prebuild".to_string()),
+            skip_post_attach: Some("prebuild".to_string()),
+            ..Default::default()
+        };
+        let plan = LifecyclePlan::for_up(&config, options);
+        let provider = marked_provider();
+        let executor = LifecycleExecutor::new(provider.clone())
+            .with_prebuild(Some("example:prebuild".to_string()));
+        let hook_commands = |provider: &MockProvider| -> Vec<String> {
+            hook_execs(provider.exec_calls())
+                .into_iter()
+                .filter_map(|command| command.last().cloned())
+                .collect()
+        };
+
+        executor.execute(&config, &plan).await.expect("prebuild");
+        assert_eq!(
+            hook_commands(&provider),
+            ["echo on create", "echo update content"]
+        );
+        let commit = MockCall::CommitContainer {
+            container_name: "mock-demo".to_string(),
+            tag: "example:prebuild".to_string(),
+        };
+        assert!(provider.calls().contains(&commit));
+
+        let again = executor
+            .execute(&config, &plan)
+            .await
+            .expect("prebuild again");
+        assert!(again.reused);
+        assert_eq!(hook_commands(&provider)[2..], ["echo update content"]);
+    }
+
+    #[tokio::test]
+    async fn executor_goes_without_capabilities_the_provider_lacks() {
+        let config = sample_config();
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let provider = MockProvider::new().with_capabilities(ProviderCapabilities::default());
+
+        let outcome = LifecycleExecutor::new(provider.clone())
+            .execute(&config, &plan)
+            .await
+            .expect("up without networks or volumes");
+        assert_eq!(outcome.container.name.as_deref(), Some("mock-demo"));
+        assert!(!provider
+            .calls()
+            .iter()
+            .any(|call| matches!(call, MockCall::EnsureNetworks | MockCall::EnsureVolumes)));
+
+        let err = LifecycleExecutor::new(provider.clone())
+            .with_prebuild(Some("example:prebuild".to_string()))
+            .execute(&config, &plan)
+            .await
+            .expect_err("prebuild needs commit");
+        assert!(
+            matches!(err, DevcontainerError::Unsupported(message) if message.contains("--prebuild"))
+        );
+    }
+
+    #[tokio::test]
+    async fn set_up_reads_container_metadata_and_skips_marked_hooks() {
+        let metadata = serde_json::json!([{
+            "onCreateCommand": "echo on create",
+            "postStartCommand": "echo post start",
+            "remoteUser": "vscode"
+        }]);
+        let container = RunningContainer {
+            id: Some("mock-demo-id".to_string()),
+            ..Default::default()
+        };
+        let provider = marked_provider().with_container(
+            container.clone(),
+            vec![ContainerLabel::new(
+                crate::config::merge::IMAGE_METADATA_LABEL,
+                metadata.to_string(),
+            )],
+        );
+
+        let metadata = crate::provider::container_metadata(&provider, &container)
+            .await
+            .expect("metadata");
+        let config = ResolvedConfig::default()
+            .with_image_metadata(&metadata)
+            .expect("layer metadata");
+        assert_eq!(config.remote_user.as_deref(), Some("vscode"));
+        let plan = LifecyclePlan::for_hooks(&config, &LifecycleHook::ALL);
+        let phases = LifecycleExecutor::new(provider.clone())
+            .set_up(&config, &plan, &container)
+            .await
+            .expect("set up");
+
+        assert!(phases.contains(&LifecyclePhase::PostStart));
+        let commands: Vec<String> = hook_execs(provider.exec_calls())
+            .into_iter()
+            .filter_map(|command| command.last().cloned())
+            .collect();
+        assert_eq!(commands, ["echo post start"]);
+    }
+
+    #[tokio::test]
+    async fn executor_runs_initialize_command_on_host() {
+        let workspace = tempfile::tempdir().expect("tempdir");
+        let mut config = sample_config();
+        config.workspace_folder = workspace.path().to_path_buf();
+        config.initialize_command = Some(CommandDefinition::from_string("touch initialized"));
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        assert_eq!(
+            hook_detail(&plan, LifecyclePhase::Initialize),
+            LifecycleEventDetail::Hook {
+                hook: LifecycleHook::Initialize,
+                action: HookAction::Execute,
+            }
+        );
+
+        let provider = MockProvider::new();
+        let outcome = LifecycleExecutor::new(provider.clone())
+            .execute(&config, &plan)
+            .await
+            .expect("lifecycle execution succeeds");
+
+        assert!(workspace.path().join("initialized").exists());
+        assert_eq!(outcome.executed_phases[1], LifecyclePhase::Initialize);
+        assert!(provider
+            .exec_calls()
+            .iter()
+            .all(|command| !command.concat().contains("initialized")));
+
+        let skipped = LifecyclePlan::for_up(&config, LifecyclePlanOptions::skip_all("exec"));
+        assert!(matches!(
+            hook_detail(&skipped, LifecyclePhase::Initialize),
+            LifecycleEventDetail::Hook {
+                action: HookAction::Skip { .. },
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn executor_runs_hooks_in_spec_order() {
+        let mut config = sample_config();
+        config.on_create_command = Some(CommandDefinition::from_string("echo on create"));
+        config.update_content_command = Some(CommandDefinition::from_string("echo update content"));
+        config.post_start_command = Some(CommandDefinition::from_string("echo post start"));
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let provider = marked_provider();
+        let executor = LifecycleExecutor::new(provider.clone());
+        let hook_commands = |provider: &MockProvider| -> Vec<String> {
+            hook_execs(provider.exec_calls())
+                .into_iter()
+                .filter_map(|command| command.last().cloned())
+                .collect()
+        };
+
+        executor.execute(&config, &plan).await.expect("first up");
+        assert_eq!(
+            hook_commands(&provider),
+            vec![
+                "echo on create",
+                "echo update content",
+                "echo post create",
+                "echo post start",
+                "post-attach",
+            ]
+        );
+
+        let second = executor.execute(&config, &plan).await.expect("second up");
+        assert!(second.reused);
+        assert_eq!(
+            hook_commands(&provider)[5..],
+            ["echo post start", "post-attach"]
+        );
+    }
+
+    #[tokio::test]
+    async fn executor_defers_hooks_after_wait_for() {
+        let state_dir = tempfile::tempdir().expect("tempdir");
+        let mut config = sample_config();
+        config.post_start_command = Some(CommandDefinition::from_string("echo post start"));
+        config.wait_for = Some("postCreateCommand".to_string());
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let provider = MockProvider::new();
+        let store = StateStore::new(state_dir.path());
+        let executor = LifecycleExecutor::new(provider.clone()).with_state_store(store.clone());
+        let hook_commands = |provider: &MockProvider| -> Vec<String> {
+            hook_execs(provider.exec_calls())
+                .into_iter()
+                .filter_map(|command| command.last().cloned())
+                .collect()
+        };
+
+        let outcome = executor.execute(&config, &plan).await.expect("up");
+        assert_eq!(
+            outcome.pending_hooks,
+            vec![LifecycleHook::PostStart, LifecycleHook::PostAttach]
+        );
+        assert_eq!(hook_commands(&provider), vec!["echo post create"]);
+        let state = store.load(&config).expect("load").expect("state recorded");
+        assert_eq!(state.status_of("postCreate"), Some(HookStatus::Completed));
+        assert_eq!(state.pending_hooks(), vec!["postStart", "postAttach"]);
+
+        let phases = executor
+            .continue_pending(&config, &plan, &outcome.container)
+            .await
+            .expect("continue");
+        assert_eq!(
+            phases,
+            vec![LifecyclePhase::PostStart, LifecyclePhase::PostAttach]
+        );
+        assert_eq!(
+            hook_commands(&provider)[1..],
+            ["echo post start", "post-attach"]
+        );
+        let state = store.load(&config).expect("load").expect("state recorded");
+        assert!(state.pending_hooks().is_empty());
+        assert_eq!(state.status_of("postAttach"), Some(HookStatus::Completed));
+
+        assert!("bogus".parse::<LifecycleHook>().is_err());
+    }
+
+    #[tokio::test]
+    async fn executor_resumes_from_failed_hook() {
+        let state_dir = tempfile::tempdir().expect("tempdir");
+        let mut config = sample_config();
+        config.on_create_command = Some(CommandDefinition::from_string("echo on create"));
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let flaky = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let fail_once = Arc::clone(&flaky);
+        let provider = MockProvider::new().with_exec_handler(move |command| {
+            let failing = command.concat().contains("post create")
+                && fail_once.swap(false, std::sync::atomic::Ordering::SeqCst);
+            ExecResult {
+                exit_code: i32::from(failing),
+                stdout: String::new(),
+                stderr: String::new(),
+            }
+        });
+        let store = StateStore::new(state_dir.path());
+        let executor = LifecycleExecutor::new(provider.clone())
+            .with_state_store(store.clone())
+            .with_resume(true)
+            .with_keep_on_failure(true);
+
+        executor
+            .execute(&config, &plan)
+            .await
+            .expect_err("postCreate fails");
+        let state = store.load(&config).expect("load").expect("state recorded");
+        assert!(state.has_completed("create"));
+        assert_eq!(state.status_of("onCreate"), Some(HookStatus::Completed));
+        assert_eq!(state.status_of("postCreate"), Some(HookStatus::Failed));
+
+        let outcome = executor.execute(&config, &plan).await.expect("resumed up");
+        assert!(outcome.reused);
+        let commands: Vec<String> = hook_execs(provider.exec_calls())
+            .into_iter()
+            .filter_map(|command| command.last().cloned())
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                "echo on create",
+                "echo post create",
+                "echo post create",
+                "post-attach"
+            ]
+        );
+        let state = store.load(&config).expect("load").expect("state recorded");
+        assert_eq!(state.status_of("onCreate"), Some(HookStatus::Skipped));
+        assert_eq!(state.status_of("postCreate"), Some(HookStatus::Completed));
+    }
+
+    #[tokio::test]
+    async fn executor_transcribes_hook_commands_up_to_the_failure() {
+        let state_dir = tempfile::tempdir().expect("tempdir");
+        let config = sample_config();
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let provider = MockProvider::new().with_exec_handler(|command| {
+            let failing = command.concat().contains("post create");
+            ExecResult {
+                exit_code: i32::from(failing) * 2,
+                stdout: (1..=30).map(|line| format!("line {line}\n")).collect(),
+                stderr: if failing { "boom\n" } else { "" }.to_string(),
+            }
+        });
+        let transcript = HookTranscript::new();
+
+        LifecycleExecutor::new(provider)
+            .with_state_store(StateStore::new(state_dir.path()))
+            .with_transcript(transcript.clone())
+            .execute(&config, &plan)
+            .await
+            .expect_err("postCreate fails");
+
+        let runs = transcript.runs();
+        let failed = runs.last().expect("postCreate recorded");
+        assert_eq!(failed.hook, "postCreate");
+        assert_eq!(failed.exit_code, Some(2));
+        assert_eq!(
+            failed.command.last().map(String::as_str),
+            Some("echo post create")
+        );
+        assert_eq!(failed.output_tail.len(), transcript::OUTPUT_TAIL_LINES);
+        assert_eq!(failed.output_tail.last().map(String::as_str), Some("boom"));
+        let log = std::fs::read_to_string(failed.log_file.as_ref().expect("log written"))
+            .expect("read log");
+        assert!(log.starts_with("line 1\n"));
+        assert!(!runs.iter().any(|run| run.hook == "postAttach"));
+    }
+
+    #[tokio::test]
+    async fn executor_restarts_container_and_retries_hook_after_daemon_restart() {
+        let config = sample_config();
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let attempts = Arc::new(Mutex::new(0));
+        let counted = Arc::clone(&attempts);
+        let provider = MockProvider::new().with_exec_handler(move |command| {
+            let mut attempts = counted.lock().expect("attempts lock");
+            if command.concat().contains("post create") {
+                *attempts += 1;
+            }
+            if *attempts == 1 {
+                ExecResult {
+                    exit_code: 1,
+                    stdout: String::new(),
+                    stderr: "error during connect: unexpected EOF".to_string(),
+                }
+            } else {
+                ExecResult::default()
+            }
+        });
+
+        let outcome = LifecycleExecutor::new(provider.clone())
+            .execute(&config, &plan)
+            .await
+            .expect("postCreate retried");
+
+        assert_eq!(*attempts.lock().expect("attempts lock"), 2);
+        assert!(outcome
+            .executed_phases
+            .contains(&LifecyclePhase::PostAttach));
+        let starts = provider
+            .calls()
+            .into_iter()
+            .filter(|call| matches!(call, MockCall::StartContainer { .. }))
+            .count();
+        assert_eq!(starts, 2);
+    }
+
+    #[tokio::test]
+    async fn executor_does_not_retry_hooks_of_a_stopped_container_without_a_restart() {
+        let config = sample_config();
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let attempts = Arc::new(Mutex::new(0));
+        let counted = Arc::clone(&attempts);
+        let provider = MockProvider::new()
+            .with_exec_handler(move |command| {
+                let mut attempts = counted.lock().expect("attempts lock");
+                if command.concat().contains("post create") {
+                    *attempts += 1;
+                    return ExecResult {
+                        exit_code: 137,
+                        ..Default::default()
+                    };
+                }
+                ExecResult::default()
+            })
+            .with_activity(vec![ContainerActivity {
+                running: false,
+                sessions: 0,
+            }]);
+
+        let err = LifecycleExecutor::new(provider)
+            .execute(&config, &plan)
+            .await
+            .expect_err("postCreate failed");
+
+        assert!(matches!(
+            err,
+            DevcontainerError::HookFailed { exit_code: 137, .. }
+        ));
+        assert_eq!(*attempts.lock().expect("attempts lock"), 1);
+    }
+
+    #[tokio::test]
+    async fn run_hooks_reruns_only_the_planned_hooks_in_the_container() {
+        let config = sample_config();
+        let plan = LifecyclePlan::for_hooks(
+            &config,
+            &[LifecycleHook::PostStart, LifecycleHook::PostAttach],
+        );
+        assert_eq!(plan.steps.len(), 2);
+        let provider = MockProvider::new();
+
+        let phases = LifecycleExecutor::new(provider.clone())
+            .run_hooks(&config, &plan, &RunningContainer::default())
+            .await
+            .expect("hooks run");
+
+        assert_eq!(
+            phases,
+            [LifecyclePhase::PostStart, LifecyclePhase::PostAttach]
+        );
+        let commands: Vec<Vec<String>> = provider
+            .calls()
+            .into_iter()
+            .filter_map(|call| match call {
+                MockCall::Exec { command } | MockCall::ExecAs { command, .. } => Some(command),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(commands, [["echo", "post-attach"]]);
+    }
+
+    #[tokio::test]
+    async fn executor_runs_hooks_as_configured_user() {
+        let mut config = sample_config();
+        config.remote_user = Some("vscode".to_string());
+        config
+            .hook_users
+            .insert("postCreate".to_string(), "root".to_string());
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let provider = MockProvider::new();
+
+        LifecycleExecutor::new(provider.clone())
+            .execute(&config, &plan)
+            .await
+            .expect("lifecycle execution succeeds");
+
+        let users: Vec<String> = provider
+            .calls()
+            .into_iter()
+            .filter_map(|call| match call {
+                MockCall::ExecAs { user, command } if !is_marker_command(&command) => Some(user),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(users, vec!["root".to_string(), "vscode".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn executor_applies_remote_env_to_hooks() {
+        let mut config = sample_config();
+        config
+            .remote_env
+            .insert("MODE".to_string(), Some("remote".to_string()));
+        config.cli_env.insert("MODE".to_string(), "cli".to_string());
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let provider = MockProvider::new();
+
+        LifecycleExecutor::new(provider.clone())
+            .execute(&config, &plan)
+            .await
+            .expect("lifecycle execution succeeds");
+
+        assert_eq!(
+            provider.exec_calls()[0],
+            vec!["env", "MODE=cli", "/bin/sh", "-c", "echo post create"]
+        );
+    }
+
+    #[tokio::test]
+    async fn executor_applies_hook_env_to_its_hook_only() {
+        let mut config = sample_config();
+        config
+            .remote_env
+            .insert("CI".to_string(), Some("false".to_string()));
+        config.hook_env.insert(
+            "postCreate".to_string(),
+            std::collections::BTreeMap::from([("CI".to_string(), Some("true".to_string()))]),
+        );
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let provider = MockProvider::new();
+
+        LifecycleExecutor::new(provider.clone())
+            .execute(&config, &plan)
+            .await
+            .expect("lifecycle execution succeeds");
+
+        let calls = hook_execs(provider.exec_calls());
+        assert_eq!(
+            calls[0],
+            vec!["env", "CI=true", "/bin/sh", "-c", "echo post create"]
+        );
+        assert_eq!(calls[1][..2], ["env", "CI=false"]);
+    }
+
+    #[tokio::test]
+    async fn executor_streams_hook_output_to_sink() {
+        let config = sample_config();
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let provider = MockProvider::new().with_exec_handler(|command| ExecResult {
+            exit_code: 0,
+            stdout: format!("ran {}\n", command.last().expect("command")),
+            stderr: String::new(),
+        });
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        LifecycleExecutor::new(provider)
+            .with_output_sink(OutputSink::channel(sender))
+            .execute(&config, &plan)
+            .await
+            .expect("lifecycle execution succeeds");
+
+        let mut lines = Vec::new();
+        while let Ok(line) = receiver.try_recv() {
+            lines.push(line.line);
+        }
+        assert_eq!(lines, vec!["ran echo post create", "ran post-attach"]);
+    }
+
+    #[tokio::test]
+    async fn executor_runs_object_form_commands_concurrently() {
+        let mut config = sample_config();
+        config.post_create_command = Some(CommandDefinition::Parallel(
+            [
+                ("lint", "echo lint"),
+                ("test", "echo test"),
+                ("docs", "echo docs"),
+            ]
+            .into_iter()
+            .map(|(name, command)| (name.to_string(), CommandArgs::String(command.to_string())))
+            .collect(),
+        ));
+        config.post_attach_command = None;
+        let plan = LifecyclePlan::for_up(&config, LifecyclePlanOptions::default());
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let provider = MockProvider::new()
+            .with_exec_delay(std::time::Duration::from_millis(200))
+            .with_exec_handler(|command| {
+                let script = command.last().expect("command");
+                ExecResult {
+                    exit_code: if script.contains("docs") { 0 } else { 1 },
+                    stdout: format!("ran {script}\n"),
+                    stderr: String::new(),
+                }
+            });
+
+        let started = std::time::Instant::now();
+        let err = LifecycleExecutor::new(provider.clone())
+            .with_output_sink(OutputSink::channel(sender))
+            .execute(&config, &plan)
+            .await
+            .expect_err("two commands fail");
+
+        assert!(started.elapsed() < std::time::Duration::from_millis(550));
+        assert_eq!(provider.exec_calls().len(), 3);
+        let message = err.to_string();
+        assert!(message.contains("2 of 3 postCreate commands failed"));
+        assert!(message.contains("'lint'") && message.contains("'test'"));
+
+        let mut lines = Vec::new();
+        while let Ok(line) = receiver.try_recv() {
+            lines.push(line.line);
+        }
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                "[docs] ran echo docs",
+                "[lint] ran echo lint",
+                "[test] ran echo test"
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn executor_skips_hooks_when_commands_absent() {
         let mut config = sample_config();
@@ -903,10 +2825,14 @@ mod tests {
             outcome.executed_phases,
             vec![
                 LifecyclePhase::Resolve,
+                LifecyclePhase::Initialize,
                 LifecyclePhase::Build,
                 LifecyclePhase::Create,
                 LifecyclePhase::Start,
+                LifecyclePhase::OnCreate,
+                LifecyclePhase::UpdateContent,
                 LifecyclePhase::PostCreate,
+                LifecyclePhase::PostStart,
                 LifecyclePhase::PostAttach,
             ]
         );
@@ -0,0 +1,86 @@
+//! Per-command results of lifecycle hooks, for machine-readable `up` output that
+//! CI systems can annotate failures from without re-parsing logs.
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+
+use crate::provider::OutputSink;
+
+/// Lines of output kept in a [`HookRun`]; the log file has the rest.
+pub const OUTPUT_TAIL_LINES: usize = 20;
+
+/// One command of a lifecycle hook; parallel hooks produce one run per named command.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HookRun {
+    pub hook: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_name: Option<String>,
+    pub command: Vec<String>,
+    /// `None` when the command could not be started at all.
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    /// Last [`OUTPUT_TAIL_LINES`] lines of stdout and stderr, interleaved as produced.
+    pub output_tail: Vec<String>,
+    /// Full output, when the executor has a state store to keep it in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_file: Option<PathBuf>,
+}
+
+/// Shared record of the hook commands an executor ran, in completion order. It
+/// outlives a failed `execute`, so the failing hook can still be reported.
+#[derive(Debug, Clone, Default)]
+pub struct HookTranscript {
+    runs: Arc<Mutex<Vec<HookRun>>>,
+}
+
+impl HookTranscript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn runs(&self) -> Vec<HookRun> {
+        self.runs.lock().expect("hook transcript lock").clone()
+    }
+
+    pub(crate) fn push(&self, run: HookRun) {
+        self.runs.lock().expect("hook transcript lock").push(run);
+    }
+}
+
+/// Keeps every line sent through [`OutputCapture::sink`] while forwarding it on.
+pub(crate) struct OutputCapture {
+    lines: Arc<Mutex<Vec<String>>>,
+    sink: OutputSink,
+}
+
+impl OutputCapture {
+    pub(crate) fn new(inner: OutputSink) -> Self {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let captured = Arc::clone(&lines);
+        let sink = OutputSink::new(move |line| {
+            captured
+                .lock()
+                .expect("output capture lock")
+                .push(line.line.clone());
+            inner.send(line.stream, line.line);
+        });
+        Self { lines, sink }
+    }
+
+    pub(crate) fn sink(&self) -> &OutputSink {
+        &self.sink
+    }
+
+    pub(crate) fn lines(&self) -> Vec<String> {
+        self.lines.lock().expect("output capture lock").clone()
+    }
+}
+
+pub(crate) fn output_tail(lines: &[String]) -> Vec<String> {
+    lines[lines.len().saturating_sub(OUTPUT_TAIL_LINES)..].to_vec()
+}
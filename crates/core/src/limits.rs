@@ -0,0 +1,271 @@
+//! Resource limits the CLI runs under, read from cgroups (v1 and v2) and
+//! `/proc/self/limits`, to explain builds that fail in constrained CI containers.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// cgroup v1 reports "no limit" as a page-aligned `i64::MAX`; anything this large is unlimited.
+const UNLIMITED_THRESHOLD: u64 = 1 << 60;
+
+const MIN_BUILD_MEMORY: u64 = 2 * 1024 * 1024 * 1024;
+const MIN_BUILD_PROCESSES: u64 = 1024;
+const MIN_BUILD_OPEN_FILES: u64 = 1024;
+
+/// Effective limits of the current process; `None` means unlimited or unknown.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResourceLimits {
+    pub memory_bytes: Option<u64>,
+    /// Tasks allowed by the pids cgroup controller.
+    pub pids: Option<u64>,
+    pub cpus: Option<f64>,
+    /// Soft `RLIMIT_NPROC`.
+    pub processes: Option<u64>,
+    /// Soft `RLIMIT_NOFILE`.
+    pub open_files: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub fn detect() -> Self {
+        let cgroup = fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+        let proc_limits = fs::read_to_string("/proc/self/limits").unwrap_or_default();
+        Self::from_sources(Path::new(CGROUP_ROOT), &cgroup, &proc_limits)
+    }
+
+    fn from_sources(root: &Path, cgroup: &str, proc_limits: &str) -> Self {
+        let cgroups = Cgroups::parse(root, cgroup);
+        Self {
+            memory_bytes: cgroups.limit("memory", "memory.limit_in_bytes", "memory.max"),
+            pids: cgroups.limit("pids", "pids.max", "pids.max"),
+            cpus: cgroups.cpus(),
+            processes: soft_limit(proc_limits, "Max processes"),
+            open_files: soft_limit(proc_limits, "Max open files"),
+        }
+    }
+
+    /// Limits likely to break image builds or feature installs.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if let Some(memory) = self
+            .memory_bytes
+            .filter(|memory| *memory < MIN_BUILD_MEMORY)
+        {
+            warnings.push(format!(
+                "memory is limited to {}; image builds and feature installs may be killed when they run out",
+                format_bytes(memory)
+            ));
+        }
+        let processes = [self.pids, self.processes].into_iter().flatten().min();
+        if let Some(processes) = processes.filter(|count| *count < MIN_BUILD_PROCESSES) {
+            warnings.push(format!(
+                "processes are limited to {processes}; parallel builds and package managers may fail to fork"
+            ));
+        }
+        if let Some(files) = self
+            .open_files
+            .filter(|files| *files < MIN_BUILD_OPEN_FILES)
+        {
+            warnings.push(format!(
+                "open files are limited to {files}; builds with many layers or dependencies may fail"
+            ));
+        }
+        warnings
+    }
+
+    /// Logs [`warnings`](Self::warnings) before work that needs resources.
+    pub fn warn_if_constrained(&self) {
+        for warning in self.warnings() {
+            tracing::warn!(limits = %self, "Constrained environment: {warning}");
+        }
+    }
+}
+
+impl fmt::Display for ResourceLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = |value: Option<u64>| value.map_or("unlimited".to_string(), |v| v.to_string());
+        write!(
+            f,
+            "memory={} pids={} cpus={} processes={} open_files={}",
+            self.memory_bytes
+                .map_or("unlimited".to_string(), format_bytes),
+            count(self.pids),
+            self.cpus
+                .map_or("unlimited".to_string(), |cpus| format!("{cpus:.2}")),
+            count(self.processes),
+            count(self.open_files),
+        )
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const MIB: u64 = 1024 * 1024;
+    if bytes >= 1024 * MIB {
+        format!("{:.1} GiB", bytes as f64 / (1024 * MIB) as f64)
+    } else {
+        format!("{} MiB", bytes / MIB)
+    }
+}
+
+/// Cgroup memberships of the current process, by v1 controller; v2 uses the empty name.
+struct Cgroups<'a> {
+    root: &'a Path,
+    memberships: BTreeMap<&'a str, &'a str>,
+}
+
+impl<'a> Cgroups<'a> {
+    fn parse(root: &'a Path, cgroup: &'a str) -> Self {
+        let mut memberships = BTreeMap::new();
+        for line in cgroup.lines() {
+            let mut parts = line.splitn(3, ':');
+            let (Some(_), Some(controllers), Some(path)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                continue;
+            };
+            for controller in controllers.split(',') {
+                memberships.insert(controller, path);
+            }
+        }
+        Self { root, memberships }
+    }
+
+    /// Smallest limit set on the process's cgroup or any ancestor.
+    fn limit(&self, controller: &str, v1_file: &str, v2_file: &str) -> Option<u64> {
+        let v1 = self
+            .v1_dirs(controller)
+            .into_iter()
+            .map(|dir| dir.join(v1_file));
+        let v2 = self.v2_dirs().into_iter().map(|dir| dir.join(v2_file));
+        v1.chain(v2).filter_map(|path| read_limit(&path)).min()
+    }
+
+    fn cpus(&self) -> Option<f64> {
+        let v1 = self.v1_dirs("cpu").into_iter().filter_map(|dir| {
+            let quota: i64 = read_trimmed(&dir.join("cpu.cfs_quota_us"))?.parse().ok()?;
+            let period: u64 = read_trimmed(&dir.join("cpu.cfs_period_us"))?.parse().ok()?;
+            (quota > 0 && period > 0).then(|| quota as f64 / period as f64)
+        });
+        let v2 = self.v2_dirs().into_iter().filter_map(|dir| {
+            let max = read_trimmed(&dir.join("cpu.max"))?;
+            let (quota, period) = max.split_once(' ')?;
+            let quota: u64 = quota.parse().ok()?;
+            let period: u64 = period.parse().ok()?;
+            (period > 0).then(|| quota as f64 / period as f64)
+        });
+        v1.chain(v2).min_by(f64::total_cmp)
+    }
+
+    /// v1 controllers are mounted in a subdirectory named after them.
+    fn v1_dirs(&self, controller: &str) -> Vec<PathBuf> {
+        match self.memberships.get(controller) {
+            Some(path) => ancestors(&self.root.join(controller), path),
+            None => Vec::new(),
+        }
+    }
+
+    fn v2_dirs(&self) -> Vec<PathBuf> {
+        match self.memberships.get("") {
+            Some(path) => ancestors(self.root, path),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// `base/path` and each of its parents up to `base`. Inside a container the
+/// process's cgroup path may not be mounted, leaving only `base`.
+fn ancestors(base: &Path, path: &str) -> Vec<PathBuf> {
+    let mut dir = base.join(path.trim_start_matches('/'));
+    let mut dirs = Vec::new();
+    loop {
+        dirs.push(dir.clone());
+        if dir == base || !dir.pop() {
+            break;
+        }
+    }
+    dirs
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|contents| contents.trim().to_string())
+}
+
+fn read_limit(path: &Path) -> Option<u64> {
+    read_trimmed(path)?
+        .parse()
+        .ok()
+        .filter(|limit| *limit < UNLIMITED_THRESHOLD)
+}
+
+/// Soft limit of the `/proc/self/limits` row starting with `name`.
+fn soft_limit(proc_limits: &str, name: &str) -> Option<u64> {
+    proc_limits
+        .lines()
+        .find_map(|line| line.strip_prefix(name))
+        .and_then(|values| values.split_whitespace().next())
+        .and_then(|soft| soft.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn write(path: PathBuf, contents: &str) {
+        fs::create_dir_all(path.parent().expect("parent")).expect("create dirs");
+        fs::write(path, contents).expect("write");
+    }
+
+    #[test]
+    fn reads_v1_and_v2_limits_and_warns() {
+        let root = tempdir().expect("tempdir");
+        let v1 = root.path().join("v1");
+        write(v1.join("memory/ci/memory.limit_in_bytes"), "536870912\n");
+        write(
+            v1.join("memory/memory.limit_in_bytes"),
+            "9223372036854771712\n",
+        );
+        write(v1.join("pids/pids.max"), "max\n");
+        write(v1.join("cpu/cpu.cfs_quota_us"), "150000\n");
+        write(v1.join("cpu/cpu.cfs_period_us"), "100000\n");
+        let proc_limits = "\
+Limit                     Soft Limit           Hard Limit           Units
+Max processes             512                  4096                 processes
+Max open files            unlimited            unlimited            files
+";
+        let limits = ResourceLimits::from_sources(
+            &v1,
+            "4:memory:/ci\n3:pids:/ci\n2:cpu,cpuacct:/\n0::/\n",
+            proc_limits,
+        );
+        assert_eq!(
+            limits,
+            ResourceLimits {
+                memory_bytes: Some(512 * 1024 * 1024),
+                pids: None,
+                cpus: Some(1.5),
+                processes: Some(512),
+                open_files: None,
+            }
+        );
+        let warnings = limits.warnings();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("512 MiB"));
+        assert!(warnings[1].contains("limited to 512"));
+
+        let v2 = root.path().join("v2");
+        write(v2.join("memory.max"), "max\n");
+        write(v2.join("job/pids.max"), "64\n");
+        write(v2.join("job/cpu.max"), "max 100000\n");
+        let limits = ResourceLimits::from_sources(&v2, "0::/job\n", "");
+        assert_eq!(limits.memory_bytes, None);
+        assert_eq!(limits.pids, Some(64));
+        assert_eq!(limits.cpus, None);
+        assert!(ResourceLimits::default().warnings().is_empty());
+    }
+}
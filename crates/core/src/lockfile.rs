@@ -0,0 +1,397 @@
+//! `devcontainer-lock.json`, next to the configuration, pinning the registry
+//! features and the image it uses to digests, so the environment comes out the
+//! same on every machine until the lockfile is upgraded.
+//!
+//! `up` and `build` bring the image up from the digest an existing lockfile
+//! pins without writing it, unless asked to: [`LockMode::Update`] adds the
+//! entries the lockfile lacks and drops the ones the configuration no longer
+//! uses, [`LockMode::Frozen`] fails on a lockfile that does not match, as CI
+//! wants, and [`LockMode::Upgrade`] resolves every entry afresh. Pulling with
+//! [`PullPolicy::Always`] refreshes the image's pin when updating and bypasses
+//! it otherwise, so floating tags can still be brought up to date.
+//!
+//! Only the image pin is applied; feature pins are recorded so changes to them
+//! show up in review, but features are still fetched by their tag. Local
+//! features are versioned with the configuration, and features from tarball
+//! URLs or references already carrying a digest are left out. The base images
+//! of a Dockerfile are not pinned.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{PullPolicy, ResolvedConfig},
+    provider::Provider,
+    DevcontainerError, Result,
+};
+
+/// File name of the lockfile, next to `devcontainer.json`.
+pub const LOCKFILE_NAME: &str = "devcontainer-lock.json";
+
+/// How [`sync_lockfile`] treats entries the lockfile lacks or already holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockMode {
+    /// Use the pins of an existing lockfile, never resolving or writing any.
+    #[default]
+    Use,
+    /// Keep pinned entries and resolve the ones the lockfile lacks.
+    Update,
+    /// Fail when the lockfile does not pin exactly what the configuration uses.
+    Frozen,
+    /// Resolve every entry afresh.
+    Upgrade,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lockfile {
+    /// Registry features by id, as the configuration names them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub features: BTreeMap<String, LockedFeature>,
+    /// Images by reference, as the configuration names them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub images: BTreeMap<String, LockedImage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockedFeature {
+    /// The feature's repository at the pinned digest, `repository@sha256:...`.
+    pub resolved: String,
+    /// Digest of the feature's manifest, `sha256:...`.
+    pub integrity: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockedImage {
+    /// The image's repository at the pinned digest, `repository@sha256:...`.
+    pub resolved: String,
+}
+
+impl Lockfile {
+    /// Lockfile of the configuration at `config_path`: `devcontainer-lock.json`
+    /// beside it, or `.devcontainer-lock.json` beside a `.devcontainer.json`.
+    pub fn path_for(config_path: &Path) -> PathBuf {
+        let hidden = config_path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            config_path.with_file_name(format!(".{LOCKFILE_NAME}"))
+        } else {
+            config_path.with_file_name(LOCKFILE_NAME)
+        }
+    }
+
+    /// Reads the lockfile at `path`, if there is one.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(DevcontainerError::Configuration(format!(
+                    "Failed to read {}: {err}",
+                    path.display()
+                )))
+            }
+        };
+        serde_json::from_str(&contents).map(Some).map_err(|err| {
+            DevcontainerError::Configuration(format!(
+                "{} is not a valid lockfile: {err}",
+                path.display()
+            ))
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut contents = serde_json::to_string_pretty(self)
+            .map_err(|err| DevcontainerError::Other(err.into()))?;
+        contents.push('\n');
+        fs::write(path, contents).map_err(|err| {
+            DevcontainerError::Configuration(format!("Failed to write {}: {err}", path.display()))
+        })
+    }
+}
+
+/// Brings the lockfile of `config` in line with its features and image as
+/// `mode` asks, writing it when it changed, and points `config` at the pinned
+/// image unless it is pulled afresh.
+pub async fn sync_lockfile<P: Provider + ?Sized>(
+    provider: &P,
+    config: &mut ResolvedConfig,
+    mode: LockMode,
+) -> Result<Lockfile> {
+    let path = Lockfile::path_for(&config.config_path);
+    let current = Lockfile::load(&path)?.unwrap_or_default();
+    let pull = config.pull_policy == PullPolicy::Always;
+    if mode == LockMode::Use {
+        if !pull {
+            pin_image(config, &current);
+        }
+        return Ok(current);
+    }
+
+    let mut lockfile = Lockfile::default();
+    for id in config.features.keys() {
+        if !lockable(id) {
+            continue;
+        }
+        let kept = current
+            .features
+            .get(id)
+            .filter(|_| mode != LockMode::Upgrade)
+            .cloned();
+        let locked = match kept {
+            Some(locked) => Some(locked),
+            None => resolve(provider, id, mode)
+                .await?
+                .map(|digest| LockedFeature {
+                    resolved: format!("{}@{digest}", repository(id)),
+                    integrity: digest,
+                }),
+        };
+        if let Some(locked) = locked {
+            lockfile.features.insert(id.clone(), locked);
+        }
+    }
+    if let Some(image) = config
+        .image_reference
+        .as_deref()
+        .filter(|image| !image.contains('@'))
+    {
+        let refresh = mode == LockMode::Upgrade || (pull && mode == LockMode::Update);
+        let kept = current.images.get(image).filter(|_| !refresh).cloned();
+        let locked = match kept {
+            Some(locked) => Some(locked),
+            None => resolve(provider, image, mode)
+                .await?
+                .map(|digest| LockedImage {
+                    resolved: format!("{}@{digest}", repository(image)),
+                }),
+        };
+        if let Some(locked) = locked {
+            lockfile.images.insert(image.to_string(), locked);
+        }
+    }
+
+    if lockfile != current {
+        if mode == LockMode::Frozen {
+            return Err(DevcontainerError::Configuration(format!(
+                "{} does not match the configuration; run `devcontainer upgrade` and commit it",
+                path.display()
+            )));
+        }
+        lockfile.save(&path)?;
+        tracing::info!(path = %path.display(), "Updated lockfile");
+    }
+
+    if pull && mode == LockMode::Frozen {
+        tracing::info!("Pulling the image afresh instead of the digest the lockfile pins");
+    } else {
+        pin_image(config, &lockfile);
+    }
+    Ok(lockfile)
+}
+
+/// Points `config` at the digest `lockfile` pins its image to, if any.
+fn pin_image(config: &mut ResolvedConfig, lockfile: &Lockfile) {
+    if let Some(locked) = config
+        .image_reference
+        .as_ref()
+        .and_then(|image| lockfile.images.get(image))
+    {
+        tracing::debug!(image = %locked.resolved, "Using image pinned by the lockfile");
+        config.image_reference = Some(locked.resolved.clone());
+    }
+}
+
+/// Whether the feature `id` comes from a registry at a version the lockfile can pin.
+fn lockable(id: &str) -> bool {
+    let local = id.starts_with("./") || id.starts_with("../");
+    let tarball = id.starts_with("https://") || id.starts_with("http://");
+    !local && !tarball && !id.contains('@')
+}
+
+/// Digest of `reference`, which a frozen lockfile should have pinned, or `None`
+/// when it cannot be resolved outside an upgrade. A frozen lockfile need not
+/// pin what the provider cannot resolve at all, as updating skips it too.
+async fn resolve<P: Provider + ?Sized>(
+    provider: &P,
+    reference: &str,
+    mode: LockMode,
+) -> Result<Option<String>> {
+    match mode {
+        LockMode::Frozen => match provider.resolve_digest(reference).await {
+            Err(DevcontainerError::Unsupported(reason)) => {
+                tracing::debug!(reference, %reason, "Not locking reference");
+                Ok(None)
+            }
+            _ => Err(DevcontainerError::Configuration(format!(
+                "The lockfile does not pin {reference}; run `devcontainer upgrade` and commit it"
+            ))),
+        },
+        LockMode::Upgrade => provider.resolve_digest(reference).await.map(Some),
+        LockMode::Use | LockMode::Update => match provider.resolve_digest(reference).await {
+            Ok(digest) => Ok(Some(digest)),
+            Err(DevcontainerError::Unsupported(reason)) => {
+                tracing::debug!(reference, %reason, "Not locking reference");
+                Ok(None)
+            }
+            Err(err) => {
+                tracing::warn!(reference, error = %err, "Failed to resolve digest; not locking it");
+                Ok(None)
+            }
+        },
+    }
+}
+
+/// `reference` without its tag.
+fn repository(reference: &str) -> &str {
+    let name_start = reference.rfind('/').map_or(0, |slash| slash + 1);
+    match reference[name_start..].find(':') {
+        Some(tag) => &reference[..name_start + tag],
+        None => reference,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::provider::mock::{MockCall, MockProvider};
+
+    fn resolutions(provider: &MockProvider) -> usize {
+        provider
+            .calls()
+            .iter()
+            .filter(|call| matches!(call, MockCall::ResolveDigest { .. }))
+            .count()
+    }
+
+    #[tokio::test]
+    async fn pins_registry_features_and_the_image() {
+        let dir = tempdir().expect("tempdir");
+        let config = ResolvedConfig {
+            config_path: dir.path().join("devcontainer.json"),
+            image_reference: Some("localhost:5000/base:1".to_string()),
+            features: serde_json::from_value(json!({
+                "ghcr.io/devcontainers/features/node:1": {},
+                "./local": {},
+                "https://example.com/feature.tgz": {}
+            }))
+            .expect("features"),
+            ..Default::default()
+        };
+        let provider = MockProvider::new();
+
+        let mut pinned = config.clone();
+        let lockfile = sync_lockfile(&provider, &mut pinned, LockMode::Update)
+            .await
+            .expect("lock");
+        assert_eq!(
+            Lockfile::load(&dir.path().join(LOCKFILE_NAME)).expect("load"),
+            Some(lockfile.clone())
+        );
+        let node = &lockfile.features["ghcr.io/devcontainers/features/node:1"];
+        assert_eq!(
+            node.resolved,
+            format!("ghcr.io/devcontainers/features/node@{}", node.integrity)
+        );
+        assert_eq!(lockfile.features.len(), 1);
+        let image = pinned.image_reference.expect("image");
+        assert!(image.starts_with("localhost:5000/base@sha256:"));
+        assert_eq!(resolutions(&provider), 2);
+
+        let mut again = config.clone();
+        sync_lockfile(&provider, &mut again, LockMode::Frozen)
+            .await
+            .expect("lockfile matches");
+        assert_eq!(again.image_reference, Some(image));
+        assert_eq!(resolutions(&provider), 2);
+
+        let mut changed = config.clone();
+        changed.features.insert(
+            "ghcr.io/devcontainers/features/git:1".to_string(),
+            json!({}),
+        );
+        let err = sync_lockfile(&provider, &mut changed.clone(), LockMode::Frozen)
+            .await
+            .expect_err("lockfile lacks git");
+        assert!(err.to_string().contains("devcontainer upgrade"));
+
+        sync_lockfile(&provider, &mut changed, LockMode::Upgrade)
+            .await
+            .expect("upgrade");
+        assert_eq!(resolutions(&provider), 6);
+    }
+
+    #[tokio::test]
+    async fn uses_existing_pins_without_writing_and_pull_refreshes_them() {
+        let dir = tempdir().expect("tempdir");
+        let path = dir.path().join(LOCKFILE_NAME);
+        let config = ResolvedConfig {
+            config_path: dir.path().join("devcontainer.json"),
+            image_reference: Some("localhost:5000/base:1".to_string()),
+            ..Default::default()
+        };
+        let provider = MockProvider::new();
+
+        let mut unlocked = config.clone();
+        sync_lockfile(&provider, &mut unlocked, LockMode::Use)
+            .await
+            .expect("use");
+        assert!(!path.exists());
+        assert_eq!(unlocked.image_reference, config.image_reference);
+
+        let stale = Lockfile {
+            images: BTreeMap::from([(
+                "localhost:5000/base:1".to_string(),
+                LockedImage {
+                    resolved: "localhost:5000/base@sha256:0ld".to_string(),
+                },
+            )]),
+            ..Default::default()
+        };
+        stale.save(&path).expect("save");
+        let mut pinned = config.clone();
+        sync_lockfile(&provider, &mut pinned, LockMode::Use)
+            .await
+            .expect("use");
+        assert_eq!(
+            pinned.image_reference.as_deref(),
+            Some("localhost:5000/base@sha256:0ld")
+        );
+        assert_eq!(resolutions(&provider), 0);
+
+        let mut pulled = ResolvedConfig {
+            pull_policy: PullPolicy::Always,
+            ..config.clone()
+        };
+        sync_lockfile(&provider, &mut pulled.clone(), LockMode::Frozen)
+            .await
+            .expect("frozen");
+        let mut bypassed = pulled.clone();
+        sync_lockfile(&provider, &mut bypassed, LockMode::Use)
+            .await
+            .expect("use");
+        assert_eq!(bypassed.image_reference, config.image_reference);
+
+        let refreshed = sync_lockfile(&provider, &mut pulled, LockMode::Update)
+            .await
+            .expect("update");
+        assert_eq!(resolutions(&provider), 1);
+        assert_ne!(refreshed, stale);
+        assert_eq!(Lockfile::load(&path).expect("load"), Some(refreshed));
+        assert_ne!(
+            pulled.image_reference.as_deref(),
+            Some("localhost:5000/base@sha256:0ld")
+        );
+    }
+}
@@ -0,0 +1,368 @@
+//! High-level API for embedding the engine in other Rust tools, such as IDE
+//! integrations or bots, without shelling out to the CLI binary.
+//!
+//! A [`DevcontainerManager`] drives one configuration through the same resolver,
+//! lifecycle executor and state store as the CLI, so both see the same
+//! devcontainers.
+
+use std::{path::PathBuf, sync::Arc};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::{ConfigOverrides, ConfigResolver, ConfigSource, ResolvedConfig},
+    lifecycle::{
+        transcript::{HookRun, HookTranscript},
+        LifecycleExecutor, LifecycleHook, LifecyclePlan, LifecyclePlanOptions,
+    },
+    provider::{
+        discover_containers, layer_image_metadata, owned_by_workspace, ContainerActivity,
+        ExecResult, OutputLine, OutputSink, Provider, ProviderCleanupOptions, RunningContainer,
+    },
+    state::{LifecycleState, StateStore},
+    telemetry::ProgressEvent,
+    Result,
+};
+
+/// Something a [`DevcontainerManager`] operation reports while it runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManagerEvent {
+    /// Progress of a lifecycle phase.
+    Progress(ProgressEvent),
+    /// A line of output from a lifecycle hook or an executed command.
+    Output(OutputLine),
+}
+
+type EventHandler = dyn Fn(ManagerEvent) + Send + Sync;
+
+/// Result of [`DevcontainerManager::up`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpOutcome {
+    pub container: RunningContainer,
+    /// Whether an existing container with an unchanged configuration was reused.
+    pub reused: bool,
+    pub remote_user: String,
+    pub remote_workspace_folder: PathBuf,
+    /// Hook commands run, in completion order.
+    pub hooks: Vec<HookRun>,
+}
+
+/// Result of [`DevcontainerManager::status`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DevcontainerStatus {
+    /// Record of the last `up`, when there is one.
+    pub state: Option<LifecycleState>,
+    /// `None` when nothing is recorded or the engine cannot report it.
+    pub activity: Option<ContainerActivity>,
+    /// Whether the configuration changed since the recorded `up`.
+    pub config_changed: bool,
+}
+
+/// Brings one devcontainer configuration up and down, and runs commands in it.
+pub struct DevcontainerManager<P: Provider> {
+    provider: P,
+    source: ConfigSource,
+    overrides: ConfigOverrides,
+    plan_options: LifecyclePlanOptions,
+    state: Option<StateStore>,
+    events: Option<Arc<EventHandler>>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl<P: Provider> DevcontainerManager<P> {
+    /// Manages the configuration of `source`, recording it in the default state
+    /// store like the CLI does.
+    pub fn new(provider: P, source: ConfigSource) -> Self {
+        Self {
+            provider,
+            source,
+            overrides: ConfigOverrides::default(),
+            plan_options: LifecyclePlanOptions::default(),
+            state: StateStore::default_location(),
+            events: None,
+            cancellation: None,
+        }
+    }
+
+    pub fn with_overrides(mut self, overrides: ConfigOverrides) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Hooks to skip on [`up`](Self::up).
+    pub fn with_plan_options(mut self, options: LifecyclePlanOptions) -> Self {
+        self.plan_options = options;
+        self
+    }
+
+    pub fn with_state_store(mut self, store: StateStore) -> Self {
+        self.state = Some(store);
+        self
+    }
+
+    pub fn with_events(mut self, handler: impl Fn(ManagerEvent) + Send + Sync + 'static) -> Self {
+        self.events = Some(Arc::new(handler));
+        self
+    }
+
+    /// Interrupts [`up`](Self::up), and the `up` of [`exec`](Self::exec), once
+    /// `token` is cancelled, removing the container they were creating.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    pub fn resolve(&self) -> Result<ResolvedConfig> {
+        ConfigResolver::new(self.source.clone())
+            .with_overrides(self.overrides.clone())
+            .resolve()
+    }
+
+    /// Creates or reuses the devcontainer and runs every lifecycle hook in it
+    /// before returning, as nothing continues hooks deferred by `waitFor`.
+    pub async fn up(&self) -> Result<UpOutcome> {
+        let resolved = layer_image_metadata(&self.provider, self.resolve()?).await?;
+        let plan = LifecyclePlan::for_up(&resolved, self.plan_options.clone());
+        let transcript = HookTranscript::new();
+        let executor = self
+            .executor()
+            .with_wait_for(LifecycleHook::ALL.last().copied())
+            .with_transcript(transcript.clone());
+        let outcome = executor.execute(&resolved, &plan).await?;
+        Ok(UpOutcome {
+            container: outcome.container,
+            reused: outcome.reused,
+            remote_user: resolved
+                .remote_user
+                .clone()
+                .unwrap_or_else(|| "root".to_string()),
+            remote_workspace_folder: outcome.remote_workspace_folder,
+            hooks: transcript.runs(),
+        })
+    }
+
+    /// Stops and removes the devcontainer, any other container of the workspace
+    /// with its labels, and the resources `options` select, forgetting its record.
+    pub async fn down(&self, options: &ProviderCleanupOptions) -> Result<()> {
+        let resolved = self.resolve()?;
+        let preparation = self.provider.prepare(&resolved).await?;
+        // `up` may have named the container differently, e.g. under another
+        // container workspace folder, so its record takes precedence.
+        let container = self
+            .recorded_container(&resolved)
+            .unwrap_or_else(|| RunningContainer {
+                name: Some(preparation.container_name.clone()),
+                ..Default::default()
+            });
+
+        for discovered in discover_containers(&self.provider, &resolved.id_labels).await? {
+            if discovered.name.is_some() && discovered.name == container.name {
+                continue;
+            }
+            if !owned_by_workspace(&self.provider, &resolved, &discovered).await? {
+                tracing::warn!(
+                    container = ?discovered.name,
+                    "Leaving a container with the same id labels that belongs to another workspace"
+                );
+                continue;
+            }
+            self.provider
+                .stop_container(&resolved, &preparation, &discovered)
+                .await?;
+            self.provider
+                .remove_container(&discovered, options.remove_volumes)
+                .await?;
+        }
+
+        self.provider
+            .stop_container(&resolved, &preparation, &container)
+            .await?;
+        self.provider
+            .cleanup(&resolved, &preparation, options)
+            .await?;
+        if let Some(store) = &self.state {
+            store.remove(&resolved)?;
+        }
+        Ok(())
+    }
+
+    /// Runs `command` in the devcontainer with its remote environment, bringing
+    /// it up without hooks when there is none yet.
+    pub async fn exec(&self, command: &[String]) -> Result<ExecResult> {
+        let resolved = self.resolve()?;
+        let container = self.ensure_container(&resolved).await?;
+        let command = resolved.env_stack().wrap_command(command);
+        self.provider
+            .exec_streaming(&container, None, &command, &self.output_sink())
+            .await
+    }
+
+    pub async fn status(&self) -> Result<DevcontainerStatus> {
+        let resolved = self.resolve()?;
+        let state = match &self.state {
+            Some(store) => store.load(&resolved)?,
+            None => None,
+        };
+        let activity = match state.as_ref().and_then(LifecycleState::running_container) {
+            Some(container) => match self.provider.container_activity(&container).await {
+                Ok(activity) => Some(activity),
+                Err(err) => {
+                    tracing::debug!(error = %err, "Container activity is unavailable");
+                    None
+                }
+            },
+            None => None,
+        };
+        let config_changed = state.as_ref().is_some_and(|state| {
+            state.config_hash.as_deref() != Some(resolved.config_hash().as_str())
+        });
+        Ok(DevcontainerStatus {
+            state,
+            activity,
+            config_changed,
+        })
+    }
+
+    fn executor(&self) -> LifecycleExecutor<&P> {
+        let mut executor = LifecycleExecutor::new(&self.provider);
+        if let Some(store) = &self.state {
+            executor = executor.with_state_store(store.clone());
+        }
+        if let Some(token) = &self.cancellation {
+            executor = executor.with_cancellation(token.clone());
+        }
+        if let Some(events) = &self.events {
+            let events = Arc::clone(events);
+            executor = executor
+                .with_output_sink(self.output_sink())
+                .with_progress_handler(move |event| events(ManagerEvent::Progress(event.clone())));
+        }
+        executor
+    }
+
+    /// Forwards output to the event handler, or logs it without one.
+    fn output_sink(&self) -> OutputSink {
+        match &self.events {
+            Some(events) => {
+                let events = Arc::clone(events);
+                OutputSink::new(move |line| events(ManagerEvent::Output(line)))
+            }
+            None => OutputSink::tracing("exec"),
+        }
+    }
+
+    fn recorded_container(&self, resolved: &ResolvedConfig) -> Option<RunningContainer> {
+        match self.state.as_ref()?.load(resolved) {
+            Ok(state) => state?.running_container(),
+            Err(err) => {
+                tracing::debug!(error = %err, "Ignoring unreadable devcontainer record");
+                None
+            }
+        }
+    }
+
+    async fn ensure_container(&self, resolved: &ResolvedConfig) -> Result<RunningContainer> {
+        if let Some(container) = self.recorded_container(resolved) {
+            if self.provider.start_container(&container).await.is_ok() {
+                return Ok(container);
+            }
+        }
+        if let Some(container) = discover_containers(&self.provider, &resolved.id_labels)
+            .await?
+            .into_iter()
+            .next()
+        {
+            self.provider.start_container(&container).await?;
+            return Ok(container);
+        }
+
+        let plan = LifecyclePlan::for_up(
+            resolved,
+            LifecyclePlanOptions::skip_all("exec command requested"),
+        );
+        Ok(self.executor().execute(resolved, &plan).await?.container)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        sync::{Arc, Mutex},
+    };
+
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::provider::{
+        mock::{MockCall, MockProvider},
+        OutputStream,
+    };
+
+    #[tokio::test]
+    async fn manages_a_devcontainer_through_its_lifecycle() {
+        let workspace = tempdir().expect("tempdir");
+        fs::write(
+            workspace.path().join("devcontainer.json"),
+            json!({ "image": "example:image", "postCreateCommand": "make" }).to_string(),
+        )
+        .expect("write config");
+        let state = tempdir().expect("state dir");
+        let provider = MockProvider::new().with_exec_handler(|command| ExecResult {
+            stdout: command.join(" "),
+            ..Default::default()
+        });
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        let manager = DevcontainerManager::new(
+            provider,
+            ConfigSource::Workspace(workspace.path().to_path_buf()),
+        )
+        .with_state_store(StateStore::new(state.path()))
+        .with_events(move |event| recorded.lock().expect("events lock").push(event));
+
+        let up = manager.up().await.expect("up");
+        assert!(!up.reused);
+        assert_eq!(up.remote_user, "root");
+        assert_eq!(up.hooks.len(), 1);
+        assert!(events
+            .lock()
+            .expect("events lock")
+            .iter()
+            .any(|event| matches!(
+                event,
+                ManagerEvent::Progress(ProgressEvent::Start { phase, .. })
+                    if phase == "lifecycle.hook.postCreate"
+            )));
+
+        let status = manager.status().await.expect("status");
+        assert!(status.state.is_some());
+        assert_eq!(status.activity.map(|activity| activity.running), Some(true));
+        assert!(!status.config_changed);
+
+        let command = ["echo".to_string(), "hi".to_string()];
+        let result = manager.exec(&command).await.expect("exec");
+        assert_eq!(result.exit_code, 0);
+        assert!(events
+            .lock()
+            .expect("events lock")
+            .contains(&ManagerEvent::Output(OutputLine {
+                stream: OutputStream::Stdout,
+                line: result.stdout.clone(),
+            })));
+
+        manager
+            .down(&ProviderCleanupOptions::default())
+            .await
+            .expect("down");
+        assert!(manager.provider().calls().contains(&MockCall::Cleanup {
+            container_name: up.container.name.clone().expect("container name"),
+        }));
+        assert!(manager.status().await.expect("status").state.is_none());
+    }
+}
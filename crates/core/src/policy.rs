@@ -0,0 +1,379 @@
+//! Admin policies restricting the configurations a machine will run, such as
+//! one an organisation rolls out to every developer laptop.
+//!
+//! The policy at `/etc/devcontainers/policy.json` (`%ProgramData%` on Windows)
+//! always applies; `--policy` adds another on top. A policy can deny privileged
+//! containers, restrict the registries and images containers come from,
+//! including the base images of a Dockerfile and sidecars, cap
+//! `hostRequirements` and allow only listed `runArgs` flags:
+//!
+//! ```json
+//! {
+//!     "denyPrivileged": true,
+//!     "allowedRegistries": ["mcr.microsoft.com", "ghcr.io"],
+//!     "allowedImages": ["mcr.microsoft.com/devcontainers/*", "ghcr.io/acme/base"],
+//!     "maxHostRequirements": { "cpus": 8, "memory": "16gb", "storage": "64gb" },
+//!     "allowedRunArgs": ["--add-host", "--shm-size"]
+//! }
+//! ```
+//!
+//! The CLI calls [`enforce`] before it builds or creates a container, naming
+//! every violation at once. Existing containers can still be inspected and
+//! removed after a policy is tightened. Properties features set in their
+//! metadata are not covered.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    config::{merge::parse_size, ResolvedConfig},
+    dockerfile,
+    registry::registry_for_image,
+    DevcontainerError, Result,
+};
+
+/// Policy applying to every configuration when no other location is configured.
+pub const SYSTEM_POLICY_PATH: &str = "/etc/devcontainers/policy.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Policy {
+    /// File the policy was read from, named in violations.
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+    /// Rejects `"privileged": true` and `--privileged` in `runArgs`.
+    #[serde(default)]
+    pub deny_privileged: bool,
+    /// Registries images may be pulled from, such as `docker.io`; any when unset.
+    #[serde(default)]
+    pub allowed_registries: Option<Vec<String>>,
+    /// Images allowed, with or without their tag; a trailing `*` matches any
+    /// reference starting with the rest. Any when unset.
+    #[serde(default)]
+    pub allowed_images: Option<Vec<String>>,
+    #[serde(default)]
+    pub max_host_requirements: Option<MaxHostRequirements>,
+    /// `runArgs` flags allowed, such as `--shm-size`; any when unset and none
+    /// when empty.
+    #[serde(default)]
+    pub allowed_run_args: Option<Vec<String>>,
+}
+
+/// Largest `hostRequirements` a configuration may ask for.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaxHostRequirements {
+    #[serde(default)]
+    pub cpus: Option<u64>,
+    /// Size such as `16gb`.
+    #[serde(default)]
+    pub memory: Option<String>,
+    /// Size such as `64gb`.
+    #[serde(default)]
+    pub storage: Option<String>,
+}
+
+impl Policy {
+    /// Location of the system policy: `%ProgramData%\devcontainers\policy.json`
+    /// on Windows, else [`SYSTEM_POLICY_PATH`].
+    pub fn system_path() -> PathBuf {
+        match env::var_os("ProgramData") {
+            Some(dir) if cfg!(windows) => {
+                PathBuf::from(dir).join("devcontainers").join("policy.json")
+            }
+            _ => PathBuf::from(SYSTEM_POLICY_PATH),
+        }
+    }
+
+    /// The system policy, if the machine has one.
+    pub fn system() -> Result<Option<Self>> {
+        let path = Self::system_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        Self::load(&path).map(Some)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path).map_err(|err| {
+            DevcontainerError::Configuration(format!(
+                "Failed to read policy {}: {err}",
+                path.display()
+            ))
+        })?;
+        let mut policy: Self = json5::from_str(&raw).map_err(|err| {
+            DevcontainerError::Configuration(format!(
+                "{} is not a valid policy: {err}",
+                path.display()
+            ))
+        })?;
+        if let Some(max) = &policy.max_host_requirements {
+            for (name, size) in [("memory", &max.memory), ("storage", &max.storage)] {
+                if let Some(size) = size.as_deref().filter(|size| parse_size(size).is_none()) {
+                    return Err(DevcontainerError::Configuration(format!(
+                        "maxHostRequirements.{name} '{size}' in {} is not a size such as 8gb",
+                        path.display()
+                    )));
+                }
+            }
+        }
+        policy.source = Some(path.to_path_buf());
+        Ok(policy)
+    }
+
+    /// What `config` does that the policy forbids.
+    pub fn violations(&self, config: &ResolvedConfig) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        let privileged_flag = config.run_args.iter().any(|arg| match arg.split_once('=') {
+            Some((flag, value)) => flag == "--privileged" && value != "false",
+            None => arg == "--privileged",
+        });
+        if self.deny_privileged && (config.privileged || privileged_flag) {
+            violations.push("privileged containers are not allowed".to_string());
+        }
+
+        for (property, image) in images(config) {
+            if let Some(reason) = self.image_rejection(&image) {
+                violations.push(format!("{property} '{image}' {reason}"));
+            }
+        }
+
+        if let (Some(max), Some(requested)) =
+            (&self.max_host_requirements, &config.host_requirements)
+        {
+            if let (Some(max), Some(cpus)) = (max.cpus, requested.cpus) {
+                if cpus > max {
+                    violations.push(format!(
+                        "hostRequirements.cpus {cpus} is more than the {max} allowed"
+                    ));
+                }
+            }
+            let sizes = [
+                ("memory", &max.memory, &requested.memory),
+                ("storage", &max.storage, &requested.storage),
+            ];
+            for (name, max, requested) in sizes {
+                let (Some(max), Some(requested)) = (max, requested) else {
+                    continue;
+                };
+                let over = match (parse_size(max), parse_size(requested)) {
+                    (Some(max), Some(requested)) => requested > max,
+                    _ => false,
+                };
+                if over {
+                    violations.push(format!(
+                        "hostRequirements.{name} {requested} is more than the {max} allowed"
+                    ));
+                }
+            }
+        }
+
+        if let Some(allowed) = &self.allowed_run_args {
+            for arg in config.run_args.iter().filter(|arg| arg.starts_with('-')) {
+                let flag = arg.split_once('=').map_or(arg.as_str(), |(flag, _)| flag);
+                if !allowed.iter().any(|allowed| allowed == flag) {
+                    violations.push(format!("runArgs flag '{flag}' is not allowed"));
+                }
+            }
+        }
+
+        violations
+    }
+
+    /// Why `image` may not be used, if it may not.
+    fn image_rejection(&self, image: &str) -> Option<String> {
+        if let Some(registries) = &self.allowed_registries {
+            let registry = registry_for_image(image);
+            if !registries
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(&registry))
+            {
+                return Some(format!(
+                    "is from {registry}, which is not an allowed registry ({})",
+                    registries.join(", ")
+                ));
+            }
+        }
+        if let Some(images) = &self.allowed_images {
+            if !images.iter().any(|pattern| image_matches(image, pattern)) {
+                return Some("is not an allowed image".to_string());
+            }
+        }
+        None
+    }
+}
+
+/// Fails with every violation of `policies` by `config`.
+pub fn enforce(policies: &[Policy], config: &ResolvedConfig) -> Result<()> {
+    let mut diagnostics = Vec::new();
+    for policy in policies {
+        let source = policy
+            .source
+            .as_deref()
+            .map_or_else(|| "policy".to_string(), |path| path.display().to_string());
+        diagnostics.extend(
+            policy
+                .violations(config)
+                .into_iter()
+                .map(|violation| format!("  {source}: {violation}")),
+        );
+    }
+    if diagnostics.is_empty() {
+        return Ok(());
+    }
+    Err(DevcontainerError::Configuration(format!(
+        "{} is not allowed by policy:\n{}",
+        config.config_path.display(),
+        diagnostics.join("\n")
+    )))
+}
+
+/// Images `config` runs or builds from, each with the property naming it.
+fn images(config: &ResolvedConfig) -> Vec<(String, String)> {
+    let mut images = Vec::new();
+    if let Some(image) = &config.image_reference {
+        images.push(("image".to_string(), image.clone()));
+    }
+    if let Some(path) = &config.dockerfile {
+        // An unreadable Dockerfile fails the build's preflight instead.
+        if let Ok(contents) = fs::read_to_string(path) {
+            for image in dockerfile::base_images(&contents, &config.build.args) {
+                images.push(("Dockerfile base image".to_string(), image));
+            }
+        }
+    }
+    for sidecar in &config.sidecars {
+        images.push((
+            format!("sidecars.{}.image", sidecar.name),
+            sidecar.image.clone(),
+        ));
+    }
+    images
+}
+
+/// Whether `image` is `pattern`, `pattern` at some tag or digest, or starts with
+/// `pattern` up to its trailing `*`.
+fn image_matches(image: &str, pattern: &str) -> bool {
+    if let Some(prefix) = pattern.strip_suffix('*') {
+        return image.starts_with(prefix);
+    }
+    let name_start = image.rfind('/').map_or(0, |slash| slash + 1);
+    let repository = match image[name_start..].find([':', '@']) {
+        Some(tag) => &image[..name_start + tag],
+        None => image,
+    };
+    image == pattern || repository == pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::config::{HostRequirements, Sidecar};
+
+    #[test]
+    fn reports_every_violation_of_the_policies() {
+        let dir = tempdir().expect("tempdir");
+        let policy_path = dir.path().join("policy.json");
+        fs::write(
+            &policy_path,
+            r#"{
+                // Company-wide rules.
+                "denyPrivileged": true,
+                "allowedRegistries": ["mcr.microsoft.com", "docker.io"],
+                "allowedImages": ["mcr.microsoft.com/devcontainers/*", "redis"],
+                "maxHostRequirements": { "cpus": 8, "memory": "16gb" },
+                "allowedRunArgs": ["--shm-size"]
+            }"#,
+        )
+        .expect("write policy");
+        let policies = [Policy::load(&policy_path).expect("load policy")];
+        let dockerfile = dir.path().join("Dockerfile");
+        fs::write(
+            &dockerfile,
+            "FROM mcr.microsoft.com/devcontainers/base:bookworm\nFROM ghcr.io/acme/tools:1\n",
+        )
+        .expect("write Dockerfile");
+
+        let allowed = ResolvedConfig {
+            config_path: dir.path().join("devcontainer.json"),
+            image_reference: Some("mcr.microsoft.com/devcontainers/rust:1".to_string()),
+            run_args: vec!["--shm-size=1g".to_string()],
+            sidecars: vec![Sidecar {
+                name: "cache".to_string(),
+                image: "redis:7".to_string(),
+                env: BTreeMap::new(),
+                ports: Vec::new(),
+            }],
+            host_requirements: Some(HostRequirements {
+                cpus: Some(4),
+                memory: Some("8gb".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        enforce(&policies, &allowed).expect("allowed configuration");
+
+        let violating = ResolvedConfig {
+            image_reference: None,
+            dockerfile: Some(dockerfile),
+            privileged: true,
+            run_args: vec!["--shm-size=1g".to_string(), "--network=host".to_string()],
+            host_requirements: Some(HostRequirements {
+                cpus: Some(16),
+                memory: Some("32gb".to_string()),
+                ..Default::default()
+            }),
+            ..allowed
+        };
+        let err = enforce(&policies, &violating).expect_err("violations");
+        let message = err.to_string();
+        for violation in [
+            "privileged containers are not allowed",
+            "Dockerfile base image 'ghcr.io/acme/tools:1' is from ghcr.io, which is not an allowed registry",
+            "hostRequirements.cpus 16 is more than the 8 allowed",
+            "hostRequirements.memory 32gb is more than the 16gb allowed",
+            "runArgs flag '--network' is not allowed",
+        ] {
+            assert!(message.contains(violation), "{violation} missing from {message}");
+        }
+        assert!(!message.contains("devcontainers/base"));
+        assert!(!message.contains("sidecars"));
+    }
+
+    #[test]
+    fn denies_privileged_run_args_with_a_value() {
+        let policy = Policy {
+            deny_privileged: true,
+            ..Default::default()
+        };
+        let with_run_args = |arg: &str| ResolvedConfig {
+            run_args: vec![arg.to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.violations(&with_run_args("--privileged=true")),
+            ["privileged containers are not allowed"]
+        );
+        assert!(policy
+            .violations(&with_run_args("--privileged=false"))
+            .is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_policy_properties() {
+        let dir = tempdir().expect("tempdir");
+        let path = dir.path().join("policy.json");
+        fs::write(&path, r#"{ "denyPrivilege": true }"#).expect("write policy");
+        let err = Policy::load(&path).expect_err("typo");
+        assert!(err.to_string().contains("not a valid policy"));
+    }
+}
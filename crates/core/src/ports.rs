@@ -0,0 +1,320 @@
+//! Local ports for the container ports listed in `forwardPorts`, and detection
+//! of those the container starts listening on later.
+//!
+//! Each forwarded port takes the label and scheme of its `portsAttributes`. A
+//! local port already taken on the host is swapped for a free one, unless the
+//! attributes set `requireLocalPort`. Ports detected later are only reported:
+//! an engine cannot publish ports of a running container, so forwarding them
+//! is left to the editor.
+
+use std::{
+    collections::BTreeSet,
+    net::{Ipv4Addr, TcpListener, UdpSocket},
+    time::Duration,
+};
+
+use tokio::time::sleep;
+
+use crate::{
+    config::{ForwardPort, OnAutoForward, PortAttributes, PortProtocol, ResolvedConfig},
+    provider::{Provider, RunningContainer},
+    telemetry::{ProgressEvent, ProgressStatus},
+    DevcontainerError, Result,
+};
+
+/// How often listening sockets are checked when no interval is configured.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Phase of the progress events announcing ports detected for `onAutoForward`.
+const AUTO_FORWARD_PHASE: &str = "autoForward";
+
+/// The forwarded ports of `config`, labelled from their attributes but with the
+/// local ports as configured.
+pub fn with_attributes(config: &ResolvedConfig) -> Vec<ForwardPort> {
+    config
+        .forward_ports
+        .iter()
+        .map(|port| {
+            let attributes = config.port_attributes(port.container_port);
+            ForwardPort {
+                label: attributes.and_then(|attributes| attributes.label.clone()),
+                scheme: attributes.and_then(|attributes| attributes.protocol),
+                ..port.clone()
+            }
+        })
+        .collect()
+}
+
+/// The forwarded ports of `config`, with the local ports they are reached at.
+pub fn forward(config: &ResolvedConfig) -> Result<Vec<ForwardPort>> {
+    with_attributes(config)
+        .into_iter()
+        .map(|port| {
+            let attributes = config.port_attributes(port.container_port);
+            assign_local_port(port, attributes)
+        })
+        .collect()
+}
+
+/// `port`, moved to a free local port when its own is taken and `attributes`
+/// allow it.
+fn assign_local_port(
+    mut port: ForwardPort,
+    attributes: Option<&PortAttributes>,
+) -> Result<ForwardPort> {
+    if local_port_free(port.local_port, &port.protocol) {
+        return Ok(port);
+    }
+    if attributes.is_some_and(|attributes| attributes.require_local_port) {
+        return Err(DevcontainerError::Configuration(format!(
+            "Local port {} for forwarded port {} is in use and requireLocalPort is set",
+            port.local_port, port.container_port
+        )));
+    }
+    let requested = port.local_port;
+    port.local_port = free_local_port(&port.protocol)?;
+    tracing::info!(
+        container_port = port.container_port,
+        requested,
+        local_port = port.local_port,
+        "Local port in use; forwarding from another"
+    );
+    Ok(port)
+}
+
+/// Detects ports the container starts listening on and reports them as their
+/// attributes' `onAutoForward` asks, until the container stops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortWatcher {
+    poll_interval: Duration,
+}
+
+impl Default for PortWatcher {
+    fn default() -> Self {
+        Self {
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+}
+
+impl PortWatcher {
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Polls the container's listening TCP sockets, passing each newly detected
+    /// port, labelled from its attributes, to `detected`. Ports in `forwardPorts`
+    /// and ports whose attributes say `ignore` are left alone; every other port
+    /// is announced as a progress event unless its attributes say `silent`.
+    pub async fn run<P: Provider + ?Sized>(
+        &self,
+        provider: &P,
+        config: &ResolvedConfig,
+        container: &RunningContainer,
+        mut detected: impl FnMut(&ForwardPort),
+    ) -> Result<()> {
+        let mut seen: BTreeSet<u16> = config
+            .forward_ports
+            .iter()
+            .map(|port| port.container_port)
+            .collect();
+        loop {
+            if !provider.container_activity(container).await?.running {
+                tracing::info!(?container.name, "Container is no longer running; port watching ended");
+                return Ok(());
+            }
+
+            let command = ["cat", "/proc/net/tcp", "/proc/net/tcp6"].map(str::to_string);
+            // A kernel without IPv6 has no tcp6 table, failing `cat` after the tcp one.
+            let tables = provider.exec(container, &command).await?.stdout;
+            for port in listening_ports(&tables) {
+                if !seen.insert(port) {
+                    continue;
+                }
+                let attributes = config.port_attributes(port);
+                let action = attributes
+                    .map(|attributes| attributes.on_auto_forward)
+                    .unwrap_or_default();
+                if action == OnAutoForward::Ignore {
+                    tracing::debug!(port, "Not reporting ignored port");
+                    continue;
+                }
+                let port = ForwardPort {
+                    local_port: port,
+                    container_port: port,
+                    protocol: PortProtocol::Tcp,
+                    label: attributes.and_then(|attributes| attributes.label.clone()),
+                    scheme: attributes.and_then(|attributes| attributes.protocol),
+                };
+                if action != OnAutoForward::Silent {
+                    let name = match &port.label {
+                        Some(label) => format!("Detected port {} ({label})", port.container_port),
+                        None => format!("Detected port {}", port.container_port),
+                    };
+                    ProgressEvent::progress(
+                        AUTO_FORWARD_PHASE,
+                        name,
+                        ProgressStatus::Succeeded,
+                        None,
+                    )
+                    .emit();
+                }
+                detected(&port);
+            }
+
+            sleep(self.poll_interval).await;
+        }
+    }
+}
+
+/// Ports of the sockets in the `LISTEN` state in `/proc/net/tcp` style `tables`.
+fn listening_ports(tables: &str) -> BTreeSet<u16> {
+    tables
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.get(3) != Some(&"0A") {
+                return None;
+            }
+            let (_, port) = fields.get(1)?.rsplit_once(':')?;
+            u16::from_str_radix(port, 16).ok()
+        })
+        .collect()
+}
+
+fn local_port_free(port: u16, protocol: &PortProtocol) -> bool {
+    match protocol {
+        PortProtocol::Tcp => TcpListener::bind((Ipv4Addr::LOCALHOST, port)).is_ok(),
+        PortProtocol::Udp => UdpSocket::bind((Ipv4Addr::LOCALHOST, port)).is_ok(),
+    }
+}
+
+fn free_local_port(protocol: &PortProtocol) -> Result<u16> {
+    let address = match protocol {
+        PortProtocol::Tcp => {
+            TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).and_then(|socket| socket.local_addr())
+        }
+        PortProtocol::Udp => {
+            UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).and_then(|socket| socket.local_addr())
+        }
+    };
+    address
+        .map(|address| address.port())
+        .map_err(|err| DevcontainerError::Other(err.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::{
+        config::PortScheme,
+        provider::{mock::MockProvider, ContainerActivity, ExecResult},
+    };
+
+    fn config_forwarding(port: u16, attributes: PortAttributes) -> ResolvedConfig {
+        ResolvedConfig {
+            forward_ports: vec![ForwardPort {
+                local_port: port,
+                container_port: port,
+                ..Default::default()
+            }],
+            ports_attributes: BTreeMap::from([(port.to_string(), attributes)]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn forwards_with_attributes_and_moves_off_taken_ports() {
+        let taken = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).expect("bind");
+        let port = taken.local_addr().expect("address").port();
+
+        let config = config_forwarding(
+            port,
+            PortAttributes {
+                label: Some("Web".to_string()),
+                protocol: Some(PortScheme::Https),
+                ..Default::default()
+            },
+        );
+        let forwarded = forward(&config).expect("forward");
+        assert_eq!(forwarded[0].container_port, port);
+        assert_ne!(forwarded[0].local_port, port);
+        assert_eq!(forwarded[0].label.as_deref(), Some("Web"));
+        assert_eq!(
+            forwarded[0].address(),
+            format!("https://localhost:{}", forwarded[0].local_port)
+        );
+
+        let config = config_forwarding(
+            port,
+            PortAttributes {
+                require_local_port: true,
+                ..Default::default()
+            },
+        );
+        let err = forward(&config).expect_err("local port is taken");
+        assert!(err.to_string().contains("requireLocalPort"));
+    }
+
+    #[tokio::test]
+    async fn detects_new_listening_ports() {
+        let tables = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:0BB8 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 1
+   1: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 2
+   2: 0100007F:1F91 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 3
+   3: 0100007F:A2C4 0100007F:0BB8 01 00000000:00000000 00:00000000 00000000  1000        0 4
+";
+        let running = ContainerActivity {
+            running: true,
+            sessions: 0,
+        };
+        let provider = MockProvider::new()
+            .with_activity(vec![running, running, ContainerActivity::default()])
+            .with_exec_handler(move |_| ExecResult {
+                exit_code: 1,
+                stdout: tables.to_string(),
+                stderr: "cat: /proc/net/tcp6: No such file or directory".to_string(),
+            });
+        let config = ResolvedConfig {
+            forward_ports: vec![ForwardPort {
+                local_port: 3000,
+                container_port: 3000,
+                ..Default::default()
+            }],
+            ports_attributes: BTreeMap::from([
+                (
+                    "8080".to_string(),
+                    PortAttributes {
+                        label: Some("Api".to_string()),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "8081".to_string(),
+                    PortAttributes {
+                        on_auto_forward: OnAutoForward::Ignore,
+                        ..Default::default()
+                    },
+                ),
+            ]),
+            ..Default::default()
+        };
+
+        let mut detected = Vec::new();
+        PortWatcher::default()
+            .with_poll_interval(Duration::from_millis(1))
+            .run(&provider, &config, &RunningContainer::default(), |port| {
+                detected.push(port.clone())
+            })
+            .await
+            .expect("watch");
+
+        assert_eq!(detected.len(), 1);
+        assert_eq!(detected[0].container_port, 8080);
+        assert_eq!(detected[0].label.as_deref(), Some("Api"));
+    }
+}
@@ -0,0 +1,544 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+
+use super::{
+    ContainerActivity, ContainerDetails, ContainerFilter, ContainerStatus, ExecOptions, ExecResult,
+    ImageInfo, InteractiveSession, OutputSink, OutputStream, Provider, ProviderCapabilities,
+    ProviderCleanupOptions, ProviderImage, ProviderKind, ProviderPreparation, RunningContainer,
+};
+use crate::{
+    config::{ContainerLabel, ResolvedConfig},
+    DevcontainerError, Result,
+};
+
+type ExecHandler = dyn Fn(&[String]) -> ExecResult + Send + Sync;
+type LabeledContainers = Vec<(RunningContainer, Vec<ContainerLabel>)>;
+
+/// Provider call recorded by [`MockProvider`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockCall {
+    Prepare {
+        project_name: String,
+    },
+    EnsureNetworks,
+    EnsureVolumes,
+    BuildImage {
+        image: String,
+    },
+    ImageExists {
+        image: String,
+    },
+    ResolveDigest {
+        reference: String,
+    },
+    CreateContainer {
+        container_name: String,
+        image: String,
+    },
+    FindContainers {
+        labels: Vec<ContainerLabel>,
+    },
+    InspectContainer {
+        container_name: String,
+    },
+    ListContainers {
+        filter: ContainerFilter,
+    },
+    RemoveContainer {
+        container_name: String,
+    },
+    StartContainer {
+        container_name: String,
+    },
+    Exec {
+        command: Vec<String>,
+    },
+    ExecAs {
+        user: String,
+        command: Vec<String>,
+    },
+    Attach {
+        session: InteractiveSession,
+    },
+    CopyInto {
+        source: PathBuf,
+        destination: PathBuf,
+    },
+    ContainerActivity {
+        container_name: String,
+    },
+    CommitContainer {
+        container_name: String,
+        tag: String,
+    },
+    StopContainer {
+        container_name: String,
+    },
+    Cleanup {
+        container_name: String,
+    },
+}
+
+/// In-memory provider that records every call, for tests and dry runs.
+#[derive(Clone)]
+pub struct MockProvider {
+    calls: Arc<Mutex<Vec<MockCall>>>,
+    exec_handler: Arc<ExecHandler>,
+    containers: Arc<Mutex<LabeledContainers>>,
+    images: Arc<Mutex<BTreeMap<String, ImageInfo>>>,
+    activity: Arc<Mutex<VecDeque<ContainerActivity>>>,
+    exec_delay: Option<Duration>,
+    capabilities: ProviderCapabilities,
+}
+
+impl MockProvider {
+    pub fn new() -> Self {
+        Self {
+            calls: Arc::new(Mutex::new(Vec::new())),
+            exec_handler: Arc::new(|_| ExecResult::default()),
+            containers: Arc::new(Mutex::new(Vec::new())),
+            images: Arc::new(Mutex::new(BTreeMap::new())),
+            activity: Arc::new(Mutex::new(VecDeque::new())),
+            exec_delay: None,
+            capabilities: ProviderCapabilities::all(),
+        }
+    }
+
+    /// Registers an existing container discoverable through `find_containers`.
+    pub fn with_container(self, container: RunningContainer, labels: Vec<ContainerLabel>) -> Self {
+        self.containers
+            .lock()
+            .expect("mock provider lock")
+            .push((container, labels));
+        self
+    }
+
+    /// Registers a locally available image returned by `image_exists`.
+    pub fn with_image(self, reference: impl Into<String>, details: ImageInfo) -> Self {
+        self.images
+            .lock()
+            .expect("mock provider lock")
+            .insert(reference.into(), details);
+        self
+    }
+
+    /// Reports these activity samples from successive `container_activity` calls, then
+    /// a running container without sessions.
+    pub fn with_activity(self, samples: Vec<ContainerActivity>) -> Self {
+        self.activity
+            .lock()
+            .expect("mock provider lock")
+            .extend(samples);
+        self
+    }
+
+    /// Computes the result of every `exec` call from the executed command.
+    pub fn with_exec_handler(
+        mut self,
+        handler: impl Fn(&[String]) -> ExecResult + Send + Sync + 'static,
+    ) -> Self {
+        self.exec_handler = Arc::new(handler);
+        self
+    }
+
+    /// Delays every `exec_streaming` call, simulating long-running commands.
+    pub fn with_exec_delay(mut self, delay: Duration) -> Self {
+        self.exec_delay = Some(delay);
+        self
+    }
+
+    /// Reports `capabilities` instead of all of them, standing in for a more
+    /// limited engine.
+    pub fn with_capabilities(mut self, capabilities: ProviderCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.calls.lock().expect("mock provider lock").clone()
+    }
+
+    pub fn exec_calls(&self) -> Vec<Vec<String>> {
+        self.calls()
+            .into_iter()
+            .filter_map(|call| match call {
+                MockCall::Exec { command } | MockCall::ExecAs { command, .. } => Some(command),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Registered containers matching `matches`, reported as running.
+    fn container_details(
+        &self,
+        matches: impl Fn(&RunningContainer, &[ContainerLabel]) -> bool,
+    ) -> Vec<ContainerDetails> {
+        self.containers
+            .lock()
+            .expect("mock provider lock")
+            .iter()
+            .filter(|(container, labels)| matches(container, labels))
+            .map(|(container, labels)| ContainerDetails {
+                id: container
+                    .id
+                    .clone()
+                    .unwrap_or_else(|| container_name(container)),
+                name: container.name.clone(),
+                status: ContainerStatus::Running,
+                labels: labels
+                    .iter()
+                    .map(|label| (label.name.clone(), label.value.clone()))
+                    .collect(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn record(&self, call: MockCall) {
+        self.calls.lock().expect("mock provider lock").push(call);
+    }
+}
+
+impl Default for MockProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn container_name(container: &RunningContainer) -> String {
+    container
+        .name
+        .clone()
+        .or_else(|| container.id.clone())
+        .unwrap_or_default()
+}
+
+#[async_trait]
+impl Provider for MockProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Mock
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.capabilities
+    }
+
+    async fn prepare(&self, config: &ResolvedConfig) -> Result<ProviderPreparation> {
+        self.record(MockCall::Prepare {
+            project_name: config.project_name.clone(),
+        });
+
+        Ok(ProviderPreparation {
+            image: ProviderImage::Reference(
+                config
+                    .image_reference
+                    .clone()
+                    .unwrap_or_else(|| "mock:latest".to_string()),
+            ),
+            container_name: format!("mock-{}", config.project_name),
+            project_slug: config.project_name.clone(),
+            networks: Vec::new(),
+            volumes: Vec::new(),
+            workspace_mount_path: PathBuf::from("/workspaces").join(&config.project_name),
+            workspace_volume: None,
+            sidecars: Vec::new(),
+        })
+    }
+
+    async fn ensure_networks(
+        &self,
+        _config: &ResolvedConfig,
+        _preparation: &ProviderPreparation,
+    ) -> Result<Vec<String>> {
+        self.record(MockCall::EnsureNetworks);
+        Ok(Vec::new())
+    }
+
+    async fn ensure_volumes(
+        &self,
+        _config: &ResolvedConfig,
+        _preparation: &ProviderPreparation,
+    ) -> Result<Vec<String>> {
+        self.record(MockCall::EnsureVolumes);
+        Ok(Vec::new())
+    }
+
+    async fn build_image(
+        &self,
+        _config: &ResolvedConfig,
+        preparation: &ProviderPreparation,
+    ) -> Result<String> {
+        let image = preparation.image.reference().to_string();
+        self.record(MockCall::BuildImage {
+            image: image.clone(),
+        });
+        Ok(image)
+    }
+
+    async fn image_exists(&self, reference: &str) -> Result<Option<ImageInfo>> {
+        self.record(MockCall::ImageExists {
+            image: reference.to_string(),
+        });
+        Ok(self
+            .images
+            .lock()
+            .expect("mock provider lock")
+            .get(reference)
+            .cloned())
+    }
+
+    /// Digests derive from the reference, so a reference always resolves the same.
+    async fn resolve_digest(&self, reference: &str) -> Result<String> {
+        self.record(MockCall::ResolveDigest {
+            reference: reference.to_string(),
+        });
+        let hex: String = Sha256::digest(reference.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        Ok(format!("sha256:{hex}"))
+    }
+
+    async fn create_container(
+        &self,
+        config: &ResolvedConfig,
+        preparation: &ProviderPreparation,
+        image_reference: &str,
+    ) -> Result<RunningContainer> {
+        self.record(MockCall::CreateContainer {
+            container_name: preparation.container_name.clone(),
+            image: image_reference.to_string(),
+        });
+        let container = RunningContainer {
+            id: Some(format!("{}-id", preparation.container_name)),
+            name: Some(preparation.container_name.clone()),
+            sidecars: Vec::new(),
+        };
+        let mut labels = config.id_labels.clone();
+        labels.push(config.config_hash_label());
+        let mut containers = self.containers.lock().expect("mock provider lock");
+        containers.retain(|(existing, _)| existing.name != container.name);
+        containers.push((container.clone(), labels));
+        Ok(container)
+    }
+
+    async fn find_containers(&self, labels: &[ContainerLabel]) -> Result<Vec<RunningContainer>> {
+        self.record(MockCall::FindContainers {
+            labels: labels.to_vec(),
+        });
+        let containers = self.containers.lock().expect("mock provider lock");
+        Ok(containers
+            .iter()
+            .filter(|(_, container_labels)| {
+                labels.iter().all(|label| container_labels.contains(label))
+            })
+            .map(|(container, _)| container.clone())
+            .collect())
+    }
+
+    async fn inspect_container(
+        &self,
+        container: &RunningContainer,
+    ) -> Result<Option<ContainerDetails>> {
+        let name = container_name(container);
+        self.record(MockCall::InspectContainer {
+            container_name: name.clone(),
+        });
+        Ok(self
+            .container_details(|existing, _| {
+                existing.name.as_ref() == Some(&name) || existing.id.as_ref() == Some(&name)
+            })
+            .into_iter()
+            .next())
+    }
+
+    async fn list_containers(&self, filter: &ContainerFilter) -> Result<Vec<ContainerDetails>> {
+        self.record(MockCall::ListContainers {
+            filter: filter.clone(),
+        });
+        Ok(self.container_details(|_, labels| {
+            filter.labels.iter().all(|label| labels.contains(label))
+        }))
+    }
+
+    async fn remove_container(
+        &self,
+        container: &RunningContainer,
+        _remove_volumes: bool,
+    ) -> Result<()> {
+        let name = container_name(container);
+        self.record(MockCall::RemoveContainer {
+            container_name: name.clone(),
+        });
+        self.containers
+            .lock()
+            .expect("mock provider lock")
+            .retain(|(existing, _)| container_name(existing) != name);
+        Ok(())
+    }
+
+    async fn start_container(&self, container: &RunningContainer) -> Result<()> {
+        self.record(MockCall::StartContainer {
+            container_name: container_name(container),
+        });
+        Ok(())
+    }
+
+    async fn exec(&self, _container: &RunningContainer, command: &[String]) -> Result<ExecResult> {
+        self.record(MockCall::Exec {
+            command: command.to_vec(),
+        });
+        Ok((self.exec_handler)(command))
+    }
+
+    async fn exec_as(
+        &self,
+        _container: &RunningContainer,
+        user: &str,
+        command: &[String],
+    ) -> Result<ExecResult> {
+        self.record(MockCall::ExecAs {
+            user: user.to_string(),
+            command: command.to_vec(),
+        });
+        Ok((self.exec_handler)(command))
+    }
+
+    async fn exec_streaming(
+        &self,
+        container: &RunningContainer,
+        user: Option<&str>,
+        command: &[String],
+        output: &OutputSink,
+    ) -> Result<ExecResult> {
+        let result = match user {
+            Some(user) => self.exec_as(container, user, command).await?,
+            None => self.exec(container, command).await?,
+        };
+        if let Some(delay) = self.exec_delay {
+            tokio::time::sleep(delay).await;
+        }
+        output.replay(OutputStream::Stdout, &result.stdout);
+        output.replay(OutputStream::Stderr, &result.stderr);
+        Ok(result)
+    }
+
+    async fn exec_with_options(
+        &self,
+        container: &RunningContainer,
+        options: &ExecOptions,
+        command: &[String],
+        output: &OutputSink,
+    ) -> Result<ExecResult> {
+        self.exec_streaming(container, options.user.as_deref(), command, output)
+            .await
+    }
+
+    async fn attach(
+        &self,
+        _container: &RunningContainer,
+        session: &InteractiveSession,
+    ) -> Result<i32> {
+        self.record(MockCall::Attach {
+            session: session.clone(),
+        });
+        Ok(0)
+    }
+
+    async fn daemon_ready(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn container_activity(&self, container: &RunningContainer) -> Result<ContainerActivity> {
+        self.record(MockCall::ContainerActivity {
+            container_name: container_name(container),
+        });
+        Ok(self
+            .activity
+            .lock()
+            .expect("mock provider lock")
+            .pop_front()
+            .unwrap_or(ContainerActivity {
+                running: true,
+                sessions: 0,
+            }))
+    }
+
+    async fn container_labels(
+        &self,
+        container: &RunningContainer,
+    ) -> Result<BTreeMap<String, String>> {
+        let name = container_name(container);
+        self.containers
+            .lock()
+            .expect("mock provider lock")
+            .iter()
+            .find(|(existing, _)| {
+                existing.name.as_ref() == Some(&name) || existing.id.as_ref() == Some(&name)
+            })
+            .map(|(_, labels)| {
+                labels
+                    .iter()
+                    .map(|label| (label.name.clone(), label.value.clone()))
+                    .collect()
+            })
+            .ok_or_else(|| DevcontainerError::Provider(format!("No such container: {name}")))
+    }
+
+    async fn copy_into(
+        &self,
+        _container: &RunningContainer,
+        source: &Path,
+        destination: &Path,
+    ) -> Result<()> {
+        self.record(MockCall::CopyInto {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    async fn commit_container(
+        &self,
+        _config: &ResolvedConfig,
+        container: &RunningContainer,
+        tag: &str,
+    ) -> Result<()> {
+        self.record(MockCall::CommitContainer {
+            container_name: container_name(container),
+            tag: tag.to_string(),
+        });
+        Ok(())
+    }
+
+    async fn stop_container(
+        &self,
+        _config: &ResolvedConfig,
+        _preparation: &ProviderPreparation,
+        container: &RunningContainer,
+    ) -> Result<()> {
+        self.record(MockCall::StopContainer {
+            container_name: container_name(container),
+        });
+        Ok(())
+    }
+
+    async fn cleanup(
+        &self,
+        _config: &ResolvedConfig,
+        preparation: &ProviderPreparation,
+        _options: &ProviderCleanupOptions,
+    ) -> Result<()> {
+        self.record(MockCall::Cleanup {
+            container_name: preparation.container_name.clone(),
+        });
+        Ok(())
+    }
+}
@@ -1,34 +1,136 @@
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use async_trait::async_trait;
 
-use crate::{config::ResolvedConfig, Result};
+use serde_json::Value;
+
+use crate::{
+    agent::{parse_env, ENV_MARKER},
+    config::{
+        merge::{parse_image_metadata, IMAGE_METADATA_LABEL},
+        ContainerLabel, ForwardPort, GpuRequirement, HostRequirements, Mount, ResolvedConfig,
+        LABEL_CONFIG_HASH, LABEL_PROJECT,
+    },
+    limits::format_bytes,
+    registry::RegistryMirror,
+    DevcontainerError, Result,
+};
+
+pub mod mock;
+pub mod output;
+pub mod progress;
+pub mod runner;
+pub mod runtime;
+
+pub use output::{OutputLine, OutputSink, OutputStream};
+pub use progress::{BuildProgress, LayerStatus, PullProgress};
+pub use runner::{
+    CommandOutput, CommandRunner, CommandSpec, LocalRunner, RecordingRunner, SshRunner,
+};
+pub use runtime::ProviderRegistry;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProviderKind {
     Docker,
     Podman,
+    Nerdctl,
     Mock,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+impl fmt::Display for ProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProviderKind::Docker => "docker",
+            ProviderKind::Podman => "podman",
+            ProviderKind::Nerdctl => "nerdctl",
+            ProviderKind::Mock => "mock",
+        })
+    }
+}
+
+impl FromStr for ProviderKind {
+    type Err = DevcontainerError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "docker" => Ok(ProviderKind::Docker),
+            "podman" => Ok(ProviderKind::Podman),
+            "nerdctl" => Ok(ProviderKind::Nerdctl),
+            "mock" => Ok(ProviderKind::Mock),
+            other => Err(DevcontainerError::Configuration(format!(
+                "Unknown provider '{other}'; expected docker, podman or nerdctl"
+            ))),
+        }
+    }
+}
+
+/// Optional behaviours a provider implements. Callers check these before using
+/// the matching methods and carry on without them where they can; a provider
+/// reporting none still runs containers and commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct ProviderCapabilities {
     pub supports_features: bool,
     pub supports_templates: bool,
+    /// [`Provider::attach`] wires sessions to the terminal.
     pub supports_attach: bool,
+    /// [`Provider::ensure_networks`] creates the networks containers join.
+    pub supports_networks: bool,
+    /// [`Provider::ensure_volumes`] creates named volumes.
+    pub supports_volumes: bool,
+    /// Container ports can be published on the host.
+    pub supports_port_forwarding: bool,
+    /// [`Provider::commit_container`] saves containers as images.
+    pub supports_commit: bool,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl ProviderCapabilities {
+    /// Every capability, as docker-compatible engines have.
+    pub fn all() -> Self {
+        Self {
+            supports_features: true,
+            supports_templates: true,
+            supports_attach: true,
+            supports_networks: true,
+            supports_volumes: true,
+            supports_port_forwarding: true,
+            supports_commit: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct ProviderBuildContext {
     pub dockerfile: PathBuf,
     pub build_context: PathBuf,
     pub tag: String,
+    pub args: BTreeMap<String, String>,
+    pub target: Option<String>,
+    pub cache_from: Vec<String>,
+    /// Cache export destinations; only BuildKit builds can export a cache.
+    pub cache_to: Vec<String>,
+    /// Target platforms such as `linux/arm64`; the daemon's own when empty.
+    pub platforms: Vec<String>,
+    /// Registry names for the image, such as `ghcr.io/org/image:tag`.
+    pub image_names: Vec<String>,
+    /// Push `image_names` once built; multi-platform builds push straight
+    /// from the builder since they cannot be loaded locally.
+    pub push: bool,
+    /// Extra build command arguments, appended as given.
+    pub options: Vec<String>,
+    /// Pull base images even when present locally.
+    pub pull: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ProviderImage {
     Reference(String),
-    Build(ProviderBuildContext),
+    Build(Box<ProviderBuildContext>),
 }
 
 impl ProviderImage {
@@ -40,12 +142,43 @@ impl ProviderImage {
     }
 }
 
+/// Identity and labels of a locally available image.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub id: String,
+    /// Registry digest, `name@sha256:...`; `None` for images only built locally.
+    pub digest: Option<String>,
+    /// `os/arch[/variant]` the image was built for, when the engine reports it.
+    pub platform: Option<String>,
+    pub labels: BTreeMap<String, String>,
+}
+
+impl ImageInfo {
+    /// Entries of the image's `devcontainer.metadata` label; empty for images not
+    /// built by a devcontainer tool.
+    pub fn metadata(&self) -> Result<Vec<Value>> {
+        match self.labels.get(IMAGE_METADATA_LABEL) {
+            Some(label) => parse_image_metadata(label),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct VolumeSpec {
     pub name: String,
     pub mount_path: PathBuf,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SidecarSpec {
+    pub name: String,
+    pub container_name: String,
+    pub image: String,
+    pub env: BTreeMap<String, String>,
+    pub ports: Vec<ForwardPort>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ProviderPreparation {
     pub image: ProviderImage,
@@ -54,12 +187,26 @@ pub struct ProviderPreparation {
     pub networks: Vec<String>,
     pub volumes: Vec<VolumeSpec>,
     pub workspace_mount_path: PathBuf,
+    /// Volume, also listed in `volumes`, holding a copy of the workspace when the
+    /// engine cannot bind-mount host paths, as with a remote daemon.
+    pub workspace_volume: Option<String>,
+    pub sidecars: Vec<SidecarSpec>,
+}
+
+/// Networks and volumes an `up` created rather than adopted, which only it may
+/// remove again when it fails.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CreatedResources {
+    pub networks: Vec<String>,
+    pub volumes: Vec<String>,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct RunningContainer {
     pub id: Option<String>,
     pub name: Option<String>,
+    /// Container names of sidecars created alongside this container.
+    pub sidecars: Vec<String>,
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
@@ -69,6 +216,179 @@ pub struct ExecResult {
     pub stderr: String,
 }
 
+/// Whether a container runs and how many exec or attach sessions are open in it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContainerActivity {
+    pub running: bool,
+    pub sessions: usize,
+}
+
+/// Lifecycle state of a container as the engine reports it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ContainerStatus {
+    Created,
+    Running,
+    Paused,
+    Restarting,
+    Removing,
+    Exited,
+    Dead,
+    #[default]
+    Unknown,
+}
+
+impl fmt::Display for ContainerStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ContainerStatus::Created => "created",
+            ContainerStatus::Running => "running",
+            ContainerStatus::Paused => "paused",
+            ContainerStatus::Restarting => "restarting",
+            ContainerStatus::Removing => "removing",
+            ContainerStatus::Exited => "exited",
+            ContainerStatus::Dead => "dead",
+            ContainerStatus::Unknown => "unknown",
+        })
+    }
+}
+
+/// Reads an engine's state name; states this version does not know are `Unknown`.
+impl From<&str> for ContainerStatus {
+    fn from(value: &str) -> Self {
+        match value.to_ascii_lowercase().as_str() {
+            "created" => ContainerStatus::Created,
+            "running" => ContainerStatus::Running,
+            "paused" => ContainerStatus::Paused,
+            "restarting" => ContainerStatus::Restarting,
+            "removing" => ContainerStatus::Removing,
+            "exited" | "stopped" => ContainerStatus::Exited,
+            "dead" => ContainerStatus::Dead,
+            _ => ContainerStatus::Unknown,
+        }
+    }
+}
+
+/// What the engine reports about an existing container.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContainerDetails {
+    pub id: String,
+    pub name: Option<String>,
+    pub status: ContainerStatus,
+    /// Image reference the container was created from, as given.
+    pub image: Option<String>,
+    /// Content digest of that image, `sha256:...`, which outlives retagging.
+    pub image_digest: Option<String>,
+    /// Labels of the container, including those inherited from its image.
+    pub labels: BTreeMap<String, String>,
+    /// Mounts as `Mount::Object`s; volumes are named by their volume.
+    pub mounts: Vec<Mount>,
+}
+
+impl ContainerDetails {
+    pub fn running(&self) -> bool {
+        self.status == ContainerStatus::Running
+    }
+
+    /// Whether the container carries every label of `labels`.
+    pub fn has_labels(&self, labels: &[ContainerLabel]) -> bool {
+        labels
+            .iter()
+            .all(|label| self.labels.get(&label.name) == Some(&label.value))
+    }
+
+    pub fn container(&self) -> RunningContainer {
+        RunningContainer {
+            id: Some(self.id.clone()),
+            name: self.name.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Which containers [`Provider::list_containers`] returns, running or not.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContainerFilter {
+    /// Labels every listed container carries; all containers when empty.
+    pub labels: Vec<ContainerLabel>,
+    /// Leave out containers that are not running.
+    pub running_only: bool,
+}
+
+impl ContainerFilter {
+    pub fn labelled(labels: &[ContainerLabel]) -> Self {
+        Self {
+            labels: labels.to_vec(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Resources of the machine running the engine; `None` where the engine does not
+/// report them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostCapabilities {
+    pub cpus: Option<u64>,
+    pub memory_bytes: Option<u64>,
+    /// Free space where the engine keeps images and containers.
+    pub storage_bytes: Option<u64>,
+    pub gpu: Option<bool>,
+}
+
+impl HostCapabilities {
+    /// Requirements the host is known to miss, described for an error message.
+    /// Optional GPUs and capabilities the engine does not report never fail.
+    pub fn unmet(&self, requirements: &HostRequirements) -> Vec<String> {
+        let mut unmet = Vec::new();
+        if let (Some(required), Some(available)) = (requirements.cpus, self.cpus) {
+            if available < required {
+                unmet.push(format!("{required} CPUs required, {available} available"));
+            }
+        }
+        for (name, required, available) in [
+            ("memory", requirements.memory_bytes(), self.memory_bytes),
+            ("storage", requirements.storage_bytes(), self.storage_bytes),
+        ] {
+            if let (Some(required), Some(available)) = (required, available) {
+                if available < required {
+                    unmet.push(format!(
+                        "{} of {name} required, {} available",
+                        format_bytes(required),
+                        format_bytes(available)
+                    ));
+                }
+            }
+        }
+        if requirements.gpu() == GpuRequirement::Required && self.gpu == Some(false) {
+            unmet.push("a GPU required, none available".to_string());
+        }
+        unmet
+    }
+}
+
+/// Where and as whom a streamed command runs, when not the container's defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecOptions {
+    pub user: Option<String>,
+    pub working_dir: Option<PathBuf>,
+}
+
+impl ExecOptions {
+    pub fn as_user(user: Option<&str>) -> Self {
+        Self {
+            user: user.map(str::to_string),
+            ..Default::default()
+        }
+    }
+}
+
+/// Interactive session attached to the caller's terminal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InteractiveSession {
+    pub user: Option<String>,
+    pub working_dir: Option<PathBuf>,
+    pub command: Vec<String>,
+}
+
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct ProviderCleanupOptions {
     pub remove_volumes: bool,
@@ -85,17 +405,29 @@ pub trait Provider: Send + Sync {
 
     async fn prepare(&self, config: &ResolvedConfig) -> Result<ProviderPreparation>;
 
+    /// Creates or adopts the networks of `preparation`, returning those created.
     async fn ensure_networks(
         &self,
-        config: &ResolvedConfig,
-        preparation: &ProviderPreparation,
-    ) -> Result<()>;
+        _config: &ResolvedConfig,
+        _preparation: &ProviderPreparation,
+    ) -> Result<Vec<String>> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot create networks",
+            self.kind()
+        )))
+    }
 
+    /// Creates or adopts the volumes of `preparation`, returning those created.
     async fn ensure_volumes(
         &self,
-        config: &ResolvedConfig,
-        preparation: &ProviderPreparation,
-    ) -> Result<()>;
+        _config: &ResolvedConfig,
+        _preparation: &ProviderPreparation,
+    ) -> Result<Vec<String>> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot create volumes",
+            self.kind()
+        )))
+    }
 
     async fn build_image(
         &self,
@@ -103,6 +435,24 @@ pub trait Provider: Send + Sync {
         preparation: &ProviderPreparation,
     ) -> Result<String>;
 
+    /// Looks an image up locally, returning `None` when it has not been pulled or
+    /// built yet.
+    async fn image_exists(&self, reference: &str) -> Result<Option<ImageInfo>> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot inspect image {reference}",
+            self.kind()
+        )))
+    }
+
+    /// Digest, `sha256:...`, of the manifest `reference` names in its registry,
+    /// for images and OCI artifacts such as features alike.
+    async fn resolve_digest(&self, reference: &str) -> Result<String> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot resolve the digest of {reference}",
+            self.kind()
+        )))
+    }
+
     async fn create_container(
         &self,
         config: &ResolvedConfig,
@@ -112,8 +462,193 @@ pub trait Provider: Send + Sync {
 
     async fn start_container(&self, container: &RunningContainer) -> Result<()>;
 
+    /// Finds containers (running or stopped) carrying all of the given labels.
+    async fn find_containers(&self, _labels: &[ContainerLabel]) -> Result<Vec<RunningContainer>> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot discover containers by label",
+            self.kind()
+        )))
+    }
+
+    /// Reports the state of `container`, or `None` when the engine has no such
+    /// container.
+    async fn inspect_container(
+        &self,
+        _container: &RunningContainer,
+    ) -> Result<Option<ContainerDetails>> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot inspect containers",
+            self.kind()
+        )))
+    }
+
+    /// Reports the containers matching `filter`, running ones first.
+    async fn list_containers(&self, _filter: &ContainerFilter) -> Result<Vec<ContainerDetails>> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot list containers",
+            self.kind()
+        )))
+    }
+
+    /// Force-removes a single container, optionally with its anonymous volumes.
+    async fn remove_container(
+        &self,
+        _container: &RunningContainer,
+        _remove_volumes: bool,
+    ) -> Result<()> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot remove individual containers",
+            self.kind()
+        )))
+    }
+
     async fn exec(&self, container: &RunningContainer, command: &[String]) -> Result<ExecResult>;
 
+    /// Runs a command in the container as a specific user.
+    async fn exec_as(
+        &self,
+        _container: &RunningContainer,
+        user: &str,
+        _command: &[String],
+    ) -> Result<ExecResult> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot run commands as user '{user}'",
+            self.kind()
+        )))
+    }
+
+    /// Runs a command, forwarding output lines to `output` while it runs.
+    ///
+    /// Providers that cannot stream replay the captured output once the command exits.
+    async fn exec_streaming(
+        &self,
+        container: &RunningContainer,
+        user: Option<&str>,
+        command: &[String],
+        output: &OutputSink,
+    ) -> Result<ExecResult> {
+        let result = match user {
+            Some(user) => self.exec_as(container, user, command).await?,
+            None => self.exec(container, command).await?,
+        };
+        output.replay(OutputStream::Stdout, &result.stdout);
+        output.replay(OutputStream::Stderr, &result.stderr);
+        Ok(result)
+    }
+
+    /// Like [`Provider::exec_streaming`], also honouring the working directory of
+    /// `options`.
+    async fn exec_with_options(
+        &self,
+        container: &RunningContainer,
+        options: &ExecOptions,
+        command: &[String],
+        output: &OutputSink,
+    ) -> Result<ExecResult> {
+        if let Some(working_dir) = &options.working_dir {
+            return Err(DevcontainerError::Unsupported(format!(
+                "{:?} provider cannot run commands in {}",
+                self.kind(),
+                working_dir.display()
+            )));
+        }
+        self.exec_streaming(container, options.user.as_deref(), command, output)
+            .await
+    }
+
+    /// Runs an interactive session wired to the current terminal and returns its exit code.
+    async fn attach(
+        &self,
+        _container: &RunningContainer,
+        _session: &InteractiveSession,
+    ) -> Result<i32> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot attach interactive sessions",
+            self.kind()
+        )))
+    }
+
+    /// Pull-through caches configured on the engine's daemon, such as the
+    /// `registry-mirrors` of docker's `daemon.json`, in preference order.
+    async fn registry_mirrors(&self) -> Result<Vec<RegistryMirror>> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot report registry mirrors",
+            self.kind()
+        )))
+    }
+
+    /// Reports the resources of the machine the engine runs containers on.
+    async fn host_capabilities(&self) -> Result<HostCapabilities> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot report host capabilities",
+            self.kind()
+        )))
+    }
+
+    /// Checks that the engine's daemon answers, failing with
+    /// [`DevcontainerError::DaemonAccess`] while it is down.
+    async fn daemon_ready(&self) -> Result<()> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot check its daemon",
+            self.kind()
+        )))
+    }
+
+    /// Resets the engine's clock to the host's, for engines in a VM whose clock
+    /// drifts while the host sleeps.
+    async fn sync_clock(&self) -> Result<()> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot resynchronize its clock",
+            self.kind()
+        )))
+    }
+
+    /// Reports whether the container runs and its open sessions, for idle detection.
+    async fn container_activity(&self, _container: &RunningContainer) -> Result<ContainerActivity> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot report container activity",
+            self.kind()
+        )))
+    }
+
+    /// Labels of `container`, including those inherited from its image.
+    async fn container_labels(
+        &self,
+        _container: &RunningContainer,
+    ) -> Result<BTreeMap<String, String>> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot read container labels",
+            self.kind()
+        )))
+    }
+
+    /// Copies a host file or directory into the container at `destination`.
+    async fn copy_into(
+        &self,
+        _container: &RunningContainer,
+        _source: &Path,
+        _destination: &Path,
+    ) -> Result<()> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot copy files into containers",
+            self.kind()
+        )))
+    }
+
+    /// Saves the filesystem of `container` as the image `tag`, labelled with
+    /// `config` as image metadata so the image works as a prebuild.
+    async fn commit_container(
+        &self,
+        _config: &ResolvedConfig,
+        _container: &RunningContainer,
+        tag: &str,
+    ) -> Result<()> {
+        Err(DevcontainerError::Unsupported(format!(
+            "{:?} provider cannot commit containers to image {tag}",
+            self.kind()
+        )))
+    }
+
     async fn stop_container(
         &self,
         config: &ResolvedConfig,
@@ -127,4 +662,759 @@ pub trait Provider: Send + Sync {
         preparation: &ProviderPreparation,
         options: &ProviderCleanupOptions,
     ) -> Result<()>;
+
+    /// Removes what a failed or cancelled `up` half-created for `preparation`:
+    /// its container and sidecars, and the networks and volumes in `created`.
+    async fn rollback(
+        &self,
+        config: &ResolvedConfig,
+        preparation: &ProviderPreparation,
+        created: &CreatedResources,
+    ) -> Result<()> {
+        let created_only = ProviderPreparation {
+            networks: created.networks.clone(),
+            volumes: preparation
+                .volumes
+                .iter()
+                .filter(|volume| created.volumes.contains(&volume.name))
+                .cloned()
+                .collect(),
+            ..preparation.clone()
+        };
+        let options = ProviderCleanupOptions {
+            remove_volumes: true,
+            remove_unknown: false,
+        };
+        self.cleanup(config, &created_only, &options).await
+    }
+}
+
+/// Implements [`Provider`] for pointers to one by forwarding every method, so a
+/// provider chosen at runtime, such as one picked by [`ProviderRegistry`], or a
+/// borrowed one can drive everything generic over [`Provider`].
+macro_rules! forward_provider {
+    ($($pointer:ty),*) => {$(
+        #[async_trait]
+        impl<P: Provider + ?Sized> Provider for $pointer {
+            fn kind(&self) -> ProviderKind {
+                (**self).kind()
+            }
+
+            fn capabilities(&self) -> ProviderCapabilities {
+                (**self).capabilities()
+            }
+
+            async fn prepare(&self, config: &ResolvedConfig) -> Result<ProviderPreparation> {
+                (**self).prepare(config).await
+            }
+
+            async fn ensure_networks(
+                &self,
+                config: &ResolvedConfig,
+                preparation: &ProviderPreparation,
+            ) -> Result<Vec<String>> {
+                (**self).ensure_networks(config, preparation).await
+            }
+
+            async fn ensure_volumes(
+                &self,
+                config: &ResolvedConfig,
+                preparation: &ProviderPreparation,
+            ) -> Result<Vec<String>> {
+                (**self).ensure_volumes(config, preparation).await
+            }
+
+            async fn build_image(
+                &self,
+                config: &ResolvedConfig,
+                preparation: &ProviderPreparation,
+            ) -> Result<String> {
+                (**self).build_image(config, preparation).await
+            }
+
+            async fn image_exists(&self, reference: &str) -> Result<Option<ImageInfo>> {
+                (**self).image_exists(reference).await
+            }
+
+            async fn resolve_digest(&self, reference: &str) -> Result<String> {
+                (**self).resolve_digest(reference).await
+            }
+
+            async fn create_container(
+                &self,
+                config: &ResolvedConfig,
+                preparation: &ProviderPreparation,
+                image_reference: &str,
+            ) -> Result<RunningContainer> {
+                (**self)
+                    .create_container(config, preparation, image_reference)
+                    .await
+            }
+
+            async fn start_container(&self, container: &RunningContainer) -> Result<()> {
+                (**self).start_container(container).await
+            }
+
+            async fn find_containers(&self, labels: &[ContainerLabel]) -> Result<Vec<RunningContainer>> {
+                (**self).find_containers(labels).await
+            }
+
+            async fn inspect_container(
+                &self,
+                container: &RunningContainer,
+            ) -> Result<Option<ContainerDetails>> {
+                (**self).inspect_container(container).await
+            }
+
+            async fn list_containers(
+                &self,
+                filter: &ContainerFilter,
+            ) -> Result<Vec<ContainerDetails>> {
+                (**self).list_containers(filter).await
+            }
+
+            async fn remove_container(
+                &self,
+                container: &RunningContainer,
+                remove_volumes: bool,
+            ) -> Result<()> {
+                (**self).remove_container(container, remove_volumes).await
+            }
+
+            async fn exec(&self, container: &RunningContainer, command: &[String]) -> Result<ExecResult> {
+                (**self).exec(container, command).await
+            }
+
+            async fn exec_as(
+                &self,
+                container: &RunningContainer,
+                user: &str,
+                command: &[String],
+            ) -> Result<ExecResult> {
+                (**self).exec_as(container, user, command).await
+            }
+
+            async fn exec_streaming(
+                &self,
+                container: &RunningContainer,
+                user: Option<&str>,
+                command: &[String],
+                output: &OutputSink,
+            ) -> Result<ExecResult> {
+                (**self)
+                    .exec_streaming(container, user, command, output)
+                    .await
+            }
+
+            async fn exec_with_options(
+                &self,
+                container: &RunningContainer,
+                options: &ExecOptions,
+                command: &[String],
+                output: &OutputSink,
+            ) -> Result<ExecResult> {
+                (**self)
+                    .exec_with_options(container, options, command, output)
+                    .await
+            }
+
+            async fn attach(
+                &self,
+                container: &RunningContainer,
+                session: &InteractiveSession,
+            ) -> Result<i32> {
+                (**self).attach(container, session).await
+            }
+
+            async fn registry_mirrors(&self) -> Result<Vec<RegistryMirror>> {
+                (**self).registry_mirrors().await
+            }
+
+            async fn host_capabilities(&self) -> Result<HostCapabilities> {
+                (**self).host_capabilities().await
+            }
+
+            async fn daemon_ready(&self) -> Result<()> {
+                (**self).daemon_ready().await
+            }
+
+            async fn sync_clock(&self) -> Result<()> {
+                (**self).sync_clock().await
+            }
+
+            async fn container_activity(&self, container: &RunningContainer) -> Result<ContainerActivity> {
+                (**self).container_activity(container).await
+            }
+
+            async fn container_labels(
+                &self,
+                container: &RunningContainer,
+            ) -> Result<BTreeMap<String, String>> {
+                (**self).container_labels(container).await
+            }
+
+            async fn copy_into(
+                &self,
+                container: &RunningContainer,
+                source: &Path,
+                destination: &Path,
+            ) -> Result<()> {
+                (**self).copy_into(container, source, destination).await
+            }
+
+            async fn commit_container(
+                &self,
+                config: &ResolvedConfig,
+                container: &RunningContainer,
+                tag: &str,
+            ) -> Result<()> {
+                (**self).commit_container(config, container, tag).await
+            }
+
+            async fn stop_container(
+                &self,
+                config: &ResolvedConfig,
+                preparation: &ProviderPreparation,
+                container: &RunningContainer,
+            ) -> Result<()> {
+                (**self)
+                    .stop_container(config, preparation, container)
+                    .await
+            }
+
+            async fn cleanup(
+                &self,
+                config: &ResolvedConfig,
+                preparation: &ProviderPreparation,
+                options: &ProviderCleanupOptions,
+            ) -> Result<()> {
+                (**self).cleanup(config, preparation, options).await
+            }
+
+            async fn rollback(
+                &self,
+                config: &ResolvedConfig,
+                preparation: &ProviderPreparation,
+                created: &CreatedResources,
+            ) -> Result<()> {
+                (**self).rollback(config, preparation, created).await
+            }
+        }
+    )*};
+}
+
+forward_provider!(Box<P>, &P);
+
+/// Login shell used when the user's shell cannot be determined.
+pub const FALLBACK_SHELL: &str = "/bin/sh";
+
+/// Looks up the login shell of `user` (or the container's default user) from the
+/// passwd database, falling back to [`FALLBACK_SHELL`].
+pub async fn probe_login_shell<P: Provider + ?Sized>(
+    provider: &P,
+    container: &RunningContainer,
+    user: Option<&str>,
+) -> Result<String> {
+    // The user is passed as `$1`, never interpolated into the script.
+    let command: Vec<String> = match user {
+        Some(user) => vec!["/bin/sh", "-c", "getent passwd \"$1\"", "sh", user],
+        None => vec!["/bin/sh", "-c", "getent passwd \"$(id -un)\""],
+    }
+    .into_iter()
+    .map(str::to_string)
+    .collect();
+    let result = provider.exec(container, &command).await?;
+
+    let shell = result
+        .stdout
+        .lines()
+        .next()
+        .and_then(|entry| entry.split(':').nth(6))
+        .map(str::trim)
+        .filter(|shell| result.exit_code == 0 && shell.starts_with('/'))
+        .filter(|shell| !shell.ends_with("/nologin") && !shell.ends_with("/false"));
+
+    Ok(shell.unwrap_or(FALLBACK_SHELL).to_string())
+}
+
+/// Environment `user` (or the container's default user) gets from their login
+/// shell, started interactively so rc files run as they would in a terminal.
+///
+/// A probe that fails is logged and yields no variables. Variables tracking the
+/// probing shell itself, such as `PWD`, are left out.
+pub async fn probe_user_env<P: Provider + ?Sized>(
+    provider: &P,
+    container: &RunningContainer,
+    user: Option<&str>,
+) -> Result<BTreeMap<String, String>> {
+    let shell = probe_login_shell(provider, container, user).await?;
+    let script = format!("printf '%s\\0' {ENV_MARKER}; cat /proc/self/environ");
+    let command = vec![shell, "-lic".to_string(), script];
+    let result = match user {
+        Some(user) => provider.exec_as(container, user, &command).await?,
+        None => provider.exec(container, &command).await?,
+    };
+    if result.exit_code != 0 {
+        tracing::warn!(
+            exit_code = result.exit_code,
+            stderr = %result.stderr.trim(),
+            "Probing the user environment failed"
+        );
+        return Ok(BTreeMap::new());
+    }
+    let mut env = parse_env(&result.stdout);
+    for name in SHELL_STATE_VARS {
+        env.remove(name);
+    }
+    Ok(env)
+}
+
+/// Variables describing the probing shell rather than the user's environment.
+const SHELL_STATE_VARS: [&str; 4] = ["PWD", "OLDPWD", "SHLVL", "_"];
+
+/// Runs a streamed command, killing it inside the container once `timeout` elapses.
+///
+/// Dropping a pending exec only stops the local client, so the command runs under a
+/// shell wrapper that records its PID for the follow-up kill. Expiry is reported as
+/// [`DevcontainerError::Timeout`].
+pub async fn exec_with_timeout<P: Provider + ?Sized>(
+    provider: &P,
+    container: &RunningContainer,
+    options: &ExecOptions,
+    command: &[String],
+    output: &OutputSink,
+    timeout: Duration,
+) -> Result<ExecResult> {
+    let pid_file = timeout_pid_file();
+    let wrapped = timeout_wrapper(&pid_file, command);
+    let pending = provider.exec_with_options(container, options, &wrapped, output);
+    if let Ok(result) = tokio::time::timeout(timeout, pending).await {
+        return result;
+    }
+
+    let kill = timeout_kill_command(&pid_file);
+    let killed = match options.user.as_deref() {
+        Some(user) => provider.exec_as(container, user, &kill).await,
+        None => provider.exec(container, &kill).await,
+    };
+    if let Err(err) = killed {
+        tracing::warn!(error = %err, "Failed to stop timed out command");
+    }
+
+    Err(DevcontainerError::Timeout(format!(
+        "command did not finish within {timeout:?}"
+    )))
+}
+
+fn timeout_pid_file() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos())
+        .unwrap_or_default();
+    format!("/tmp/devcontainer-exec-{}-{nanos}.pid", std::process::id())
+}
+
+fn timeout_wrapper(pid_file: &str, command: &[String]) -> Vec<String> {
+    let script = format!(
+        "\"$@\" & pid=$!; echo \"$pid\" > {pid_file}; wait \"$pid\"; status=$?; rm -f {pid_file}; exit \"$status\""
+    );
+    let mut wrapped = vec![
+        "/bin/sh".to_string(),
+        "-c".to_string(),
+        script,
+        "sh".to_string(),
+    ];
+    wrapped.extend(command.iter().cloned());
+    wrapped
+}
+
+fn timeout_kill_command(pid_file: &str) -> Vec<String> {
+    let script = format!(
+        "pid=$(cat {pid_file} 2>/dev/null) || exit 0; kill -TERM \"$pid\" 2>/dev/null && sleep 1 && kill -KILL \"$pid\" 2>/dev/null; rm -f {pid_file}"
+    );
+    vec!["/bin/sh".to_string(), "-c".to_string(), script]
+}
+
+/// Finds containers by label, running ones first, treating providers without
+/// discovery support as having no existing containers.
+pub async fn discover_containers<P: Provider + ?Sized>(
+    provider: &P,
+    labels: &[ContainerLabel],
+) -> Result<Vec<RunningContainer>> {
+    if labels.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    match provider
+        .list_containers(&ContainerFilter::labelled(labels))
+        .await
+    {
+        Ok(containers) => {
+            return Ok(containers
+                .iter()
+                .filter(|details| details.status != ContainerStatus::Dead)
+                .map(ContainerDetails::container)
+                .collect())
+        }
+        Err(DevcontainerError::Unsupported(_)) => {}
+        Err(err) => return Err(err),
+    }
+    match provider.find_containers(labels).await {
+        Ok(containers) => Ok(containers),
+        Err(DevcontainerError::Unsupported(reason)) => {
+            tracing::debug!(reason = %reason, "Container discovery unavailable");
+            Ok(Vec::new())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Whether `container`, found by the id labels of `config`, is this workspace's
+/// own: it carries the project's label or a default label identifying the
+/// workspace. Custom id labels may match other workspaces' containers too.
+pub async fn owned_by_workspace<P: Provider + ?Sized>(
+    provider: &P,
+    config: &ResolvedConfig,
+    container: &RunningContainer,
+) -> Result<bool> {
+    let details = match provider.inspect_container(container).await {
+        Ok(Some(details)) => details,
+        Ok(None) => return Ok(false),
+        Err(DevcontainerError::Unsupported(reason)) => {
+            tracing::debug!(reason = %reason, "Cannot tell which workspace owns the container");
+            return Ok(false);
+        }
+        Err(err) => return Err(err),
+    };
+    let defaults = ContainerLabel::defaults_for(&config.workspace_folder, &config.config_path);
+    Ok(
+        details.labels.get(LABEL_PROJECT) == Some(&config.project_name)
+            || defaults
+                .iter()
+                .any(|label| details.labels.get(&label.name) == Some(&label.value)),
+    )
+}
+
+/// Cache host that artifact fetches are routed through: `preferred` when given,
+/// else the daemon's first registry mirror. Detection failures only log a warning.
+pub async fn artifact_mirror<P: Provider + ?Sized>(
+    provider: &P,
+    preferred: Option<&RegistryMirror>,
+) -> Option<RegistryMirror> {
+    if let Some(mirror) = preferred {
+        return Some(mirror.clone());
+    }
+    match provider.registry_mirrors().await {
+        Ok(mirrors) => mirrors.into_iter().next(),
+        Err(DevcontainerError::Unsupported(_)) => None,
+        Err(err) => {
+            tracing::warn!(error = %err, "Failed to detect daemon registry mirrors");
+            None
+        }
+    }
+}
+
+/// Entries of the `devcontainer.metadata` label of the image `config` runs, pulling
+/// or building the image first when it is not available locally.
+pub async fn image_metadata<P: Provider + ?Sized>(
+    provider: &P,
+    config: &ResolvedConfig,
+) -> Result<Vec<Value>> {
+    let preparation = provider.prepare(config).await?;
+    let reference = preparation.image.reference();
+    let details = match provider.image_exists(reference).await? {
+        Some(details) => details,
+        None => {
+            tracing::info!(image = %reference, "Image not available locally; fetching it for its metadata");
+            let image = provider.build_image(config, &preparation).await?;
+            provider.image_exists(&image).await?.ok_or_else(|| {
+                DevcontainerError::Provider(format!(
+                    "Image {image} is missing after it was fetched"
+                ))
+            })?
+        }
+    };
+    details.metadata()
+}
+
+/// Entries of the `devcontainer.metadata` label of `container`, which it inherits
+/// from its image; empty for containers not created from a devcontainer image.
+pub async fn container_metadata<P: Provider + ?Sized>(
+    provider: &P,
+    container: &RunningContainer,
+) -> Result<Vec<Value>> {
+    match provider
+        .container_labels(container)
+        .await?
+        .get(IMAGE_METADATA_LABEL)
+    {
+        Some(label) => parse_image_metadata(label),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Suggestion printed wherever a container no longer matches its configuration.
+pub const REBUILD_HINT: &str = "run `devcontainer up --remove-existing-container` to rebuild it";
+
+/// Whether `container` was created from another version of `config`, by the
+/// [`ResolvedConfig::config_hash`] label it was created with. Containers without
+/// the label, such as those of other tools, and engines that cannot inspect
+/// containers never count as drifted.
+pub async fn config_drifted<P: Provider + ?Sized>(
+    provider: &P,
+    config: &ResolvedConfig,
+    container: &RunningContainer,
+) -> bool {
+    match provider.inspect_container(container).await {
+        Ok(Some(details)) => details
+            .labels
+            .get(LABEL_CONFIG_HASH)
+            .is_some_and(|hash| *hash != config.config_hash()),
+        Ok(None) => false,
+        Err(err) => {
+            tracing::debug!(error = %err, "Cannot compare the container with its configuration");
+            false
+        }
+    }
+}
+
+/// Logs a warning when `container` no longer matches `config`, see [`config_drifted`].
+pub async fn warn_if_drifted<P: Provider + ?Sized>(
+    provider: &P,
+    config: &ResolvedConfig,
+    container: &RunningContainer,
+) {
+    if config_drifted(provider, config, container).await {
+        tracing::warn!(
+            container = ?container.name.as_ref().or(container.id.as_ref()),
+            "The devcontainer no longer matches its configuration; {REBUILD_HINT}"
+        );
+    }
+}
+
+/// Layers the `devcontainer.metadata` of the image `config` runs under `config`, see
+/// [`ResolvedConfig::with_image_metadata`].
+///
+/// Referenced images are pulled when missing. Images built from a Dockerfile are
+/// not built early, ahead of `initializeCommand`; until the first build their
+/// metadata is unknown and `config` is returned as is.
+pub async fn layer_image_metadata<P: Provider + ?Sized>(
+    provider: &P,
+    config: ResolvedConfig,
+) -> Result<ResolvedConfig> {
+    let preparation = provider.prepare(&config).await?;
+    let details = match provider.image_exists(preparation.image.reference()).await {
+        Ok(Some(details)) => details,
+        Ok(None) => match &preparation.image {
+            ProviderImage::Reference(_) => {
+                let image = provider.build_image(&config, &preparation).await?;
+                provider.image_exists(&image).await?.unwrap_or_default()
+            }
+            ProviderImage::Build(build) => {
+                tracing::debug!(image = %build.tag, "Image not built yet; skipping its metadata");
+                return Ok(config);
+            }
+        },
+        Err(DevcontainerError::Unsupported(reason)) => {
+            tracing::debug!(reason = %reason, "Image metadata unavailable");
+            return Ok(config);
+        }
+        Err(err) => return Err(err),
+    };
+
+    let metadata = details.metadata()?;
+    if metadata.is_empty() {
+        return Ok(config);
+    }
+    tracing::debug!(
+        entries = metadata.len(),
+        "Layering image metadata under the configuration"
+    );
+    config.with_image_metadata(&metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::{MockCall, MockProvider};
+
+    #[test]
+    fn host_capabilities_report_unmet_requirements() {
+        let requirements = HostRequirements {
+            cpus: Some(4),
+            memory: Some("8gb".to_string()),
+            storage: Some("32gb".to_string()),
+            gpu: Some(serde_json::json!(true)),
+        };
+        let host = HostCapabilities {
+            cpus: Some(2),
+            memory_bytes: Some(16 << 30),
+            storage_bytes: None,
+            gpu: Some(false),
+        };
+
+        assert_eq!(
+            host.unmet(&requirements),
+            [
+                "4 CPUs required, 2 available",
+                "a GPU required, none available"
+            ]
+        );
+        let optional_gpu = HostRequirements {
+            gpu: Some(serde_json::json!("optional")),
+            ..requirements
+        };
+        assert_eq!(host.unmet(&optional_gpu).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn probe_login_shell_reads_passwd_entry() {
+        let container = RunningContainer::default();
+        let provider = MockProvider::new().with_exec_handler(|command| ExecResult {
+            exit_code: 0,
+            stdout: if command[2] == "getent passwd \"$1\"" && command[4] == "vscode" {
+                "vscode:x:1000:1000::/home/vscode:/bin/zsh\n".to_string()
+            } else {
+                "nobody:x:65534:65534::/:/usr/sbin/nologin\n".to_string()
+            },
+            stderr: String::new(),
+        });
+
+        let shell = probe_login_shell(&provider, &container, Some("vscode"))
+            .await
+            .expect("probe shell");
+        assert_eq!(shell, "/bin/zsh");
+
+        let shell = probe_login_shell(&provider, &container, None)
+            .await
+            .expect("probe shell");
+        assert_eq!(shell, FALLBACK_SHELL);
+    }
+
+    #[tokio::test]
+    async fn probe_user_env_reads_environment_after_marker() {
+        let container = RunningContainer::default();
+        let provider = MockProvider::new().with_exec_handler(|command| ExecResult {
+            exit_code: 0,
+            stdout: if command[0] == "/bin/sh" && command[1] == "-c" {
+                "vscode:x:1000:1000::/home/vscode:/bin/bash\n".to_string()
+            } else {
+                format!("welcome\n{ENV_MARKER}\0PATH=/home/vscode/.cargo/bin:/usr/bin\0NVM_DIR=/nvm\0PWD=/home/vscode\0SHLVL=2\0")
+            },
+            stderr: String::new(),
+        });
+
+        let env = probe_user_env(&provider, &container, Some("vscode"))
+            .await
+            .expect("probe env");
+        assert_eq!(
+            env.get("PATH").map(String::as_str),
+            Some("/home/vscode/.cargo/bin:/usr/bin")
+        );
+        assert_eq!(env.get("NVM_DIR").map(String::as_str), Some("/nvm"));
+        assert_eq!(env.len(), 2);
+
+        let execs = provider.exec_calls();
+        assert_eq!(execs[1][..2], ["/bin/bash", "-lic"]);
+    }
+
+    #[tokio::test]
+    async fn config_drift_compares_the_creation_hash_label() {
+        let config = ResolvedConfig {
+            project_name: "demo".to_string(),
+            image_reference: Some("example:1".to_string()),
+            ..Default::default()
+        };
+        let container = RunningContainer {
+            name: Some("demo".to_string()),
+            ..Default::default()
+        };
+        let provider =
+            MockProvider::new().with_container(container.clone(), vec![config.config_hash_label()]);
+        assert!(!config_drifted(&provider, &config, &container).await);
+
+        let changed = ResolvedConfig {
+            image_reference: Some("example:2".to_string()),
+            ..config.clone()
+        };
+        assert!(config_drifted(&provider, &changed, &container).await);
+
+        let unlabelled = RunningContainer {
+            name: Some("other".to_string()),
+            ..Default::default()
+        };
+        let provider = provider.with_container(unlabelled.clone(), Vec::new());
+        assert!(!config_drifted(&provider, &changed, &unlabelled).await);
+    }
+
+    #[tokio::test]
+    async fn exec_with_timeout_kills_hanging_command() {
+        let container = RunningContainer::default();
+        let provider = MockProvider::new().with_exec_delay(Duration::from_secs(30));
+        let command = vec!["sleep".to_string(), "infinity".to_string()];
+
+        let err = exec_with_timeout(
+            &provider,
+            &container,
+            &ExecOptions::default(),
+            &command,
+            &OutputSink::discard(),
+            Duration::from_millis(10),
+        )
+        .await
+        .expect_err("command times out");
+        assert!(matches!(err, DevcontainerError::Timeout(_)));
+
+        let execs = provider.exec_calls();
+        assert_eq!(execs.len(), 2);
+        assert_eq!(execs[0][4..], command);
+        let pid_file = execs[0][2].split_whitespace().nth(5).expect("pid file");
+        assert!(execs[1][2].contains("kill -TERM"));
+        assert!(execs[1][2].contains(pid_file));
+
+        let quick = MockProvider::new();
+        let result = exec_with_timeout(
+            &quick,
+            &container,
+            &ExecOptions::default(),
+            &command,
+            &OutputSink::discard(),
+            Duration::from_secs(5),
+        )
+        .await
+        .expect("command finishes");
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(quick.exec_calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn image_metadata_reads_label_of_local_image() {
+        let config = ResolvedConfig {
+            image_reference: Some("example:image".to_string()),
+            ..Default::default()
+        };
+        let details = ImageInfo {
+            labels: BTreeMap::from([(
+                IMAGE_METADATA_LABEL.to_string(),
+                r#"[{"remoteUser":"vscode"}]"#.to_string(),
+            )]),
+            ..Default::default()
+        };
+        let provider = MockProvider::new().with_image("example:image", details);
+
+        let metadata = image_metadata(&provider, &config).await.expect("metadata");
+        assert_eq!(
+            metadata,
+            vec![serde_json::json!({ "remoteUser": "vscode" })]
+        );
+        assert!(!provider
+            .calls()
+            .iter()
+            .any(|call| matches!(call, MockCall::BuildImage { .. })));
+
+        let err = image_metadata(&MockProvider::new(), &config)
+            .await
+            .expect_err("mock images never appear");
+        assert!(matches!(err, DevcontainerError::Provider(_)));
+    }
 }
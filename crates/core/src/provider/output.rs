@@ -0,0 +1,173 @@
+use std::{fmt, sync::Arc};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Stream a line of process output was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Single line of output from a running process, without its line terminator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputLine {
+    pub stream: OutputStream,
+    pub line: String,
+}
+
+type OutputHandler = dyn Fn(OutputLine) + Send + Sync;
+
+type RawHandler = dyn Fn(OutputStream, &[u8]) + Send + Sync;
+
+/// Receives process output line by line while a command is still running.
+#[derive(Clone)]
+pub struct OutputSink {
+    handler: Arc<OutputHandler>,
+    raw: Option<Arc<RawHandler>>,
+}
+
+impl OutputSink {
+    pub fn new(handler: impl Fn(OutputLine) + Send + Sync + 'static) -> Self {
+        Self {
+            handler: Arc::new(handler),
+            raw: None,
+        }
+    }
+
+    /// Receives output as the bytes the process wrote, unsplit and undecoded, so
+    /// binary output such as a tar stream passes through intact. Lines sent to
+    /// the sink arrive with a trailing newline. The command's result then holds
+    /// no output, as it all went to `handler`.
+    pub fn passthrough(handler: impl Fn(OutputStream, &[u8]) + Send + Sync + 'static) -> Self {
+        let raw: Arc<RawHandler> = Arc::new(handler);
+        let lines = raw.clone();
+        Self {
+            handler: Arc::new(move |line: OutputLine| {
+                lines(line.stream, format!("{}\n", line.line).as_bytes())
+            }),
+            raw: Some(raw),
+        }
+    }
+
+    /// Whether the sink takes output as raw bytes, see [`OutputSink::passthrough`].
+    pub fn is_passthrough(&self) -> bool {
+        self.raw.is_some()
+    }
+
+    /// Sends bytes as read from the process: unchanged to a passthrough sink,
+    /// else as lossily decoded lines.
+    pub fn send_raw(&self, stream: OutputStream, bytes: &[u8]) {
+        match &self.raw {
+            Some(raw) => raw(stream, bytes),
+            None => self.replay(stream, &String::from_utf8_lossy(bytes)),
+        }
+    }
+
+    /// Forwards every line to a progress channel; lines are dropped once the receiver closes.
+    pub fn channel(sender: UnboundedSender<OutputLine>) -> Self {
+        Self::new(move |line| {
+            let _ = sender.send(line);
+        })
+    }
+
+    /// Logs every line through `tracing`, tagged with `source`.
+    pub fn tracing(source: impl Into<String>) -> Self {
+        let source = source.into();
+        Self::new(move |line| match line.stream {
+            OutputStream::Stdout => tracing::info!(source = %source, "{}", line.line),
+            OutputStream::Stderr => tracing::warn!(source = %source, "{}", line.line),
+        })
+    }
+
+    /// Forwards every line to this sink prefixed with `[name] `, to tell apart
+    /// commands whose output is interleaved.
+    pub fn prefixed(&self, name: &str) -> Self {
+        let inner = self.clone();
+        let prefix = format!("[{name}] ");
+        Self::new(move |line| inner.send(line.stream, format!("{prefix}{}", line.line)))
+    }
+
+    /// Discards all output.
+    pub fn discard() -> Self {
+        Self::new(|_| {})
+    }
+
+    pub fn send(&self, stream: OutputStream, line: impl Into<String>) {
+        (self.handler)(OutputLine {
+            stream,
+            line: line.into(),
+        });
+    }
+
+    /// Replays already captured output, for providers that cannot stream.
+    pub fn replay(&self, stream: OutputStream, output: &str) {
+        for line in output.lines() {
+            self.send(stream, line);
+        }
+    }
+}
+
+impl Default for OutputSink {
+    fn default() -> Self {
+        Self::tracing("process")
+    }
+}
+
+impl fmt::Debug for OutputSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutputSink").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    #[test]
+    fn channel_sink_forwards_replayed_lines() {
+        let (sender, mut receiver) = unbounded_channel();
+        let sink = OutputSink::channel(sender);
+
+        sink.replay(OutputStream::Stdout, "step 1\nstep 2\n");
+        sink.prefixed("lint").send(OutputStream::Stderr, "warning");
+
+        let mut lines = Vec::new();
+        while let Ok(line) = receiver.try_recv() {
+            lines.push(line);
+        }
+        assert_eq!(
+            lines,
+            vec![
+                OutputLine {
+                    stream: OutputStream::Stdout,
+                    line: "step 1".to_string()
+                },
+                OutputLine {
+                    stream: OutputStream::Stdout,
+                    line: "step 2".to_string()
+                },
+                OutputLine {
+                    stream: OutputStream::Stderr,
+                    line: "[lint] warning".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn passthrough_sink_receives_bytes_unchanged() {
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = OutputSink::passthrough({
+            let received = received.clone();
+            move |_, bytes| received.lock().unwrap().extend_from_slice(bytes)
+        });
+
+        sink.send_raw(OutputStream::Stdout, b"\x00\xff\r\n");
+        sink.send(OutputStream::Stdout, "done");
+
+        assert!(sink.is_passthrough());
+        assert_eq!(*received.lock().unwrap(), b"\x00\xff\r\ndone\n");
+    }
+}
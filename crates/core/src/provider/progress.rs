@@ -0,0 +1,165 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::telemetry::{ProgressEvent, ProgressStatus};
+
+/// Event code of image pull progress, alongside the lifecycle step codes.
+pub const PULL_PROGRESS_CODE: &str = "lifecycle.build.pull";
+
+/// Event code of image build progress, which advances the build step itself.
+pub const BUILD_PROGRESS_CODE: &str = "lifecycle.build.image";
+
+/// Stage of one image layer during a pull, in the order layers move through them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LayerStatus {
+    Waiting,
+    Downloading,
+    Verifying,
+    Downloaded,
+    Extracting,
+    Complete,
+}
+
+impl fmt::Display for LayerStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LayerStatus::Waiting => "waiting",
+            LayerStatus::Downloading => "downloading",
+            LayerStatus::Verifying => "verifying",
+            LayerStatus::Downloaded => "downloaded",
+            LayerStatus::Extracting => "extracting",
+            LayerStatus::Complete => "complete",
+        })
+    }
+}
+
+/// Layer-level progress of pulling one image, reported as lifecycle events.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PullProgress {
+    image: String,
+    layers: BTreeMap<String, LayerStatus>,
+}
+
+impl PullProgress {
+    pub fn new(image: impl Into<String>) -> Self {
+        Self {
+            image: image.into(),
+            layers: BTreeMap::new(),
+        }
+    }
+
+    /// Records a layer's status, emitting an event when it advances. Returns whether it did.
+    pub fn observe(&mut self, layer: &str, status: LayerStatus) -> bool {
+        if self
+            .layers
+            .get(layer)
+            .is_some_and(|previous| *previous >= status)
+        {
+            return false;
+        }
+        self.layers.insert(layer.to_string(), status);
+        ProgressEvent::progress(
+            PULL_PROGRESS_CODE,
+            format!("Pulling {}", self.image),
+            ProgressStatus::Running,
+            Some(self.to_string()),
+        )
+        .emit();
+
+        if status == LayerStatus::Complete {
+            tracing::info!(
+                code = PULL_PROGRESS_CODE,
+                image = %self.image,
+                layer,
+                completed = self.completed(),
+                total = self.total(),
+                "Pulled image layer ({self})"
+            );
+        } else {
+            tracing::debug!(
+                code = PULL_PROGRESS_CODE,
+                image = %self.image,
+                layer,
+                status = %status,
+                completed = self.completed(),
+                total = self.total(),
+                "Image layer progress"
+            );
+        }
+        true
+    }
+
+    pub fn completed(&self) -> usize {
+        self.layers
+            .values()
+            .filter(|status| **status == LayerStatus::Complete)
+            .count()
+    }
+
+    /// Layers seen so far; grows until the registry has listed every layer.
+    pub fn total(&self) -> usize {
+        self.layers.len()
+    }
+}
+
+impl fmt::Display for PullProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{} layers", self.completed(), self.total())
+    }
+}
+
+/// Step-level progress of building one image, reported as lifecycle events.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildProgress {
+    current: Option<(u64, u64, String)>,
+}
+
+impl BuildProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that step `step` of `total` runs `instruction`, emitting an event
+    /// when that differs from the last step seen. Returns whether it did.
+    pub fn observe(&mut self, step: u64, total: u64, instruction: &str) -> bool {
+        if self.current.as_ref().is_some_and(|(current, of, running)| {
+            (*current, *of, running.as_str()) == (step, total, instruction)
+        }) {
+            return false;
+        }
+        self.current = Some((step, total, instruction.to_string()));
+        tracing::debug!(
+            code = BUILD_PROGRESS_CODE,
+            step,
+            total,
+            instruction,
+            "Image build progress"
+        );
+        ProgressEvent::progress(
+            BUILD_PROGRESS_CODE,
+            "Building image",
+            ProgressStatus::Running,
+            Some(format!("{step}/{total} {instruction}")),
+        )
+        .emit();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_layers_without_going_backwards() {
+        let mut progress = PullProgress::new("example:image");
+        assert!(progress.observe("a1", LayerStatus::Waiting));
+        assert!(progress.observe("b2", LayerStatus::Downloading));
+        assert!(progress.observe("a1", LayerStatus::Complete));
+        assert!(!progress.observe("a1", LayerStatus::Extracting));
+        assert!(!progress.observe("b2", LayerStatus::Downloading));
+
+        assert_eq!(progress.completed(), 1);
+        assert_eq!(progress.total(), 2);
+        assert_eq!(progress.to_string(), "1/2 layers");
+    }
+}
@@ -0,0 +1,553 @@
+//! Execution of provider CLIs such as `docker`, shared by providers so they can run
+//! locally, on a remote host over SSH, or against a recording fake in tests.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    process::{Child, Command},
+};
+use tracing::Instrument;
+
+use super::{OutputSink, OutputStream};
+use crate::{DevcontainerError, Result};
+
+/// Program invocation handed to a [`CommandRunner`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    /// Variables set on top of the runner's environment.
+    pub env: Vec<(String, String)>,
+    /// Written to the command's standard input, which is otherwise empty. Keeps
+    /// secrets off the command line, where other users can see them.
+    pub stdin: Option<String>,
+}
+
+impl CommandSpec {
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn stdin(mut self, input: impl Into<String>) -> Self {
+        self.stdin = Some(input.into());
+        self
+    }
+}
+
+impl fmt::Display for CommandSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.program.display())?;
+        for arg in &self.args {
+            write!(f, " {arg}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Captured result of a finished command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+    /// Display form of the command, for error messages.
+    pub command: String,
+    /// Exit code; a command killed by a signal reports 128 plus the signal number,
+    /// as shells do.
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == 0
+    }
+
+    pub fn ensure_success(self) -> Result<Self> {
+        if self.success() {
+            Ok(self)
+        } else {
+            Err(DevcontainerError::Provider(format!(
+                "Command '{}' exited with code {}. stdout: {} stderr: {}",
+                self.command,
+                self.exit_code,
+                self.stdout.trim(),
+                self.stderr.trim()
+            )))
+        }
+    }
+}
+
+/// Runs provider CLI commands somewhere: locally, remotely or in a fake.
+#[async_trait]
+pub trait CommandRunner: fmt::Debug + Send + Sync {
+    /// Resolves `program` before it is first run, failing early when it is missing.
+    fn locate(&self, program: &Path) -> Result<PathBuf> {
+        Ok(program.to_path_buf())
+    }
+
+    async fn run(&self, command: &CommandSpec) -> Result<CommandOutput>;
+
+    /// Runs `command`, forwarding each output line to `output` as it is produced.
+    async fn run_streaming(
+        &self,
+        command: &CommandSpec,
+        output: &OutputSink,
+    ) -> Result<CommandOutput>;
+
+    /// Runs `command` attached to the current terminal, returning its exit code.
+    async fn run_interactive(&self, command: &CommandSpec) -> Result<i32>;
+}
+
+/// Runs commands as subprocesses of the CLI.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalRunner;
+
+impl LocalRunner {
+    fn command(spec: &CommandSpec) -> Command {
+        let mut command = Command::new(&spec.program);
+        command.args(&spec.args);
+        command.envs(spec.env.iter().map(|(key, value)| (key, value)));
+        command.stdin(if spec.stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        });
+        command
+    }
+
+    /// Feeds the spec's input to `child` and closes its standard input.
+    async fn write_stdin(spec: &CommandSpec, child: &mut Child) -> Result<()> {
+        let (Some(input), Some(mut stdin)) = (&spec.stdin, child.stdin.take()) else {
+            return Ok(());
+        };
+        stdin.write_all(input.as_bytes()).await.map_err(|err| {
+            DevcontainerError::Provider(format!("Failed to write input of '{spec}': {err}"))
+        })
+    }
+}
+
+#[async_trait]
+impl CommandRunner for LocalRunner {
+    /// Bare program names are looked up on `PATH`.
+    fn locate(&self, program: &Path) -> Result<PathBuf> {
+        if program.components().count() != 1 {
+            return Ok(program.to_path_buf());
+        }
+        which::which(program).map_err(|err| {
+            DevcontainerError::Provider(format!(
+                "Failed to locate binary '{}': {err}",
+                program.display()
+            ))
+        })
+    }
+
+    async fn run(&self, spec: &CommandSpec) -> Result<CommandOutput> {
+        let span = command_span(spec);
+        // Dropping the future, as a cancelled operation does, must stop the command.
+        let mut child = Self::command(spec)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| {
+                DevcontainerError::Provider(format!("Failed to spawn '{spec}': {err}"))
+            })?;
+        Self::write_stdin(spec, &mut child).await?;
+        let output = child
+            .wait_with_output()
+            .instrument(span.clone())
+            .await
+            .map_err(|err| {
+                DevcontainerError::Provider(format!("Failed to read output of '{spec}': {err}"))
+            })?;
+
+        let result = CommandOutput {
+            command: spec.to_string(),
+            exit_code: status_code(output.status),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        };
+        span.record("exit_code", result.exit_code);
+        tracing::debug!(command = %result.command, exit_code = result.exit_code, "Command finished");
+        Ok(result)
+    }
+
+    async fn run_streaming(
+        &self,
+        spec: &CommandSpec,
+        output: &OutputSink,
+    ) -> Result<CommandOutput> {
+        let span = command_span(spec);
+        let mut child = Self::command(spec)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| {
+                DevcontainerError::Provider(format!("Failed to spawn '{spec}': {err}"))
+            })?;
+        Self::write_stdin(spec, &mut child).await?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+
+        let (stdout, stderr, status) = async {
+            tokio::try_join!(
+                forward_lines(stdout, OutputStream::Stdout, output),
+                forward_lines(stderr, OutputStream::Stderr, output),
+                child.wait(),
+            )
+        }
+        .instrument(span.clone())
+        .await
+        .map_err(|err| {
+            DevcontainerError::Provider(format!("Failed to read output of '{spec}': {err}"))
+        })?;
+
+        let result = CommandOutput {
+            command: spec.to_string(),
+            exit_code: status_code(status),
+            stdout: String::from_utf8_lossy(&stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        };
+        span.record("exit_code", result.exit_code);
+        tracing::debug!(command = %result.command, exit_code = result.exit_code, "Command finished");
+        Ok(result)
+    }
+
+    /// The terminal stays the command's input, so the spec's input is ignored.
+    async fn run_interactive(&self, spec: &CommandSpec) -> Result<i32> {
+        let status = Self::command(spec)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .await
+            .map_err(|err| {
+                DevcontainerError::Provider(format!("Failed to spawn '{spec}': {err}"))
+            })?;
+        let exit_code = status_code(status);
+        tracing::debug!(command = %spec, exit_code, "Interactive command finished");
+        Ok(exit_code)
+    }
+}
+
+#[cfg(unix)]
+fn status_code(status: ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(-1)
+}
+
+#[cfg(not(unix))]
+fn status_code(status: ExitStatus) -> i32 {
+    status.code().unwrap_or(-1)
+}
+
+/// Span timing one command, for export with
+/// [`OtlpExporter`](crate::telemetry::otlp::OtlpExporter).
+fn command_span(spec: &CommandSpec) -> tracing::Span {
+    tracing::debug_span!(
+        "command",
+        otel.name = %spec.program.display(),
+        command = %spec,
+        exit_code = tracing::field::Empty
+    )
+}
+
+/// Runs commands on another host through the `ssh` client, so a provider drives a
+/// remote engine. Paths in arguments, such as bind mount sources, refer to that host.
+#[derive(Debug, Clone)]
+pub struct SshRunner {
+    destination: String,
+    ssh: PathBuf,
+    options: Vec<String>,
+}
+
+impl SshRunner {
+    /// `destination` is anything `ssh` accepts, such as `user@host` or a config alias.
+    pub fn new(destination: impl Into<String>) -> Self {
+        Self {
+            destination: destination.into(),
+            ssh: PathBuf::from("ssh"),
+            options: Vec::new(),
+        }
+    }
+
+    pub fn with_ssh_path(mut self, ssh: impl Into<PathBuf>) -> Self {
+        self.ssh = ssh.into();
+        self
+    }
+
+    /// Extra `ssh` arguments placed before the destination, such as `-p 2222`.
+    pub fn with_options(mut self, options: Vec<String>) -> Self {
+        self.options = options;
+        self
+    }
+
+    fn remote(&self, spec: &CommandSpec, tty: bool) -> CommandSpec {
+        let mut words: Vec<String> = spec
+            .env
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        if !words.is_empty() {
+            words.insert(0, "env".to_string());
+        }
+        words.push(spec.program.display().to_string());
+        words.extend(spec.args.iter().cloned());
+        let script = words
+            .iter()
+            .map(|word| shell_quote(word))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        CommandSpec {
+            stdin: spec.stdin.clone(),
+            ..CommandSpec::new(&self.ssh)
+                .args(self.options.iter().cloned())
+                .args(if tty { vec!["-t"] } else { vec!["-T"] })
+                .args([self.destination.as_str(), "--", script.as_str()])
+        }
+    }
+}
+
+#[async_trait]
+impl CommandRunner for SshRunner {
+    async fn run(&self, spec: &CommandSpec) -> Result<CommandOutput> {
+        let mut output = LocalRunner.run(&self.remote(spec, false)).await?;
+        output.command = format!("{spec} (on {})", self.destination);
+        Ok(output)
+    }
+
+    async fn run_streaming(
+        &self,
+        spec: &CommandSpec,
+        output: &OutputSink,
+    ) -> Result<CommandOutput> {
+        let mut result = LocalRunner
+            .run_streaming(&self.remote(spec, false), output)
+            .await?;
+        result.command = format!("{spec} (on {})", self.destination);
+        Ok(result)
+    }
+
+    async fn run_interactive(&self, spec: &CommandSpec) -> Result<i32> {
+        LocalRunner.run_interactive(&self.remote(spec, true)).await
+    }
+}
+
+/// Quotes `word` for a POSIX shell, leaving plain words untouched for readability.
+fn shell_quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+type RunnerHandler = dyn Fn(&CommandSpec) -> CommandOutput + Send + Sync;
+
+/// Fake runner that records every command and answers from a handler, for testing
+/// providers without their CLI installed.
+#[derive(Clone)]
+pub struct RecordingRunner {
+    calls: Arc<Mutex<Vec<CommandSpec>>>,
+    handler: Arc<RunnerHandler>,
+}
+
+impl RecordingRunner {
+    /// Every command succeeds with empty output.
+    pub fn new() -> Self {
+        Self::with_handler(|_| CommandOutput::default())
+    }
+
+    pub fn with_handler(
+        handler: impl Fn(&CommandSpec) -> CommandOutput + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            calls: Arc::new(Mutex::new(Vec::new())),
+            handler: Arc::new(handler),
+        }
+    }
+
+    pub fn calls(&self) -> Vec<CommandSpec> {
+        self.calls.lock().expect("recorded calls").clone()
+    }
+
+    fn respond(&self, spec: &CommandSpec) -> CommandOutput {
+        self.calls
+            .lock()
+            .expect("recorded calls")
+            .push(spec.clone());
+        CommandOutput {
+            command: spec.to_string(),
+            ..(self.handler)(spec)
+        }
+    }
+}
+
+impl Default for RecordingRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for RecordingRunner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordingRunner")
+            .field("calls", &self.calls)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl CommandRunner for RecordingRunner {
+    async fn run(&self, spec: &CommandSpec) -> Result<CommandOutput> {
+        Ok(self.respond(spec))
+    }
+
+    async fn run_streaming(
+        &self,
+        spec: &CommandSpec,
+        output: &OutputSink,
+    ) -> Result<CommandOutput> {
+        let result = self.respond(spec);
+        output.replay(OutputStream::Stdout, &result.stdout);
+        output.replay(OutputStream::Stderr, &result.stderr);
+        Ok(result)
+    }
+
+    async fn run_interactive(&self, spec: &CommandSpec) -> Result<i32> {
+        Ok(self.respond(spec).exit_code)
+    }
+}
+
+/// Sends each line read from `reader` to `output`, returning everything that was read.
+/// A [passthrough](OutputSink::passthrough) sink gets the bytes as they are read
+/// instead, and nothing is kept, so streams of any size pass in constant memory.
+pub async fn forward_lines(
+    reader: impl AsyncRead + Unpin,
+    stream: OutputStream,
+    output: &OutputSink,
+) -> std::io::Result<Vec<u8>> {
+    let mut reader = BufReader::new(reader);
+    let mut captured = Vec::new();
+    if output.is_passthrough() {
+        let mut chunk = [0; 8192];
+        loop {
+            let read = reader.read(&mut chunk).await?;
+            if read == 0 {
+                return Ok(Vec::new());
+            }
+            output.send_raw(stream, &chunk[..read]);
+        }
+    }
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).await? == 0 {
+            return Ok(captured);
+        }
+        captured.extend_from_slice(&line);
+        let text = String::from_utf8_lossy(&line);
+        output.send(stream, text.trim_end_matches(['\r', '\n']));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn forward_lines_sends_each_line_and_captures_output() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let sink = OutputSink::channel(sender);
+
+        let captured = forward_lines(&b"step 1\r\nstep 2"[..], OutputStream::Stdout, &sink)
+            .await
+            .expect("read output");
+
+        assert_eq!(captured, b"step 1\r\nstep 2");
+        let mut lines = Vec::new();
+        while let Ok(line) = receiver.try_recv() {
+            lines.push(line.line);
+        }
+        assert_eq!(lines, vec!["step 1", "step 2"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn signalled_commands_report_shell_exit_status() {
+        let spec = CommandSpec {
+            program: PathBuf::from("sh"),
+            args: vec!["-c".to_string(), "kill -INT $$".to_string()],
+            ..Default::default()
+        };
+        let output = LocalRunner.run(&spec).await.expect("run");
+        assert_eq!(output.exit_code, 130);
+    }
+
+    #[tokio::test]
+    async fn forward_lines_passes_binary_output_through() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = OutputSink::passthrough({
+            let received = received.clone();
+            move |_, bytes| received.lock().unwrap().extend_from_slice(bytes)
+        });
+        let binary = b"ustar\x00\x9f\x92\r\nno newline";
+
+        let captured = forward_lines(&binary[..], OutputStream::Stdout, &sink)
+            .await
+            .expect("read output");
+
+        assert!(captured.is_empty());
+        assert_eq!(*received.lock().unwrap(), binary);
+    }
+
+    #[tokio::test]
+    async fn ssh_runner_quotes_the_remote_command() {
+        let runner = SshRunner::new("builder@ci")
+            .with_ssh_path("/bin/echo")
+            .with_options(vec!["-p".to_string(), "2222".to_string()]);
+        let spec = CommandSpec::new("docker")
+            .args(["run", "--label", "note=it's here"])
+            .env("DOCKER_HOST", "unix:///run/docker.sock");
+
+        let output = runner.run(&spec).await.expect("run through echo");
+        assert_eq!(
+            output.stdout.trim(),
+            "-p 2222 -T builder@ci -- env DOCKER_HOST=unix:///run/docker.sock docker run --label 'note=it'\\''s here'"
+        );
+        assert!(output.command.ends_with("(on builder@ci)"));
+    }
+}
@@ -0,0 +1,164 @@
+//! Selection of the container runtime to drive, probing each registered one in
+//! preference order unless one is forced.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use super::{CommandRunner, CommandSpec, LocalRunner, Provider, ProviderKind};
+use crate::{DevcontainerError, Result};
+
+type ProviderFactory = dyn Fn(&Path) -> Box<dyn Provider> + Send + Sync;
+
+struct Runtime {
+    kind: ProviderKind,
+    binary: PathBuf,
+    factory: Box<ProviderFactory>,
+}
+
+/// Container runtimes a provider can be created for, in preference order.
+pub struct ProviderRegistry {
+    runtimes: Vec<Runtime>,
+    runner: Arc<dyn CommandRunner>,
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        Self {
+            runtimes: Vec::new(),
+            runner: Arc::new(LocalRunner),
+        }
+    }
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the availability probes through `runner` instead of locally.
+    pub fn with_runner(mut self, runner: impl CommandRunner + 'static) -> Self {
+        self.runner = Arc::new(runner);
+        self
+    }
+
+    /// Adds a runtime driven through `binary`, after those already registered.
+    pub fn register(
+        mut self,
+        kind: ProviderKind,
+        binary: impl Into<PathBuf>,
+        factory: impl Fn(&Path) -> Box<dyn Provider> + Send + Sync + 'static,
+    ) -> Self {
+        self.runtimes.push(Runtime {
+            kind,
+            binary: binary.into(),
+            factory: Box::new(factory),
+        });
+        self
+    }
+
+    pub fn kinds(&self) -> Vec<ProviderKind> {
+        self.runtimes.iter().map(|runtime| runtime.kind).collect()
+    }
+
+    /// Creates the provider for `forced` without probing it, so its own errors
+    /// explain what is wrong; otherwise the first runtime whose engine answers
+    /// `version`.
+    pub async fn select(&self, forced: Option<ProviderKind>) -> Result<Box<dyn Provider>> {
+        if let Some(kind) = forced {
+            let runtime = self
+                .runtimes
+                .iter()
+                .find(|runtime| runtime.kind == kind)
+                .ok_or_else(|| {
+                    DevcontainerError::Configuration(format!(
+                        "Provider '{kind}' is not available; choose one of {}",
+                        self
+                    ))
+                })?;
+            return Ok((runtime.factory)(&runtime.binary));
+        }
+
+        for runtime in &self.runtimes {
+            if self.probe(runtime).await {
+                tracing::debug!(provider = %runtime.kind, "Detected container runtime");
+                return Ok((runtime.factory)(&runtime.binary));
+            }
+        }
+        Err(DevcontainerError::Provider(format!(
+            "No container runtime is available; tried {self}. Start one or pass --provider"
+        )))
+    }
+
+    async fn probe(&self, runtime: &Runtime) -> bool {
+        let command = CommandSpec::new(&runtime.binary).args(["version"]);
+        match self.runner.run(&command).await {
+            Ok(output) if output.success() => true,
+            Ok(output) => {
+                tracing::debug!(
+                    provider = %runtime.kind,
+                    stderr = %output.stderr.trim(),
+                    "Container runtime is not usable"
+                );
+                false
+            }
+            Err(err) => {
+                tracing::debug!(provider = %runtime.kind, error = %err, "Container runtime not found");
+                false
+            }
+        }
+    }
+}
+
+impl fmt::Display for ProviderRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self.kinds().iter().map(ToString::to_string).collect();
+        f.write_str(&names.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{mock::MockProvider, CommandOutput, RecordingRunner};
+
+    #[tokio::test]
+    async fn selects_first_answering_runtime_unless_forced() {
+        let runner = RecordingRunner::with_handler(|spec| CommandOutput {
+            command: spec.to_string(),
+            exit_code: i32::from(spec.program != Path::new("podman")),
+            stdout: String::new(),
+            stderr: "Cannot connect to the Docker daemon".to_string(),
+        });
+        let registry = ProviderRegistry::new()
+            .with_runner(runner.clone())
+            .register(ProviderKind::Docker, "docker", |_| {
+                Box::new(MockProvider::new())
+            })
+            .register(ProviderKind::Podman, "podman", |_| {
+                Box::new(MockProvider::new())
+            });
+
+        registry.select(None).await.expect("podman answers");
+        let probed: Vec<PathBuf> = runner
+            .calls()
+            .into_iter()
+            .map(|spec| spec.program)
+            .collect();
+        assert_eq!(probed, [PathBuf::from("docker"), PathBuf::from("podman")]);
+
+        registry
+            .select(Some(ProviderKind::Docker))
+            .await
+            .expect("forced without probing");
+        assert_eq!(runner.calls().len(), 2);
+        let err = registry
+            .select(Some(ProviderKind::Nerdctl))
+            .await
+            .err()
+            .expect("nerdctl not registered");
+        assert!(err.to_string().contains("choose one of docker, podman"));
+    }
+}
@@ -0,0 +1,624 @@
+//! Registry credentials shared by image pulls and pushes, which hand them to the
+//! engine through a docker config, and by OCI artifact downloads, which resolve
+//! them here the way `docker login` state would.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    errors::DevcontainerError,
+    provider::{CommandRunner, CommandSpec, LocalRunner},
+    Result,
+};
+
+pub const DOCKER_HUB_REGISTRY: &str = "docker.io";
+const DOCKER_HUB_AUTH_KEY: &str = "https://index.docker.io/v1/";
+
+/// Explicit credentials for a single registry, parsed from `host=user:token`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RegistryCredential {
+    pub registry: String,
+    pub username: String,
+    pub secret: String,
+}
+
+impl fmt::Debug for RegistryCredential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegistryCredential")
+            .field("registry", &self.registry)
+            .field("username", &self.username)
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+impl FromStr for RegistryCredential {
+    type Err = DevcontainerError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let invalid = || {
+            DevcontainerError::Configuration(
+                "Invalid registry auth: expected 'host=user:token'".to_string(),
+            )
+        };
+
+        let (registry, credentials) = value.split_once('=').ok_or_else(invalid)?;
+        let (username, secret) = credentials.split_once(':').ok_or_else(invalid)?;
+        let registry = registry.trim();
+        if registry.is_empty() || username.is_empty() || secret.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            registry: normalize_registry(registry),
+            username: username.to_string(),
+            secret: secret.to_string(),
+        })
+    }
+}
+
+/// Credentials for several registries, parsed from a JSON document: either a
+/// docker `config.json` with `auths`, as CI secrets usually hold it, or a map of
+/// hosts to `{"username", "password"}` or `{"auth"}` entries.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegistryCredentials(pub Vec<RegistryCredential>);
+
+impl FromStr for RegistryCredentials {
+    type Err = DevcontainerError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let invalid = |reason: String| {
+            DevcontainerError::Configuration(format!("Invalid registry auth JSON: {reason}"))
+        };
+
+        let mut document: Map<String, Value> =
+            serde_json::from_str(value).map_err(|err| invalid(err.to_string()))?;
+        let entries = match document.remove("auths") {
+            Some(Value::Object(auths)) => auths,
+            Some(_) => return Err(invalid("'auths' must be an object".to_string())),
+            None => document,
+        };
+
+        entries
+            .into_iter()
+            .map(|(registry, entry)| {
+                let entry: AuthEntry = serde_json::from_value(entry)
+                    .map_err(|err| invalid(format!("{registry}: {err}")))?;
+                entry
+                    .credential(&registry)
+                    .ok_or_else(|| invalid(format!("{registry}: expected a username and password")))
+            })
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+}
+
+/// Credential helper to use for a registry, parsed from `host=helper`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CredentialHelperSelection {
+    pub registry: String,
+    pub helper: String,
+}
+
+impl FromStr for CredentialHelperSelection {
+    type Err = DevcontainerError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.split_once('=') {
+            Some((registry, helper)) if !registry.trim().is_empty() && !helper.is_empty() => {
+                Ok(Self {
+                    registry: normalize_registry(registry.trim()),
+                    helper: helper.to_string(),
+                })
+            }
+            _ => Err(DevcontainerError::Configuration(format!(
+                "Invalid credential helper selection '{value}': expected 'host=helper'"
+            ))),
+        }
+    }
+}
+
+/// Registry authentication supplied explicitly, layered over ambient docker login state.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegistryAuth {
+    pub credentials: Vec<RegistryCredential>,
+    pub credential_helpers: Vec<CredentialHelperSelection>,
+}
+
+impl RegistryAuth {
+    pub fn is_empty(&self) -> bool {
+        self.credentials.is_empty() && self.credential_helpers.is_empty()
+    }
+
+    pub fn credential_for(&self, registry: &str) -> Option<&RegistryCredential> {
+        let registry = normalize_registry(registry);
+        self.credentials
+            .iter()
+            .find(|credential| credential.registry == registry)
+    }
+
+    fn helper_for(&self, registry: &str) -> Option<&str> {
+        let registry = normalize_registry(registry);
+        self.credential_helpers
+            .iter()
+            .find(|selection| selection.registry == registry)
+            .map(|selection| selection.helper.as_str())
+    }
+
+    /// Overlays these credentials on a docker `config.json` document.
+    ///
+    /// Explicit credentials are pinned to the file store (an empty `credHelpers`
+    /// entry) so a global `credsStore` cannot shadow them.
+    pub fn apply_to_docker_config(&self, base: Option<Value>) -> Value {
+        let mut document = match base {
+            Some(Value::Object(map)) => map,
+            _ => Map::new(),
+        };
+
+        let mut auths = take_object(&mut document, "auths");
+        let mut helpers = take_object(&mut document, "credHelpers");
+
+        for credential in &self.credentials {
+            let key = docker_config_key(&credential.registry);
+            auths.insert(
+                key.clone(),
+                json!({ "auth": encode_basic_auth(&credential.username, &credential.secret) }),
+            );
+            helpers.insert(key, Value::String(String::new()));
+        }
+
+        for selection in &self.credential_helpers {
+            helpers.insert(
+                docker_config_key(&selection.registry),
+                Value::String(selection.helper.clone()),
+            );
+        }
+
+        document.insert("auths".to_string(), Value::Object(auths));
+        document.insert("credHelpers".to_string(), Value::Object(helpers));
+        Value::Object(document)
+    }
+}
+
+/// Entry of a docker config's `auths`, or of [`RegistryCredentials`] JSON.
+#[derive(Default, Deserialize)]
+struct AuthEntry {
+    #[serde(default)]
+    auth: Option<String>,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default, alias = "token")]
+    password: Option<String>,
+}
+
+impl AuthEntry {
+    fn credential(&self, registry: &str) -> Option<RegistryCredential> {
+        let (username, secret) = match (&self.username, &self.password, &self.auth) {
+            (Some(username), Some(password), _) => (username.clone(), password.clone()),
+            (_, _, Some(auth)) => {
+                let decoded = BASE64_STANDARD.decode(auth.trim()).ok()?;
+                let (username, secret) = std::str::from_utf8(&decoded).ok()?.split_once(':')?;
+                (username.to_string(), secret.to_string())
+            }
+            _ => return None,
+        };
+        (!username.is_empty() && !secret.is_empty()).then(|| RegistryCredential {
+            registry: normalize_registry(registry),
+            username,
+            secret,
+        })
+    }
+}
+
+/// Login state of the docker CLI, read from its `config.json`.
+#[derive(Default, Deserialize)]
+pub struct DockerConfig {
+    #[serde(default)]
+    auths: BTreeMap<String, AuthEntry>,
+    #[serde(default, rename = "credHelpers")]
+    cred_helpers: BTreeMap<String, String>,
+    #[serde(default, rename = "credsStore")]
+    creds_store: Option<String>,
+}
+
+impl fmt::Debug for DockerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DockerConfig")
+            .field("auths", &self.auths.keys().collect::<Vec<_>>())
+            .field("cred_helpers", &self.cred_helpers)
+            .field("creds_store", &self.creds_store)
+            .finish()
+    }
+}
+
+impl DockerConfig {
+    /// Reads `config.json` in `dir`; a missing or unreadable file is empty.
+    pub fn load(dir: &Path) -> Self {
+        let path = dir.join("config.json");
+        let Ok(raw) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&raw).unwrap_or_else(|err| {
+            tracing::debug!(path = %path.display(), error = %err, "Ignoring unreadable docker config");
+            Self::default()
+        })
+    }
+
+    /// The config of the current user, under `$DOCKER_CONFIG` or `~/.docker`.
+    pub fn ambient() -> Self {
+        ambient_docker_config_dir()
+            .map(|dir| Self::load(&dir))
+            .unwrap_or_default()
+    }
+
+    fn stored_credential(&self, registry: &str) -> Option<RegistryCredential> {
+        self.auths
+            .iter()
+            .find(|(key, _)| normalize_registry(key) == registry)
+            .and_then(|(_, entry)| entry.credential(registry))
+    }
+
+    /// A per-registry helper, or the global store when the registry has none.
+    /// An empty helper pins the registry to the `auths` entries.
+    fn helper_for(&self, registry: &str) -> Option<&str> {
+        self.cred_helpers
+            .iter()
+            .find(|(key, _)| normalize_registry(key) == registry)
+            .map(|(_, helper)| helper.as_str())
+            .or(self.creds_store.as_deref())
+            .filter(|helper| !helper.is_empty())
+    }
+}
+
+/// Directory of the current user's docker config: `$DOCKER_CONFIG`, or
+/// `.docker` in the home directory.
+pub fn ambient_docker_config_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("DOCKER_CONFIG") {
+        return Some(PathBuf::from(dir));
+    }
+
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".docker"))
+}
+
+/// Finds the credentials for a registry like the docker CLI does, preferring
+/// explicit [`RegistryAuth`] over the docker config's helpers and `auths`.
+#[derive(Debug, Clone)]
+pub struct CredentialResolver {
+    auth: RegistryAuth,
+    docker_config: Arc<DockerConfig>,
+    runner: Arc<dyn CommandRunner>,
+}
+
+impl CredentialResolver {
+    /// Resolves against `auth` and the current user's docker config.
+    pub fn new(auth: RegistryAuth) -> Self {
+        Self {
+            auth,
+            docker_config: Arc::new(DockerConfig::ambient()),
+            runner: Arc::new(LocalRunner),
+        }
+    }
+
+    pub fn with_docker_config(mut self, config: DockerConfig) -> Self {
+        self.docker_config = Arc::new(config);
+        self
+    }
+
+    /// Runs `docker-credential-*` helpers through `runner`.
+    pub fn with_runner(mut self, runner: impl CommandRunner + 'static) -> Self {
+        self.runner = Arc::new(runner);
+        self
+    }
+
+    /// Credentials for `registry`, or `None` to access it anonymously.
+    pub async fn resolve(&self, registry: &str) -> Result<Option<RegistryCredential>> {
+        let registry = normalize_registry(registry);
+        if let Some(credential) = self.auth.credential_for(&registry) {
+            return Ok(Some(credential.clone()));
+        }
+        if let Some(helper) = self.auth.helper_for(&registry) {
+            return self.ask_helper(helper, &registry).await;
+        }
+        match self.docker_config.helper_for(&registry) {
+            Some(helper) => self.ask_helper(helper, &registry).await,
+            None => Ok(self.docker_config.stored_credential(&registry)),
+        }
+    }
+
+    /// Asks `docker-credential-<helper>` for the registry's credentials; a helper
+    /// that has none, or fails, leaves the registry anonymous.
+    async fn ask_helper(&self, helper: &str, registry: &str) -> Result<Option<RegistryCredential>> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct HelperCredential {
+            username: String,
+            secret: String,
+        }
+
+        let command = CommandSpec::new(format!("docker-credential-{helper}"))
+            .args(["get"])
+            .stdin(docker_config_key(registry));
+        let output = self.runner.run(&command).await?;
+        if !output.success() {
+            tracing::debug!(
+                helper,
+                registry,
+                stderr = %output.stderr.trim(),
+                "Credential helper has no credentials"
+            );
+            return Ok(None);
+        }
+
+        let credential: HelperCredential =
+            serde_json::from_str(output.stdout.trim()).map_err(|err| {
+                DevcontainerError::Provider(format!(
+                    "Credential helper '{helper}' returned invalid output for {registry}: {err}"
+                ))
+            })?;
+        Ok(Some(RegistryCredential {
+            registry: registry.to_string(),
+            username: credential.username,
+            secret: credential.secret,
+        }))
+    }
+}
+
+/// Pull-through registry cache that image and feature references are
+/// fetched through, parsed from a host or URL such as `https://cache.local:5000`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryMirror {
+    pub host: String,
+}
+
+impl FromStr for RegistryMirror {
+    type Err = DevcontainerError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let host = normalize_registry(value.trim());
+        if host.is_empty() || host.contains('/') {
+            return Err(DevcontainerError::Configuration(format!(
+                "Invalid registry mirror '{value}': expected a host such as 'cache.local:5000'"
+            )));
+        }
+        Ok(Self { host })
+    }
+}
+
+impl fmt::Display for RegistryMirror {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.host)
+    }
+}
+
+impl RegistryMirror {
+    /// `reference` with its registry replaced by the mirror, e.g.
+    /// `ghcr.io/devcontainers/features/node:1` becomes
+    /// `cache.local:5000/devcontainers/features/node:1`.
+    pub fn route(&self, reference: &str) -> String {
+        let registry = registry_for_image(reference);
+        let repository = match reference.split_once('/') {
+            Some((first, rest)) if normalize_registry(first) == registry => rest.to_string(),
+            Some(_) => reference.to_string(),
+            None => format!("library/{reference}"),
+        };
+        format!("{}/{repository}", self.host)
+    }
+}
+
+fn take_object(document: &mut Map<String, Value>, key: &str) -> Map<String, Value> {
+    match document.remove(key) {
+        Some(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Normalizes registry host aliases so lookups are stable.
+pub fn normalize_registry(registry: &str) -> String {
+    let registry = registry
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_end_matches('/');
+    let registry = registry.strip_suffix("/v1").unwrap_or(registry);
+
+    match registry {
+        "index.docker.io" | "registry-1.docker.io" | "registry.hub.docker.com" => {
+            DOCKER_HUB_REGISTRY.to_string()
+        }
+        other => other.to_ascii_lowercase(),
+    }
+}
+
+/// Returns the registry host an image reference will be pulled from.
+pub fn registry_for_image(reference: &str) -> String {
+    match reference.split_once('/') {
+        Some((first, _)) if first.contains('.') || first.contains(':') || first == "localhost" => {
+            normalize_registry(first)
+        }
+        _ => DOCKER_HUB_REGISTRY.to_string(),
+    }
+}
+
+fn docker_config_key(registry: &str) -> String {
+    if registry == DOCKER_HUB_REGISTRY {
+        DOCKER_HUB_AUTH_KEY.to_string()
+    } else {
+        registry.to_string()
+    }
+}
+
+fn encode_basic_auth(username: &str, secret: &str) -> String {
+    BASE64_STANDARD.encode(format!("{username}:{secret}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{CommandOutput, RecordingRunner};
+
+    #[test]
+    fn parses_registry_credentials() {
+        let credential: RegistryCredential = "ghcr.io=octocat:ghp_secret".parse().expect("parse");
+        assert_eq!(credential.registry, "ghcr.io");
+        assert_eq!(credential.username, "octocat");
+        assert_eq!(credential.secret, "ghp_secret");
+        assert!(!format!("{credential:?}").contains("ghp_secret"));
+
+        assert!("ghcr.io".parse::<RegistryCredential>().is_err());
+        assert!("ghcr.io=octocat".parse::<RegistryCredential>().is_err());
+        assert!("=user:token".parse::<RegistryCredential>().is_err());
+    }
+
+    #[test]
+    fn resolves_registry_for_image_references() {
+        assert_eq!(registry_for_image("ubuntu:22.04"), DOCKER_HUB_REGISTRY);
+        assert_eq!(registry_for_image("library/ubuntu"), DOCKER_HUB_REGISTRY);
+        assert_eq!(
+            registry_for_image("ghcr.io/devcontainers/base:latest"),
+            "ghcr.io"
+        );
+        assert_eq!(registry_for_image("localhost:5000/app"), "localhost:5000");
+        assert_eq!(
+            normalize_registry("https://index.docker.io/v1/"),
+            "docker.io"
+        );
+    }
+
+    #[test]
+    fn overlays_credentials_on_docker_config() {
+        let auth = RegistryAuth {
+            credentials: vec!["docker.io=user:pass".parse().expect("credential")],
+            credential_helpers: vec!["123.dkr.ecr.us-east-1.amazonaws.com=ecr-login"
+                .parse()
+                .expect("helper")],
+        };
+
+        let base = json!({
+            "credsStore": "desktop",
+            "auths": { "quay.io": { "auth": "existing" } }
+        });
+        let config = auth.apply_to_docker_config(Some(base));
+
+        assert_eq!(config["credsStore"], "desktop");
+        assert_eq!(config["auths"]["quay.io"]["auth"], "existing");
+        assert_eq!(config["auths"][DOCKER_HUB_AUTH_KEY]["auth"], "dXNlcjpwYXNz");
+        assert_eq!(config["credHelpers"][DOCKER_HUB_AUTH_KEY], "");
+        assert_eq!(
+            config["credHelpers"]["123.dkr.ecr.us-east-1.amazonaws.com"],
+            "ecr-login"
+        );
+    }
+
+    #[test]
+    fn parses_registry_credentials_from_json() {
+        let docker_config = json!({
+            "auths": { "https://index.docker.io/v1/": { "auth": "dXNlcjpwYXNz" } }
+        });
+        let RegistryCredentials(credentials) =
+            docker_config.to_string().parse().expect("docker config");
+        assert_eq!(
+            credentials,
+            ["docker.io=user:pass".parse().expect("credential")]
+        );
+
+        let map = json!({ "GHCR.io": { "username": "octocat", "token": "ghp_secret" } });
+        let RegistryCredentials(credentials) = map.to_string().parse().expect("map");
+        assert_eq!(
+            credentials,
+            ["ghcr.io=octocat:ghp_secret".parse().expect("credential")]
+        );
+
+        assert!("[]".parse::<RegistryCredentials>().is_err());
+        assert!(r#"{"ghcr.io": {"username": "octocat"}}"#.parse::<RegistryCredentials>().is_err());
+    }
+
+    #[tokio::test]
+    async fn resolves_credentials_like_the_docker_cli() {
+        let runner = RecordingRunner::with_handler(|spec| match spec.stdin.as_deref() {
+            Some("quay.io") => CommandOutput {
+                stdout: r#"{"ServerURL":"quay.io","Username":"robot","Secret":"s3cret"}"#
+                    .to_string(),
+                ..Default::default()
+            },
+            _ => CommandOutput {
+                exit_code: 1,
+                stderr: "credentials not found in native keychain".to_string(),
+                ..Default::default()
+            },
+        });
+        let docker_config: DockerConfig = serde_json::from_value(json!({
+            "credsStore": "desktop",
+            "credHelpers": { "ghcr.io": "" },
+            "auths": { "ghcr.io": { "auth": "b2N0b2NhdDpzdG9yZWQ=" } }
+        }))
+        .expect("docker config");
+        let resolver = CredentialResolver::new(RegistryAuth {
+            credentials: vec!["docker.io=user:pass".parse().expect("credential")],
+            credential_helpers: vec!["ecr.example.com=ecr-login".parse().expect("helper")],
+        })
+        .with_docker_config(docker_config)
+        .with_runner(runner.clone());
+
+        let resolve = |registry| resolver.resolve(registry);
+        assert_eq!(
+            resolve("index.docker.io").await.expect("explicit"),
+            Some("docker.io=user:pass".parse().expect("credential"))
+        );
+        assert_eq!(
+            resolve("ghcr.io").await.expect("stored"),
+            Some("ghcr.io=octocat:stored".parse().expect("credential"))
+        );
+        assert_eq!(
+            resolve("quay.io").await.expect("store"),
+            Some("quay.io=robot:s3cret".parse().expect("credential"))
+        );
+        assert_eq!(resolve("ecr.example.com").await.expect("helper"), None);
+
+        let helpers: Vec<_> = runner
+            .calls()
+            .into_iter()
+            .map(|spec| spec.program.display().to_string())
+            .collect();
+        assert_eq!(
+            helpers,
+            ["docker-credential-desktop", "docker-credential-ecr-login"]
+        );
+    }
+
+    #[test]
+    fn routes_references_through_mirror() {
+        let mirror: RegistryMirror = "https://Cache.local:5000/".parse().expect("mirror");
+        assert_eq!(mirror.host, "cache.local:5000");
+        assert_eq!(
+            mirror.route("ghcr.io/devcontainers/features/node:1"),
+            "cache.local:5000/devcontainers/features/node:1"
+        );
+        assert_eq!(
+            mirror.route("ubuntu:22.04"),
+            "cache.local:5000/library/ubuntu:22.04"
+        );
+        assert_eq!(
+            mirror.route("devcontainers/base:ubuntu"),
+            "cache.local:5000/devcontainers/base:ubuntu"
+        );
+        assert!("".parse::<RegistryMirror>().is_err());
+        assert!("cache.local/path".parse::<RegistryMirror>().is_err());
+    }
+
+    #[test]
+    fn encodes_basic_auth_with_padding() {
+        assert_eq!(encode_basic_auth("a", "b"), "YTpi");
+        assert_eq!(encode_basic_auth("ab", "c"), "YWI6Yw==");
+        assert_eq!(encode_basic_auth("ab", "cd"), "YWI6Y2Q=");
+    }
+}
@@ -0,0 +1,490 @@
+//! JSON-RPC 2.0 access to [`DevcontainerManager`] operations, so editor
+//! extensions can drive container lifecycles through one long-lived process.
+//!
+//! Messages are framed one per line. Requests run concurrently; while one runs,
+//! `progress` and `output` notifications carrying its `requestId` report on it.
+
+use std::{path::PathBuf, sync::Arc};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    sync::mpsc::{self, UnboundedSender},
+    task::JoinSet,
+};
+
+use crate::{
+    config::{ConfigOverrides, ConfigSource, ContainerLabel},
+    manager::{DevcontainerManager, ManagerEvent},
+    provider::{OutputStream, Provider, ProviderCleanupOptions},
+    state::StateStore,
+    DevcontainerError, Result,
+};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// The operation itself failed; `data.kind` names the kind of error.
+pub const OPERATION_FAILED: i64 = -32000;
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    jsonrpc: String,
+    /// Absent for notifications, which get no response.
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl From<DevcontainerError> for RpcError {
+    fn from(err: DevcontainerError) -> Self {
+        let kind = match &err {
+            DevcontainerError::Configuration(_) => "configuration",
+            DevcontainerError::Provider(_) => "provider",
+            DevcontainerError::Unsupported(_) => "unsupported",
+            DevcontainerError::Timeout(_) => "timeout",
+            DevcontainerError::Cancelled(_) => "cancelled",
+            DevcontainerError::HookFailed { .. } => "hookFailed",
+            DevcontainerError::CommandFailed { .. } => "commandFailed",
+            DevcontainerError::DaemonAccess(_) => "daemonAccess",
+            DevcontainerError::DaemonRestarted { .. } => "daemonRestarted",
+            DevcontainerError::Other(_) => "other",
+        };
+        Self {
+            code: OPERATION_FAILED,
+            message: err.to_string(),
+            data: Some(json!({ "kind": kind })),
+        }
+    }
+}
+
+/// Configuration a request operates on.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TargetParams {
+    workspace_folder: PathBuf,
+    /// Explicit configuration file, instead of discovering it in the workspace.
+    config: Option<PathBuf>,
+    /// Name of a `.devcontainer/<name>/devcontainer.json` configuration.
+    config_name: Option<String>,
+    /// Labels identifying the container, as `name=value`.
+    #[serde(default)]
+    id_labels: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DownParams {
+    #[serde(flatten)]
+    target: TargetParams,
+    #[serde(default)]
+    remove_volumes: bool,
+    #[serde(default)]
+    remove_unknown: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExecParams {
+    #[serde(flatten)]
+    target: TargetParams,
+    command: Vec<String>,
+}
+
+/// Serves lifecycle operations on one provider to any number of connections.
+#[derive(Clone)]
+pub struct RpcServer {
+    provider: Arc<dyn Provider>,
+    overrides: ConfigOverrides,
+    state: Option<StateStore>,
+}
+
+impl RpcServer {
+    pub fn new(provider: Arc<dyn Provider>) -> Self {
+        Self {
+            provider,
+            overrides: ConfigOverrides::default(),
+            state: None,
+        }
+    }
+
+    /// Applied to every request, under the workspace folder it names.
+    pub fn with_overrides(mut self, overrides: ConfigOverrides) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Records devcontainers in `store` instead of the default state store.
+    pub fn with_state_store(mut self, store: StateStore) -> Self {
+        self.state = Some(store);
+        self
+    }
+
+    /// Answers the requests read from `reader` on `writer` until `reader` ends
+    /// and every request has been answered.
+    pub async fn serve<R, W>(&self, reader: R, writer: W) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Value>();
+        let writing = tokio::spawn(async move {
+            let mut writer = writer;
+            while let Some(message) = receiver.recv().await {
+                writer.write_all(format!("{message}\n").as_bytes()).await?;
+                writer.flush().await?;
+            }
+            Ok::<_, std::io::Error>(())
+        });
+
+        let mut lines = BufReader::new(reader).lines();
+        let mut requests = JoinSet::new();
+        while let Some(line) = lines
+            .next_line()
+            .await
+            .map_err(|err| DevcontainerError::Other(err.into()))?
+        {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let request = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => request,
+                Err(err) => {
+                    let error = RpcError::new(PARSE_ERROR, err.to_string());
+                    let _ = sender.send(response(Value::Null, Err(error)));
+                    continue;
+                }
+            };
+            let server = self.clone();
+            let sender = sender.clone();
+            requests.spawn(async move { server.handle(request, sender).await });
+        }
+        while requests.join_next().await.is_some() {}
+        drop(sender);
+
+        writing
+            .await
+            .map_err(|err| DevcontainerError::Other(err.into()))?
+            .map_err(|err| DevcontainerError::Other(err.into()))
+    }
+
+    /// Serves every connection to a unix socket at `path`, replacing a stale
+    /// socket file left there.
+    #[cfg(unix)]
+    pub async fn serve_unix(&self, path: &std::path::Path) -> Result<()> {
+        remove_stale_socket(path).await?;
+        let listener = tokio::net::UnixListener::bind(path).map_err(|err| {
+            DevcontainerError::Configuration(format!(
+                "Failed to listen on {}: {err}",
+                path.display()
+            ))
+        })?;
+        tracing::info!(socket = %path.display(), "Serving JSON-RPC");
+        loop {
+            let (stream, _) = listener
+                .accept()
+                .await
+                .map_err(|err| DevcontainerError::Other(err.into()))?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                let (reader, writer) = stream.into_split();
+                if let Err(err) = server.serve(reader, writer).await {
+                    tracing::warn!(error = %err, "JSON-RPC connection failed");
+                }
+            });
+        }
+    }
+
+    async fn handle(&self, request: Request, sender: UnboundedSender<Value>) {
+        let result = if request.jsonrpc == "2.0" {
+            self.dispatch(&request, &sender).await
+        } else {
+            Err(RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""))
+        };
+        if let Some(id) = request.id {
+            let _ = sender.send(response(id, result));
+        }
+    }
+
+    async fn dispatch(
+        &self,
+        request: &Request,
+        sender: &UnboundedSender<Value>,
+    ) -> std::result::Result<Value, RpcError> {
+        match request.method.as_str() {
+            "up" => {
+                let target: TargetParams = params(&request.params)?;
+                let up = self.manager(target, request, sender)?.up().await?;
+                Ok(json!({
+                    "containerId": up.container.id.or(up.container.name),
+                    "reused": up.reused,
+                    "remoteUser": up.remote_user,
+                    "remoteWorkspaceFolder": up.remote_workspace_folder,
+                    "hooks": up.hooks,
+                }))
+            }
+            "down" => {
+                let down: DownParams = params(&request.params)?;
+                let options = ProviderCleanupOptions {
+                    remove_volumes: down.remove_volumes,
+                    remove_unknown: down.remove_unknown,
+                };
+                self.manager(down.target, request, sender)?
+                    .down(&options)
+                    .await?;
+                Ok(Value::Null)
+            }
+            "exec" => {
+                let exec: ExecParams = params(&request.params)?;
+                if exec.command.is_empty() {
+                    return Err(RpcError::new(INVALID_PARAMS, "command must not be empty"));
+                }
+                let result = self
+                    .manager(exec.target, request, sender)?
+                    .exec(&exec.command)
+                    .await?;
+                Ok(json!({
+                    "exitCode": result.exit_code,
+                    "stdout": result.stdout,
+                    "stderr": result.stderr,
+                }))
+            }
+            "status" => {
+                let target: TargetParams = params(&request.params)?;
+                let status = self.manager(target, request, sender)?.status().await?;
+                Ok(json!({
+                    "state": status.state,
+                    "running": status.activity.map(|activity| activity.running),
+                    "sessions": status.activity.map(|activity| activity.sessions),
+                    "configChanged": status.config_changed,
+                }))
+            }
+            method => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method '{method}'"),
+            )),
+        }
+    }
+
+    /// Manager for `target` that reports its events as notifications on `request`.
+    fn manager(
+        &self,
+        target: TargetParams,
+        request: &Request,
+        sender: &UnboundedSender<Value>,
+    ) -> std::result::Result<DevcontainerManager<&dyn Provider>, RpcError> {
+        let id_labels = target
+            .id_labels
+            .iter()
+            .map(|label| label.parse::<ContainerLabel>())
+            .collect::<Result<Vec<_>>>()
+            .map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))?;
+        let source = match (target.config, target.config_name) {
+            (Some(config), _) => ConfigSource::ExplicitFile(config),
+            (None, Some(name)) => ConfigSource::Named {
+                workspace: target.workspace_folder.clone(),
+                name,
+            },
+            (None, None) => ConfigSource::Workspace(target.workspace_folder.clone()),
+        };
+        let overrides = self
+            .overrides
+            .clone()
+            .with_workspace_folder(target.workspace_folder)
+            .with_id_labels(id_labels);
+
+        let request_id = request.id.clone().unwrap_or(Value::Null);
+        let sender = sender.clone();
+        let mut manager = DevcontainerManager::new(&*self.provider, source)
+            .with_overrides(overrides)
+            .with_events(move |event| {
+                let _ = sender.send(notification(&request_id, event));
+            });
+        if let Some(store) = &self.state {
+            manager = manager.with_state_store(store.clone());
+        }
+        Ok(manager)
+    }
+}
+
+fn params<T: DeserializeOwned>(params: &Value) -> std::result::Result<T, RpcError> {
+    serde_json::from_value(params.clone())
+        .map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+}
+
+fn response(id: Value, result: std::result::Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+}
+
+fn notification(request_id: &Value, event: ManagerEvent) -> Value {
+    let (method, params) = match event {
+        ManagerEvent::Progress(event) => (
+            "progress",
+            json!({ "requestId": request_id, "event": event }),
+        ),
+        ManagerEvent::Output(line) => (
+            "output",
+            json!({
+                "requestId": request_id,
+                "stream": match line.stream {
+                    OutputStream::Stdout => "stdout",
+                    OutputStream::Stderr => "stderr",
+                },
+                "line": line.line,
+            }),
+        ),
+    };
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+/// Removes the socket at `path` when nothing listens on it any more, refusing
+/// to remove any other file or a socket another daemon still serves.
+#[cfg(unix)]
+async fn remove_stale_socket(path: &std::path::Path) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => {
+            return Err(DevcontainerError::Configuration(format!(
+                "Failed to inspect {}: {err}",
+                path.display()
+            )))
+        }
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(DevcontainerError::Configuration(format!(
+            "{} exists and is not a socket; refusing to replace it",
+            path.display()
+        )));
+    }
+    if tokio::net::UnixStream::connect(path).await.is_ok() {
+        return Err(DevcontainerError::Configuration(format!(
+            "Another process is already serving {}",
+            path.display()
+        )));
+    }
+    std::fs::remove_file(path).map_err(|err| {
+        DevcontainerError::Configuration(format!(
+            "Failed to replace socket {}: {err}",
+            path.display()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::provider::mock::MockProvider;
+
+    #[tokio::test]
+    async fn answers_requests_with_progress_notifications() {
+        let workspace = tempdir().expect("tempdir");
+        fs::write(
+            workspace.path().join("devcontainer.json"),
+            json!({ "image": "example:image" }).to_string(),
+        )
+        .expect("write config");
+        let state = tempdir().expect("state dir");
+        let server = RpcServer::new(Arc::new(MockProvider::new()))
+            .with_state_store(StateStore::new(state.path()));
+
+        let target = json!({ "workspaceFolder": workspace.path() });
+        let requests = [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "up", "params": target }).to_string(),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "rebuild", "params": target }).to_string(),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "exec", "params": target }).to_string(),
+            "{not json".to_string(),
+        ];
+        let (mut output, server_output) = tokio::io::duplex(64 * 1024);
+        server
+            .serve(
+                format!("{}\n", requests.join("\n")).as_bytes(),
+                server_output,
+            )
+            .await
+            .expect("serve");
+        let mut written = String::new();
+        output
+            .read_to_string(&mut written)
+            .await
+            .expect("read output");
+        let messages: Vec<Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("json message"))
+            .collect();
+
+        let answer = |id: i64| {
+            messages
+                .iter()
+                .find(|message| message["id"] == id)
+                .unwrap_or_else(|| panic!("response to {id}"))
+        };
+        assert_eq!(answer(1)["result"]["reused"], false);
+        assert_eq!(answer(2)["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(answer(3)["error"]["code"], INVALID_PARAMS);
+        assert!(messages
+            .iter()
+            .any(|message| message["id"].is_null() && message["error"]["code"] == PARSE_ERROR));
+        assert!(messages.iter().any(|message| {
+            message["method"] == "progress"
+                && message["params"]["requestId"] == 1
+                && message["params"]["event"]["type"] == "start"
+        }));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn replaces_only_stale_sockets() {
+        let dir = tempdir().expect("tempdir");
+
+        let file = dir.path().join("file");
+        fs::write(&file, "data").expect("write file");
+        let err = remove_stale_socket(&file).await.expect_err("regular file");
+        assert!(err.to_string().contains("not a socket"));
+        assert!(file.exists());
+
+        let socket = dir.path().join("rpc.sock");
+        let live = tokio::net::UnixListener::bind(&socket).expect("bind");
+        let err = remove_stale_socket(&socket).await.expect_err("live socket");
+        assert!(err.to_string().contains("already serving"));
+        assert!(socket.exists());
+
+        drop(live);
+        remove_stale_socket(&socket).await.expect("stale socket");
+        assert!(!socket.exists());
+        remove_stale_socket(&socket)
+            .await
+            .expect("nothing to remove");
+    }
+}
@@ -0,0 +1,130 @@
+//! Access for the remote user to unix sockets forwarded with `forwardSockets`.
+//!
+//! A forwarded socket keeps its host owner and mode, which rarely match the
+//! container's remote user. Changing them would change the host socket too, so
+//! the user joins a container group with the socket's gid instead.
+
+use crate::{
+    config::ResolvedConfig,
+    provider::{Provider, RunningContainer},
+    DevcontainerError, Result,
+};
+
+/// Adds `$2` to a group with the gid of socket `$1`, creating the group if the
+/// image has none, using whichever of shadow-utils or busybox is installed.
+const JOIN_SOCKET_GROUP: &str = r#"gid=$(stat -c %g "$1") || exit 1
+group=$(getent group "$gid" | cut -d: -f1)
+if [ -z "$group" ]; then
+    group="devcontainer-socket-$gid"
+    groupadd -g "$gid" "$group" 2>/dev/null || addgroup -g "$gid" "$group"
+fi
+usermod -aG "$group" "$2" 2>/dev/null || addgroup "$2" "$group""#;
+
+/// Lets the remote user read and write every forwarded socket. Sockets that stay
+/// inaccessible, e.g. because their group cannot write them, only log a warning.
+pub async fn grant_socket_access<P: Provider + ?Sized>(
+    provider: &P,
+    config: &ResolvedConfig,
+    container: &RunningContainer,
+) -> Result<()> {
+    let Some(user) = config.remote_user.as_deref().filter(|user| *user != "root") else {
+        return Ok(());
+    };
+
+    for socket in &config.forward_sockets {
+        let target = socket.target().display().to_string();
+        if accessible(provider, container, user, &target).await? {
+            continue;
+        }
+
+        let result = provider
+            .exec_as(
+                container,
+                "root",
+                &[
+                    "sh".to_string(),
+                    "-c".to_string(),
+                    JOIN_SOCKET_GROUP.to_string(),
+                    "sh".to_string(),
+                    target.clone(),
+                    user.to_string(),
+                ],
+            )
+            .await?;
+        if result.exit_code != 0 {
+            return Err(DevcontainerError::Provider(format!(
+                "Failed to give {user} access to forwarded socket {target}: {}",
+                result.stderr.trim()
+            )));
+        }
+        if !accessible(provider, container, user, &target).await? {
+            tracing::warn!(
+                socket = %target,
+                user,
+                "Forwarded socket is not group-writable; the remote user cannot use it"
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn accessible<P: Provider + ?Sized>(
+    provider: &P,
+    container: &RunningContainer,
+    user: &str,
+    target: &str,
+) -> Result<bool> {
+    let command = ["test", "-r", target, "-a", "-w", target].map(str::to_string);
+    Ok(provider.exec_as(container, user, &command).await?.exit_code == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        config::SocketForward,
+        provider::{
+            mock::{MockCall, MockProvider},
+            ExecResult,
+        },
+    };
+
+    #[tokio::test]
+    async fn joins_the_socket_group_only_when_needed() {
+        let provider = MockProvider::new().with_exec_handler(|command| ExecResult {
+            exit_code: i32::from(command[0] == "test" && command[2] == "/run/lsp.sock"),
+            ..Default::default()
+        });
+        let config = ResolvedConfig {
+            remote_user: Some("vscode".to_string()),
+            forward_sockets: vec![
+                SocketForward {
+                    source: PathBuf::from("/tmp/agent.sock"),
+                    target: None,
+                },
+                SocketForward {
+                    source: PathBuf::from("/tmp/lsp.sock"),
+                    target: Some(PathBuf::from("/run/lsp.sock")),
+                },
+            ],
+            ..Default::default()
+        };
+
+        grant_socket_access(&provider, &config, &RunningContainer::default())
+            .await
+            .expect("socket access");
+
+        let root_commands: Vec<Vec<String>> = provider
+            .calls()
+            .into_iter()
+            .filter_map(|call| match call {
+                MockCall::ExecAs { user, command } if user == "root" => Some(command),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(root_commands.len(), 1);
+        assert_eq!(root_commands[0][4..], ["/run/lsp.sock", "vscode"]);
+    }
+}
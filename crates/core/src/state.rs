@@ -0,0 +1,475 @@
+//! Host-side record of the devcontainers `up` manages and their lifecycle
+//! progress, so later commands find them without recomputing names and hooks
+//! continuing in the background after `up` returns can be inspected.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::{ForwardPort, ResolvedConfig},
+    provider::RunningContainer,
+    DevcontainerError, Result,
+};
+
+/// Overrides the directory lifecycle state is stored in.
+pub const STATE_DIR_ENV: &str = "DEVCONTAINER_STATE_DIR";
+
+/// Logs of earlier runs kept by [`StateStore::new_run_log`].
+pub const RUN_LOGS_KEPT: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HookStatus {
+    Pending,
+    Running,
+    Completed,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HookProgress {
+    pub hook: String,
+    pub status: HookStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Lifecycle progress of one workspace's devcontainer.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LifecycleState {
+    /// Name of the devcontainer.
+    #[serde(default)]
+    pub container: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<String>,
+    /// Local workspace folder the devcontainer was brought up for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<PathBuf>,
+    /// [`ResolvedConfig::config_hash`] of the configuration last brought up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forwarded_ports: Vec<ForwardPort>,
+    /// Ports `watch-ports` saw the container listen on, which are not forwarded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub detected_ports: Vec<ForwardPort>,
+    /// Seconds since the Unix epoch when `up` last started the devcontainer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_up: Option<u64>,
+    #[serde(default)]
+    pub hooks: Vec<HookProgress>,
+    /// Non-hook phases that finished, such as `build` and `create`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub completed_phases: Vec<String>,
+    /// Image the build phase produced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Seconds the container clock was ahead of the host after start; negative when behind.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock_skew_seconds: Option<i64>,
+    /// Seconds since the Unix epoch of the last update.
+    #[serde(default)]
+    pub updated_at: u64,
+}
+
+impl LifecycleState {
+    /// Records `container` as the devcontainer `up` just started for `config`,
+    /// with the local ports its `forwardPorts` were given.
+    pub fn record_up(
+        &mut self,
+        config: &ResolvedConfig,
+        container: &RunningContainer,
+        forwarded_ports: Vec<ForwardPort>,
+    ) {
+        self.container = container.name.clone();
+        self.container_id = container.id.clone();
+        self.workspace = Some(config.workspace_folder.clone());
+        self.config_hash = Some(config.config_hash());
+        self.forwarded_ports = forwarded_ports;
+        self.detected_ports.clear();
+        self.last_up = Some(unix_seconds());
+    }
+
+    /// The recorded devcontainer, if `up` got as far as starting one.
+    pub fn running_container(&self) -> Option<RunningContainer> {
+        if self.container.is_none() && self.container_id.is_none() {
+            return None;
+        }
+        Some(RunningContainer {
+            id: self.container_id.clone(),
+            name: self.container.clone(),
+            ..Default::default()
+        })
+    }
+
+    pub fn status_of(&self, hook: &str) -> Option<HookStatus> {
+        self.hooks
+            .iter()
+            .find(|progress| progress.hook == hook)
+            .map(|progress| progress.status)
+    }
+
+    /// Whether a hook completed or was deliberately skipped, so resuming need not run it.
+    pub fn hook_done(&self, hook: &str) -> bool {
+        matches!(
+            self.status_of(hook),
+            Some(HookStatus::Completed | HookStatus::Skipped)
+        )
+    }
+
+    pub fn has_completed(&self, phase: &str) -> bool {
+        self.completed_phases.iter().any(|done| done == phase)
+    }
+
+    pub fn complete_phase(&mut self, phase: &str) {
+        if !self.has_completed(phase) {
+            self.completed_phases.push(phase.to_string());
+        }
+    }
+
+    /// Hooks still waiting to run, in recorded order.
+    pub fn pending_hooks(&self) -> Vec<&str> {
+        self.hooks
+            .iter()
+            .filter(|progress| progress.status == HookStatus::Pending)
+            .map(|progress| progress.hook.as_str())
+            .collect()
+    }
+
+    pub fn set(&mut self, hook: &str, status: HookStatus, message: Option<String>) {
+        match self.hooks.iter_mut().find(|progress| progress.hook == hook) {
+            Some(progress) => {
+                progress.status = status;
+                progress.message = message;
+            }
+            None => self.hooks.push(HookProgress {
+                hook: hook.to_string(),
+                status,
+                message,
+            }),
+        }
+    }
+}
+
+/// Directory of [`LifecycleState`] files, one per workspace configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateStore {
+    root: PathBuf,
+}
+
+impl StateStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// `$DEVCONTAINER_STATE_DIR`, else the platform's per-user state directory.
+    pub fn default_location() -> Option<Self> {
+        if let Some(dir) = env::var_os(STATE_DIR_ENV) {
+            return Some(Self::new(dir));
+        }
+
+        let base = env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+            .or_else(|| {
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+            })?;
+        Some(Self::new(base.join("devcontainers-cli")))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn load(&self, config: &ResolvedConfig) -> Result<Option<LifecycleState>> {
+        let path = self.path_for(config);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(DevcontainerError::Configuration(format!(
+                    "Failed to read lifecycle state {}: {err}",
+                    path.display()
+                )))
+            }
+        };
+
+        serde_json::from_str(&contents).map(Some).map_err(|err| {
+            DevcontainerError::Configuration(format!(
+                "Lifecycle state {} is corrupt: {err}",
+                path.display()
+            ))
+        })
+    }
+
+    pub fn save(&self, config: &ResolvedConfig, state: &LifecycleState) -> Result<()> {
+        let path = self.path_for(config);
+        fs::create_dir_all(&self.root).map_err(|err| {
+            DevcontainerError::Configuration(format!(
+                "Failed to create state directory {}: {err}",
+                self.root.display()
+            ))
+        })?;
+
+        let mut state = state.clone();
+        state.updated_at = unix_seconds();
+        let contents = serde_json::to_vec_pretty(&state)
+            .map_err(|err| DevcontainerError::Other(err.into()))?;
+
+        // Write then rename so a concurrent reader never sees a partial file.
+        let staging = path.with_extension("json.tmp");
+        fs::write(&staging, contents)
+            .and_then(|()| fs::rename(&staging, &path))
+            .map_err(|err| {
+                DevcontainerError::Configuration(format!(
+                    "Failed to write lifecycle state {}: {err}",
+                    path.display()
+                ))
+            })
+    }
+
+    /// Loads, modifies and saves the state of `config`'s devcontainer.
+    pub fn update(
+        &self,
+        config: &ResolvedConfig,
+        change: impl FnOnce(&mut LifecycleState),
+    ) -> Result<()> {
+        let mut state = self.load(config)?.unwrap_or_default();
+        change(&mut state);
+        self.save(config, &state)
+    }
+
+    /// Every recorded devcontainer, most recently brought up first. Unreadable
+    /// files are skipped with a warning rather than hiding the rest.
+    pub fn list(&self) -> Result<Vec<LifecycleState>> {
+        let entries = match fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(DevcontainerError::Configuration(format!(
+                    "Failed to read state directory {}: {err}",
+                    self.root.display()
+                )))
+            }
+        };
+
+        let mut states = Vec::new();
+        for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let parsed = fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|contents| {
+                    serde_json::from_str::<LifecycleState>(&contents).map_err(|err| err.to_string())
+                });
+            match parsed {
+                Ok(state) => states.push(state),
+                Err(err) => {
+                    tracing::warn!(path = %path.display(), error = %err, "Skipping unreadable lifecycle state")
+                }
+            }
+        }
+        states.sort_by_key(|state| std::cmp::Reverse(state.last_up));
+        Ok(states)
+    }
+
+    /// Forgets `config`'s devcontainer, as after `down`.
+    pub fn remove(&self, config: &ResolvedConfig) -> Result<()> {
+        let path = self.path_for(config);
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(DevcontainerError::Configuration(format!(
+                "Failed to remove lifecycle state {}: {err}",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Writes the full output of one hook command, replacing the previous run's log.
+    pub fn write_log(
+        &self,
+        config: &ResolvedConfig,
+        name: &str,
+        lines: &[String],
+    ) -> Result<PathBuf> {
+        let dir = self.root.join("logs");
+        let path = dir.join(format!("{}-{name}.log", state_key(config)));
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        fs::create_dir_all(&dir)
+            .and_then(|()| fs::write(&path, contents))
+            .map_err(|err| {
+                DevcontainerError::Configuration(format!(
+                    "Failed to write hook log {}: {err}",
+                    path.display()
+                ))
+            })?;
+        Ok(path)
+    }
+
+    /// Path for the full log of the current run, removing the oldest run logs
+    /// so that at most `keep` remain once it is written.
+    pub fn new_run_log(&self, keep: usize) -> Result<PathBuf> {
+        let dir = self.root.join("runs");
+        let io_error = |err: std::io::Error| {
+            DevcontainerError::Configuration(format!(
+                "Failed to prepare run log directory {}: {err}",
+                dir.display()
+            ))
+        };
+        fs::create_dir_all(&dir).map_err(io_error)?;
+
+        let mut logs: Vec<PathBuf> = fs::read_dir(&dir)
+            .map_err(io_error)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("run-") && name.ends_with(".log"))
+            })
+            .collect();
+        // Names start with a zero-padded timestamp, so they sort oldest first.
+        logs.sort();
+        let excess = (logs.len() + 1).saturating_sub(keep.max(1));
+        for old in &logs[..excess] {
+            fs::remove_file(old).map_err(io_error)?;
+        }
+
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis())
+            .unwrap_or_default();
+        Ok(dir.join(format!("run-{millis:015}-{}.log", std::process::id())))
+    }
+
+    fn path_for(&self, config: &ResolvedConfig) -> PathBuf {
+        self.root.join(format!("{}.json", state_key(config)))
+    }
+}
+
+/// Seconds since the Unix epoch, or zero for a clock set before it.
+pub(crate) fn unix_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// State files are keyed by the identifying labels, which stay stable across
+/// configuration edits.
+fn state_key(config: &ResolvedConfig) -> String {
+    let mut hasher = Sha256::new();
+    for label in &config.id_labels {
+        hasher.update(label.to_string().as_bytes());
+        hasher.update([0]);
+    }
+    let digest = hasher.finalize();
+    digest[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ContainerLabel, LABEL_LOCAL_FOLDER};
+    use tempfile::tempdir;
+
+    #[test]
+    fn round_trips_state_per_workspace() {
+        let dir = tempdir().expect("tempdir");
+        let store = StateStore::new(dir.path().join("state"));
+        let config = ResolvedConfig {
+            id_labels: vec![ContainerLabel::new(LABEL_LOCAL_FOLDER, "/work/a")],
+            ..Default::default()
+        };
+        let other = ResolvedConfig {
+            id_labels: vec![ContainerLabel::new(LABEL_LOCAL_FOLDER, "/work/b")],
+            ..Default::default()
+        };
+
+        assert_eq!(store.load(&config).expect("load"), None);
+        store
+            .update(&config, |state| {
+                state.container = Some("devcontainer-a".to_string());
+                state.set("postCreate", HookStatus::Completed, None);
+                state.set("postStart", HookStatus::Pending, None);
+            })
+            .expect("update");
+        store
+            .update(&config, |state| {
+                state.set("postStart", HookStatus::Failed, Some("exit 1".to_string()))
+            })
+            .expect("update");
+
+        let state = store.load(&config).expect("load").expect("state saved");
+        assert_eq!(state.container.as_deref(), Some("devcontainer-a"));
+        assert_eq!(state.status_of("postStart"), Some(HookStatus::Failed));
+        assert!(state.pending_hooks().is_empty());
+        assert!(state.updated_at > 0);
+        assert_eq!(store.load(&other).expect("load"), None);
+    }
+
+    #[test]
+    fn rotates_run_logs() {
+        let dir = tempdir().expect("tempdir");
+        let store = StateStore::new(dir.path());
+        let runs = dir.path().join("runs");
+        fs::create_dir_all(&runs).expect("runs dir");
+        for name in ["run-1.log", "run-2.log", "run-3.log", "notes.txt"] {
+            fs::write(runs.join(name), "").expect("old log");
+        }
+
+        let log = store.new_run_log(3).expect("run log");
+        assert_eq!(log.parent(), Some(runs.as_path()));
+        assert!(!runs.join("run-1.log").exists());
+        assert!(runs.join("run-2.log").exists());
+        assert!(runs.join("notes.txt").exists());
+    }
+
+    #[test]
+    fn lists_and_forgets_recorded_devcontainers() {
+        let dir = tempdir().expect("tempdir");
+        let store = StateStore::new(dir.path());
+        let config = ResolvedConfig {
+            workspace_folder: PathBuf::from("/work/a"),
+            id_labels: vec![ContainerLabel::new(LABEL_LOCAL_FOLDER, "/work/a")],
+            ..Default::default()
+        };
+        let container = RunningContainer {
+            id: Some("abc123".to_string()),
+            name: Some("devcontainer-a".to_string()),
+            ..Default::default()
+        };
+        assert!(store.list().expect("list").is_empty());
+
+        store
+            .update(&config, |state| {
+                state.record_up(&config, &container, Vec::new())
+            })
+            .expect("update");
+        fs::write(dir.path().join("corrupt.json"), "{").expect("write corrupt state");
+
+        let states = store.list().expect("list");
+        assert_eq!(states.len(), 1);
+        assert_eq!(states[0].workspace, Some(PathBuf::from("/work/a")));
+        assert_eq!(states[0].config_hash, Some(config.config_hash()));
+        assert_eq!(states[0].running_container(), Some(container));
+        assert!(states[0].last_up.is_some());
+
+        store.remove(&config).expect("remove");
+        store.remove(&config).expect("removing twice is fine");
+        assert_eq!(store.load(&config).expect("load"), None);
+    }
+}
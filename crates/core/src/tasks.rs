@@ -0,0 +1,149 @@
+//! Named commands from the `tasks` extension, run in the devcontainer like lifecycle hooks.
+
+use futures_util::future::join_all;
+
+use crate::{
+    config::{CommandArgs, CommandDefinition, ResolvedConfig},
+    lifecycle::aggregate_parallel_results,
+    provider::{OutputSink, Provider, RunningContainer},
+    DevcontainerError, Result,
+};
+
+/// Runs the task `name` as the remote user with the hook environment, streaming
+/// its output to `output`. Object-form tasks run their commands in parallel.
+pub async fn run_task<P: Provider + ?Sized>(
+    provider: &P,
+    config: &ResolvedConfig,
+    container: &RunningContainer,
+    name: &str,
+    output: &OutputSink,
+) -> Result<()> {
+    let task = config.tasks.get(name).ok_or_else(|| {
+        let known: Vec<&str> = config.tasks.keys().map(String::as_str).collect();
+        DevcontainerError::Configuration(if known.is_empty() {
+            format!("Unknown task '{name}'; the configuration defines no tasks")
+        } else {
+            format!(
+                "Unknown task '{name}'; expected one of {}",
+                known.join(", ")
+            )
+        })
+    })?;
+
+    let label = format!("task '{name}'");
+    match task {
+        CommandDefinition::Single(command) => {
+            run_task_command(provider, config, container, &label, command, output).await
+        }
+        CommandDefinition::Parallel(commands) => {
+            let results = join_all(commands.iter().map(|(command_name, command)| {
+                let label = format!("{label} command '{command_name}'");
+                let output = output.prefixed(command_name);
+                async move {
+                    run_task_command(provider, config, container, &label, command, &output).await
+                }
+            }))
+            .await;
+            aggregate_parallel_results(&label, results)
+        }
+    }
+}
+
+async fn run_task_command<P: Provider + ?Sized>(
+    provider: &P,
+    config: &ResolvedConfig,
+    container: &RunningContainer,
+    label: &str,
+    command: &CommandArgs,
+    output: &OutputSink,
+) -> Result<()> {
+    let args = config
+        .env_stack()
+        .wrap_command(&config.command_args(command));
+    tracing::debug!(task = label, command = ?args, "Running task");
+    let result = provider
+        .exec_streaming(container, config.remote_user.as_deref(), &args, output)
+        .await?;
+    if result.exit_code != 0 {
+        return Err(DevcontainerError::HookFailed {
+            hook: label.to_string(),
+            exit_code: result.exit_code,
+            message: format!("{label} failed with exit code {}", result.exit_code),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::provider::{
+        mock::{MockCall, MockProvider},
+        ExecResult,
+    };
+
+    #[tokio::test]
+    async fn runs_tasks_as_remote_user_and_reports_failures() {
+        let provider = MockProvider::new().with_exec_handler(|command| ExecResult {
+            exit_code: if command.iter().any(|arg| arg == "cargo clippy") {
+                3
+            } else {
+                0
+            },
+            ..Default::default()
+        });
+        let mut config = ResolvedConfig {
+            remote_user: Some("vscode".to_string()),
+            tasks: BTreeMap::from([
+                (
+                    "test".to_string(),
+                    CommandDefinition::from_array(vec!["cargo".into(), "test".into()]),
+                ),
+                (
+                    "lint".to_string(),
+                    CommandDefinition::from_string("cargo clippy"),
+                ),
+            ]),
+            ..Default::default()
+        };
+        config
+            .remote_env
+            .insert("CI".to_string(), Some("true".to_string()));
+        let container = RunningContainer {
+            name: Some("mock-demo".to_string()),
+            ..Default::default()
+        };
+        let output = OutputSink::tracing("task");
+
+        run_task(&provider, &config, &container, "test", &output)
+            .await
+            .expect("test task");
+        assert_eq!(
+            provider.calls(),
+            vec![MockCall::ExecAs {
+                user: "vscode".to_string(),
+                command: vec![
+                    "env".into(),
+                    "CI=true".into(),
+                    "cargo".into(),
+                    "test".into()
+                ],
+            }]
+        );
+
+        let err = run_task(&provider, &config, &container, "lint", &output)
+            .await
+            .expect_err("lint fails");
+        assert!(matches!(
+            err,
+            DevcontainerError::HookFailed { exit_code: 3, .. }
+        ));
+
+        let err = run_task(&provider, &config, &container, "deploy", &output)
+            .await
+            .expect_err("unknown task");
+        assert!(err.to_string().contains("expected one of lint, test"));
+    }
+}
@@ -0,0 +1,169 @@
+//! Progress bars on an interactive terminal, drawn from [`ProgressEvent`]s so a
+//! long pull or build shows how far along it is.
+//!
+//! Each phase gets a spinner; a `stepDetail` that starts with `n/m`, such as
+//! `3/7 layers`, turns it into a bar. Log lines are printed above the bars.
+
+use std::{collections::BTreeMap, sync::Mutex, time::Duration};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+use super::{ProgressEvent, ProgressStatus};
+
+const TICK: Duration = Duration::from_millis(120);
+
+struct PhaseBar {
+    bar: ProgressBar,
+    /// Whether a `start` event opened the bar. Others, such as image pulls, are
+    /// finished by the `stop` of the phase around them.
+    started: bool,
+}
+
+pub(super) struct ProgressBars {
+    multi: MultiProgress,
+    bars: Mutex<BTreeMap<String, PhaseBar>>,
+}
+
+impl ProgressBars {
+    pub(super) fn stderr() -> Self {
+        Self {
+            multi: MultiProgress::with_draw_target(ProgressDrawTarget::stderr()),
+            bars: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Runs `write` with the bars cleared, so its output is not drawn over.
+    pub(super) fn suspend<R>(&self, write: impl FnOnce() -> R) -> R {
+        self.multi.suspend(write)
+    }
+
+    pub(super) fn handle(&self, event: &ProgressEvent) {
+        let mut bars = self.bars.lock().expect("progress bars lock");
+        match event {
+            ProgressEvent::Start { phase, text, .. } => {
+                let bar = self.add(text);
+                bars.insert(phase.clone(), PhaseBar { bar, started: true });
+            }
+            ProgressEvent::Progress {
+                phase,
+                name,
+                status,
+                step_detail,
+                ..
+            } => {
+                let entry = bars.entry(phase.clone()).or_insert_with(|| PhaseBar {
+                    bar: self.add(name),
+                    started: false,
+                });
+                match status {
+                    ProgressStatus::Running => {
+                        if let Some(detail) = step_detail {
+                            show_detail(&entry.bar, detail);
+                        }
+                    }
+                    ProgressStatus::Succeeded => finish(&entry.bar, true),
+                    ProgressStatus::Failed => finish(&entry.bar, false),
+                }
+            }
+            ProgressEvent::Stop { phase, .. } => {
+                if let Some(entry) = bars.remove(phase) {
+                    if !entry.bar.is_finished() {
+                        finish(&entry.bar, true);
+                    }
+                }
+                bars.retain(|_, entry| {
+                    if !entry.started {
+                        finish(&entry.bar, true);
+                    }
+                    entry.started
+                });
+            }
+        }
+    }
+
+    fn add(&self, name: &str) -> ProgressBar {
+        let bar = self.multi.add(ProgressBar::new_spinner());
+        bar.set_style(spinner_style());
+        bar.set_prefix(name.to_string());
+        bar.enable_steady_tick(TICK);
+        bar
+    }
+}
+
+/// Shows `detail`, moving the bar when it starts with a `n/m` count.
+fn show_detail(bar: &ProgressBar, detail: &str) {
+    let (count, rest) = detail.split_once(' ').unwrap_or((detail, ""));
+    match parse_count(count) {
+        Some((position, length)) => {
+            if bar.length().is_none() {
+                bar.set_style(counted_style());
+            }
+            bar.set_length(length);
+            bar.set_position(position);
+            bar.set_message(rest.to_string());
+        }
+        None => bar.set_message(detail.to_string()),
+    }
+}
+
+fn parse_count(count: &str) -> Option<(u64, u64)> {
+    let (position, length) = count.split_once('/')?;
+    Some((position.parse().ok()?, length.parse().ok()?))
+}
+
+fn finish(bar: &ProgressBar, succeeded: bool) {
+    let template = if succeeded {
+        "{prefix:.green} done in {elapsed}"
+    } else {
+        "{prefix:.red} failed after {elapsed}"
+    };
+    bar.set_style(ProgressStyle::with_template(template).expect("progress template"));
+    bar.finish();
+}
+
+fn spinner_style() -> ProgressStyle {
+    ProgressStyle::with_template("{spinner:.cyan} {prefix} {wide_msg:.dim} {elapsed}")
+        .expect("progress template")
+}
+
+fn counted_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{spinner:.cyan} {prefix} [{bar:30.cyan/blue}] {pos}/{len} {wide_msg:.dim}",
+    )
+    .expect("progress template")
+    .progress_chars("=> ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counted_details_drive_the_bar() {
+        let bars = ProgressBars {
+            multi: MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+            bars: Mutex::new(BTreeMap::new()),
+        };
+        bars.handle(&ProgressEvent::progress(
+            "lifecycle.build.pull",
+            "Pulling example:image",
+            ProgressStatus::Running,
+            Some("2/5 layers".to_string()),
+        ));
+        let pull = bars.bars.lock().expect("progress bars lock")["lifecycle.build.pull"]
+            .bar
+            .clone();
+        assert_eq!((pull.position(), pull.length()), (2, Some(5)));
+        assert_eq!(pull.message(), "layers");
+
+        bars.handle(&ProgressEvent::Stop {
+            phase: "lifecycle.build.image".to_string(),
+            text: "Build image".to_string(),
+            level: 3,
+            timestamp: 2,
+            start_timestamp: 1,
+        });
+        assert!(pull.is_finished());
+        assert!(bars.bars.lock().expect("progress bars lock").is_empty());
+    }
+}
@@ -0,0 +1,604 @@
+mod bars;
+pub mod otlp;
+
+use anyhow::anyhow;
+use std::{
+    error::Error as StdError,
+    fs::{self, File},
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_subscriber::{
+    field::RecordFields,
+    filter::{filter_fn, LevelFilter},
+    fmt::{
+        self,
+        format::{DefaultFields, Writer},
+        FormatFields, MakeWriter,
+    },
+    layer::Context,
+    prelude::*,
+    EnvFilter, Layer,
+};
+
+use crate::{DevcontainerError, Result};
+use bars::ProgressBars;
+use otlp::OtlpExporter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    #[default]
+    Auto,
+    Text,
+    Json,
+}
+
+/// Replaces every redacted value in log output.
+pub const REDACTED: &str = "********";
+
+/// Values, such as `--secrets-file` entries, masked in everything logged.
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Masks `values` in all log output from now on, including the event log.
+/// Empty values are ignored, as they would mask everything.
+pub fn redact<I, S>(values: I)
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut secrets = SECRETS.write().expect("secrets lock");
+    secrets.extend(
+        values
+            .into_iter()
+            .map(Into::into)
+            .filter(|value| !value.is_empty()),
+    );
+    // Longest first, so a secret containing another is masked whole.
+    secrets.sort_by_key(|value| std::cmp::Reverse(value.len()));
+    secrets.dedup();
+}
+
+/// `text` with every value passed to [`redact`] replaced by [`REDACTED`].
+pub fn redact_text(text: &str) -> String {
+    let secrets = SECRETS.read().expect("secrets lock");
+    secrets.iter().fold(text.to_string(), |text, secret| {
+        text.replace(secret, REDACTED)
+    })
+}
+
+/// Writer that masks redacted values in each event the fmt layer writes whole.
+struct RedactingWriter<W>(W);
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .write_all(redact_text(&String::from_utf8_lossy(buf)).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+struct Redacting<M>(M);
+
+/// Default field formatting for log files. Span fields are formatted once per
+/// formatter type, so sharing the console's would copy its colors into files.
+#[derive(Default)]
+struct PlainFields(DefaultFields);
+
+impl<'writer> FormatFields<'writer> for PlainFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        writer: Writer<'writer>,
+        fields: R,
+    ) -> std::fmt::Result {
+        self.0.format_fields(writer, fields)
+    }
+}
+
+/// Stderr, written with any progress bars cleared.
+struct Console(Option<Arc<ProgressBars>>);
+
+impl Write for Console {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &self.0 {
+            Some(bars) => bars.suspend(|| io::stderr().write_all(buf))?,
+            None => io::stderr().write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter(self.0.make_writer())
+    }
+}
+
+/// Target of [`ProgressEvent`]s, which the console prints as NDJSON with
+/// [`LogFormat::Json`] and draws as progress bars on a terminal otherwise.
+pub const PROGRESS_TARGET: &str = "devcontainer::progress";
+
+/// Level of `start` and `stop` events, as the reference CLI numbers `info`.
+const PROGRESS_LEVEL: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProgressStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl ProgressStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProgressStatus::Running => "running",
+            ProgressStatus::Succeeded => "succeeded",
+            ProgressStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Progress record in the reference CLI's JSON log format, so IDE frontends can
+/// render it. `phase` is the lifecycle event code the record belongs to, and
+/// timestamps are milliseconds since the Unix epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ProgressEvent {
+    Start {
+        phase: String,
+        text: String,
+        level: u8,
+        timestamp: u64,
+    },
+    Progress {
+        phase: String,
+        name: String,
+        status: ProgressStatus,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        step_detail: Option<String>,
+        timestamp: u64,
+    },
+    Stop {
+        phase: String,
+        text: String,
+        level: u8,
+        timestamp: u64,
+        start_timestamp: u64,
+    },
+}
+
+impl ProgressEvent {
+    pub fn progress(
+        phase: impl Into<String>,
+        name: impl Into<String>,
+        status: ProgressStatus,
+        step_detail: Option<String>,
+    ) -> Self {
+        ProgressEvent::Progress {
+            phase: phase.into(),
+            name: name.into(),
+            status,
+            step_detail,
+            timestamp: now_millis(),
+        }
+    }
+
+    pub fn emit(&self) {
+        let record = serde_json::to_string(self).expect("progress events serialize");
+        tracing::info!(target: PROGRESS_TARGET, record = record.as_str(), "Progress");
+    }
+}
+
+/// Receives progress events in-process, besides their log records.
+pub type ProgressHandler = dyn Fn(&ProgressEvent) + Send + Sync;
+
+/// A phase reported as `start`, `progress` updates and `stop`. Dropping it
+/// before [`succeed`](Self::succeed), e.g. on an early error return, reports
+/// it failed.
+#[must_use]
+pub struct PhaseProgress {
+    phase: String,
+    text: String,
+    started: u64,
+    finished: bool,
+    span: tracing::Span,
+    observer: Option<Arc<ProgressHandler>>,
+}
+
+impl std::fmt::Debug for PhaseProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PhaseProgress")
+            .field("phase", &self.phase)
+            .field("text", &self.text)
+            .field("finished", &self.finished)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PhaseProgress {
+    pub fn start(phase: impl Into<String>, text: impl Into<String>) -> Self {
+        Self::observed(phase, text, None)
+    }
+
+    /// Like [`start`](Self::start), also passing every event to `observer`.
+    pub fn observed(
+        phase: impl Into<String>,
+        text: impl Into<String>,
+        observer: Option<Arc<ProgressHandler>>,
+    ) -> Self {
+        let (phase, text) = (phase.into(), text.into());
+        let span = tracing::info_span!(
+            "lifecycle_phase",
+            otel.name = %phase,
+            text = %text,
+            status = tracing::field::Empty
+        );
+        let progress = Self {
+            phase,
+            text,
+            started: now_millis(),
+            finished: false,
+            span,
+            observer,
+        };
+        progress.emit(ProgressEvent::Start {
+            phase: progress.phase.clone(),
+            text: progress.text.clone(),
+            level: PROGRESS_LEVEL,
+            timestamp: progress.started,
+        });
+        progress.report(ProgressStatus::Running, None);
+        progress
+    }
+
+    pub fn update(&self, detail: impl Into<String>) {
+        self.report(ProgressStatus::Running, Some(detail.into()));
+    }
+
+    pub fn succeed(mut self) {
+        self.finish(ProgressStatus::Succeeded);
+    }
+
+    fn report(&self, status: ProgressStatus, detail: Option<String>) {
+        self.emit(ProgressEvent::progress(
+            &self.phase,
+            &self.text,
+            status,
+            detail,
+        ));
+    }
+
+    fn emit(&self, event: ProgressEvent) {
+        event.emit();
+        if let Some(observer) = &self.observer {
+            observer(&event);
+        }
+    }
+
+    fn finish(&mut self, status: ProgressStatus) {
+        if std::mem::replace(&mut self.finished, true) {
+            return;
+        }
+        self.report(status, None);
+        self.span.record("status", status.as_str());
+        self.emit(ProgressEvent::Stop {
+            phase: self.phase.clone(),
+            text: self.text.clone(),
+            level: PROGRESS_LEVEL,
+            timestamp: now_millis(),
+            start_timestamp: self.started,
+        });
+    }
+}
+
+impl Drop for PhaseProgress {
+    fn drop(&mut self) {
+        self.finish(ProgressStatus::Failed);
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| {
+            u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX)
+        })
+}
+
+/// Prints the record of each progress event as one line on stderr, or draws it
+/// on the progress bars.
+enum ProgressLayer {
+    Json,
+    Bars(Arc<ProgressBars>),
+}
+
+impl<S: Subscriber> Layer<S> for ProgressLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut record = ProgressRecord(None);
+        event.record(&mut record);
+        let Some(record) = record.0 else {
+            return;
+        };
+        match self {
+            ProgressLayer::Json => {
+                let _ = RedactingWriter(io::stderr()).write_all(format!("{record}\n").as_bytes());
+            }
+            ProgressLayer::Bars(bars) => {
+                if let Ok(event) = serde_json::from_str::<ProgressEvent>(&redact_text(&record)) {
+                    bars.handle(&event);
+                }
+            }
+        }
+    }
+}
+
+struct ProgressRecord(Option<String>);
+
+impl Visit for ProgressRecord {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "record" {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+}
+
+pub fn init(level: &str, format: LogFormat) -> Result<()> {
+    init_with_event_log(level, format, None)
+}
+
+/// Like [`init`], additionally writing every event at debug level and above to
+/// `event_log` as JSON Lines, regardless of the console verbosity.
+///
+/// The file is truncated, so it always holds the most recent run.
+pub fn init_with_event_log(level: &str, format: LogFormat, event_log: Option<&Path>) -> Result<()> {
+    let mut options = TelemetryOptions::default();
+    if let Some(path) = event_log {
+        options = options.with_event_log(path);
+    }
+    init_with_options(level, format, options)
+}
+
+/// Outputs besides the console, for [`init_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct TelemetryOptions {
+    event_log: Option<PathBuf>,
+    log_files: Vec<PathBuf>,
+    otlp: Option<OtlpExporter>,
+}
+
+impl TelemetryOptions {
+    /// Writes every event at debug level and above to `path` as JSON Lines.
+    pub fn with_event_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.event_log = Some(path.into());
+        self
+    }
+
+    /// Writes every event at trace level to `path` as plain text; repeatable.
+    pub fn with_log_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.log_files.push(path.into());
+        self
+    }
+
+    /// Records lifecycle phases and provider commands as spans for `otlp` to export.
+    pub fn with_otlp(mut self, otlp: OtlpExporter) -> Self {
+        self.otlp = Some(otlp);
+        self
+    }
+}
+
+/// Like [`init`], additionally writing to the outputs of `options` regardless
+/// of the console verbosity. Log files are truncated, so they always hold the
+/// most recent run.
+pub fn init_with_options(level: &str, format: LogFormat, options: TelemetryOptions) -> Result<()> {
+    let env_filter = EnvFilter::try_new(level).unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let progress = match format {
+        LogFormat::Json => Some(ProgressLayer::Json),
+        LogFormat::Auto | LogFormat::Text if io::stderr().is_terminal() => {
+            Some(ProgressLayer::Bars(Arc::new(ProgressBars::stderr())))
+        }
+        LogFormat::Auto | LogFormat::Text => None,
+    };
+    let bars = match &progress {
+        Some(ProgressLayer::Bars(bars)) => Some(Arc::clone(bars)),
+        _ => None,
+    };
+
+    // Logs go to stderr so stdout carries only command results, such as `up --output json`.
+    let console = fmt::layer()
+        .with_writer(Redacting(move || Console(bars.clone())))
+        .with_target(false)
+        .with_level(true);
+    let console = match format {
+        LogFormat::Json => console.json().boxed(),
+        LogFormat::Auto | LogFormat::Text => console.boxed(),
+    }
+    .with_filter(env_filter)
+    .with_filter(filter_fn(|metadata| metadata.target() != PROGRESS_TARGET));
+    let progress = progress
+        .map(|layer| layer.with_filter(filter_fn(|metadata| metadata.target() == PROGRESS_TARGET)));
+
+    let events = options
+        .event_log
+        .as_deref()
+        .map(|path| {
+            let file = create_log(path, "event log")?;
+            Ok::<_, DevcontainerError>(
+                fmt::layer()
+                    .json()
+                    .with_writer(Redacting(Mutex::new(file)))
+                    .with_filter(LevelFilter::DEBUG),
+            )
+        })
+        .transpose()?;
+    let log_files = options
+        .log_files
+        .iter()
+        .map(|path| {
+            let file = create_log(path, "log file")?;
+            Ok(fmt::layer()
+                .fmt_fields(PlainFields::default())
+                .with_ansi(false)
+                .with_writer(Redacting(Mutex::new(file)))
+                .with_filter(LevelFilter::TRACE))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let otlp = options
+        .otlp
+        .map(|exporter| exporter.layer().with_filter(LevelFilter::DEBUG));
+
+    tracing_subscriber::registry()
+        .with(console)
+        .with(progress)
+        .with(events)
+        .with(log_files)
+        .with(otlp)
+        .try_init()
+        .map_err(|err| anyhow!(Box::new(err) as Box<dyn StdError + Send + Sync>))?;
+
+    Ok(())
+}
+
+fn create_log(path: &Path, what: &str) -> Result<File> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent).map_err(|err| {
+            DevcontainerError::Configuration(format!(
+                "Failed to create {what} directory {}: {err}",
+                parent.display()
+            ))
+        })?;
+    }
+    File::create(path).map_err(|err| {
+        DevcontainerError::Configuration(format!(
+            "Failed to create {what} {}: {err}",
+            path.display()
+        ))
+    })
+}
+
+/// Renders one line of an event log as `timestamp LEVEL target: message key=value ...`.
+///
+/// Lines that are not events, such as one cut short by a crash, are returned as is.
+pub fn format_event_line(line: &str) -> String {
+    let Ok(Value::Object(event)) = serde_json::from_str::<Value>(line) else {
+        return line.to_string();
+    };
+    let text = |key: &str| event.get(key).and_then(Value::as_str).unwrap_or_default();
+
+    let mut fields = match event.get("fields") {
+        Some(Value::Object(fields)) => fields.clone(),
+        _ => Map::new(),
+    };
+    let message = match fields.remove("message") {
+        Some(Value::String(message)) => message,
+        Some(other) => other.to_string(),
+        None => String::new(),
+    };
+
+    let mut rendered = format!(
+        "{} {:>5} {}: {message}",
+        text("timestamp"),
+        text("level"),
+        text("target")
+    );
+    for (key, value) in fields {
+        match value {
+            Value::String(value) => rendered.push_str(&format!(" {key}={value}")),
+            other => rendered.push_str(&format!(" {key}={other}")),
+        }
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_registered_secrets_from_output() {
+        redact(["hunter2", "", "hunter2-long"]);
+        let mut output = Vec::new();
+        RedactingWriter(&mut output)
+            .write_all(b"token=hunter2-long password=hunter2\n")
+            .expect("write");
+        assert_eq!(
+            String::from_utf8(output).expect("utf-8"),
+            "token=******** password=********\n"
+        );
+        assert_eq!(redact_text("nothing secret"), "nothing secret");
+    }
+
+    #[test]
+    fn progress_events_use_the_reference_format() {
+        let event = ProgressEvent::progress(
+            "lifecycle.build.pull",
+            "Pulling example:image",
+            ProgressStatus::Running,
+            Some("1/2 layers".to_string()),
+        );
+        let ProgressEvent::Progress { timestamp, .. } = event else {
+            unreachable!("progress event");
+        };
+        assert_eq!(
+            serde_json::to_value(&event).expect("serialize"),
+            serde_json::json!({
+                "type": "progress",
+                "phase": "lifecycle.build.pull",
+                "name": "Pulling example:image",
+                "status": "running",
+                "stepDetail": "1/2 layers",
+                "timestamp": timestamp,
+            })
+        );
+
+        let stop = ProgressEvent::Stop {
+            phase: "lifecycle.build.image".to_string(),
+            text: "Build image".to_string(),
+            level: PROGRESS_LEVEL,
+            timestamp: 20,
+            start_timestamp: 10,
+        };
+        assert_eq!(
+            serde_json::to_value(&stop).expect("serialize")["startTimestamp"],
+            10
+        );
+    }
+
+    #[test]
+    fn formats_event_log_lines() {
+        let line = r#"{"timestamp":"2024-05-01T10:00:00.000Z","level":"DEBUG","fields":{"message":"Lifecycle hook completed","hook":"postCreate","exit_code":1},"target":"devcontainer_core::lifecycle"}"#;
+        assert_eq!(
+            format_event_line(line),
+            "2024-05-01T10:00:00.000Z DEBUG devcontainer_core::lifecycle: Lifecycle hook completed exit_code=1 hook=postCreate"
+        );
+        assert_eq!(format_event_line("{\"timest"), "{\"timest");
+    }
+}
@@ -0,0 +1,371 @@
+//! Export of spans, such as lifecycle phases and provider commands, to an
+//! OpenTelemetry collector over OTLP/HTTP with JSON encoding.
+//!
+//! Spans are collected while the CLI runs and sent in a single request by
+//! [`OtlpExporter::flush`], through `curl` like the rest of the CLI's transfers.
+
+use std::{
+    fmt, fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use super::redact_text;
+use crate::{
+    provider::{CommandRunner, CommandSpec, LocalRunner},
+    DevcontainerError, Result,
+};
+
+/// Environment variable the collector endpoint is read from, as in other
+/// OpenTelemetry SDKs.
+pub const ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Span field that replaces the span's name in the export.
+const NAME_FIELD: &str = "otel.name";
+
+const SERVICE_NAME: &str = "devcontainer";
+
+/// Limits on the export, which runs as the CLI exits, so an unreachable
+/// collector delays the exit by seconds at most.
+const CONNECT_TIMEOUT_SECS: u64 = 2;
+const EXPORT_TIMEOUT_SECS: u64 = 5;
+
+/// OTLP status codes.
+const STATUS_UNSET: u8 = 0;
+const STATUS_ERROR: u8 = 2;
+
+/// Collects closed spans of one run and exports them as a single trace.
+#[derive(Clone)]
+pub struct OtlpExporter {
+    url: String,
+    curl: PathBuf,
+    runner: Arc<dyn CommandRunner>,
+    trace_id: String,
+    span_seed: u64,
+    next_span: Arc<AtomicU64>,
+    spans: Arc<Mutex<Vec<Value>>>,
+}
+
+impl fmt::Debug for OtlpExporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OtlpExporter")
+            .field("url", &self.url)
+            .field("trace_id", &self.trace_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl OtlpExporter {
+    /// Exports to the collector at `endpoint`, such as `http://localhost:4318`;
+    /// `/v1/traces` is appended unless the endpoint already names it.
+    pub fn new(endpoint: &str) -> Self {
+        let endpoint = endpoint.trim_end_matches('/');
+        let url = if endpoint.ends_with("/v1/traces") {
+            endpoint.to_string()
+        } else {
+            format!("{endpoint}/v1/traces")
+        };
+        let seed = Sha256::digest(format!("{}-{}", now_nanos(), std::process::id()));
+        let span_seed = u64::from_be_bytes(seed[16..24].try_into().expect("eight bytes"));
+        Self {
+            url,
+            curl: PathBuf::from("curl"),
+            runner: Arc::new(LocalRunner),
+            trace_id: hex(&seed[..16]),
+            span_seed,
+            next_span: Arc::new(AtomicU64::new(1)),
+            spans: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn with_curl(mut self, curl: impl Into<PathBuf>) -> Self {
+        self.curl = curl.into();
+        self
+    }
+
+    pub fn with_runner(mut self, runner: impl CommandRunner + 'static) -> Self {
+        self.runner = Arc::new(runner);
+        self
+    }
+
+    pub fn trace_id(&self) -> &str {
+        &self.trace_id
+    }
+
+    /// Layer recording the timing and fields of every span it is given.
+    pub fn layer(&self) -> OtlpLayer {
+        OtlpLayer {
+            exporter: self.clone(),
+        }
+    }
+
+    /// Sends the spans closed since the last flush. Spans still open, such as
+    /// one around the whole command, are not part of the export.
+    pub async fn flush(&self) -> Result<()> {
+        let spans = std::mem::take(&mut *self.spans.lock().expect("otlp spans lock"));
+        if spans.is_empty() {
+            return Ok(());
+        }
+        let payload = json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [attribute("service.name", json!({ "stringValue": SERVICE_NAME }))]
+                },
+                "scopeSpans": [{
+                    "scope": { "name": SERVICE_NAME, "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }],
+        });
+
+        // Payloads of long runs exceed what fits in a command-line argument.
+        let path = std::env::temp_dir().join(format!("devcontainer-otlp-{}.json", self.trace_id));
+        fs::write(&path, payload.to_string()).map_err(|err| {
+            DevcontainerError::Other(anyhow::anyhow!("{}: {err}", path.display()))
+        })?;
+        let command = CommandSpec::new(&self.curl).args([
+            "--fail".to_string(),
+            "--silent".to_string(),
+            "--show-error".to_string(),
+            "--header".to_string(),
+            "Content-Type: application/json".to_string(),
+            "--data-binary".to_string(),
+            format!("@{}", path.display()),
+            "--connect-timeout".to_string(),
+            CONNECT_TIMEOUT_SECS.to_string(),
+            "--max-time".to_string(),
+            EXPORT_TIMEOUT_SECS.to_string(),
+            self.url.clone(),
+        ]);
+        let output = self.runner.run(&command).await;
+        let _ = fs::remove_file(&path);
+        let output = output?;
+        if !output.success() {
+            return Err(DevcontainerError::Provider(format!(
+                "Failed to export spans to {}: {}",
+                self.url,
+                output.stderr.trim()
+            )));
+        }
+        Ok(())
+    }
+
+    fn span_id(&self) -> String {
+        let counter = self.next_span.fetch_add(1, Ordering::Relaxed);
+        format!("{:016x}", self.span_seed.wrapping_add(counter))
+    }
+}
+
+/// Tracing layer of an [`OtlpExporter`].
+pub struct OtlpLayer {
+    exporter: OtlpExporter,
+}
+
+/// Span data kept in the registry until the span closes.
+struct OpenSpan {
+    span_id: String,
+    parent_span_id: Option<String>,
+    start: u64,
+    fields: SpanFields,
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let parent_span_id = span.parent().and_then(|parent| {
+            parent
+                .extensions()
+                .get::<OpenSpan>()
+                .map(|open| open.span_id.clone())
+        });
+        let mut fields = SpanFields::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(OpenSpan {
+            span_id: self.exporter.span_id(),
+            parent_span_id,
+            start: now_nanos(),
+            fields,
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(open) = span.extensions_mut().get_mut::<OpenSpan>() {
+                values.record(&mut open.fields);
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(open) = span.extensions_mut().remove::<OpenSpan>() else {
+            return;
+        };
+        let name = open
+            .fields
+            .name
+            .clone()
+            .unwrap_or_else(|| span.name().to_string());
+        let mut record = json!({
+            "traceId": self.exporter.trace_id,
+            "spanId": open.span_id,
+            "name": name,
+            // SPAN_KIND_INTERNAL
+            "kind": 1,
+            "startTimeUnixNano": open.start.to_string(),
+            "endTimeUnixNano": now_nanos().to_string(),
+            "attributes": open.fields.attributes,
+            "status": { "code": if open.fields.failed { STATUS_ERROR } else { STATUS_UNSET } },
+        });
+        if let Some(parent) = open.parent_span_id {
+            record["parentSpanId"] = Value::String(parent);
+        }
+        self.exporter
+            .spans
+            .lock()
+            .expect("otlp spans lock")
+            .push(record);
+    }
+}
+
+/// Span fields as OTLP attributes. A span fails when it records an `error`, a
+/// non-zero `exit_code` or a `status` of `failed`.
+#[derive(Default)]
+struct SpanFields {
+    name: Option<String>,
+    attributes: Vec<Value>,
+    failed: bool,
+}
+
+impl SpanFields {
+    fn push(&mut self, field: &Field, value: Value) {
+        let key = field.name();
+        self.attributes.retain(|existing| existing["key"] != key);
+        self.attributes.push(attribute(key, value));
+    }
+}
+
+impl Visit for SpanFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        let value = redact_text(value);
+        match field.name() {
+            NAME_FIELD => {
+                self.name = Some(value);
+                return;
+            }
+            "error" => self.failed = true,
+            "status" if value == "failed" => self.failed = true,
+            _ => {}
+        }
+        self.push(field, json!({ "stringValue": value }));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if field.name() == "exit_code" && value != 0 {
+            self.failed = true;
+        }
+        self.push(field, json!({ "intValue": value.to_string() }));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, json!({ "intValue": value.to_string() }));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, json!({ "boolValue": value }));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    json!({ "key": key, "value": value })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn now_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| {
+            u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::provider::{CommandOutput, RecordingRunner};
+
+    #[tokio::test]
+    async fn exports_closed_spans_with_parents_and_status() {
+        let payloads = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&payloads);
+        let runner = RecordingRunner::with_handler(move |spec| {
+            let path = spec.args[6].trim_start_matches('@');
+            let payload: Value =
+                serde_json::from_str(&fs::read_to_string(path).expect("payload")).expect("json");
+            recorded.lock().expect("payloads lock").push(payload);
+            CommandOutput::default()
+        });
+        let exporter = OtlpExporter::new("http://collector:4318/").with_runner(runner.clone());
+        let subscriber = tracing_subscriber::registry().with(exporter.layer());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let phase = tracing::info_span!(
+                "lifecycle_phase",
+                otel.name = "lifecycle.build.image",
+                status = tracing::field::Empty
+            );
+            phase.in_scope(|| {
+                let _command = tracing::debug_span!("command", exit_code = 1).entered();
+            });
+            phase.record("status", "succeeded");
+        });
+        exporter.flush().await.expect("export");
+        exporter.flush().await.expect("nothing left to export");
+
+        assert_eq!(runner.calls().len(), 1);
+        assert_eq!(
+            runner.calls()[0].args.last().map(String::as_str),
+            Some("http://collector:4318/v1/traces")
+        );
+        assert!(runner.calls()[0].args.contains(&"--max-time".to_string()));
+        let payload = payloads.lock().expect("payloads lock")[0].clone();
+        let spans = &payload["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        let (command, phase) = (&spans[0], &spans[1]);
+        assert_eq!(command["name"], "command");
+        assert_eq!(command["status"]["code"], STATUS_ERROR);
+        assert_eq!(command["parentSpanId"], phase["spanId"]);
+        assert_eq!(phase["name"], "lifecycle.build.image");
+        assert_eq!(phase["traceId"], exporter.trace_id());
+        assert_eq!(phase["status"]["code"], STATUS_UNSET);
+        assert!(phase.get("parentSpanId").is_none());
+    }
+}
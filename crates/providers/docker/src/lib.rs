@@ -1,23 +1,65 @@
 use std::{
+    collections::BTreeMap,
+    fs,
+    io::IsTerminal,
     path::{Path, PathBuf},
-    process::{ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use async_trait::async_trait;
 use devcontainer_core::{
-    config::ResolvedConfig,
+    config::{
+        merge::IMAGE_METADATA_LABEL, validate_run_args, ContainerLabel, Mount, PortProtocol,
+        PullPolicy, ResolvedConfig, SocketForward, LABEL_PROJECT, PROVIDER_CUSTOMIZATION,
+    },
     provider::{
-        ExecResult, Provider, ProviderBuildContext, ProviderCleanupOptions, ProviderImage,
-        ProviderKind, ProviderPreparation, RunningContainer,
+        artifact_mirror, BuildProgress, CommandOutput, CommandRunner, CommandSpec,
+        ContainerActivity, ContainerDetails, ContainerFilter, ExecOptions, ExecResult,
+        HostCapabilities, ImageInfo, InteractiveSession, LayerStatus, LocalRunner, OutputSink,
+        Provider, ProviderBuildContext, ProviderCapabilities, ProviderCleanupOptions,
+        ProviderImage, ProviderKind, ProviderPreparation, PullProgress, RunningContainer,
+        SidecarSpec, VolumeSpec,
     },
-    DevcontainerError, Result,
+    registry::{ambient_docker_config_dir, RegistryAuth, RegistryMirror},
+    DaemonAccessError, DevcontainerError, Result,
+};
+use serde::{
+    de::{DeserializeOwned, IgnoredAny},
+    Deserialize,
 };
-use tokio::process::Command;
+use tempfile::TempDir;
 use tracing::{debug, info, warn};
 
+/// Runs the image's entrypoint and command, passed as arguments, then keeps the
+/// container alive once they exit, as the reference CLI does. Waiting on a
+/// background `sleep` lets the trap stop the container straight away.
+const KEEP_ALIVE_SCRIPT: &str = r#"echo Container started
+trap "exit 0" 15
+"$@"
+while sleep 1 & wait $!; do :; done"#;
+
+/// Attempts at pulling an image before giving up.
+const PULL_ATTEMPTS: u32 = 3;
+/// Wait before retrying a failed pull, doubled before each further attempt.
+const PULL_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Small image providing `hwclock` for [`Provider::sync_clock`].
+const CLOCK_SYNC_IMAGE: &str = "alpine:3";
+
+/// Image providing `git` for cloning a repository into a workspace volume.
+const CLONE_IMAGE: &str = "alpine/git:latest";
+
 #[derive(Debug, Clone)]
 pub struct DockerProvider {
+    kind: ProviderKind,
     docker_path: PathBuf,
+    docker_host: Option<String>,
+    registry_auth: RegistryAuth,
+    registry_mirror: Option<RegistryMirror>,
+    output: OutputSink,
+    runner: Arc<dyn CommandRunner>,
+    pull_backoff: Duration,
 }
 
 impl DockerProvider {
@@ -27,12 +69,297 @@ impl DockerProvider {
 
     pub fn from_path(path: impl Into<PathBuf>) -> Self {
         Self {
+            kind: ProviderKind::Docker,
             docker_path: path.into(),
+            docker_host: None,
+            registry_auth: RegistryAuth::default(),
+            registry_mirror: None,
+            output: OutputSink::tracing("docker"),
+            runner: Arc::new(LocalRunner),
+            pull_backoff: PULL_BACKOFF,
+        }
+    }
+
+    /// Runs `docker` through `runner`, for example on a remote host with an
+    /// [`SshRunner`](devcontainer_core::provider::SshRunner).
+    pub fn with_runner(mut self, runner: impl CommandRunner + 'static) -> Self {
+        self.runner = Arc::new(runner);
+        self
+    }
+
+    /// Reports itself as `kind`, for docker-compatible CLIs such as `podman` and
+    /// `nerdctl` driven through [`DockerProvider::from_path`].
+    pub fn with_kind(mut self, kind: ProviderKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Talks to the daemon at `host`, such as `ssh://user@box` or `tcp://box:2376`,
+    /// instead of the one `DOCKER_HOST` or `DOCKER_CONTEXT` selects.
+    pub fn with_docker_host(mut self, host: Option<String>) -> Self {
+        self.docker_host = host;
+        self
+    }
+
+    /// Receives `docker build` and `docker pull` output line by line as it is produced.
+    pub fn with_output_sink(mut self, output: OutputSink) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Uses explicit registry credentials for pulls and builds in addition to
+    /// the ambient docker login state.
+    pub fn with_registry_auth(mut self, auth: RegistryAuth) -> Self {
+        self.registry_auth = auth;
+        self
+    }
+
+    /// Pulls images and resolves digests through the pull-through cache `mirror`
+    /// first, falling back to their own registry when it fails. Digests are
+    /// otherwise resolved through the daemon's first registry mirror.
+    pub fn with_registry_mirror(mut self, mirror: Option<RegistryMirror>) -> Self {
+        self.registry_mirror = mirror;
+        self
+    }
+
+    /// Waits `backoff` before retrying a failed pull, doubling it before each
+    /// further attempt, instead of [`PULL_BACKOFF`].
+    pub fn with_pull_backoff(mut self, backoff: Duration) -> Self {
+        self.pull_backoff = backoff;
+        self
+    }
+
+    /// Pulls `reference`, through the registry mirror when one is set, and
+    /// returns the name the image is known by locally: `reference` itself, or its
+    /// name on the mirror for references pinned to a digest, which cannot be tagged.
+    async fn pull_image(&self, cli: &DockerCli, reference: &str) -> Result<String> {
+        if let Some(mirror) = &self.registry_mirror {
+            let routed = mirror.route(reference);
+            let pull = cli
+                .run_streaming(
+                    vec!["pull".to_string(), routed.clone()],
+                    &pull_progress_sink(&routed, &self.output),
+                )
+                .await?;
+            if pull.success() {
+                if reference.contains('@') {
+                    return Ok(routed);
+                }
+                cli.run_expect_success(vec!["tag".to_string(), routed, reference.to_string()])
+                    .await?;
+                return Ok(reference.to_string());
+            }
+            warn!(
+                image = %reference,
+                mirror = %mirror,
+                error = %pull.stderr.trim(),
+                "Pulling through the registry mirror failed; pulling from the registry"
+            );
+        }
+        self.pull_from_registry(cli, reference).await?;
+        Ok(reference.to_string())
+    }
+
+    /// Pulls `reference`, retrying with backoff since registries fail transiently.
+    /// An unreachable daemon and failures the registry reports as permanent, such
+    /// as a missing manifest or denied access, are not retried.
+    async fn pull_from_registry(&self, cli: &DockerCli, reference: &str) -> Result<()> {
+        let output = pull_progress_sink(reference, &self.output);
+        let mut backoff = self.pull_backoff;
+        let mut attempt = 1;
+        loop {
+            let pull = cli
+                .run_streaming(vec!["pull".to_string(), reference.to_string()], &output)
+                .await?;
+            if pull.success()
+                || attempt == PULL_ATTEMPTS
+                || DaemonAccessError::from_stderr(&pull.stderr).is_some()
+                || permanent_pull_failure(&pull.stderr)
+            {
+                return pull.ensure_success().map(drop);
+            }
+            warn!(
+                image = %reference,
+                attempt,
+                error = %pull.stderr.trim(),
+                "Pulling image failed; retrying in {}s",
+                backoff.as_secs_f32()
+            );
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            attempt += 1;
         }
     }
 
     fn cli(&self) -> Result<DockerCli> {
-        DockerCli::new(&self.docker_path)
+        let cli = DockerCli::new(Arc::clone(&self.runner), &self.docker_path)?;
+        Ok(match &self.docker_host {
+            Some(host) => cli.with_env("DOCKER_HOST", host.clone()),
+            None => cli,
+        })
+    }
+
+    /// Whether the daemon runs on another machine and so cannot bind-mount local
+    /// paths. Without `--docker-host`, the current context's endpoint decides,
+    /// which reflects both `DOCKER_HOST` and `DOCKER_CONTEXT`.
+    async fn daemon_is_remote(&self, cli: &DockerCli) -> Result<bool> {
+        if let Some(host) = &self.docker_host {
+            return Ok(is_remote_endpoint(host));
+        }
+        let output = cli
+            .run(vec![
+                "context".to_string(),
+                "inspect".to_string(),
+                "--format".to_string(),
+                "{{.Endpoints.docker.Host}}".to_string(),
+            ])
+            .await?;
+        if !output.success() {
+            debug!(stderr = %output.stderr.trim(), "Cannot inspect docker context; assuming a local daemon");
+            return Ok(false);
+        }
+        Ok(is_remote_endpoint(output.stdout.trim()))
+    }
+
+    /// Returns a CLI that authenticates with explicit registry credentials, along
+    /// with the temporary docker config directory that must outlive its use.
+    fn authenticated_cli(&self) -> Result<(DockerCli, Option<TempDir>)> {
+        let cli = self.cli()?;
+        if self.registry_auth.is_empty() {
+            return Ok((cli, None));
+        }
+
+        let config_dir = write_registry_config(&self.registry_auth, ambient_docker_config_dir())?;
+        let cli = cli.with_env("DOCKER_CONFIG", path_to_string(config_dir.path())?);
+        Ok((cli, Some(config_dir)))
+    }
+
+    /// `devcontainer.metadata` label of an image built from `build`: the entries
+    /// of the image its Dockerfile starts from, then `config`'s, so the image
+    /// works as a prebuild. The base image is pulled when missing so its
+    /// metadata can be read before the build.
+    async fn build_metadata_label(
+        &self,
+        cli: &DockerCli,
+        config: &ResolvedConfig,
+        build: &ProviderBuildContext,
+    ) -> Result<String> {
+        let base = fs::read_to_string(&build.dockerfile)
+            .ok()
+            .and_then(|contents| {
+                devcontainer_core::dockerfile::target_base_image(
+                    &contents,
+                    &build.args,
+                    build.target.as_deref(),
+                )
+            });
+        let mut metadata = match base {
+            Some(base) => self.base_image_metadata(cli, config, &base).await?,
+            None => Vec::new(),
+        };
+        metadata.push(config.image_metadata_entry());
+        serde_json::to_string(&metadata).map_err(|err| DevcontainerError::Other(err.into()))
+    }
+
+    /// Metadata entries of the `base` image a build starts from, pulling it as
+    /// the pull policy allows; empty when it cannot be had.
+    async fn base_image_metadata(
+        &self,
+        cli: &DockerCli,
+        config: &ResolvedConfig,
+        base: &str,
+    ) -> Result<Vec<serde_json::Value>> {
+        if config.pull_policy != PullPolicy::Always {
+            if let Some(image) = self.image_exists(base).await? {
+                return image.metadata();
+            }
+        }
+        let mut local = base.to_string();
+        if config.pull_policy != PullPolicy::Never {
+            match self.pull_image(cli, base).await {
+                Ok(pulled) => local = pulled,
+                Err(err) => {
+                    warn!(image = %base, error = %err, "Cannot read the base image's devcontainer metadata");
+                    return Ok(Vec::new());
+                }
+            }
+        }
+        match self.image_exists(&local).await? {
+            Some(image) => image.metadata(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Records `config` in the `devcontainer.metadata` label of the freshly committed
+    /// `tag`, after the entries inherited from its image, so it works as a prebuild.
+    ///
+    /// Labels cannot be edited in place; a `FROM tag` build adds the label without layers.
+    async fn label_image_metadata(
+        &self,
+        cli: &DockerCli,
+        config: &ResolvedConfig,
+        tag: &str,
+    ) -> Result<()> {
+        let mut metadata = match self.image_exists(tag).await? {
+            Some(details) => details.metadata()?,
+            None => Vec::new(),
+        };
+        metadata.push(config.image_metadata_entry());
+        let label =
+            serde_json::to_string(&metadata).map_err(|err| DevcontainerError::Other(err.into()))?;
+
+        let context = TempDir::new().map_err(|err| DevcontainerError::Other(err.into()))?;
+        let dockerfile = context.path().join("Dockerfile");
+        fs::write(&dockerfile, format!("FROM {tag}\n")).map_err(|err| {
+            DevcontainerError::Provider(format!("Failed to write {}: {err}", dockerfile.display()))
+        })?;
+
+        debug!(image = %tag, "Labelling image with devcontainer metadata");
+        cli.run_expect_success(vec![
+            "build".to_string(),
+            "--quiet".to_string(),
+            "--label".to_string(),
+            format!("{IMAGE_METADATA_LABEL}={label}"),
+            "-f".to_string(),
+            path_to_string(&dockerfile)?,
+            "-t".to_string(),
+            tag.to_string(),
+            path_to_string(context.path())?,
+        ])
+        .await?;
+        Ok(())
+    }
+
+    async fn run_exec(
+        &self,
+        container: &RunningContainer,
+        options: &ExecOptions,
+        command: &[String],
+        output: Option<&OutputSink>,
+    ) -> Result<ExecResult> {
+        if command.is_empty() {
+            return Ok(ExecResult::default());
+        }
+
+        let cli = self.cli()?;
+        let identifier = container
+            .name
+            .as_ref()
+            .or(container.id.as_ref())
+            .ok_or_else(|| DevcontainerError::Provider("Container has no identifier".into()))?;
+
+        let args = exec_args(identifier, options, command)?;
+        let output = match output {
+            Some(output) => cli.run_streaming(args, output).await?,
+            None => cli.run(args).await?,
+        };
+        let exit_code = output.exit_code;
+
+        Ok(ExecResult {
+            exit_code,
+            stdout: output.stdout,
+            stderr: output.stderr,
+        })
     }
 }
 
@@ -45,7 +372,11 @@ impl Default for DockerProvider {
 #[async_trait]
 impl Provider for DockerProvider {
     fn kind(&self) -> ProviderKind {
-        ProviderKind::Docker
+        self.kind
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities::all()
     }
 
     async fn prepare(&self, config: &ResolvedConfig) -> Result<ProviderPreparation> {
@@ -74,138 +405,196 @@ impl Provider for DockerProvider {
                     dockerfile.display()
                 )));
             }
-
-            let build_context = dockerfile
-                .parent()
-                .map(Path::to_path_buf)
-                .unwrap_or_else(|| config.workspace_folder.clone());
+            devcontainer_core::dockerfile::preflight(dockerfile, &config.build.args)?;
+
+            let build_context = config.build.context.clone().unwrap_or_else(|| {
+                dockerfile
+                    .parent()
+                    .map(Path::to_path_buf)
+                    .unwrap_or_else(|| config.workspace_folder.clone())
+            });
             let tag = format!("devcontainer-{project_slug}:latest");
-            ProviderImage::Build(ProviderBuildContext {
+            ProviderImage::Build(Box::new(ProviderBuildContext {
                 dockerfile: dockerfile.clone(),
                 build_context,
                 tag,
-            })
+                args: config.build.args.clone(),
+                target: config.build.target.clone(),
+                cache_from: config.build.cache_from.clone(),
+                cache_to: config.build.cache_to.clone(),
+                platforms: config.build.platforms.clone(),
+                image_names: config.build.image_names.clone(),
+                push: config.build.push,
+                options: config.build.options.clone(),
+                pull: config.pull_policy == PullPolicy::Always,
+            }))
         } else {
             return Err(DevcontainerError::Configuration(
-                "devcontainer.json must define either `image` or `dockerFile`".into(),
+                "devcontainer.json must define `image`, `build.dockerfile` or `dockerFile`".into(),
             ));
         };
 
+        let sidecars: Vec<SidecarSpec> = config
+            .sidecars
+            .iter()
+            .map(|sidecar| SidecarSpec {
+                name: sidecar.name.clone(),
+                container_name: format!("{container_name}-{}", sanitize_name(&sidecar.name)),
+                image: sidecar.image.clone(),
+                env: sidecar.env.clone(),
+                ports: sidecar.ports.clone(),
+            })
+            .collect();
+
+        // Sidecars need a shared network so the devcontainer can reach them by name.
+        let networks = if sidecars.is_empty() {
+            Vec::new()
+        } else {
+            vec![container_name.clone()]
+        };
+
+        let mut volumes =
+            writable_workspace_volumes(config, &container_name, &workspace_mount_path);
+
+        // A remote daemon would bind-mount its own filesystem, so the workspace is
+        // copied into a volume mounted before any volumes nested inside it. A
+        // custom `workspaceMount` is used as written.
+        let workspace_volume = if config.workspace_mount.is_none()
+            && self.daemon_is_remote(&cli).await?
+        {
+            if config.read_only_workspace {
+                return Err(DevcontainerError::Unsupported(
+                    "Read-only workspaces need a local docker daemon".into(),
+                ));
+            }
+            let name = format!("{container_name}-workspace");
+            info!(volume = %name, "Docker daemon is remote; copying the workspace into a volume");
+            volumes.insert(
+                0,
+                VolumeSpec {
+                    name: name.clone(),
+                    mount_path: workspace_mount_path.clone(),
+                },
+            );
+            Some(name)
+        } else {
+            None
+        };
+
         Ok(ProviderPreparation {
             image,
             container_name,
             project_slug,
-            networks: Vec::new(),
-            volumes: Vec::new(),
+            networks,
+            volumes,
             workspace_mount_path,
+            workspace_volume,
+            sidecars,
         })
     }
 
+    /// Networks left behind by an earlier, partially failed run are adopted when
+    /// they carry this project's label and recreated otherwise.
     async fn ensure_networks(
         &self,
-        _config: &ResolvedConfig,
+        config: &ResolvedConfig,
         preparation: &ProviderPreparation,
-    ) -> Result<()> {
+    ) -> Result<Vec<String>> {
         let cli = self.cli()?;
+        let mut created = Vec::new();
 
         for network in &preparation.networks {
-            let inspect = cli
-                .run(vec![
-                    "network".to_string(),
-                    "inspect".to_string(),
-                    network.clone(),
-                ])
-                .await?;
-            if inspect.status.success() {
-                debug!(network = %network, "Docker network already exists");
-                continue;
+            match resource_owner(&cli, "network", network).await? {
+                Some(owner) if owner.is_project(&config.project_name) => {
+                    debug!(network = %network, "Adopting existing docker network");
+                    continue;
+                }
+                Some(owner) => {
+                    warn!(network = %network, %owner, "Recreating docker network owned elsewhere");
+                    let removed = cli
+                        .run(vec![
+                            "network".to_string(),
+                            "rm".to_string(),
+                            network.clone(),
+                        ])
+                        .await?;
+                    if !removed.success() {
+                        return Err(DevcontainerError::Provider(format!(
+                            "Docker network {network} exists but is {owner}, and could not be \
+                             recreated: {}. Remove it with `docker network rm {network}`.",
+                            removed.stderr.trim()
+                        )));
+                    }
+                }
+                None => {}
             }
 
-            let stderr_lower = inspect.stderr.to_ascii_lowercase();
-            if stderr_lower.contains("no such network") || stderr_lower.contains("not found") {
-                info!(network = %network, "Creating docker network");
-                cli.run_expect_success(vec![
-                    "network".to_string(),
-                    "create".to_string(),
-                    network.clone(),
-                ])
-                .await?;
-            } else {
-                return Err(DevcontainerError::Provider(format!(
-                    "Failed to inspect docker network {network}: {}",
-                    inspect.stderr.trim()
-                )));
-            }
+            info!(network = %network, "Creating docker network");
+            cli.run_expect_success(vec![
+                "network".to_string(),
+                "create".to_string(),
+                "--label".to_string(),
+                format!("{LABEL_PROJECT}={}", config.project_name),
+                network.clone(),
+            ])
+            .await?;
+            created.push(network.clone());
         }
 
-        Ok(())
+        Ok(created)
     }
 
+    /// Volumes are never recreated since they hold data: unlabeled ones predate
+    /// labelling and are adopted, while another project's volumes are refused.
     async fn ensure_volumes(
         &self,
-        _config: &ResolvedConfig,
+        config: &ResolvedConfig,
         preparation: &ProviderPreparation,
-    ) -> Result<()> {
+    ) -> Result<Vec<String>> {
         let cli = self.cli()?;
-
-        for volume in &preparation.volumes {
-            let inspect = cli
-                .run(vec![
-                    "volume".to_string(),
-                    "inspect".to_string(),
-                    volume.name.clone(),
-                ])
-                .await?;
-            if inspect.status.success() {
-                debug!(volume = %volume.name, "Docker volume already exists");
-                continue;
+        let mut created = Vec::new();
+
+        let names = preparation
+            .volumes
+            .iter()
+            .map(|volume| &volume.name)
+            .chain(&config.workspace_volume);
+        for name in names {
+            if ensure_volume(&cli, config, name).await? {
+                created.push(name.clone());
             }
+        }
 
-            if inspect.stderr.contains("No such volume") {
-                info!(volume = %volume.name, "Creating docker volume");
-                cli.run_expect_success(vec![
-                    "volume".to_string(),
-                    "create".to_string(),
-                    volume.name.clone(),
-                ])
-                .await?;
-            } else {
-                return Err(DevcontainerError::Provider(format!(
-                    "Failed to inspect docker volume {}: {}",
-                    volume.name,
-                    inspect.stderr.trim()
-                )));
-            }
+        if let (Some(volume), Some(url)) = (&config.workspace_volume, &config.clone_url) {
+            clone_into_volume(&cli, volume, url).await?;
         }
 
-        Ok(())
+        Ok(created)
     }
 
     async fn build_image(
         &self,
-        _config: &ResolvedConfig,
+        config: &ResolvedConfig,
         preparation: &ProviderPreparation,
     ) -> Result<String> {
-        let cli = self.cli()?;
+        let (cli, _registry_config) = self.authenticated_cli()?;
 
         match &preparation.image {
             ProviderImage::Reference(reference) => {
-                let inspect = cli
-                    .run(vec![
-                        "image".to_string(),
-                        "inspect".to_string(),
-                        reference.clone(),
-                    ])
-                    .await?;
-                if inspect.status.success() {
-                    debug!(image = %reference, "Using locally available image");
-                    return Ok(reference.clone());
+                if config.pull_policy != PullPolicy::Always {
+                    if let Some(image) = self.image_exists(reference).await? {
+                        debug!(image = %reference, id = %image.id, "Using locally available image");
+                        return Ok(reference.clone());
+                    }
+                }
+                if config.pull_policy == PullPolicy::Never {
+                    return Err(DevcontainerError::Configuration(format!(
+                        "Image {reference} is not available locally and the pull policy is never"
+                    )));
                 }
 
                 info!(image = %reference, "Pulling image via docker pull");
-                cli.run_expect_success(vec!["pull".to_string(), reference.clone()])
-                    .await?;
-                Ok(reference.clone())
+                self.pull_image(&cli, reference).await
             }
             ProviderImage::Build(build) => {
                 info!(
@@ -214,22 +603,96 @@ impl Provider for DockerProvider {
                     "Building devcontainer image"
                 );
 
-                let dockerfile = path_to_string(&build.dockerfile)?;
-                let context = path_to_string(&build.build_context)?;
+                let buildx = cli.has_buildx().await?;
+                if !buildx && !build.platforms.is_empty() {
+                    return Err(DevcontainerError::Unsupported(
+                        "Building for --platform requires docker buildx".into(),
+                    ));
+                }
+                if build.platforms.len() > 1 && !build.push {
+                    return Err(DevcontainerError::Configuration(
+                        "Multi-platform images cannot be loaded into the local image store; \
+                         pass --push to push the manifest list"
+                            .into(),
+                    ));
+                }
+                if build.push && build.image_names.is_empty() {
+                    return Err(DevcontainerError::Configuration(
+                        "Pushing an image requires at least one --image-name".into(),
+                    ));
+                }
+                if !buildx && !build.cache_to.is_empty() {
+                    warn!("docker buildx is unavailable; ignoring --cache-to with a classic build");
+                }
+                let metadata = self.build_metadata_label(&cli, config, build).await?;
+                let args = build_command_args(build, buildx, &metadata)?;
+
+                cli.run_streaming(args, &build_progress_sink(&self.output))
+                    .await?
+                    .ensure_success()?;
 
-                cli.run_expect_success(vec![
-                    "build".to_string(),
-                    "-f".to_string(),
-                    dockerfile,
-                    "-t".to_string(),
-                    build.tag.clone(),
-                    context,
-                ])
-                .await?;
+                if pushes_from_builder(build) {
+                    return pushed_manifest_reference(&cli, &build.image_names[0]).await;
+                }
+
+                for name in &build.image_names {
+                    cli.run_expect_success(vec![
+                        "tag".to_string(),
+                        build.tag.clone(),
+                        name.clone(),
+                    ])
+                    .await?;
+                }
+                if !build.push {
+                    return Ok(build.tag.clone());
+                }
+
+                let mut digest = None;
+                for name in &build.image_names {
+                    info!(image = %name, "Pushing image");
+                    let output = cli
+                        .run_streaming(vec!["push".to_string(), name.clone()], &self.output)
+                        .await?
+                        .ensure_success()?;
+                    digest = pushed_digest(&output.stdout);
+                }
+                let digest = digest.ok_or_else(|| {
+                    DevcontainerError::Provider(
+                        "docker push did not report the pushed image digest".into(),
+                    )
+                })?;
+                Ok(format!("{}@{digest}", build.image_names[0]))
+            }
+        }
+    }
+
+    async fn image_exists(&self, reference: &str) -> Result<Option<ImageInfo>> {
+        let cli = self.cli()?;
+        let inspected: Option<InspectedImage> = cli.inspect("image", reference).await?;
+        Ok(inspected.map(Into::into))
+    }
 
-                Ok(build.tag.clone())
+    /// Registries are asked through `docker buildx imagetools`, which reads any
+    /// OCI manifest, not only those of images.
+    async fn resolve_digest(&self, reference: &str) -> Result<String> {
+        let (cli, _registry_config) = self.authenticated_cli()?;
+        if !cli.has_buildx().await? {
+            return Err(DevcontainerError::Unsupported(format!(
+                "Resolving the digest of {reference} requires docker buildx"
+            )));
+        }
+        if let Some(mirror) = artifact_mirror(self, self.registry_mirror.as_ref()).await {
+            match manifest_digest(&cli, &mirror.route(reference)).await {
+                Ok(digest) => return Ok(digest),
+                Err(err) => debug!(
+                    reference,
+                    mirror = %mirror,
+                    error = %err,
+                    "Registry mirror cannot resolve the digest; asking the registry"
+                ),
             }
         }
+        manifest_digest(&cli, reference).await
     }
 
     async fn create_container(
@@ -239,6 +702,7 @@ impl Provider for DockerProvider {
         image_reference: &str,
     ) -> Result<RunningContainer> {
         let cli = self.cli()?;
+        let settings = DockerSettings::from_config(config)?;
 
         let identifier = &preparation.container_name;
         let remove = cli
@@ -249,10 +713,7 @@ impl Provider for DockerProvider {
                 identifier.clone(),
             ])
             .await?;
-        if !remove.status.success()
-            && !remove.stderr.contains("No such container")
-            && !remove.stderr.is_empty()
-        {
+        if !remove.success() && !matches!(cli.is_absent("container", identifier).await, Ok(true)) {
             warn!(
                 container = %identifier,
                 stderr = %remove.stderr.trim(),
@@ -277,13 +738,61 @@ impl Provider for DockerProvider {
         }
 
         args.push("--label".to_string());
-        args.push(format!("devcontainer.project={}", config.project_name));
+        args.push(format!("{LABEL_PROJECT}={}", config.project_name));
+
+        for label in config
+            .id_labels
+            .iter()
+            .chain(std::iter::once(&config.config_hash_label()))
+        {
+            args.push("--label".to_string());
+            args.push(label.to_string());
+        }
+
+        for (name, value) in config.env_stack().container_env() {
+            args.push("--env".to_string());
+            args.push(format!("{name}={value}"));
+        }
+
+        for port in &config.app_ports {
+            args.push("--publish".to_string());
+            args.push(port.clone());
+        }
 
         args.push("--workdir".to_string());
         args.push(workspace_dst.clone());
 
-        args.push("--mount".to_string());
-        args.push(format!("type=bind,src={workspace_src},dst={workspace_dst}"));
+        if let Some(workspace_mount) = &config.workspace_mount {
+            let mut mount = workspace_mount.to_mount_arg();
+            if config.read_only_workspace {
+                create_writable_mount_points(config)?;
+                mount.push_str(",readonly");
+            }
+            if let Some(propagation) = config.workspace_mount_propagation {
+                warn!(%propagation, "Ignoring workspaceMountPropagation for a custom workspaceMount");
+            }
+            if let Some(consistency) = config.workspace_mount_consistency {
+                warn!(%consistency, "Ignoring workspaceMountConsistency for a custom workspaceMount");
+            }
+            args.push("--mount".to_string());
+            args.push(mount);
+        } else if preparation.workspace_volume.is_none() {
+            let mut mount = format!("type=bind,src={workspace_src},dst={workspace_dst}");
+            if config.read_only_workspace {
+                create_writable_mount_points(config)?;
+                mount.push_str(",readonly");
+            }
+            if let Some(propagation) = config.workspace_mount_propagation {
+                mount.push_str(&format!(",bind-propagation={propagation}"));
+            }
+            if let Some(consistency) = config.workspace_mount_consistency {
+                mount.push_str(&format!(",consistency={consistency}"));
+            }
+            args.push("--mount".to_string());
+            args.push(mount);
+        } else if let Some(propagation) = config.workspace_mount_propagation {
+            warn!(%propagation, "Ignoring workspaceMountPropagation for a workspace copied into a volume");
+        }
 
         for volume in &preparation.volumes {
             let mount_path = path_to_string(&volume.mount_path)?;
@@ -291,16 +800,82 @@ impl Provider for DockerProvider {
             args.push(format!("type=volume,src={},dst={mount_path}", volume.name));
         }
 
-        args.push(image_reference.to_string());
-        args.push("sleep".to_string());
-        args.push("infinity".to_string());
+        let socket_mounts = config.forward_sockets.iter().map(SocketForward::to_mount);
+        for mount in config.mounts.iter().cloned().chain(socket_mounts) {
+            args.push("--mount".to_string());
+            args.push(mount.to_mount_arg());
+        }
+
+        if config.init {
+            args.push("--init".to_string());
+        }
+        if config.privileged {
+            args.push("--privileged".to_string());
+        }
+        for capability in &config.cap_add {
+            args.push("--cap-add".to_string());
+            args.push(capability.clone());
+        }
+        for option in &config.security_opt {
+            args.push("--security-opt".to_string());
+            args.push(option.clone());
+        }
+
+        args.extend(config.run_args.iter().cloned());
+        for option in &settings.storage_opts {
+            args.push("--storage-opt".to_string());
+            args.push(option.clone());
+        }
+        args.extend(settings.create_args);
+
+        if config.overrides_command() {
+            args.push(image_reference.to_string());
+            args.push("sleep".to_string());
+            args.push("infinity".to_string());
+        } else {
+            let inspected: Option<InspectedImage> = cli.inspect("image", image_reference).await?;
+            let image_config = inspected
+                .ok_or_else(|| {
+                    DevcontainerError::Provider(format!(
+                        "Image {image_reference} not found; cannot run its command"
+                    ))
+                })?
+                .config
+                .unwrap_or_default();
+            args.push("--entrypoint".to_string());
+            args.push("/bin/sh".to_string());
+            args.push(image_reference.to_string());
+            args.push("-c".to_string());
+            args.push(KEEP_ALIVE_SCRIPT.to_string());
+            args.push("-".to_string());
+            args.extend(image_config.entrypoint.unwrap_or_default());
+            args.extend(image_config.cmd.unwrap_or_default());
+        }
 
         let output = cli.run_expect_success(args).await?;
         let id = output.stdout.trim().to_string();
 
+        // `docker cp` streams a tar over the API, so it reaches remote daemons too.
+        if preparation.workspace_volume.is_some() {
+            info!(workspace = %workspace_src, "Copying workspace into the container");
+            cli.run_expect_success(vec![
+                "cp".to_string(),
+                format!("{workspace_src}/."),
+                format!("{identifier}:{workspace_dst}"),
+            ])
+            .await?;
+        }
+
+        let mut sidecars = Vec::with_capacity(preparation.sidecars.len());
+        for sidecar in &preparation.sidecars {
+            create_sidecar(&cli, config, preparation, sidecar).await?;
+            sidecars.push(sidecar.container_name.clone());
+        }
+
         Ok(RunningContainer {
             id: if id.is_empty() { None } else { Some(id) },
             name: Some(identifier.clone()),
+            sidecars,
         })
     }
 
@@ -312,16 +887,34 @@ impl Provider for DockerProvider {
             .or(container.id.as_ref())
             .ok_or_else(|| DevcontainerError::Provider("Container has no identifier".into()))?;
 
+        for sidecar in &container.sidecars {
+            info!(sidecar = %sidecar, "Starting sidecar container");
+            cli.run_expect_success(vec!["start".to_string(), sidecar.clone()])
+                .await?;
+        }
+
         cli.run_expect_success(vec!["start".to_string(), identifier.clone()])
             .await?;
+
+        if !self.container_activity(container).await?.running {
+            return Err(DevcontainerError::Provider(format!(
+                "Container {identifier} exited right after starting \
+                 (see `docker logs {identifier}`)"
+            )));
+        }
         Ok(())
     }
 
-    async fn exec(&self, container: &RunningContainer, command: &[String]) -> Result<ExecResult> {
-        if command.is_empty() {
-            return Ok(ExecResult::default());
-        }
+    async fn find_containers(&self, labels: &[ContainerLabel]) -> Result<Vec<RunningContainer>> {
+        let cli = self.cli()?;
+        let output = cli.run_expect_success(find_containers_args(labels)).await?;
+        Ok(parse_container_listing(&output.stdout))
+    }
 
+    async fn inspect_container(
+        &self,
+        container: &RunningContainer,
+    ) -> Result<Option<ContainerDetails>> {
         let cli = self.cli()?;
         let identifier = container
             .name
@@ -329,37 +922,295 @@ impl Provider for DockerProvider {
             .or(container.id.as_ref())
             .ok_or_else(|| DevcontainerError::Provider("Container has no identifier".into()))?;
 
-        let mut args = Vec::with_capacity(2 + command.len());
-        args.push("exec".to_string());
-        args.push(identifier.clone());
-        args.extend(command.iter().cloned());
+        let inspected: Option<InspectedContainer> = cli.inspect("container", identifier).await?;
+        Ok(inspected.map(Into::into))
+    }
 
-        let output = cli.run(args).await?;
-        let exit_code = output.status.code().unwrap_or(-1);
+    async fn list_containers(&self, filter: &ContainerFilter) -> Result<Vec<ContainerDetails>> {
+        let cli = self.cli()?;
+        let output = cli.run_expect_success(list_containers_args(filter)).await?;
+        let ids: Vec<String> = output
+            .stdout
+            .lines()
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+            .collect();
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
 
-        Ok(ExecResult {
-            exit_code,
-            stdout: output.stdout,
-            stderr: output.stderr,
-        })
+        let mut args = vec!["container".to_string(), "inspect".to_string()];
+        args.extend(ids);
+        // Containers removed since the listing are left out of the output.
+        let output = cli.run(args).await?;
+        let inspected: Vec<InspectedContainer> = parse_inspect_all(&output, "container", "list")?;
+        let mut containers: Vec<ContainerDetails> = inspected.into_iter().map(Into::into).collect();
+        containers.sort_by_key(|container| !container.running());
+        Ok(containers)
     }
 
-    async fn stop_container(
+    async fn remove_container(
         &self,
-        _config: &ResolvedConfig,
-        preparation: &ProviderPreparation,
         container: &RunningContainer,
+        remove_volumes: bool,
     ) -> Result<()> {
         let cli = self.cli()?;
-
         let identifier = container
             .name
             .as_ref()
             .or(container.id.as_ref())
-            .cloned()
-            .unwrap_or_else(|| preparation.container_name.clone());
+            .ok_or_else(|| DevcontainerError::Provider("Container has no identifier".into()))?;
 
-        let output = cli
+        let mut args = vec![
+            "container".to_string(),
+            "rm".to_string(),
+            "--force".to_string(),
+        ];
+        if remove_volumes {
+            args.push("--volumes".to_string());
+        }
+        args.push(identifier.clone());
+
+        let output = cli.run(args).await?;
+        if output.success() || cli.is_absent("container", identifier).await? {
+            info!(container = %identifier, "Removed container");
+            Ok(())
+        } else {
+            Err(DevcontainerError::Provider(format!(
+                "Failed to remove container {identifier}: {}",
+                output.stderr.trim()
+            )))
+        }
+    }
+
+    async fn exec(&self, container: &RunningContainer, command: &[String]) -> Result<ExecResult> {
+        self.run_exec(container, &ExecOptions::default(), command, None)
+            .await
+    }
+
+    async fn exec_as(
+        &self,
+        container: &RunningContainer,
+        user: &str,
+        command: &[String],
+    ) -> Result<ExecResult> {
+        self.run_exec(container, &ExecOptions::as_user(Some(user)), command, None)
+            .await
+    }
+
+    async fn exec_streaming(
+        &self,
+        container: &RunningContainer,
+        user: Option<&str>,
+        command: &[String],
+        output: &OutputSink,
+    ) -> Result<ExecResult> {
+        self.run_exec(
+            container,
+            &ExecOptions::as_user(user),
+            command,
+            Some(output),
+        )
+        .await
+    }
+
+    async fn exec_with_options(
+        &self,
+        container: &RunningContainer,
+        options: &ExecOptions,
+        command: &[String],
+        output: &OutputSink,
+    ) -> Result<ExecResult> {
+        self.run_exec(container, options, command, Some(output))
+            .await
+    }
+
+    async fn attach(
+        &self,
+        container: &RunningContainer,
+        session: &InteractiveSession,
+    ) -> Result<i32> {
+        let cli = self.cli()?;
+        let identifier = container
+            .name
+            .as_ref()
+            .or(container.id.as_ref())
+            .ok_or_else(|| DevcontainerError::Provider("Container has no identifier".into()))?;
+
+        let tty = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+        let args = attach_args(identifier, session, tty)?;
+        cli.run_interactive(args).await
+    }
+
+    async fn registry_mirrors(&self) -> Result<Vec<RegistryMirror>> {
+        let cli = self.cli()?;
+        let output = cli
+            .run_expect_success(vec![
+                "info".to_string(),
+                "--format".to_string(),
+                "{{json .RegistryConfig.Mirrors}}".to_string(),
+            ])
+            .await?;
+        parse_registry_mirrors(&output.stdout)
+    }
+
+    /// Asks the daemon for its version, which fails while it is down or restarting.
+    async fn daemon_ready(&self) -> Result<()> {
+        self.cli()?.verify_binary().await
+    }
+
+    /// Free storage is only measured for a local daemon, with `df` on its data root.
+    async fn host_capabilities(&self) -> Result<HostCapabilities> {
+        let cli = self.cli()?;
+        let info = docker_info(&cli).await?;
+
+        let storage_bytes = match &info.docker_root_dir {
+            Some(root) if !self.daemon_is_remote(&cli).await? => {
+                let df = CommandSpec::new("df").args(["-Pk", root.as_str()]);
+                match self.runner.run(&df).await {
+                    Ok(output) if output.success() => parse_df_available(&output.stdout),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        Ok(HostCapabilities {
+            cpus: info.ncpu,
+            memory_bytes: info.mem_total,
+            storage_bytes,
+            gpu: Some(info.runtimes.contains_key("nvidia")),
+        })
+    }
+
+    /// Containers share the engine's kernel clock, which on Docker Desktop is the
+    /// VM's; a privileged container reloads it from the hardware clock. Only
+    /// done for a local engine in a VM: on a native engine the kernel clock is
+    /// this host's, and a remote engine's skew was measured against this host.
+    async fn sync_clock(&self) -> Result<()> {
+        let cli = self.cli()?;
+        if self.daemon_is_remote(&cli).await? {
+            return Err(DevcontainerError::Unsupported(
+                "The docker daemon is remote, so its clock cannot be fixed from here; \
+                 synchronize the remote host's clock, for example with NTP"
+                    .into(),
+            ));
+        }
+        if !docker_info(&cli).await?.runs_in_vm() {
+            return Err(DevcontainerError::Unsupported(
+                "The docker engine runs directly on this host and shares its clock; \
+                 synchronize the host clock instead, for example with `timedatectl set-ntp true`"
+                    .into(),
+            ));
+        }
+        cli.run_expect_success(vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            "--privileged".to_string(),
+            CLOCK_SYNC_IMAGE.to_string(),
+            "hwclock".to_string(),
+            "-s".to_string(),
+        ])
+        .await?;
+        Ok(())
+    }
+
+    async fn container_activity(&self, container: &RunningContainer) -> Result<ContainerActivity> {
+        let cli = self.cli()?;
+        let identifier = container
+            .name
+            .as_ref()
+            .or(container.id.as_ref())
+            .ok_or_else(|| DevcontainerError::Provider("Container has no identifier".into()))?;
+
+        let inspected: Option<InspectedContainer> = cli.inspect("container", identifier).await?;
+        Ok(inspected.map(Into::into).unwrap_or_default())
+    }
+
+    async fn container_labels(
+        &self,
+        container: &RunningContainer,
+    ) -> Result<BTreeMap<String, String>> {
+        let cli = self.cli()?;
+        let identifier = container
+            .name
+            .as_ref()
+            .or(container.id.as_ref())
+            .ok_or_else(|| DevcontainerError::Provider("Container has no identifier".into()))?;
+
+        let inspected: InspectedContainer =
+            cli.inspect("container", identifier).await?.ok_or_else(|| {
+                DevcontainerError::Provider(format!("No such container: {identifier}"))
+            })?;
+        Ok(inspected.config.labels.unwrap_or_default())
+    }
+
+    async fn copy_into(
+        &self,
+        container: &RunningContainer,
+        source: &Path,
+        destination: &Path,
+    ) -> Result<()> {
+        let cli = self.cli()?;
+        let identifier = container
+            .name
+            .as_ref()
+            .or(container.id.as_ref())
+            .ok_or_else(|| DevcontainerError::Provider("Container has no identifier".into()))?;
+
+        let source = path_to_string(source)?;
+        let destination = path_to_string(destination)?;
+
+        cli.run_expect_success(vec![
+            "cp".to_string(),
+            source,
+            format!("{identifier}:{destination}"),
+        ])
+        .await?;
+        Ok(())
+    }
+
+    /// Commits, then labels the committed image the way builds are, on top of
+    /// the metadata the container inherited from its image.
+    async fn commit_container(
+        &self,
+        config: &ResolvedConfig,
+        container: &RunningContainer,
+        tag: &str,
+    ) -> Result<()> {
+        let cli = self.cli()?;
+        let identifier = container
+            .name
+            .as_ref()
+            .or(container.id.as_ref())
+            .ok_or_else(|| DevcontainerError::Provider("Container has no identifier".into()))?;
+
+        info!(container = %identifier, image = %tag, "Committing container");
+        cli.run_expect_success(vec![
+            "commit".to_string(),
+            identifier.clone(),
+            tag.to_string(),
+        ])
+        .await?;
+        self.label_image_metadata(&cli, config, tag).await
+    }
+
+    async fn stop_container(
+        &self,
+        _config: &ResolvedConfig,
+        preparation: &ProviderPreparation,
+        container: &RunningContainer,
+    ) -> Result<()> {
+        let cli = self.cli()?;
+
+        let identifier = container
+            .name
+            .as_ref()
+            .or(container.id.as_ref())
+            .cloned()
+            .unwrap_or_else(|| preparation.container_name.clone());
+
+        let output = cli
             .run(vec![
                 "container".to_string(),
                 "stop".to_string(),
@@ -367,20 +1218,37 @@ impl Provider for DockerProvider {
             ])
             .await?;
 
-        if output.status.success() {
+        if output.success() {
             info!(container = %identifier, "Stopped container");
-            return Ok(());
+        } else if !cli.is_running(&identifier).await? {
+            debug!(container = %identifier, stderr = %output.stderr.trim(), "Container already stopped or missing");
+        } else {
+            return Err(DevcontainerError::Provider(format!(
+                "Failed to stop container {identifier}: {}",
+                output.stderr.trim()
+            )));
         }
 
-        if output.stderr.contains("No such container") || output.stderr.contains("is not running") {
-            debug!(container = %identifier, stderr = %output.stderr.trim(), "Container already stopped or missing");
-            return Ok(());
+        for sidecar in &preparation.sidecars {
+            let output = cli
+                .run(vec![
+                    "container".to_string(),
+                    "stop".to_string(),
+                    sidecar.container_name.clone(),
+                ])
+                .await?;
+            if output.success() {
+                info!(sidecar = %sidecar.container_name, "Stopped sidecar container");
+            } else {
+                debug!(
+                    sidecar = %sidecar.container_name,
+                    stderr = %output.stderr.trim(),
+                    "Sidecar container already stopped or missing"
+                );
+            }
         }
 
-        Err(DevcontainerError::Provider(format!(
-            "Failed to stop container {identifier}: {}",
-            output.stderr.trim()
-        )))
+        Ok(())
     }
 
     async fn cleanup(
@@ -403,8 +1271,10 @@ impl Provider for DockerProvider {
         args.push(preparation.container_name.clone());
 
         let remove_container = cli.run(args).await?;
-        if !remove_container.status.success()
-            && !remove_container.stderr.contains("No such container")
+        if !remove_container.success()
+            && !cli
+                .is_absent("container", &preparation.container_name)
+                .await?
         {
             return Err(DevcontainerError::Provider(format!(
                 "Failed to remove container {}: {}",
@@ -413,6 +1283,27 @@ impl Provider for DockerProvider {
             )));
         }
 
+        for sidecar in &preparation.sidecars {
+            let mut args = vec![
+                "container".to_string(),
+                "rm".to_string(),
+                "--force".to_string(),
+            ];
+            if options.remove_volumes {
+                args.push("--volumes".to_string());
+            }
+            args.push(sidecar.container_name.clone());
+
+            let output = cli.run(args).await?;
+            if !output.success() && !cli.is_absent("container", &sidecar.container_name).await? {
+                return Err(DevcontainerError::Provider(format!(
+                    "Failed to remove sidecar container {}: {}",
+                    sidecar.container_name,
+                    output.stderr.trim()
+                )));
+            }
+        }
+
         for network in &preparation.networks {
             let output = cli
                 .run(vec![
@@ -421,13 +1312,12 @@ impl Provider for DockerProvider {
                     network.clone(),
                 ])
                 .await?;
-            if output.status.success() {
+            if output.success() {
                 info!(network = %network, "Removed docker network");
                 continue;
             }
 
-            let stderr_lower = output.stderr.to_ascii_lowercase();
-            if stderr_lower.contains("no such network") || stderr_lower.contains("not found") {
+            if cli.is_absent("network", network).await? {
                 debug!(network = %network, "Docker network already absent");
                 continue;
             }
@@ -447,9 +1337,9 @@ impl Provider for DockerProvider {
                         volume.name.clone(),
                     ])
                     .await?;
-                if output.status.success() {
+                if output.success() {
                     info!(volume = %volume.name, "Removed docker volume");
-                } else if output.stderr.contains("No such volume") {
+                } else if cli.is_absent("volume", &volume.name).await? {
                     debug!(volume = %volume.name, "Docker volume already absent");
                 } else {
                     return Err(DevcontainerError::Provider(format!(
@@ -469,25 +1359,282 @@ impl Provider for DockerProvider {
     }
 }
 
+fn exec_args(identifier: &str, options: &ExecOptions, command: &[String]) -> Result<Vec<String>> {
+    let mut args = vec!["exec".to_string()];
+    if let Some(user) = &options.user {
+        args.push("--user".to_string());
+        args.push(user.clone());
+    }
+    if let Some(working_dir) = &options.working_dir {
+        args.push("--workdir".to_string());
+        args.push(path_to_string(working_dir)?);
+    }
+    args.push(identifier.to_string());
+    args.extend(command.iter().cloned());
+    Ok(args)
+}
+
+fn attach_args(identifier: &str, session: &InteractiveSession, tty: bool) -> Result<Vec<String>> {
+    let mut args = vec![
+        "exec".to_string(),
+        if tty { "-it" } else { "-i" }.to_string(),
+    ];
+    if let Some(user) = &session.user {
+        args.push("--user".to_string());
+        args.push(user.clone());
+    }
+    if let Some(working_dir) = &session.working_dir {
+        args.push("--workdir".to_string());
+        args.push(path_to_string(working_dir)?);
+    }
+    args.push(identifier.to_string());
+    args.extend(session.command.iter().cloned());
+    Ok(args)
+}
+
+fn find_containers_args(labels: &[ContainerLabel]) -> Vec<String> {
+    let mut args = vec!["ps".to_string(), "--all".to_string()];
+    for label in labels {
+        args.push("--filter".to_string());
+        args.push(format!("label={label}"));
+    }
+    args.push("--format".to_string());
+    args.push("{{.ID}}\t{{.Names}}".to_string());
+    args
+}
+
+fn list_containers_args(filter: &ContainerFilter) -> Vec<String> {
+    let mut args = vec!["ps".to_string()];
+    if !filter.running_only {
+        args.push("--all".to_string());
+    }
+    for label in &filter.labels {
+        args.push("--filter".to_string());
+        args.push(format!("label={label}"));
+    }
+    args.push("--format".to_string());
+    args.push("{{.ID}}".to_string());
+    args
+}
+
+fn parse_container_listing(stdout: &str) -> Vec<RunningContainer> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() {
+                return None;
+            }
+            let (id, name) = line.split_once('\t').unwrap_or((line, ""));
+            Some(RunningContainer {
+                id: Some(id.to_string()),
+                name: (!name.is_empty()).then(|| name.to_string()),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+async fn create_sidecar(
+    cli: &DockerCli,
+    config: &ResolvedConfig,
+    preparation: &ProviderPreparation,
+    sidecar: &SidecarSpec,
+) -> Result<()> {
+    let remove = cli
+        .run(vec![
+            "container".to_string(),
+            "rm".to_string(),
+            "--force".to_string(),
+            sidecar.container_name.clone(),
+        ])
+        .await?;
+    if !remove.success()
+        && !matches!(
+            cli.is_absent("container", &sidecar.container_name).await,
+            Ok(true)
+        )
+    {
+        warn!(
+            sidecar = %sidecar.container_name,
+            stderr = %remove.stderr.trim(),
+            "Failed to remove existing sidecar before create"
+        );
+    }
+
+    info!(sidecar = %sidecar.name, image = %sidecar.image, "Creating sidecar container");
+    cli.run_expect_success(sidecar_create_args(config, preparation, sidecar))
+        .await?;
+    Ok(())
+}
+
+/// Creates volume `name` for the project unless it exists, reporting whether it
+/// did. Fails when another project owns the volume.
+async fn ensure_volume(cli: &DockerCli, config: &ResolvedConfig, name: &str) -> Result<bool> {
+    match resource_owner(cli, "volume", name).await? {
+        Some(owner @ ResourceOwner::Project(_)) if !owner.is_project(&config.project_name) => {
+            Err(DevcontainerError::Provider(format!(
+                "Docker volume {name} is {owner}; remove it with `docker volume rm {name}` \
+                 or rename this project"
+            )))
+        }
+        Some(_) => {
+            debug!(volume = %name, "Adopting existing docker volume");
+            Ok(false)
+        }
+        None => {
+            info!(volume = %name, "Creating docker volume");
+            cli.run_expect_success(vec![
+                "volume".to_string(),
+                "create".to_string(),
+                "--label".to_string(),
+                format!("{LABEL_PROJECT}={}", config.project_name),
+                name.to_string(),
+            ])
+            .await?;
+            Ok(true)
+        }
+    }
+}
+
+/// Clones `url` into `volume` from a helper container, unless the volume already
+/// holds files, such as the checkout of an earlier `up`.
+async fn clone_into_volume(cli: &DockerCli, volume: &str, url: &str) -> Result<()> {
+    info!(%volume, %url, "Cloning the repository into the workspace volume");
+    cli.run_expect_success(vec![
+        "run".to_string(),
+        "--rm".to_string(),
+        "--mount".to_string(),
+        format!("type=volume,source={volume},target=/workspace"),
+        "--entrypoint".to_string(),
+        "sh".to_string(),
+        CLONE_IMAGE.to_string(),
+        "-c".to_string(),
+        r#"[ -z "$(ls -A /workspace)" ] || exit 0; git clone -- "$1" /workspace"#.to_string(),
+        "sh".to_string(),
+        url.to_string(),
+    ])
+    .await?;
+    Ok(())
+}
+
+fn sidecar_create_args(
+    config: &ResolvedConfig,
+    preparation: &ProviderPreparation,
+    sidecar: &SidecarSpec,
+) -> Vec<String> {
+    let mut args = vec![
+        "create".to_string(),
+        "--name".to_string(),
+        sidecar.container_name.clone(),
+        "--hostname".to_string(),
+        sidecar.name.clone(),
+    ];
+
+    if let Some(network) = preparation.networks.first() {
+        args.push("--network".to_string());
+        args.push(network.clone());
+        args.push("--network-alias".to_string());
+        args.push(sidecar.name.clone());
+    }
+
+    args.push("--label".to_string());
+    args.push(format!("{LABEL_PROJECT}={}", config.project_name));
+    args.push("--label".to_string());
+    args.push(format!("devcontainer.sidecar={}", sidecar.name));
+
+    for (key, value) in &sidecar.env {
+        args.push("--env".to_string());
+        args.push(format!("{key}={value}"));
+    }
+
+    for port in &sidecar.ports {
+        args.push("--publish".to_string());
+        args.push(format!(
+            "{}:{}/{}",
+            port.local_port,
+            port.container_port,
+            match port.protocol {
+                PortProtocol::Tcp => "tcp",
+                PortProtocol::Udp => "udp",
+            }
+        ));
+    }
+
+    args.push(sidecar.image.clone());
+    args
+}
+
+/// Writes a docker config directory layering explicit credentials over the
+/// ambient configuration. Every other ambient entry, such as `cli-plugins`,
+/// `contexts` and `buildx`, is linked in so plugins, the current context and
+/// buildx builders keep working.
+fn write_registry_config(auth: &RegistryAuth, ambient_dir: Option<PathBuf>) -> Result<TempDir> {
+    let config_dir = tempfile::Builder::new()
+        .prefix("devcontainer-docker-config-")
+        .tempdir()
+        .map_err(|err| {
+            DevcontainerError::Provider(format!(
+                "Failed to create temporary docker config directory: {err}"
+            ))
+        })?;
+
+    let base = ambient_dir
+        .as_ref()
+        .and_then(|dir| fs::read_to_string(dir.join("config.json")).ok())
+        .and_then(|raw| serde_json::from_str(&raw).ok());
+    let document = auth.apply_to_docker_config(base);
+
+    let config_path = config_dir.path().join("config.json");
+    fs::write(&config_path, document.to_string()).map_err(|err| {
+        DevcontainerError::Provider(format!("Failed to write {}: {err}", config_path.display()))
+    })?;
+
+    #[cfg(unix)]
+    for entry in ambient_dir
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        let name = entry.file_name();
+        if name == "config.json" {
+            continue;
+        }
+        if let Err(err) = std::os::unix::fs::symlink(entry.path(), config_dir.path().join(&name)) {
+            debug!(
+                entry = %name.to_string_lossy(),
+                error = %err,
+                "Failed to link docker config entry into temporary config"
+            );
+        }
+    }
+
+    Ok(config_dir)
+}
+
+/// `docker` invocations with the provider's binary and environment, executed by
+/// the provider's [`CommandRunner`].
 #[derive(Debug, Clone)]
 struct DockerCli {
+    runner: Arc<dyn CommandRunner>,
     program: PathBuf,
+    env: Vec<(String, String)>,
 }
 
 impl DockerCli {
-    fn new(path: &Path) -> Result<Self> {
-        let resolved = if path.components().count() == 1 {
-            which::which(path).map_err(|err| {
-                DevcontainerError::Provider(format!(
-                    "Failed to locate docker binary '{}': {err}",
-                    path.display()
-                ))
-            })?
-        } else {
-            PathBuf::from(path)
-        };
+    fn new(runner: Arc<dyn CommandRunner>, path: &Path) -> Result<Self> {
+        let program = runner.locate(path)?;
+        Ok(Self {
+            runner,
+            program,
+            env: Vec::new(),
+        })
+    }
 
-        Ok(Self { program: resolved })
+    fn with_env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
     }
 
     async fn verify_binary(&self) -> Result<()> {
@@ -499,13 +1646,15 @@ impl DockerCli {
             ])
             .await?;
 
-        if output.status.success() {
+        if output.success() {
             debug!(
                 docker = %self.program.display(),
                 version = %output.stdout.trim(),
                 "Docker CLI reachable"
             );
             Ok(())
+        } else if let Some(err) = DaemonAccessError::from_stderr(&output.stderr) {
+            Err(err.into())
         } else {
             Err(DevcontainerError::Provider(format!(
                 "Failed to execute '{} --version': {}",
@@ -515,64 +1664,615 @@ impl DockerCli {
         }
     }
 
+    fn spec(&self, args: Vec<String>) -> CommandSpec {
+        CommandSpec {
+            program: self.program.clone(),
+            args,
+            env: self.env.clone(),
+            stdin: None,
+        }
+    }
+
     async fn run(&self, args: Vec<String>) -> Result<CommandOutput> {
-        let mut command = Command::new(&self.program);
-        command.args(&args);
-        command.stdin(Stdio::null());
-        let output = command.output().await.map_err(|err| {
-            DevcontainerError::Provider(format!(
-                "Failed to spawn '{}': {err}",
-                format_command(&self.program, &args)
-            ))
-        })?;
+        self.runner.run(&self.spec(args)).await
+    }
 
-        Ok(CommandOutput::new(
-            format_command(&self.program, &args),
-            output.status,
-            output.stdout,
-            output.stderr,
-        ))
+    /// Runs a command attached to the current terminal, returning its exit code.
+    async fn run_interactive(&self, args: Vec<String>) -> Result<i32> {
+        self.runner.run_interactive(&self.spec(args)).await
+    }
+
+    /// Runs a command, forwarding each output line to `output` as it is produced.
+    async fn run_streaming(&self, args: Vec<String>, output: &OutputSink) -> Result<CommandOutput> {
+        self.runner.run_streaming(&self.spec(args), output).await
     }
 
     async fn run_expect_success(&self, args: Vec<String>) -> Result<CommandOutput> {
         let output = self.run(args).await?;
         output.ensure_success()
     }
-}
 
-#[derive(Debug, Clone)]
-struct CommandOutput {
-    command: String,
-    status: ExitStatus,
-    stdout: String,
-    stderr: String,
-}
+    /// Inspects a docker `kind` of object, returning `None` when it does not exist.
+    async fn inspect<T: DeserializeOwned>(&self, kind: &str, name: &str) -> Result<Option<T>> {
+        let output = self
+            .run(vec![
+                kind.to_string(),
+                "inspect".to_string(),
+                name.to_string(),
+            ])
+            .await?;
+        parse_inspect(&output, kind, name)
+    }
 
-impl CommandOutput {
-    fn new(command: String, status: ExitStatus, stdout: Vec<u8>, stderr: Vec<u8>) -> Self {
-        Self {
-            command,
-            status,
-            stdout: String::from_utf8_lossy(&stdout).into_owned(),
-            stderr: String::from_utf8_lossy(&stderr).into_owned(),
-        }
+    /// Whether a failed removal or stop left nothing behind, because the object
+    /// was already gone.
+    async fn is_absent(&self, kind: &str, name: &str) -> Result<bool> {
+        let inspected: Option<IgnoredAny> = self.inspect(kind, name).await?;
+        Ok(inspected.is_none())
     }
 
-    fn ensure_success(self) -> Result<Self> {
-        if self.status.success() {
-            Ok(self)
-        } else {
-            let code = self.status.code().unwrap_or(-1);
-            Err(DevcontainerError::Provider(format!(
-                "Command '{}' exited with code {code}. stdout: {} stderr: {}",
-                self.command,
-                self.stdout.trim(),
-                self.stderr.trim()
-            )))
-        }
+    async fn is_running(&self, container: &str) -> Result<bool> {
+        let inspected: Option<InspectedContainer> = self.inspect("container", container).await?;
+        Ok(inspected.is_some_and(|container| container.state.running))
+    }
+
+    /// Whether the `buildx` plugin is installed, so builds can use BuildKit.
+    async fn has_buildx(&self) -> Result<bool> {
+        let output = self
+            .run(vec!["buildx".to_string(), "version".to_string()])
+            .await?;
+        debug!(available = output.success(), "Checked for docker buildx");
+        Ok(output.success())
     }
 }
 
+/// Multi-platform images cannot be loaded into the local image store, so buildx
+/// pushes them itself instead of `docker push` after the build.
+fn pushes_from_builder(build: &ProviderBuildContext) -> bool {
+    build.push && build.platforms.len() > 1
+}
+
+/// Digest from the `tag: digest: sha256:... size: N` line `docker push` ends with.
+fn pushed_digest(stdout: &str) -> Option<String> {
+    stdout.lines().rev().find_map(|line| {
+        let (_, rest) = line.split_once("digest: ")?;
+        rest.split_whitespace().next().map(str::to_string)
+    })
+}
+
+/// `name@digest` of a manifest list buildx pushed.
+async fn pushed_manifest_reference(cli: &DockerCli, name: &str) -> Result<String> {
+    let digest = manifest_digest(cli, name).await?;
+    Ok(format!("{name}@{digest}"))
+}
+
+/// Digest of the manifest, or manifest list, `reference` names in its registry.
+async fn manifest_digest(cli: &DockerCli, reference: &str) -> Result<String> {
+    let output = cli
+        .run_expect_success(vec![
+            "buildx".to_string(),
+            "imagetools".to_string(),
+            "inspect".to_string(),
+            "--format".to_string(),
+            "{{json .Manifest}}".to_string(),
+            reference.to_string(),
+        ])
+        .await?;
+    let manifest: serde_json::Value =
+        serde_json::from_str(output.stdout.trim()).map_err(|err| {
+            DevcontainerError::Provider(format!("Unexpected manifest of {reference}: {err}"))
+        })?;
+    manifest["digest"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| DevcontainerError::Provider(format!("{reference} reports no digest")))
+}
+
+/// Arguments building `build` with `docker buildx build`, loading the result into
+/// the local image store, or with the classic builder when `buildx` is false.
+fn build_command_args(
+    build: &ProviderBuildContext,
+    buildx: bool,
+    metadata: &str,
+) -> Result<Vec<String>> {
+    let mut args = if buildx {
+        let output = if pushes_from_builder(build) {
+            "--push"
+        } else {
+            "--load"
+        };
+        vec![
+            "buildx".to_string(),
+            "build".to_string(),
+            output.to_string(),
+        ]
+    } else {
+        vec!["build".to_string()]
+    };
+    args.extend([
+        "--progress".to_string(),
+        "plain".to_string(),
+        "-f".to_string(),
+        path_to_string(&build.dockerfile)?,
+    ]);
+    // Images pushed by the builder never exist locally, so only their registry names apply.
+    let tags = if pushes_from_builder(build) {
+        build.image_names.as_slice()
+    } else {
+        std::slice::from_ref(&build.tag)
+    };
+    for tag in tags {
+        args.push("-t".to_string());
+        args.push(tag.clone());
+    }
+    args.push("--label".to_string());
+    args.push(format!("{IMAGE_METADATA_LABEL}={metadata}"));
+    for (name, value) in &build.args {
+        args.push("--build-arg".to_string());
+        args.push(format!("{name}={value}"));
+    }
+    if let Some(target) = &build.target {
+        args.push("--target".to_string());
+        args.push(target.clone());
+    }
+    if !build.platforms.is_empty() {
+        args.push("--platform".to_string());
+        args.push(build.platforms.join(","));
+    }
+    for image in &build.cache_from {
+        args.push("--cache-from".to_string());
+        args.push(image.clone());
+    }
+    if buildx {
+        for destination in &build.cache_to {
+            args.push("--cache-to".to_string());
+            args.push(destination.clone());
+        }
+    }
+    if build.pull {
+        args.push("--pull".to_string());
+    }
+    args.extend(build.options.iter().cloned());
+    args.push(path_to_string(&build.build_context)?);
+    Ok(args)
+}
+
+/// Forwards `docker pull` output to `output` while tracking layer progress.
+fn pull_progress_sink(image: &str, output: &OutputSink) -> OutputSink {
+    let progress = Mutex::new(PullProgress::new(image));
+    let output = output.clone();
+    OutputSink::new(move |line| {
+        if let Some((layer, status)) = parse_pull_line(&line.line) {
+            progress
+                .lock()
+                .expect("pull progress lock")
+                .observe(layer, status);
+        }
+        output.send(line.stream, line.line);
+    })
+}
+
+/// Forwards `docker build` output to `output` while tracking step progress.
+fn build_progress_sink(output: &OutputSink) -> OutputSink {
+    let progress = Mutex::new(BuildProgress::new());
+    let output = output.clone();
+    OutputSink::new(move |line| {
+        if let Some((step, total, instruction)) = parse_build_step(&line.line) {
+            progress
+                .lock()
+                .expect("build progress lock")
+                .observe(step, total, instruction);
+        }
+        output.send(line.stream, line.line);
+    })
+}
+
+/// Step and instruction from a line of `--progress plain` output, such as
+/// `#7 [stage-1 2/4] RUN make`, or of a classic build, such as `Step 2/4 : RUN make`.
+fn parse_build_step(line: &str) -> Option<(u64, u64, &str)> {
+    let (count, instruction) = if let Some(rest) = line.strip_prefix("Step ") {
+        rest.split_once(" : ")?
+    } else {
+        let (_, rest) = line.strip_prefix('#')?.split_once(" [")?;
+        let (label, instruction) = rest.split_once("] ")?;
+        (label.rsplit(' ').next()?, instruction)
+    };
+    let (step, total) = count.split_once('/')?;
+    Some((step.parse().ok()?, total.parse().ok()?, instruction.trim()))
+}
+
+/// Layer status from a line of non-interactive `docker pull` output, such as
+/// `3f4ca61aafcd: Pull complete`.
+fn parse_pull_line(line: &str) -> Option<(&str, LayerStatus)> {
+    let (layer, message) = line.split_once(": ")?;
+    if layer.len() < 12 || !layer.chars().all(|ch| ch.is_ascii_hexdigit()) {
+        return None;
+    }
+    let status = match message.trim() {
+        "Pulling fs layer" | "Waiting" => LayerStatus::Waiting,
+        "Downloading" => LayerStatus::Downloading,
+        "Verifying Checksum" => LayerStatus::Verifying,
+        "Download complete" => LayerStatus::Downloaded,
+        "Extracting" => LayerStatus::Extracting,
+        "Pull complete" | "Already exists" => LayerStatus::Complete,
+        _ => return None,
+    };
+    Some((layer, status))
+}
+
+/// Parses `{{json .RegistryConfig.Mirrors}}`, which is `null` without mirrors.
+fn parse_registry_mirrors(output: &str) -> Result<Vec<RegistryMirror>> {
+    let mirrors: Option<Vec<String>> = serde_json::from_str(output.trim()).map_err(|err| {
+        DevcontainerError::Provider(format!(
+            "Unexpected docker info output for registry mirrors: {err}"
+        ))
+    })?;
+    mirrors
+        .unwrap_or_default()
+        .iter()
+        .map(|mirror| mirror.parse())
+        .collect()
+}
+
+/// Fields of `docker container inspect` output behind [`ContainerActivity`] and
+/// [`ContainerDetails`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct InspectedContainer {
+    id: String,
+    /// With a leading `/`.
+    name: String,
+    /// Identifier of the image, `sha256:...`.
+    image: String,
+    state: InspectedContainerState,
+    /// `null` without sessions.
+    #[serde(rename = "ExecIDs")]
+    exec_ids: Option<Vec<String>>,
+    config: InspectedContainerConfig,
+    /// `null` without mounts on some engines.
+    mounts: Option<Vec<InspectedMount>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct InspectedContainerConfig {
+    /// `null` without labels.
+    labels: Option<BTreeMap<String, String>>,
+    /// Image reference the container was created from.
+    image: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct InspectedContainerState {
+    running: bool,
+    status: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct InspectedMount {
+    #[serde(rename = "Type")]
+    mount_type: String,
+    /// Volume name, for volume mounts.
+    name: Option<String>,
+    source: String,
+    destination: String,
+}
+
+impl From<InspectedContainer> for ContainerDetails {
+    fn from(container: InspectedContainer) -> Self {
+        let name = container.name.trim_start_matches('/');
+        ContainerDetails {
+            id: container.id,
+            name: (!name.is_empty()).then(|| name.to_string()),
+            status: container.state.status.as_str().into(),
+            image: (!container.config.image.is_empty()).then_some(container.config.image),
+            image_digest: (!container.image.is_empty()).then_some(container.image),
+            labels: container.config.labels.unwrap_or_default(),
+            mounts: container
+                .mounts
+                .unwrap_or_default()
+                .into_iter()
+                .map(|mount| Mount::Object {
+                    source: match mount.name {
+                        Some(name) if mount.mount_type == "volume" => Some(name),
+                        _ => (!mount.source.is_empty()).then_some(mount.source),
+                    },
+                    mount_type: mount.mount_type,
+                    target: mount.destination,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<InspectedContainer> for ContainerActivity {
+    fn from(container: InspectedContainer) -> Self {
+        ContainerActivity {
+            running: container.state.running,
+            sessions: container.exec_ids.map_or(0, |ids| ids.len()),
+        }
+    }
+}
+
+/// Reads the JSON array `<kind> inspect` prints without `--format`. A missing
+/// object still gets an empty array, from docker and podman alike, while a
+/// failure such as an unreachable daemon prints none, which tells the two apart
+/// without matching error messages that change across versions and locales.
+fn parse_inspect<T: DeserializeOwned>(
+    output: &CommandOutput,
+    kind: &str,
+    name: &str,
+) -> Result<Option<T>> {
+    Ok(parse_inspect_all(output, kind, name)?.into_iter().next())
+}
+
+/// Like [`parse_inspect`], for inspecting several objects at once; those missing
+/// are left out.
+fn parse_inspect_all<T: DeserializeOwned>(
+    output: &CommandOutput,
+    kind: &str,
+    name: &str,
+) -> Result<Vec<T>> {
+    match serde_json::from_str(output.stdout.trim()) {
+        Ok(found) => Ok(found),
+        Err(_) if !output.success() => Err(DevcontainerError::Provider(format!(
+            "Failed to inspect docker {kind} {name}: {}",
+            output.stderr.trim()
+        ))),
+        Err(err) => Err(DevcontainerError::Provider(format!(
+            "Unexpected inspect output for docker {kind} {name}: {err}"
+        ))),
+    }
+}
+
+/// Fields of `docker info` output behind [`HostCapabilities`] and clock fixes.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct DockerInfo {
+    #[serde(rename = "NCPU")]
+    ncpu: Option<u64>,
+    mem_total: Option<u64>,
+    docker_root_dir: Option<String>,
+    runtimes: BTreeMap<String, serde_json::Value>,
+    name: String,
+    operating_system: String,
+    kernel_version: String,
+}
+
+impl DockerInfo {
+    /// Whether the engine runs in a VM of its own, as Docker Desktop, colima,
+    /// Rancher Desktop and OrbStack do, rather than on the host's kernel.
+    fn runs_in_vm(&self) -> bool {
+        if !cfg!(target_os = "linux") {
+            return true;
+        }
+        let operating_system = self.operating_system.to_ascii_lowercase();
+        let name = self.name.to_ascii_lowercase();
+        operating_system.contains("docker desktop")
+            || operating_system.contains("orbstack")
+            || self.kernel_version.contains("linuxkit")
+            || ["colima", "lima-", "rancher-desktop", "docker-desktop"]
+                .iter()
+                .any(|vm| name.starts_with(vm))
+    }
+}
+
+async fn docker_info(cli: &DockerCli) -> Result<DockerInfo> {
+    let output = cli
+        .run_expect_success(vec![
+            "info".to_string(),
+            "--format".to_string(),
+            "{{json .}}".to_string(),
+        ])
+        .await?;
+    serde_json::from_str(output.stdout.trim())
+        .map_err(|err| DevcontainerError::Provider(format!("Unexpected docker info output: {err}")))
+}
+
+/// Bytes available in the filesystem `df -Pk` reported on.
+fn parse_df_available(output: &str) -> Option<u64> {
+    let available = output.lines().nth(1)?.split_whitespace().nth(3)?;
+    available.parse::<u64>().ok()?.checked_mul(1024)
+}
+
+/// Fields of `docker image inspect` output behind [`ImageInfo`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct InspectedImage {
+    id: String,
+    /// `null` for images never pulled from or pushed to a registry.
+    repo_digests: Option<Vec<String>>,
+    os: Option<String>,
+    architecture: Option<String>,
+    variant: Option<String>,
+    config: Option<InspectedImageConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct InspectedImageConfig {
+    /// `null` for images without labels.
+    labels: Option<BTreeMap<String, String>>,
+    entrypoint: Option<Vec<String>>,
+    cmd: Option<Vec<String>>,
+}
+
+impl From<InspectedImage> for ImageInfo {
+    fn from(image: InspectedImage) -> Self {
+        let platform = match (image.os, image.architecture) {
+            (Some(os), Some(arch)) => Some(match image.variant {
+                Some(variant) => format!("{os}/{arch}/{variant}"),
+                None => format!("{os}/{arch}"),
+            }),
+            _ => None,
+        };
+        ImageInfo {
+            id: image.id,
+            digest: image
+                .repo_digests
+                .and_then(|digests| digests.into_iter().next()),
+            platform,
+            labels: image
+                .config
+                .and_then(|config| config.labels)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Docker tuning from `customizations.devcontainer-rs.docker`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct DockerSettings {
+    /// Extra `docker create` arguments, held to the same rules as `runArgs`.
+    #[serde(default)]
+    create_args: Vec<String>,
+    /// Storage driver options, such as `size=20G`, passed as `--storage-opt`.
+    #[serde(default)]
+    storage_opts: Vec<String>,
+}
+
+impl DockerSettings {
+    fn from_config(config: &ResolvedConfig) -> Result<Self> {
+        let Some(settings) = config.provider_settings(ProviderKind::Docker) else {
+            return Ok(Self::default());
+        };
+        let settings: Self = serde_json::from_value(serde_json::Value::Object(settings.clone()))
+            .map_err(|err| {
+                DevcontainerError::Configuration(format!(
+                    "Invalid customizations.{PROVIDER_CUSTOMIZATION}.docker: {err}"
+                ))
+            })?;
+        validate_run_args(
+            &format!("customizations.{PROVIDER_CUSTOMIZATION}.docker.createArgs"),
+            &settings.create_args,
+            !config.sidecars.is_empty(),
+        )?;
+        Ok(settings)
+    }
+}
+
+/// Whether a docker endpoint such as `ssh://user@box` or `tcp://10.0.0.5:2376`
+/// points at another machine; unix sockets, named pipes and loopback do not.
+fn is_remote_endpoint(endpoint: &str) -> bool {
+    match endpoint.split_once("://") {
+        Some(("ssh", _)) => true,
+        Some(("tcp" | "http" | "https", address)) => {
+            let authority = address.split('/').next().unwrap_or_default();
+            let host = match authority.strip_prefix('[') {
+                Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+                None => authority.split(':').next().unwrap_or_default(),
+            };
+            !matches!(host, "localhost" | "127.0.0.1" | "::1" | "0.0.0.0" | "")
+        }
+        _ => false,
+    }
+}
+
+/// Registry error codes and CLI messages of pulls that fail the same way on
+/// every attempt.
+const PERMANENT_PULL_ERRORS: &[&str] = &[
+    "manifest unknown",
+    "name unknown",
+    "not found",
+    "denied",
+    "unauthorized",
+    "invalid reference format",
+    "no matching manifest",
+];
+
+/// Whether the `stderr` of a failed `docker pull` reports a failure that
+/// retrying cannot fix.
+fn permanent_pull_failure(stderr: &str) -> bool {
+    let stderr = stderr.to_ascii_lowercase();
+    PERMANENT_PULL_ERRORS
+        .iter()
+        .any(|error| stderr.contains(error))
+}
+
+/// Who a pre-existing network or volume belongs to, according to its labels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ResourceOwner {
+    Project(String),
+    Unlabeled,
+}
+
+impl ResourceOwner {
+    fn from_labels(labels: &BTreeMap<String, String>) -> Self {
+        labels
+            .get(LABEL_PROJECT)
+            .map_or(ResourceOwner::Unlabeled, |project| {
+                ResourceOwner::Project(project.clone())
+            })
+    }
+
+    fn is_project(&self, project_name: &str) -> bool {
+        matches!(self, ResourceOwner::Project(project) if project == project_name)
+    }
+}
+
+impl std::fmt::Display for ResourceOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResourceOwner::Project(project) => write!(f, "owned by project '{project}'"),
+            ResourceOwner::Unlabeled => f.write_str("not labeled with a devcontainer project"),
+        }
+    }
+}
+
+/// Labels of an inspected network or volume.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct InspectedResource {
+    /// `null` for resources without labels.
+    labels: Option<BTreeMap<String, String>>,
+}
+
+/// Inspects a docker `kind` (`network` or `volume`), returning `None` when it does not exist.
+async fn resource_owner(cli: &DockerCli, kind: &str, name: &str) -> Result<Option<ResourceOwner>> {
+    let inspected: Option<InspectedResource> = cli.inspect(kind, name).await?;
+    Ok(inspected.map(|resource| ResourceOwner::from_labels(&resource.labels.unwrap_or_default())))
+}
+
+/// Volumes shadowing the writable paths of a read-only workspace.
+fn writable_workspace_volumes(
+    config: &ResolvedConfig,
+    container_name: &str,
+    workspace_mount_path: &Path,
+) -> Vec<VolumeSpec> {
+    if !config.read_only_workspace {
+        return Vec::new();
+    }
+    config
+        .writable_workspace_paths
+        .iter()
+        .map(|path| VolumeSpec {
+            name: format!(
+                "{container_name}-{}",
+                sanitize_name(&path.to_string_lossy())
+            ),
+            mount_path: workspace_mount_path.join(path),
+        })
+        .collect()
+}
+
+/// Docker cannot create mount points inside a read-only bind mount, so writable
+/// paths missing from the checkout are created on the host first.
+fn create_writable_mount_points(config: &ResolvedConfig) -> Result<()> {
+    for path in &config.writable_workspace_paths {
+        let host_path = config.workspace_folder.join(path);
+        fs::create_dir_all(&host_path).map_err(|err| {
+            DevcontainerError::Provider(format!(
+                "Failed to create mount point {} for writable path: {err}",
+                host_path.display()
+            ))
+        })?;
+    }
+    Ok(())
+}
+
 fn sanitize_name(input: &str) -> String {
     let mut result = String::new();
 
@@ -601,15 +2301,6 @@ fn sanitize_name(input: &str) -> String {
     }
 }
 
-fn format_command(program: &Path, args: &[String]) -> String {
-    let mut command = program.display().to_string();
-    for arg in args {
-        command.push(' ');
-        command.push_str(arg);
-    }
-    command
-}
-
 fn path_to_string(path: &Path) -> Result<String> {
     if let Some(value) = path.to_str() {
         return Ok(value.to_string());
@@ -631,7 +2322,10 @@ fn path_to_string(path: &Path) -> Result<String> {
 mod tests {
     use std::fs;
 
-    use devcontainer_core::config::ResolvedConfig;
+    use devcontainer_core::{
+        config::{CommandDefinition, ForwardPort, MountConsistency, MountPropagation, Sidecar},
+        provider::{ContainerStatus, RecordingRunner},
+    };
     use tempfile::tempdir;
 
     use super::*;
@@ -651,11 +2345,7 @@ mod tests {
             container_workspace_folder: None,
             config_path,
             image_reference: Some("ghcr.io/devcontainers/base:latest".into()),
-            dockerfile: None,
-            features: Default::default(),
-            forward_ports: vec![],
-            post_create_command: None,
-            post_attach_command: None,
+            ..Default::default()
         };
 
         let preparation = provider.prepare(&config).await.unwrap();
@@ -685,11 +2375,7 @@ mod tests {
             container_workspace_folder: Some(PathBuf::from("/workspace/demo")),
             config_path,
             image_reference: Some("ghcr.io/devcontainers/base:latest".into()),
-            dockerfile: None,
-            features: Default::default(),
-            forward_ports: vec![],
-            post_create_command: None,
-            post_attach_command: None,
+            ..Default::default()
         };
 
         let preparation = provider.prepare(&config).await.unwrap();
@@ -698,4 +2384,1355 @@ mod tests {
             PathBuf::from("/workspace/demo")
         );
     }
+
+    #[test]
+    fn registry_config_layers_credentials_over_ambient_config() {
+        let ambient = tempdir().expect("ambient config dir");
+        fs::write(
+            ambient.path().join("config.json"),
+            r#"{"credsStore": "desktop", "auths": {"quay.io": {}}}"#,
+        )
+        .expect("write ambient config");
+
+        let auth = RegistryAuth {
+            credentials: vec!["ghcr.io=octocat:token".parse().expect("credential")],
+            credential_helpers: Vec::new(),
+        };
+
+        let dir = write_registry_config(&auth, Some(ambient.path().to_path_buf()))
+            .expect("write registry config");
+        let written: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(dir.path().join("config.json")).expect("read config"),
+        )
+        .expect("parse config");
+
+        assert_eq!(written["credsStore"], "desktop");
+        assert!(written["auths"]["quay.io"].is_object());
+        assert!(written["auths"]["ghcr.io"]["auth"].is_string());
+        assert_eq!(written["credHelpers"]["ghcr.io"], "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn registry_config_links_every_other_ambient_entry() {
+        let ambient = tempdir().expect("ambient config dir");
+        fs::write(
+            ambient.path().join("config.json"),
+            r#"{"currentContext": "remote"}"#,
+        )
+        .expect("write ambient config");
+        for entry in ["cli-plugins", "contexts/meta", "buildx/instances"] {
+            fs::create_dir_all(ambient.path().join(entry)).expect("create ambient entry");
+        }
+
+        let dir =
+            write_registry_config(&RegistryAuth::default(), Some(ambient.path().to_path_buf()))
+                .expect("write registry config");
+
+        for entry in ["cli-plugins", "contexts", "buildx"] {
+            let link = fs::read_link(dir.path().join(entry)).expect("linked entry");
+            assert_eq!(link, ambient.path().join(entry));
+        }
+        assert!(!fs::symlink_metadata(dir.path().join("config.json"))
+            .expect("config")
+            .is_symlink());
+    }
+
+    #[tokio::test]
+    async fn exec_goes_through_the_configured_runner() {
+        let runner = RecordingRunner::with_handler(|spec| CommandOutput {
+            exit_code: if spec.args[0] == "exec" { 3 } else { 0 },
+            stdout: "hello\n".to_string(),
+            ..Default::default()
+        });
+        let provider = DockerProvider::from_path("docker").with_runner(runner.clone());
+        let container = RunningContainer {
+            name: Some("demo".to_string()),
+            ..Default::default()
+        };
+
+        let result = provider
+            .exec(&container, &["echo".to_string(), "hello".to_string()])
+            .await
+            .expect("exec");
+        assert_eq!(result.exit_code, 3);
+        assert_eq!(result.stdout, "hello\n");
+        let calls = runner.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].program, PathBuf::from("docker"));
+        assert_eq!(calls[0].args, vec!["exec", "demo", "echo", "hello"]);
+    }
+
+    #[tokio::test]
+    async fn commit_container_labels_the_committed_image() {
+        let runner = RecordingRunner::with_handler(|spec| CommandOutput {
+            stdout: if spec.args[0] == "image" {
+                "[]".to_string()
+            } else {
+                String::new()
+            },
+            ..Default::default()
+        });
+        let provider = DockerProvider::from_path("docker").with_runner(runner.clone());
+        let container = RunningContainer {
+            name: Some("demo".to_string()),
+            ..Default::default()
+        };
+        let config = ResolvedConfig {
+            remote_user: Some("vscode".to_string()),
+            ..Default::default()
+        };
+
+        provider
+            .commit_container(&config, &container, "example:prebuild")
+            .await
+            .expect("commit");
+        let calls = runner.calls();
+        assert_eq!(calls[0].args, ["commit", "demo", "example:prebuild"]);
+        let build = calls.last().expect("label build");
+        assert_eq!(build.args[0], "build");
+        assert!(build
+            .args
+            .iter()
+            .any(|arg| arg.starts_with("devcontainer.metadata=") && arg.contains("vscode")));
+        assert!(build.args.contains(&"example:prebuild".to_string()));
+    }
+
+    #[tokio::test]
+    async fn resolve_digest_asks_the_registry_through_imagetools() {
+        let runner = RecordingRunner::with_handler(|spec| {
+            CommandOutput {
+            stdout: match spec.args[1].as_str() {
+                "imagetools" => r#"{"mediaType":"application/vnd.oci.image.manifest.v1+json","digest":"sha256:9f8e","size":1024}"#.to_string(),
+                _ => String::new(),
+            },
+            ..Default::default()
+        }
+        });
+        let provider = DockerProvider::from_path("docker").with_runner(runner.clone());
+
+        let digest = provider
+            .resolve_digest("ghcr.io/devcontainers/features/node:1")
+            .await
+            .expect("resolve");
+        assert_eq!(digest, "sha256:9f8e");
+        let calls = runner.calls();
+        assert_eq!(calls[0].args, ["buildx", "version"]);
+        assert_eq!(calls[1].args[0], "info");
+        assert_eq!(
+            calls[2].args,
+            [
+                "buildx",
+                "imagetools",
+                "inspect",
+                "--format",
+                "{{json .Manifest}}",
+                "ghcr.io/devcontainers/features/node:1"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn pulls_and_resolves_through_the_registry_mirror_first() {
+        let runner = RecordingRunner::with_handler(|spec| {
+            let mirrored = spec
+                .args
+                .iter()
+                .any(|arg| arg.starts_with("cache.local:5000/"));
+            match spec.args[0].as_str() {
+                "pull" if !mirrored => CommandOutput {
+                    exit_code: 1,
+                    stderr: "unexpected pull from the registry".to_string(),
+                    ..Default::default()
+                },
+                "buildx" if spec.args[1] == "imagetools" => CommandOutput {
+                    exit_code: if mirrored { 1 } else { 0 },
+                    stdout: r#"{"digest":"sha256:9f8e"}"#.to_string(),
+                    ..Default::default()
+                },
+                _ => CommandOutput::default(),
+            }
+        });
+        let provider = DockerProvider::from_path("docker")
+            .with_runner(runner.clone())
+            .with_pull_backoff(Duration::ZERO)
+            .with_registry_mirror(Some("cache.local:5000".parse().expect("mirror")));
+        let cli = provider.cli().expect("cli");
+
+        let local = provider
+            .pull_image(&cli, "ubuntu:22.04")
+            .await
+            .expect("pull");
+        assert_eq!(local, "ubuntu:22.04");
+        let pinned = provider
+            .pull_image(&cli, "ghcr.io/org/base@sha256:0123")
+            .await
+            .expect("pull");
+        assert_eq!(pinned, "cache.local:5000/org/base@sha256:0123");
+        let digest = provider
+            .resolve_digest("ghcr.io/devcontainers/features/node:1")
+            .await
+            .expect("resolve falls back to the registry");
+        assert_eq!(digest, "sha256:9f8e");
+
+        let calls: Vec<Vec<String>> = runner.calls().into_iter().map(|call| call.args).collect();
+        assert_eq!(
+            calls[..3],
+            [
+                vec!["pull", "cache.local:5000/library/ubuntu:22.04"],
+                vec![
+                    "tag",
+                    "cache.local:5000/library/ubuntu:22.04",
+                    "ubuntu:22.04"
+                ],
+                vec!["pull", "cache.local:5000/org/base@sha256:0123"],
+            ]
+        );
+        assert_eq!(
+            calls
+                .last()
+                .expect("registry lookup")
+                .last()
+                .map(String::as_str),
+            Some("ghcr.io/devcontainers/features/node:1")
+        );
+    }
+
+    #[tokio::test]
+    async fn list_containers_inspects_matches_running_first() {
+        let runner = RecordingRunner::with_handler(|spec| {
+            CommandOutput {
+            stdout: match spec.args[0].as_str() {
+                "ps" => "abc123\ndef456\n".to_string(),
+                _ => serde_json::json!([
+                    {
+                        "Id": "abc123",
+                        "Name": "/old-app",
+                        "Image": "sha256:0a1b",
+                        "State": { "Running": false, "Status": "exited" },
+                        "Config": { "Image": "example:image", "Labels": null },
+                        "Mounts": null
+                    },
+                    {
+                        "Id": "def456",
+                        "Name": "/devcontainer-app",
+                        "Image": "sha256:2c3d",
+                        "State": { "Running": true, "Status": "running" },
+                        "Config": {
+                            "Image": "example:image",
+                            "Labels": { "devcontainer.local_folder": "/src/app" }
+                        },
+                        "Mounts": [
+                            {
+                                "Type": "volume",
+                                "Name": "app-cache",
+                                "Source": "/var/lib/docker/volumes/app-cache/_data",
+                                "Destination": "/cache"
+                            },
+                            { "Type": "bind", "Source": "/src/app", "Destination": "/workspaces/app" }
+                        ]
+                    }
+                ])
+                .to_string(),
+            },
+            ..Default::default()
+        }
+        });
+        let provider = DockerProvider::from_path("docker").with_runner(runner.clone());
+        let labels = vec![ContainerLabel::new("devcontainer.local_folder", "/src/app")];
+
+        let containers = provider
+            .list_containers(&ContainerFilter::labelled(&labels))
+            .await
+            .expect("list");
+        let calls = runner.calls();
+        assert_eq!(
+            calls[0].args,
+            [
+                "ps",
+                "--all",
+                "--filter",
+                "label=devcontainer.local_folder=/src/app",
+                "--format",
+                "{{.ID}}"
+            ]
+        );
+        assert_eq!(calls[1].args, ["container", "inspect", "abc123", "def456"]);
+
+        assert_eq!(containers.len(), 2);
+        let running = &containers[0];
+        assert_eq!(running.name.as_deref(), Some("devcontainer-app"));
+        assert_eq!(running.status, ContainerStatus::Running);
+        assert_eq!(running.image_digest.as_deref(), Some("sha256:2c3d"));
+        assert!(running.has_labels(&labels));
+        assert_eq!(
+            running.mounts,
+            [
+                Mount::Object {
+                    mount_type: "volume".to_string(),
+                    source: Some("app-cache".to_string()),
+                    target: "/cache".to_string(),
+                },
+                Mount::Object {
+                    mount_type: "bind".to_string(),
+                    source: Some("/src/app".to_string()),
+                    target: "/workspaces/app".to_string(),
+                },
+            ]
+        );
+        assert_eq!(containers[1].status, ContainerStatus::Exited);
+        assert!(containers[1].labels.is_empty());
+    }
+
+    #[tokio::test]
+    async fn host_capabilities_read_docker_info_and_free_space() {
+        let runner = RecordingRunner::with_handler(|spec| CommandOutput {
+            stdout: match spec.args[0].as_str() {
+                "info" => serde_json::json!({
+                    "NCPU": 8,
+                    "MemTotal": 16_u64 << 30,
+                    "DockerRootDir": "/var/lib/docker",
+                    "Runtimes": { "runc": {}, "nvidia": {} }
+                })
+                .to_string(),
+                "context" => "unix:///var/run/docker.sock\n".to_string(),
+                _ => "Filesystem 1024-blocks Used Available Capacity Mounted on\n\
+                      /dev/sda1 100 50 41943040 50% /\n"
+                    .to_string(),
+            },
+            ..Default::default()
+        });
+        let provider = DockerProvider::from_path("docker").with_runner(runner.clone());
+
+        let host = provider.host_capabilities().await.expect("capabilities");
+        assert_eq!(
+            host,
+            HostCapabilities {
+                cpus: Some(8),
+                memory_bytes: Some(16 << 30),
+                storage_bytes: Some(40 << 30),
+                gpu: Some(true),
+            }
+        );
+        let df = runner.calls().pop().expect("df call");
+        assert_eq!(df.program, PathBuf::from("df"));
+        assert_eq!(df.args, ["-Pk", "/var/lib/docker"]);
+    }
+
+    #[tokio::test]
+    async fn sync_clock_only_resets_a_local_engine_in_a_vm() {
+        let provider_on = |operating_system: &'static str| {
+            let runner = RecordingRunner::with_handler(move |spec| CommandOutput {
+                stdout: match spec.args[0].as_str() {
+                    "info" => serde_json::json!({
+                        "Name": "workstation",
+                        "OperatingSystem": operating_system,
+                        "KernelVersion": "6.8.0-45-generic"
+                    })
+                    .to_string(),
+                    "context" => "unix:///var/run/docker.sock\n".to_string(),
+                    _ => String::new(),
+                },
+                ..Default::default()
+            });
+            let provider = DockerProvider::from_path("docker").with_runner(runner.clone());
+            (provider, runner)
+        };
+        let synced = |runner: &RecordingRunner| {
+            runner
+                .calls()
+                .iter()
+                .any(|call| call.args.contains(&"hwclock".to_string()))
+        };
+
+        let (provider, runner) = provider_on("Docker Desktop");
+        provider.sync_clock().await.expect("sync");
+        assert!(synced(&runner));
+
+        if cfg!(target_os = "linux") {
+            let (provider, runner) = provider_on("Ubuntu 24.04.1 LTS");
+            let err = provider.sync_clock().await.expect_err("native engine");
+            assert!(matches!(err, DevcontainerError::Unsupported(_)));
+            assert!(!synced(&runner));
+        }
+
+        let runner = RecordingRunner::new();
+        let remote = DockerProvider::from_path("docker")
+            .with_docker_host(Some("ssh://dev@buildbox".to_string()))
+            .with_runner(runner.clone());
+        let err = remote.sync_clock().await.expect_err("remote engine");
+        assert!(matches!(err, DevcontainerError::Unsupported(_)));
+        assert!(!synced(&runner));
+    }
+
+    #[tokio::test]
+    async fn pulls_images_per_policy_with_retries() {
+        let failures = Arc::new(Mutex::new(1));
+        let remaining = Arc::clone(&failures);
+        let runner = RecordingRunner::with_handler(move |spec| match spec.args[0].as_str() {
+            "image" => CommandOutput {
+                exit_code: 1,
+                stdout: "[]".to_string(),
+                ..Default::default()
+            },
+            "pull" => {
+                let mut remaining = remaining.lock().expect("failures lock");
+                if *remaining == 0 {
+                    return CommandOutput::default();
+                }
+                *remaining -= 1;
+                CommandOutput {
+                    exit_code: 1,
+                    stderr: "net/http: TLS handshake timeout".to_string(),
+                    ..Default::default()
+                }
+            }
+            _ => CommandOutput::default(),
+        });
+        let provider = DockerProvider::from_path("docker")
+            .with_runner(runner.clone())
+            .with_pull_backoff(Duration::ZERO);
+        let preparation = ProviderPreparation {
+            image: ProviderImage::Reference("example:latest".to_string()),
+            container_name: "devcontainer-demo".to_string(),
+            project_slug: "demo".to_string(),
+            networks: Vec::new(),
+            volumes: Vec::new(),
+            workspace_mount_path: PathBuf::from("/workspaces/demo"),
+            workspace_volume: None,
+            sidecars: Vec::new(),
+        };
+        let commands = |runner: &RecordingRunner| -> Vec<String> {
+            runner
+                .calls()
+                .iter()
+                .map(|call| call.args[0].clone())
+                .collect()
+        };
+
+        let config = ResolvedConfig::default();
+        let image = provider
+            .build_image(&config, &preparation)
+            .await
+            .expect("pulled after a retry");
+        assert_eq!(image, "example:latest");
+        assert_eq!(commands(&runner), ["image", "pull", "pull"]);
+
+        let always = ResolvedConfig {
+            pull_policy: PullPolicy::Always,
+            ..Default::default()
+        };
+        provider
+            .build_image(&always, &preparation)
+            .await
+            .expect("refreshed");
+        assert_eq!(commands(&runner)[3..], ["pull"]);
+
+        *failures.lock().expect("failures lock") = PULL_ATTEMPTS;
+        provider
+            .build_image(&always, &preparation)
+            .await
+            .expect_err("registry keeps failing");
+        assert_eq!(commands(&runner).len(), 4 + PULL_ATTEMPTS as usize);
+
+        let never = ResolvedConfig {
+            pull_policy: PullPolicy::Never,
+            ..Default::default()
+        };
+        let err = provider
+            .build_image(&never, &preparation)
+            .await
+            .expect_err("image missing");
+        assert!(matches!(err, DevcontainerError::Configuration(_)));
+        assert_eq!(commands(&runner).last().map(String::as_str), Some("image"));
+    }
+
+    #[tokio::test]
+    async fn pull_fails_at_once_when_the_registry_rejects_the_image() {
+        let runner = RecordingRunner::with_handler(|spec| match spec.args[0].as_str() {
+            "pull" => CommandOutput {
+                exit_code: 1,
+                stderr: "Error response from daemon: manifest for example:missing not found: \
+                         manifest unknown: manifest unknown"
+                    .to_string(),
+                ..Default::default()
+            },
+            _ => CommandOutput::default(),
+        });
+        let provider = DockerProvider::from_path("docker")
+            .with_runner(runner.clone())
+            .with_pull_backoff(Duration::ZERO);
+        let cli = provider.cli().expect("cli");
+
+        provider
+            .pull_image(&cli, "example:missing")
+            .await
+            .expect_err("manifest unknown");
+        assert_eq!(runner.calls().len(), 1);
+        assert!(permanent_pull_failure(
+            "pull access denied for example, repository does not exist or may require \
+             'docker login': denied: requested access to the resource is denied"
+        ));
+        assert!(!permanent_pull_failure("net/http: TLS handshake timeout"));
+    }
+
+    #[tokio::test]
+    async fn image_exists_reads_details_and_reports_missing_images() {
+        let runner =
+            RecordingRunner::with_handler(|spec| match spec.args.last().map(String::as_str) {
+                Some("example:image") => CommandOutput {
+                    stdout: serde_json::json!([{
+                        "Id": "sha256:abc",
+                        "RepoDigests": ["example@sha256:def"],
+                        "Os": "linux",
+                        "Architecture": "arm64",
+                        "Variant": "v8",
+                        "Config": { "Labels": { "devcontainer.metadata": "[]" } }
+                    }])
+                    .to_string(),
+                    ..Default::default()
+                },
+                Some("plain:image") => CommandOutput {
+                    stdout: r#"[{"Id":"sha256:123","RepoDigests":null,"Config":{"Labels":null}}]"#
+                        .to_string(),
+                    ..Default::default()
+                },
+                _ => CommandOutput {
+                    exit_code: 1,
+                    stdout: "[]".to_string(),
+                    stderr: "Error response from daemon: No such image: missing:image".to_string(),
+                    ..Default::default()
+                },
+            });
+        let provider = DockerProvider::from_path("docker").with_runner(runner);
+
+        let details = provider
+            .image_exists("example:image")
+            .await
+            .expect("inspect")
+            .expect("image exists");
+        assert_eq!(details.id, "sha256:abc");
+        assert_eq!(details.digest.as_deref(), Some("example@sha256:def"));
+        assert_eq!(details.platform.as_deref(), Some("linux/arm64/v8"));
+        assert_eq!(
+            details
+                .labels
+                .get("devcontainer.metadata")
+                .map(String::as_str),
+            Some("[]")
+        );
+        let plain = provider.image_exists("plain:image").await.expect("inspect");
+        assert_eq!(
+            plain,
+            Some(ImageInfo {
+                id: "sha256:123".to_string(),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            provider
+                .image_exists("missing:image")
+                .await
+                .expect("inspect"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn create_container_passes_security_properties_and_run_args() {
+        let runner = RecordingRunner::new();
+        let provider = DockerProvider::from_path("docker").with_runner(runner.clone());
+        let config = ResolvedConfig {
+            project_name: "demo".to_string(),
+            workspace_folder: PathBuf::from("/work"),
+            init: true,
+            privileged: true,
+            cap_add: vec!["SYS_PTRACE".to_string()],
+            security_opt: vec!["seccomp=unconfined".to_string()],
+            run_args: vec!["--shm-size=1g".to_string()],
+            app_ports: vec!["127.0.0.1:3000:3000".to_string(), "8080:80".to_string()],
+            workspace_mount_propagation: Some(MountPropagation::Rshared),
+            workspace_mount_consistency: Some(MountConsistency::Delegated),
+            customizations: serde_json::json!({
+                "devcontainer-rs": {
+                    "docker": { "createArgs": ["--ulimit=nofile=4096"], "storageOpts": ["size=20G"] },
+                    "podman": { "createArgs": ["--userns=keep-id"] }
+                }
+            })
+            .as_object()
+            .cloned()
+            .expect("customizations object"),
+            ..Default::default()
+        };
+        let preparation = ProviderPreparation {
+            image: ProviderImage::Reference("example:image".to_string()),
+            container_name: "devcontainer-demo".to_string(),
+            project_slug: "demo".to_string(),
+            networks: Vec::new(),
+            volumes: Vec::new(),
+            workspace_mount_path: PathBuf::from("/workspaces/demo"),
+            workspace_volume: None,
+            sidecars: Vec::new(),
+        };
+
+        provider
+            .create_container(&config, &preparation, "example:image")
+            .await
+            .expect("create");
+
+        let calls = runner.calls();
+        let create = &calls[1].args;
+        assert_eq!(create[0], "create");
+        assert!(create.contains(
+            &"type=bind,src=/work,dst=/workspaces/demo,bind-propagation=rshared,consistency=delegated"
+                .to_string()
+        ));
+        let joined = create.join(" ");
+        assert!(joined.contains("--publish 127.0.0.1:3000:3000 --publish 8080:80"));
+        let image = create
+            .iter()
+            .position(|arg| arg == "example:image")
+            .expect("image argument");
+        assert_eq!(
+            create[image - 10..image],
+            [
+                "--init",
+                "--privileged",
+                "--cap-add",
+                "SYS_PTRACE",
+                "--security-opt",
+                "seccomp=unconfined",
+                "--shm-size=1g",
+                "--storage-opt",
+                "size=20G",
+                "--ulimit=nofile=4096",
+            ]
+        );
+
+        let mut config = config;
+        config.customizations["devcontainer-rs"]["docker"] =
+            serde_json::json!({ "createArgs": ["--name", "other"] });
+        let err = provider
+            .create_container(&config, &preparation, "example:image")
+            .await
+            .expect_err("managed flag");
+        assert!(err.to_string().contains("docker.createArgs entry '--name'"));
+    }
+
+    #[tokio::test]
+    async fn create_container_keeps_image_command_without_override() {
+        let runner = RecordingRunner::with_handler(|spec| {
+            CommandOutput {
+            stdout: match spec.args.first().map(String::as_str) {
+                Some("image") => r#"[{"Id":"sha256:1","Config":{"Entrypoint":["/init"],"Cmd":["serve","--port","80"]}}]"#.to_string(),
+                _ => String::new(),
+            },
+            ..Default::default()
+        }
+        });
+        let provider = DockerProvider::from_path("docker").with_runner(runner.clone());
+        let config = ResolvedConfig {
+            project_name: "demo".to_string(),
+            workspace_folder: PathBuf::from("/work"),
+            override_command: Some(false),
+            ..Default::default()
+        };
+        let preparation = ProviderPreparation {
+            image: ProviderImage::Reference("example:image".to_string()),
+            container_name: "devcontainer-demo".to_string(),
+            project_slug: "demo".to_string(),
+            networks: Vec::new(),
+            volumes: Vec::new(),
+            workspace_mount_path: PathBuf::from("/workspaces/demo"),
+            workspace_volume: None,
+            sidecars: Vec::new(),
+        };
+
+        provider
+            .create_container(&config, &preparation, "example:image")
+            .await
+            .expect("create");
+
+        let calls = runner.calls();
+        let create = &calls
+            .iter()
+            .find(|call| call.args[0] == "create")
+            .expect("create")
+            .args;
+        assert!(!create.contains(&"--tty".to_string()));
+        let image = create
+            .iter()
+            .position(|arg| arg == "example:image")
+            .expect("image");
+        assert_eq!(create[image - 2..image], ["--entrypoint", "/bin/sh"]);
+        assert_eq!(
+            create[image + 1..],
+            [
+                "-c",
+                KEEP_ALIVE_SCRIPT,
+                "-",
+                "/init",
+                "serve",
+                "--port",
+                "80"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn create_container_uses_custom_workspace_mount() {
+        let runner = RecordingRunner::new();
+        let provider = DockerProvider::from_path("docker").with_runner(runner.clone());
+        let config = ResolvedConfig {
+            project_name: "demo".to_string(),
+            workspace_folder: PathBuf::from("/work"),
+            workspace_mount: Some(Mount::String(
+                "type=volume,source=demo-src,target=/src,consistency=cached".to_string(),
+            )),
+            ..Default::default()
+        };
+        let preparation = ProviderPreparation {
+            image: ProviderImage::Reference("example:image".to_string()),
+            container_name: "devcontainer-demo".to_string(),
+            project_slug: "demo".to_string(),
+            networks: Vec::new(),
+            volumes: Vec::new(),
+            workspace_mount_path: PathBuf::from("/src"),
+            workspace_volume: None,
+            sidecars: Vec::new(),
+        };
+
+        provider
+            .create_container(&config, &preparation, "example:image")
+            .await
+            .expect("create");
+
+        let create = &runner.calls()[1].args;
+        assert!(create
+            .contains(&"type=volume,source=demo-src,target=/src,consistency=cached".to_string()));
+        assert!(!create.iter().any(|arg| arg.starts_with("type=bind")));
+    }
+
+    #[tokio::test]
+    async fn remote_daemon_gets_a_copied_workspace_instead_of_a_bind_mount() {
+        assert!(is_remote_endpoint("ssh://dev@buildbox"));
+        assert!(is_remote_endpoint("tcp://10.0.0.5:2376"));
+        assert!(!is_remote_endpoint("tcp://127.0.0.1:2375"));
+        assert!(!is_remote_endpoint("tcp://[::1]:2375"));
+        assert!(!is_remote_endpoint("unix:///var/run/docker.sock"));
+        assert!(!is_remote_endpoint("npipe:////./pipe/docker_engine"));
+
+        let runner = RecordingRunner::new();
+        let provider = DockerProvider::from_path("docker")
+            .with_docker_host(Some("ssh://dev@buildbox".to_string()))
+            .with_runner(runner.clone());
+        let config = ResolvedConfig {
+            project_name: "demo".to_string(),
+            workspace_folder: PathBuf::from("/work"),
+            ..Default::default()
+        };
+        let preparation = ProviderPreparation {
+            image: ProviderImage::Reference("example:image".to_string()),
+            container_name: "devcontainer-demo".to_string(),
+            project_slug: "demo".to_string(),
+            networks: Vec::new(),
+            volumes: vec![VolumeSpec {
+                name: "devcontainer-demo-workspace".to_string(),
+                mount_path: PathBuf::from("/workspaces/demo"),
+            }],
+            workspace_mount_path: PathBuf::from("/workspaces/demo"),
+            workspace_volume: Some("devcontainer-demo-workspace".to_string()),
+            sidecars: Vec::new(),
+        };
+
+        provider
+            .create_container(&config, &preparation, "example:image")
+            .await
+            .expect("create");
+
+        let calls = runner.calls();
+        assert!(calls.iter().all(|call| call
+            .env
+            .contains(&("DOCKER_HOST".to_string(), "ssh://dev@buildbox".to_string()))));
+        let create = &calls[1].args;
+        assert!(create.contains(
+            &"type=volume,src=devcontainer-demo-workspace,dst=/workspaces/demo".to_string()
+        ));
+        assert!(!create.iter().any(|arg| arg.starts_with("type=bind")));
+        assert_eq!(
+            calls.last().expect("cp").args,
+            ["cp", "/work/.", "devcontainer-demo:/workspaces/demo"]
+        );
+    }
+
+    #[tokio::test]
+    async fn prepare_reports_daemon_socket_permission_denied() {
+        let runner = RecordingRunner::with_handler(|_| CommandOutput {
+            exit_code: 1,
+            stderr: "permission denied while trying to connect to the Docker daemon socket at \
+                     unix:///var/run/docker.sock: connect: permission denied"
+                .to_string(),
+            ..Default::default()
+        });
+        let provider = DockerProvider::from_path("docker").with_runner(runner);
+
+        let err = provider
+            .prepare(&ResolvedConfig::default())
+            .await
+            .expect_err("daemon unreachable");
+        assert!(matches!(
+            err,
+            DevcontainerError::DaemonAccess(DaemonAccessError::PermissionDenied { .. })
+        ));
+        assert!(err.to_string().contains("docker` group"));
+    }
+
+    #[tokio::test]
+    async fn build_image_labels_built_image_with_metadata() {
+        let runner = RecordingRunner::with_handler(|spec| {
+            match spec.args[0].as_str() {
+            "image" => CommandOutput {
+                stdout: serde_json::json!([{
+                    "Id": "sha256:abc",
+                    "Config": { "Labels": { "devcontainer.metadata": r#"[{"remoteUser":"node"}]"# } }
+                }])
+                .to_string(),
+                ..Default::default()
+            },
+            _ => CommandOutput::default(),
+        }
+        });
+        let provider = DockerProvider::from_path("docker").with_runner(runner.clone());
+        let config = ResolvedConfig {
+            remote_user: Some("vscode".to_string()),
+            post_create_command: Some(CommandDefinition::from_string("make setup")),
+            ..Default::default()
+        };
+        let dir = tempdir().expect("tempdir");
+        let dockerfile = dir.path().join("Dockerfile");
+        fs::write(&dockerfile, "FROM node:20 AS dev\nFROM dev AS release\n")
+            .expect("write Dockerfile");
+        let preparation = ProviderPreparation {
+            image: ProviderImage::Build(Box::new(ProviderBuildContext {
+                dockerfile: dockerfile.clone(),
+                build_context: PathBuf::from("/work/.devcontainer"),
+                tag: "devcontainer-demo:latest".to_string(),
+                args: BTreeMap::from([("VARIANT".to_string(), "bookworm".to_string())]),
+                target: Some("dev".to_string()),
+                cache_from: vec!["ghcr.io/example/cache:latest".to_string()],
+                cache_to: vec!["type=local,dest=/tmp/cache".to_string()],
+                options: vec!["--network=host".to_string()],
+                ..Default::default()
+            })),
+            container_name: "devcontainer-demo".to_string(),
+            project_slug: "demo".to_string(),
+            networks: Vec::new(),
+            volumes: Vec::new(),
+            workspace_mount_path: PathBuf::from("/workspaces/demo"),
+            workspace_volume: None,
+            sidecars: Vec::new(),
+        };
+
+        let image = provider
+            .build_image(&config, &preparation)
+            .await
+            .expect("build");
+        assert_eq!(image, "devcontainer-demo:latest");
+
+        let calls = runner.calls();
+        assert_eq!(calls[0].args, ["buildx", "version"]);
+        assert_eq!(calls[1].args, ["image", "inspect", "node:20"]);
+        assert_eq!(calls.len(), 3);
+        let build = &calls[2].args;
+        assert_eq!(build[..3], ["buildx", "build", "--load"]);
+        assert_eq!(build[7..9], ["-t", "devcontainer-demo:latest"]);
+        assert_eq!(
+            build[11..],
+            [
+                "--build-arg",
+                "VARIANT=bookworm",
+                "--target",
+                "dev",
+                "--cache-from",
+                "ghcr.io/example/cache:latest",
+                "--cache-to",
+                "type=local,dest=/tmp/cache",
+                "--network=host",
+                "/work/.devcontainer",
+            ]
+        );
+        assert_eq!(build[9], "--label");
+        let label = build[10]
+            .strip_prefix("devcontainer.metadata=")
+            .expect("metadata label");
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(label).expect("label json"),
+            serde_json::json!([
+                { "remoteUser": "node" },
+                { "remoteUser": "vscode", "postCreateCommand": "make setup" }
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn build_image_tags_and_pushes_image_names() {
+        let runner = RecordingRunner::with_handler(|spec| match spec.args[0].as_str() {
+            "image" => CommandOutput {
+                stdout: r#"[{"Id":"sha256:abc","Config":{"Labels":null}}]"#.to_string(),
+                ..Default::default()
+            },
+            "push" => CommandOutput {
+                stdout: "The push refers to repository [ghcr.io/example/app]\n\
+                         1: digest: sha256:0123abcd size: 1570\n"
+                    .to_string(),
+                ..Default::default()
+            },
+            _ => CommandOutput::default(),
+        });
+        let provider = DockerProvider::from_path("docker").with_runner(runner.clone());
+        let preparation = ProviderPreparation {
+            image: ProviderImage::Build(Box::new(ProviderBuildContext {
+                dockerfile: PathBuf::from("/work/Dockerfile"),
+                build_context: PathBuf::from("/work"),
+                tag: "devcontainer-demo:latest".to_string(),
+                image_names: vec![
+                    "ghcr.io/example/app:1".to_string(),
+                    "ghcr.io/example/app:latest".to_string(),
+                ],
+                push: true,
+                ..Default::default()
+            })),
+            container_name: "devcontainer-demo".to_string(),
+            project_slug: "demo".to_string(),
+            networks: Vec::new(),
+            volumes: Vec::new(),
+            workspace_mount_path: PathBuf::from("/workspaces/demo"),
+            workspace_volume: None,
+            sidecars: Vec::new(),
+        };
+
+        let image = provider
+            .build_image(&ResolvedConfig::default(), &preparation)
+            .await
+            .expect("build");
+        assert_eq!(image, "ghcr.io/example/app:1@sha256:0123abcd");
+
+        let calls: Vec<Vec<String>> = runner.calls().into_iter().map(|call| call.args).collect();
+        let tail = &calls[calls.len() - 4..];
+        assert_eq!(
+            tail,
+            [
+                vec!["tag", "devcontainer-demo:latest", "ghcr.io/example/app:1"],
+                vec![
+                    "tag",
+                    "devcontainer-demo:latest",
+                    "ghcr.io/example/app:latest"
+                ],
+                vec!["push", "ghcr.io/example/app:1"],
+                vec!["push", "ghcr.io/example/app:latest"],
+            ]
+        );
+    }
+
+    #[test]
+    fn multi_platform_build_pushes_manifest_list() {
+        let build = ProviderBuildContext {
+            dockerfile: PathBuf::from("/work/Dockerfile"),
+            build_context: PathBuf::from("/work"),
+            tag: "devcontainer-demo:latest".to_string(),
+            platforms: vec!["linux/amd64".to_string(), "linux/arm64".to_string()],
+            image_names: vec!["ghcr.io/example/devcontainer:latest".to_string()],
+            push: true,
+            ..Default::default()
+        };
+
+        let args = build_command_args(&build, true, "[]").expect("args");
+        assert_eq!(args[..3], ["buildx", "build", "--push"]);
+        assert_eq!(args[7..9], ["-t", "ghcr.io/example/devcontainer:latest"]);
+        assert_eq!(
+            args[args.len() - 3..],
+            ["--platform", "linux/amd64,linux/arm64", "/work"]
+        );
+    }
+
+    #[test]
+    fn classic_build_omits_cache_export() {
+        let build = ProviderBuildContext {
+            dockerfile: PathBuf::from("/work/Dockerfile"),
+            build_context: PathBuf::from("/work"),
+            tag: "demo:latest".to_string(),
+            cache_from: vec!["demo:cache".to_string()],
+            cache_to: vec!["type=inline".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            build_command_args(&build, false, "[]").expect("args"),
+            [
+                "build",
+                "--progress",
+                "plain",
+                "-f",
+                "/work/Dockerfile",
+                "-t",
+                "demo:latest",
+                "--label",
+                "devcontainer.metadata=[]",
+                "--cache-from",
+                "demo:cache",
+                "/work",
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_daemon_registry_mirrors() {
+        assert_eq!(
+            parse_registry_mirrors("[\"https://mirror.gcr.io/\",\"http://cache.local:5000\"]\n")
+                .expect("mirrors")
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["mirror.gcr.io", "cache.local:5000"]
+        );
+        assert!(parse_registry_mirrors("null\n").expect("none").is_empty());
+    }
+
+    #[test]
+    fn parses_build_step_lines() {
+        assert_eq!(
+            parse_build_step("#7 [stage-1 2/4] RUN make"),
+            Some((2, 4, "RUN make"))
+        );
+        assert_eq!(
+            parse_build_step("Step 1/3 : FROM debian"),
+            Some((1, 3, "FROM debian"))
+        );
+        assert_eq!(
+            parse_build_step("#1 [internal] load build definition"),
+            None
+        );
+        assert_eq!(parse_build_step("#7 0.512 make: Nothing to be done"), None);
+    }
+
+    #[test]
+    fn parses_pull_progress_lines() {
+        assert_eq!(
+            parse_pull_line("3f4ca61aafcd: Pulling fs layer"),
+            Some(("3f4ca61aafcd", LayerStatus::Waiting))
+        );
+        assert_eq!(
+            parse_pull_line("3f4ca61aafcd: Already exists"),
+            Some(("3f4ca61aafcd", LayerStatus::Complete))
+        );
+        assert_eq!(parse_pull_line("latest: Pulling from library/debian"), None);
+        assert_eq!(parse_pull_line("Digest: sha256:0123456789abcdef"), None);
+    }
+
+    #[test]
+    fn parses_inspect_output_without_matching_errors() {
+        let running = CommandOutput {
+            stdout: r#"[{"State":{"Running":true},"ExecIDs":["3f2a","9c1d"]}]"#.to_string(),
+            ..Default::default()
+        };
+        let inspected: Option<InspectedContainer> =
+            parse_inspect(&running, "container", "demo").expect("inspect");
+        assert_eq!(
+            inspected.map(ContainerActivity::from),
+            Some(ContainerActivity {
+                running: true,
+                sessions: 2,
+            })
+        );
+
+        let missing = CommandOutput {
+            exit_code: 1,
+            stdout: "[]\n".to_string(),
+            stderr: "Fehler: Kein solcher Container: demo".to_string(),
+            ..Default::default()
+        };
+        let inspected: Option<InspectedContainer> =
+            parse_inspect(&missing, "container", "demo").expect("inspect");
+        assert!(inspected.is_none());
+
+        let unreachable = CommandOutput {
+            exit_code: 1,
+            stderr: "Cannot connect to the Docker daemon".to_string(),
+            ..Default::default()
+        };
+        let err = parse_inspect::<InspectedContainer>(&unreachable, "container", "demo")
+            .expect_err("daemon down");
+        assert!(err.to_string().contains("Cannot connect"));
+    }
+
+    #[test]
+    fn attach_args_set_user_and_workdir() {
+        let session = InteractiveSession {
+            user: Some("vscode".to_string()),
+            working_dir: Some(PathBuf::from("/workspaces/demo")),
+            command: vec!["/bin/zsh".to_string(), "-l".to_string()],
+        };
+        assert_eq!(
+            attach_args("demo", &session, true).expect("args"),
+            vec![
+                "exec",
+                "-it",
+                "--user",
+                "vscode",
+                "--workdir",
+                "/workspaces/demo",
+                "demo",
+                "/bin/zsh",
+                "-l"
+            ]
+        );
+    }
+
+    #[test]
+    fn exec_args_include_user_and_workdir_when_requested() {
+        let command = vec!["apt-get".to_string(), "update".to_string()];
+        assert_eq!(
+            exec_args("demo", &ExecOptions::as_user(Some("root")), &command).expect("args"),
+            vec!["exec", "--user", "root", "demo", "apt-get", "update"]
+        );
+        assert_eq!(
+            exec_args("demo", &ExecOptions::default(), &command).expect("args"),
+            vec!["exec", "demo", "apt-get", "update"]
+        );
+        let options = ExecOptions {
+            user: Some("vscode".to_string()),
+            working_dir: Some(PathBuf::from("/workspaces/demo/api")),
+        };
+        assert_eq!(
+            exec_args("demo", &options, &command).expect("args"),
+            vec![
+                "exec",
+                "--user",
+                "vscode",
+                "--workdir",
+                "/workspaces/demo/api",
+                "demo",
+                "apt-get",
+                "update"
+            ]
+        );
+    }
+
+    #[test]
+    fn find_containers_filters_by_every_label() {
+        let labels = vec![
+            ContainerLabel::new("devcontainer.local_folder", "/src/app"),
+            ContainerLabel::new("devcontainer.config_file", "/src/app/.devcontainer.json"),
+        ];
+        assert_eq!(
+            find_containers_args(&labels),
+            vec![
+                "ps",
+                "--all",
+                "--filter",
+                "label=devcontainer.local_folder=/src/app",
+                "--filter",
+                "label=devcontainer.config_file=/src/app/.devcontainer.json",
+                "--format",
+                "{{.ID}}\t{{.Names}}",
+            ]
+        );
+
+        let containers = parse_container_listing("abc123\tdevcontainer-app\n\nfff000\t\n");
+        assert_eq!(containers.len(), 2);
+        assert_eq!(containers[0].id.as_deref(), Some("abc123"));
+        assert_eq!(containers[0].name.as_deref(), Some("devcontainer-app"));
+        assert!(containers[1].name.is_none());
+    }
+
+    #[test]
+    fn read_only_workspace_backs_writable_paths_with_volumes() {
+        let mut config = ResolvedConfig {
+            writable_workspace_paths: vec![PathBuf::from("target"), PathBuf::from("web/dist")],
+            ..Default::default()
+        };
+        let mount = Path::new("/workspaces/app");
+        assert!(writable_workspace_volumes(&config, "devcontainer-app", mount).is_empty());
+
+        config.read_only_workspace = true;
+        assert_eq!(
+            writable_workspace_volumes(&config, "devcontainer-app", mount),
+            vec![
+                VolumeSpec {
+                    name: "devcontainer-app-target".to_string(),
+                    mount_path: PathBuf::from("/workspaces/app/target"),
+                },
+                VolumeSpec {
+                    name: "devcontainer-app-web-dist".to_string(),
+                    mount_path: PathBuf::from("/workspaces/app/web/dist"),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn prepare_places_sidecars_on_project_network() {
+        let temp = tempdir().expect("temp workspace");
+        let workspace = temp.path().join("workspace");
+        fs::create_dir_all(&workspace).expect("workspace directory");
+        let config_path = temp.path().join("devcontainer.json");
+        fs::write(&config_path, "{}").expect("write config stub");
+
+        let provider = DockerProvider::from_path("/bin/echo");
+        let config = ResolvedConfig {
+            project_name: "Sample Project".into(),
+            workspace_folder: workspace,
+            config_path,
+            image_reference: Some("ghcr.io/devcontainers/base:latest".into()),
+            sidecars: vec![Sidecar {
+                name: "db".into(),
+                image: "postgres:16".into(),
+                env: [("POSTGRES_PASSWORD".to_string(), "secret".to_string())].into(),
+                ports: vec![ForwardPort {
+                    local_port: 15432,
+                    container_port: 5432,
+                    protocol: PortProtocol::Tcp,
+                    ..Default::default()
+                }],
+            }],
+            ..Default::default()
+        };
+
+        let preparation = provider.prepare(&config).await.unwrap();
+        assert_eq!(
+            preparation.networks,
+            vec!["devcontainer-sample-project".to_string()]
+        );
+        assert_eq!(preparation.sidecars.len(), 1);
+        assert_eq!(
+            preparation.sidecars[0].container_name,
+            "devcontainer-sample-project-db"
+        );
+
+        let args = sidecar_create_args(&config, &preparation, &preparation.sidecars[0]);
+        let joined = args.join(" ");
+        assert!(joined.contains("--network devcontainer-sample-project --network-alias db"));
+        assert!(joined.contains("--env POSTGRES_PASSWORD=secret"));
+        assert!(joined.contains("--publish 15432:5432/tcp"));
+        assert_eq!(args.last().map(String::as_str), Some("postgres:16"));
+    }
+
+    #[tokio::test]
+    async fn ensure_resources_adopt_own_leftovers_and_recreate_foreign_networks() {
+        let runner = RecordingRunner::with_handler(|spec| {
+            match (spec.args[0].as_str(), spec.args[1].as_str()) {
+                ("network", "inspect") => CommandOutput {
+                    stdout: r#"[{"Labels":{}}]"#.to_string(),
+                    ..Default::default()
+                },
+                ("volume", "inspect") => CommandOutput {
+                    stdout: r#"[{"Labels":{"devcontainer.project":"demo"}}]"#.to_string(),
+                    ..Default::default()
+                },
+                _ => CommandOutput::default(),
+            }
+        });
+        let provider = DockerProvider::from_path("docker").with_runner(runner.clone());
+        let config = ResolvedConfig {
+            project_name: "demo".to_string(),
+            ..Default::default()
+        };
+        let mut preparation = ProviderPreparation {
+            image: ProviderImage::Reference("example:image".to_string()),
+            container_name: "devcontainer-demo".to_string(),
+            project_slug: "demo".to_string(),
+            networks: vec!["devcontainer-demo".to_string()],
+            volumes: vec![VolumeSpec {
+                name: "devcontainer-demo-node-modules".to_string(),
+                mount_path: PathBuf::from("/workspaces/demo/node_modules"),
+            }],
+            workspace_mount_path: PathBuf::from("/workspaces/demo"),
+            workspace_volume: None,
+            sidecars: Vec::new(),
+        };
+
+        provider
+            .ensure_networks(&config, &preparation)
+            .await
+            .expect("networks");
+        provider
+            .ensure_volumes(&config, &preparation)
+            .await
+            .expect("volumes");
+        let calls: Vec<String> = runner
+            .calls()
+            .iter()
+            .map(|call| call.args[..2].join(" "))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                "network inspect",
+                "network rm",
+                "network create",
+                "volume inspect"
+            ]
+        );
+        assert!(runner.calls()[2]
+            .args
+            .contains(&"devcontainer.project=demo".to_string()));
+
+        let other = ResolvedConfig {
+            project_name: "other".to_string(),
+            ..Default::default()
+        };
+        preparation.networks.clear();
+        let err = provider
+            .ensure_volumes(&other, &preparation)
+            .await
+            .expect_err("foreign volume");
+        assert!(err.to_string().contains("owned by project 'demo'"));
+    }
+
+    #[tokio::test]
+    async fn ensure_volumes_clones_into_a_new_workspace_volume() {
+        let runner = RecordingRunner::with_handler(|spec| match spec.args[..2] {
+            [ref kind, ref verb] if kind == "volume" && verb == "inspect" => CommandOutput {
+                exit_code: 1,
+                stdout: "[]".to_string(),
+                ..Default::default()
+            },
+            _ => CommandOutput::default(),
+        });
+        let provider = DockerProvider::from_path("docker").with_runner(runner.clone());
+        let config = ResolvedConfig {
+            project_name: "demo".to_string(),
+            workspace_volume: Some("devcontainer-repo-workspace".to_string()),
+            clone_url: Some("https://example.com/org/repo.git".to_string()),
+            ..Default::default()
+        };
+        let preparation = ProviderPreparation {
+            image: ProviderImage::Reference("example:image".to_string()),
+            container_name: "devcontainer-demo".to_string(),
+            project_slug: "demo".to_string(),
+            networks: Vec::new(),
+            volumes: Vec::new(),
+            workspace_mount_path: PathBuf::from("/workspaces/repo"),
+            workspace_volume: None,
+            sidecars: Vec::new(),
+        };
+
+        let created = provider
+            .ensure_volumes(&config, &preparation)
+            .await
+            .expect("volumes");
+        assert_eq!(created, ["devcontainer-repo-workspace"]);
+
+        let calls = runner.calls();
+        assert_eq!(calls[1].args[..2], ["volume", "create"]);
+        let clone = &calls[2].args;
+        assert_eq!(clone[0], "run");
+        assert!(clone.contains(
+            &"type=volume,source=devcontainer-repo-workspace,target=/workspace".to_string()
+        ));
+        assert_eq!(
+            clone.last().map(String::as_str),
+            Some("https://example.com/org/repo.git")
+        );
+    }
 }